@@ -0,0 +1,161 @@
+// MOS 6551 Asynchronous Communications Interface Adapter, the serial port of most 6502 single
+// board computers. Four registers: data, status, command and control, repeated over the range it
+// is mapped at. The other end is a `serial::Serial`, connected with `Acia::connect`.
+//
+// Bytes go out as soon as they are written, so the transmitter is always empty (TDRE stays set),
+// which is also how the W65C51 behaves. Input is taken from the host once the receive register is
+// free. Baud rate and word format are kept for the program to read back but change
+// nothing, and the modem lines are always ready.
+//
+// http://archive.6502.org/datasheets/mos_6551_acia.pdf
+
+use crate::device::Device;
+use crate::serial::{Host, Link, Serial};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct Acia {
+    rx_data: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+    // the IRQ bit of the status register, latched until the status is read
+    irq: bool,
+    link: Link,
+}
+
+impl Default for Acia {
+    fn default() -> Self {
+        Acia::new()
+    }
+}
+
+impl Acia {
+    // registers
+    pub const DATA: u16 = 0;
+    pub const STATUS: u16 = 1;
+    pub const COMMAND: u16 = 2;
+    pub const CONTROL: u16 = 3;
+
+    // status bits
+    pub const STATUS_PARITY_ERROR: u8 = 0x01;
+    pub const STATUS_FRAMING_ERROR: u8 = 0x02;
+    pub const STATUS_OVERRUN: u8 = 0x04;
+    /// Receive data register full
+    pub const STATUS_RDRF: u8 = 0x08;
+    /// Transmit data register empty
+    pub const STATUS_TDRE: u8 = 0x10;
+    pub const STATUS_IRQ: u8 = 0x80;
+
+    // command bits
+    /// Data terminal ready, turns the receiver on
+    pub const COMMAND_DTR: u8 = 0x01;
+    /// Turns receive interrupts off
+    pub const COMMAND_RX_IRQ_OFF: u8 = 0x02;
+    pub const COMMAND_TX_MASK: u8 = 0x0C;
+    /// Transmit interrupts on, in `COMMAND_TX_MASK`
+    pub const COMMAND_TX_IRQ: u8 = 0x04;
+    /// Received bytes are sent back
+    pub const COMMAND_ECHO: u8 = 0x10;
+
+    pub fn new() -> Acia {
+        Acia {
+            rx_data: 0,
+            status: Acia::STATUS_TDRE,
+            command: 0,
+            control: 0,
+            irq: false,
+            link: Link::default(),
+        }
+    }
+
+    /// Connects the host end, the device's copies in the debugger's checkpoints share it
+    pub fn connect<H: Host + 'static>(&mut self, host: H) -> Rc<RefCell<Serial>> {
+        self.link.connect(host)
+    }
+
+    /// Puts a byte into the receive register as if it came in on RxD, overrunning the one there.
+    /// It doesn't go through the host, so going back in time doesn't see it again
+    pub fn receive(&mut self, byte: u8) {
+        if self.status & Acia::STATUS_RDRF != 0 {
+            self.status |= Acia::STATUS_OVERRUN;
+            return;
+        }
+        self.rx_data = byte;
+        self.status |= Acia::STATUS_RDRF;
+        if self.command & (Acia::COMMAND_DTR | Acia::COMMAND_RX_IRQ_OFF) == Acia::COMMAND_DTR {
+            self.irq = true;
+        }
+        if self.command & (Acia::COMMAND_ECHO | Acia::COMMAND_TX_MASK) == Acia::COMMAND_ECHO {
+            self.transmit(byte);
+        }
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.link.send(byte);
+        if self.command & Acia::COMMAND_TX_MASK == Acia::COMMAND_TX_IRQ {
+            self.irq = true;
+        }
+    }
+}
+
+impl Device for Acia {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.peek(offset);
+        match offset & 3 {
+            Acia::DATA => {
+                self.status &= !(Acia::STATUS_RDRF
+                    | Acia::STATUS_OVERRUN
+                    | Acia::STATUS_FRAMING_ERROR
+                    | Acia::STATUS_PARITY_ERROR);
+            }
+            Acia::STATUS => self.irq = false,
+            _ => {}
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 3 {
+            Acia::DATA => self.transmit(value),
+            // programmed reset
+            Acia::STATUS => {
+                self.command &= 0xE0;
+                self.status &= !Acia::STATUS_OVERRUN;
+            }
+            Acia::COMMAND => self.command = value,
+            _ => self.control = value,
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset & 3 {
+            Acia::DATA => self.rx_data,
+            Acia::STATUS if self.irq => self.status | Acia::STATUS_IRQ,
+            Acia::STATUS => self.status,
+            Acia::COMMAND => self.command,
+            _ => self.control,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.link.tick(cycles);
+        if self.status & Acia::STATUS_RDRF == 0 && self.command & Acia::COMMAND_DTR != 0 {
+            if let Some(byte) = self.link.poll() {
+                self.receive(byte);
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn reset(&mut self) {
+        self.status = Acia::STATUS_TDRE;
+        self.command = 0;
+        self.control = 0;
+        self.irq = false;
+    }
+}
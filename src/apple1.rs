@@ -0,0 +1,143 @@
+// Apple-1: RAM from $0000, the keyboard and the display on a PIA at $D010-$D013 and the Woz
+// Monitor in a 256 byte ROM at $FF00. The ROM isn't included, `setup` takes it from the user.
+//
+// `Terminal` stands in for the keyboard and the video section. A key puts its ASCII code with
+// bit 7 set on port A and strobes CA1, the Woz Monitor waits for the flag in KBDCR and reads KBD.
+// The display takes what is written to port B, PB7 is its busy line and always ready here. It
+// knows upper case, space and CR, and starts a new line after 40 columns.
+//
+// Keys are handed over one at a time, the next once the program has read the last, so a hex dump
+// pasted into the terminal goes in without losing characters however slowly the program reads.
+//
+// https://www.sbprojects.net/projects/apple1/wozmon.php
+
+use crate::device::Device;
+use crate::loader::{self, LoadError};
+use crate::pia::Pia;
+use crate::serial::{Host, Link, Serial};
+use crate::Cpu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// KBD, KBDCR, DSP and DSPCR
+pub const PIA_ADDR: u16 = 0xD010;
+pub const ROM_ADDR: u16 = 0xFF00;
+pub const COLUMNS: u8 = 40;
+
+/// The PIA with the keyboard and the display on it. IRQA and IRQB aren't connected on the Apple-1
+#[derive(Clone)]
+pub struct Terminal {
+    pia: Pia,
+    link: Link,
+    column: u8,
+    // a CR LF line end is one key
+    after_cr: bool,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::new()
+    }
+}
+
+impl Terminal {
+    pub fn new() -> Terminal {
+        let mut pia = Pia::new();
+        // the display is ready
+        pia.set_port_b(0x00);
+        Terminal { pia, link: Link::default(), column: 0, after_cr: false }
+    }
+
+    pub fn connect<H: Host + 'static>(&mut self, host: H) -> Rc<RefCell<Serial>> {
+        self.link.connect(host)
+    }
+
+    pub fn pia(&self) -> &Pia {
+        &self.pia
+    }
+
+    /// Presses a key, as if it came from the host
+    pub fn key(&mut self, byte: u8) {
+        if let Some(key) = self.translate_key(byte) {
+            self.pia.set_port_a(key | 0x80);
+            self.pia.set_ca1(false);
+            self.pia.set_ca1(true);
+        }
+    }
+
+    // Lower case becomes upper case, LF and CR LF become CR, backspace and delete become the
+    // underscore the Woz Monitor takes for rubout
+    fn translate_key(&mut self, byte: u8) -> Option<u8> {
+        let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => None,
+            b'\n' => Some(b'\r'),
+            0x08 | 0x7F => Some(b'_'),
+            0 | 0x80..=0xFF => None,
+            _ => Some(byte.to_ascii_uppercase()),
+        }
+    }
+
+    fn display(&mut self, byte: u8) {
+        match byte & 0x7F {
+            b'\r' => self.new_line(),
+            byte @ 0x20..=0x7F => {
+                // no lower case in the character generator, it shows as upper case
+                self.link.send(if byte >= 0x60 { byte - 0x20 } else { byte });
+                self.column += 1;
+                if self.column == COLUMNS {
+                    self.new_line();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.link.send(b'\r');
+        self.link.send(b'\n');
+        self.column = 0;
+    }
+}
+
+impl Device for Terminal {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.pia.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let display = offset & 3 == Pia::PB && self.pia.peek(Pia::CRB) & Pia::CR_PORT != 0;
+        self.pia.write(offset, value);
+        if display {
+            self.display(value);
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.pia.peek(offset)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.pia.tick(cycles);
+        self.link.tick(cycles);
+        // the last key was read
+        if self.pia.peek(Pia::CRA) & Pia::CR_IRQ1 == 0 {
+            if let Some(byte) = self.link.poll() {
+                self.key(byte);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pia.reset();
+    }
+}
+
+/// Loads the Woz Monitor, maps `terminal` and resets into the monitor. Returns the index of the
+/// terminal in `Cpu::devices`
+pub fn setup(cpu: &mut Cpu, rom: &[u8], terminal: Terminal) -> Result<usize, LoadError> {
+    loader::load(cpu.mem_mut(), rom, Some(ROM_ADDR))?;
+    let index = cpu.devices.map(PIA_ADDR..=PIA_ADDR + 3, terminal);
+    cpu.reset_to_vector();
+    Ok(index)
+}
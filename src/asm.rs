@@ -0,0 +1,714 @@
+// Small two pass assembler, mostly ca65 compatible syntax:
+//
+//     .org $0800
+//     count = 10
+//     start:  ldx #count
+//     @loop:  dex          ; cheap local label, scoped to the last normal label
+//             bne @loop
+//             jmp (vector)
+//     vector: .word start, <start, >start
+//             .byte "hi", 0
+//
+// Expressions support `$hex`, `%bin`, decimal and `'c'` numbers, `*` for the current address,
+// the unary operators `- ~ < >` (low and high byte) and `* / % + - << >> & ^ |`.
+// Operands with a value below $100 use the zero page encoding when one exists, unless they
+// are prefixed with `a:` (ca65 style) or are forward references.
+
+use crate::opcodes::{find_opcode, is_mnemonic, AddressingMode};
+use crate::symbols::SymbolTable;
+use crate::Mem;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A block of contiguous bytes, a new one starts at each `.org`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub chunks: Vec<Chunk>,
+    /// Labels only, `name = value` constants are not addresses
+    pub symbols: SymbolTable,
+}
+
+impl Assembly {
+    /// Address of the first emitted byte
+    pub fn origin(&self) -> u16 {
+        self.chunks.iter().find(|chunk| !chunk.data.is_empty()).map_or(0, |chunk| chunk.addr)
+    }
+
+    /// Everything from the lowest to the highest address as one block, gaps filled with zeros
+    pub fn bytes(&self) -> Vec<u8> {
+        let chunks = self.chunks.iter().filter(|chunk| !chunk.data.is_empty());
+        let start = chunks.clone().map(|chunk| chunk.addr as usize).min().unwrap_or(0);
+        let end =
+            chunks.clone().map(|chunk| chunk.addr as usize + chunk.data.len()).max().unwrap_or(0);
+        let mut bytes = vec![0; end - start];
+        for chunk in chunks {
+            let offset = chunk.addr as usize - start;
+            bytes[offset..offset + chunk.data.len()].copy_from_slice(&chunk.data);
+        }
+        bytes
+    }
+
+    pub fn load(&self, mem: &mut Mem) {
+        for chunk in &self.chunks {
+            mem.load_programm_at(chunk.addr, &chunk.data);
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let statements = parse(source)?;
+    let mut asm = Assembler { symbols: HashMap::new(), labels: Vec::new(), modes: HashMap::new() };
+    asm.pass(&statements, false)?;
+    let chunks = asm.pass(&statements, true)?;
+    let mut symbols = SymbolTable::new();
+    for name in &asm.labels {
+        symbols.insert(name, asm.symbols[name] as u16);
+    }
+    Ok(Assembly { chunks, symbols })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOp {
+    Neg,
+    Not,
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    And,
+    Xor,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(i32),
+    Symbol(String),
+    Pc,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    // forced absolute (`a:`) and the index register, if any
+    Direct(Expr, bool, Option<char>),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ByteItem {
+    Expr(Expr),
+    Text(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Directive {
+    None,
+    Assign(String, Expr),
+    Org(Expr),
+    Byte(Vec<ByteItem>),
+    Word(Vec<Expr>),
+    Res(Expr, Option<Expr>),
+    Instruction(String, Operand),
+}
+
+struct Statement {
+    line: usize,
+    label: Option<String>,
+    directive: Directive,
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, AsmError> {
+    Err(AsmError { line, message })
+}
+
+// Drops the comment, keeping semicolons inside of strings and char literals
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+// Splits on commas that are not inside quotes or parentheses
+fn split_args(text: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                args.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(text[start..].trim());
+    args
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '@'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '@'
+}
+
+fn parse(source: &str) -> Result<Vec<Statement>, AsmError> {
+    let mut statements = Vec::new();
+    let mut scope = String::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut rest = strip_comment(text).trim();
+        let mut label = None;
+        // `name:` can be followed by an instruction on the same line
+        if let Some(pos) = rest.find(':') {
+            let name = rest[..pos].trim();
+            if !name.is_empty()
+                && name.starts_with(is_ident_start)
+                && name.chars().all(is_ident_char)
+                && name != "a"
+            {
+                if name.starts_with('@') {
+                    if scope.is_empty() {
+                        return error(
+                            line,
+                            format!("local label {} without a previous label", name),
+                        );
+                    }
+                } else {
+                    scope = name.to_string();
+                }
+                label = Some(qualify(name, &scope));
+                rest = rest[pos + 1..].trim();
+            }
+        }
+        let directive =
+            parse_statement(rest, &scope).map_err(|message| AsmError { line, message })?;
+        statements.push(Statement { line, label, directive });
+    }
+    Ok(statements)
+}
+
+fn qualify(name: &str, scope: &str) -> String {
+    if name.starts_with('@') {
+        format!("{}{}", scope, name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn parse_statement(text: &str, scope: &str) -> Result<Directive, String> {
+    if text.is_empty() {
+        return Ok(Directive::None);
+    }
+    let (word, args) = match text.find(char::is_whitespace) {
+        Some(pos) => (&text[..pos], text[pos..].trim()),
+        None => (text, ""),
+    };
+    if let Some(pos) = text.find('=') {
+        let name = text[..pos].trim();
+        if !name.is_empty() && name.chars().all(is_ident_char) && !name.starts_with('@') {
+            return Ok(Directive::Assign(name.to_string(), parse_expr(&text[pos + 1..], scope)?));
+        }
+    }
+    let exprs = |args: &str| -> Result<Vec<Expr>, String> {
+        split_args(args).into_iter().map(|arg| parse_expr(arg, scope)).collect()
+    };
+    match word.to_lowercase().as_str() {
+        ".org" => Ok(Directive::Org(parse_expr(args, scope)?)),
+        ".word" | ".addr" => Ok(Directive::Word(exprs(args)?)),
+        ".byte" | ".byt" => {
+            let mut items = Vec::new();
+            for arg in split_args(args) {
+                if arg.len() >= 2 && arg.starts_with('"') && arg.ends_with('"') {
+                    items.push(ByteItem::Text(arg[1..arg.len() - 1].bytes().collect()));
+                } else {
+                    items.push(ByteItem::Expr(parse_expr(arg, scope)?));
+                }
+            }
+            Ok(Directive::Byte(items))
+        }
+        ".res" => {
+            let mut args = exprs(args)?.into_iter();
+            match (args.next(), args.next(), args.next()) {
+                (Some(count), fill, None) => Ok(Directive::Res(count, fill)),
+                _ => Err(".res expects a count and an optional fill value".to_string()),
+            }
+        }
+        name if name.starts_with('.') => Err(format!("unknown directive {}", word)),
+        name if is_mnemonic(name) => {
+            Ok(Directive::Instruction(name.to_uppercase(), parse_operand(args, scope)?))
+        }
+        _ => Err(format!("unknown instruction {}", word)),
+    }
+}
+
+// Index of the parenthesis closing the one at position 0
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_operand(text: &str, scope: &str) -> Result<Operand, String> {
+    let upper = text.to_uppercase().replace(' ', "");
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if upper == "A" {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(parse_expr(value, scope)?));
+    }
+    if text.starts_with('(') {
+        let close = closing_paren(text).ok_or("missing )")?;
+        let inner = &text[1..close];
+        let after = text[close + 1..].replace(' ', "").to_uppercase();
+        if after == ",Y" {
+            return Ok(Operand::IndirectY(parse_expr(inner, scope)?));
+        }
+        if after.is_empty() {
+            if let [value, index] = split_args(inner)[..] {
+                if index.eq_ignore_ascii_case("x") {
+                    return Ok(Operand::IndirectX(parse_expr(value, scope)?));
+                }
+                return Err(format!("invalid index register {}", index));
+            }
+            return Ok(Operand::Indirect(parse_expr(inner, scope)?));
+        }
+        // a parenthesized expression, like (base+1)*2,x
+    }
+    let (mut value, index) = match split_args(text)[..] {
+        [value] => (value, None),
+        [value, index] if index.eq_ignore_ascii_case("x") => (value, Some('X')),
+        [value, index] if index.eq_ignore_ascii_case("y") => (value, Some('Y')),
+        _ => return Err(format!("invalid operand {}", text)),
+    };
+    let mut force_abs = false;
+    if value.starts_with("a:") || value.starts_with("A:") {
+        force_abs = true;
+        value = &value[2..];
+    }
+    Ok(Operand::Direct(parse_expr(value, scope)?, force_abs, index))
+}
+
+struct ExprParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    scope: &'a str,
+}
+
+fn parse_expr(text: &str, scope: &str) -> Result<Expr, String> {
+    let mut parser = ExprParser { chars: text.trim().chars().collect(), pos: 0, scope };
+    if parser.chars.is_empty() {
+        return Err("missing expression".to_string());
+    }
+    let expr = parser.binary(0)?;
+    parser.skip_spaces();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("unexpected '{}' in expression", parser.chars[parser.pos]));
+    }
+    Ok(expr)
+}
+
+// Lowest precedence first
+const PRECEDENCE: [&[(&str, BinaryOp)]; 6] = [
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Mod)],
+];
+
+impl<'a> ExprParser<'a> {
+    fn skip_spaces(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            self.skip_spaces();
+            for (text, op) in PRECEDENCE[level] {
+                if self.starts_with(text) {
+                    self.pos += text.len();
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        let op = match self.peek() {
+            Some('-') => UnaryOp::Neg,
+            Some('~') => UnaryOp::Not,
+            Some('<') => UnaryOp::Low,
+            Some('>') => UnaryOp::High,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        let c = self.peek().ok_or("missing operand in expression")?;
+        self.pos += 1;
+        match c {
+            '(' => {
+                let expr = self.binary(0)?;
+                self.skip_spaces();
+                if self.peek() != Some(')') {
+                    return Err("missing )".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            '*' => Ok(Expr::Pc),
+            '\'' => {
+                let value = self.peek().ok_or("unterminated character")?;
+                if self.chars.get(self.pos + 1) != Some(&'\'') {
+                    return Err("unterminated character".to_string());
+                }
+                self.pos += 2;
+                Ok(Expr::Num(value as i32))
+            }
+            '$' => self.number(16),
+            '%' => self.number(2),
+            c if c.is_ascii_digit() => {
+                self.pos -= 1;
+                self.number(10)
+            }
+            c if is_ident_start(c) => {
+                let start = self.pos - 1;
+                while self.peek().is_some_and(is_ident_char) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                Ok(Expr::Symbol(qualify(&name, self.scope)))
+            }
+            c => Err(format!("unexpected '{}' in expression", c)),
+        }
+    }
+
+    fn number(&mut self, radix: u32) -> Result<Expr, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_digit(radix)) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        i32::from_str_radix(&digits, radix)
+            .map(Expr::Num)
+            .map_err(|_| format!("invalid number '{}'", digits))
+    }
+}
+
+enum EvalError {
+    Undefined(String),
+    Message(String),
+}
+
+struct Assembler {
+    symbols: HashMap<String, i32>,
+    // label names in definition order
+    labels: Vec<String>,
+    // addressing mode picked for each instruction on the first pass, so sizes don't change
+    modes: HashMap<usize, AddressingMode>,
+}
+
+impl Assembler {
+    fn eval(&self, expr: &Expr, pc: u16) -> Result<i32, EvalError> {
+        Ok(match expr {
+            Expr::Num(value) => *value,
+            Expr::Pc => pc as i32,
+            Expr::Symbol(name) => {
+                *self.symbols.get(name).ok_or_else(|| EvalError::Undefined(name.clone()))?
+            }
+            Expr::Unary(op, expr) => {
+                let value = self.eval(expr, pc)?;
+                match op {
+                    UnaryOp::Neg => -value,
+                    UnaryOp::Not => !value,
+                    UnaryOp::Low => value & 0xFF,
+                    UnaryOp::High => (value >> 8) & 0xFF,
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.eval(left, pc)?, self.eval(right, pc)?);
+                match op {
+                    BinaryOp::Mul => left.wrapping_mul(right),
+                    BinaryOp::Div | BinaryOp::Mod if right == 0 => {
+                        return Err(EvalError::Message("division by zero".to_string()))
+                    }
+                    BinaryOp::Div => left / right,
+                    BinaryOp::Mod => left % right,
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    BinaryOp::Shl => left.wrapping_shl(right as u32),
+                    BinaryOp::Shr => left.wrapping_shr(right as u32),
+                    BinaryOp::And => left & right,
+                    BinaryOp::Xor => left ^ right,
+                    BinaryOp::Or => left | right,
+                }
+            }
+        })
+    }
+
+    // On the first pass undefined symbols are fine, they may be defined later
+    fn value(
+        &self,
+        expr: &Expr,
+        pc: u16,
+        final_pass: bool,
+        line: usize,
+    ) -> Result<Option<i32>, AsmError> {
+        match self.eval(expr, pc) {
+            Ok(value) => Ok(Some(value)),
+            Err(EvalError::Undefined(_)) if !final_pass => Ok(None),
+            Err(EvalError::Undefined(name)) => error(line, format!("undefined symbol {}", name)),
+            Err(EvalError::Message(message)) => error(line, message),
+        }
+    }
+
+    fn known(&self, expr: &Expr, pc: u16, line: usize) -> Result<i32, AsmError> {
+        match self.eval(expr, pc) {
+            Ok(value) => Ok(value),
+            Err(EvalError::Undefined(name)) => {
+                error(line, format!("{} must be defined before it is used here", name))
+            }
+            Err(EvalError::Message(message)) => error(line, message),
+        }
+    }
+
+    fn pass(&mut self, statements: &[Statement], final_pass: bool) -> Result<Vec<Chunk>, AsmError> {
+        let mut chunks = vec![Chunk { addr: 0, data: Vec::new() }];
+        let mut pc: u32 = 0;
+        for stmt in statements {
+            let line = stmt.line;
+            if pc > 0x10000 {
+                return error(line, "program goes beyond $FFFF".to_string());
+            }
+            let addr = pc as u16;
+            if let Some(label) = &stmt.label {
+                if !final_pass {
+                    if self.symbols.contains_key(label) {
+                        return error(line, format!("{} is already defined", label));
+                    }
+                    self.labels.push(label.clone());
+                }
+                self.symbols.insert(label.clone(), pc as i32);
+            }
+            let mut out = Vec::new();
+            match &stmt.directive {
+                Directive::None => {}
+                Directive::Assign(name, expr) => {
+                    if let Some(value) = self.value(expr, addr, final_pass, line)? {
+                        self.symbols.insert(name.clone(), value);
+                    }
+                }
+                Directive::Org(expr) => {
+                    let value = self.known(expr, addr, line)?;
+                    pc = check_range(value, 0, 0xFFFF, line)? as u32;
+                    chunks.push(Chunk { addr: pc as u16, data: Vec::new() });
+                }
+                Directive::Byte(items) => {
+                    for item in items {
+                        match item {
+                            ByteItem::Text(text) => out.extend_from_slice(text),
+                            ByteItem::Expr(expr) => {
+                                let value = self.value(expr, addr, final_pass, line)?.unwrap_or(0);
+                                out.push(check_range(value, -128, 255, line)? as u8);
+                            }
+                        }
+                    }
+                }
+                Directive::Word(exprs) => {
+                    for expr in exprs {
+                        let value = self.value(expr, addr, final_pass, line)?.unwrap_or(0);
+                        let value = check_range(value, -32768, 0xFFFF, line)? as u16;
+                        out.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+                Directive::Res(count, fill) => {
+                    let count = self.known(count, addr, line)?;
+                    let fill = match fill {
+                        Some(fill) => self.value(fill, addr, final_pass, line)?.unwrap_or(0),
+                        None => 0,
+                    };
+                    let fill = check_range(fill, -128, 255, line)? as u8;
+                    out = vec![fill; check_range(count, 0, 0x10000, line)? as usize];
+                }
+                Directive::Instruction(mnemonic, operand) => {
+                    out = self.instruction(mnemonic, operand, addr, final_pass, line)?;
+                }
+            }
+            pc += out.len() as u32;
+            if pc > 0x10000 {
+                return error(line, "program goes beyond $FFFF".to_string());
+            }
+            chunks.last_mut().unwrap().data.extend(out);
+        }
+        Ok(chunks.into_iter().filter(|chunk| !chunk.data.is_empty()).collect())
+    }
+
+    fn instruction(
+        &mut self,
+        mnemonic: &str,
+        operand: &Operand,
+        addr: u16,
+        final_pass: bool,
+        line: usize,
+    ) -> Result<Vec<u8>, AsmError> {
+        let opcode = |mode| find_opcode(mnemonic, mode, true);
+        let (mode, expr) = match operand {
+            Operand::None if opcode(AddressingMode::Implied).is_some() => {
+                (AddressingMode::Implied, None)
+            }
+            Operand::None | Operand::Accumulator => (AddressingMode::Accumulator, None),
+            Operand::Immediate(expr) => (AddressingMode::Immediate, Some(expr)),
+            Operand::Indirect(expr) => (AddressingMode::Indirect, Some(expr)),
+            Operand::IndirectX(expr) => (AddressingMode::IndirectX, Some(expr)),
+            Operand::IndirectY(expr) => (AddressingMode::IndirectY, Some(expr)),
+            Operand::Direct(expr, _, None) if opcode(AddressingMode::Relative).is_some() => {
+                (AddressingMode::Relative, Some(expr))
+            }
+            Operand::Direct(expr, force_abs, index) => {
+                let (zp, abs) = match index {
+                    None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                    Some('X') => (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+                    _ => (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+                };
+                let mode = match self.modes.get(&line) {
+                    Some(mode) => *mode,
+                    None => {
+                        let value = self.value(expr, addr, final_pass, line)?;
+                        let fits_zp = matches!(value, Some(value) if (0..0x100).contains(&value));
+                        if (fits_zp && !force_abs && opcode(zp).is_some()) || opcode(abs).is_none()
+                        {
+                            zp
+                        } else {
+                            abs
+                        }
+                    }
+                };
+                self.modes.insert(line, mode);
+                (mode, Some(expr))
+            }
+        };
+        let opcode = match opcode(mode) {
+            Some(opcode) => opcode,
+            None => {
+                return error(line, format!("{} does not support this addressing mode", mnemonic))
+            }
+        };
+        let value = match expr {
+            Some(expr) => self.value(expr, addr, final_pass, line)?.unwrap_or(0),
+            None => 0,
+        };
+        let mut bytes = vec![opcode];
+        match mode.size() {
+            2 if mode == AddressingMode::Relative => {
+                let offset = value - (addr as i32 + 2);
+                if final_pass && !(-128..=127).contains(&offset) {
+                    return error(
+                        line,
+                        format!("branch target is out of range ({} bytes)", offset),
+                    );
+                }
+                bytes.push(offset as u8);
+            }
+            2 if mode == AddressingMode::Immediate => {
+                bytes.push(check_range(value, -128, 255, line)? as u8)
+            }
+            2 => bytes.push(check_range(value, 0, 255, line)? as u8),
+            3 => bytes
+                .extend_from_slice(&(check_range(value, 0, 0xFFFF, line)? as u16).to_le_bytes()),
+            _ => {}
+        }
+        Ok(bytes)
+    }
+}
+
+fn check_range(value: i32, min: i32, max: i32, line: usize) -> Result<i32, AsmError> {
+    if value < min || value > max {
+        return error(line, format!("value {} is out of range", value));
+    }
+    Ok(value)
+}
@@ -0,0 +1,260 @@
+// emu6502 [options] [program]
+//
+// Starts the machine language monitor, type `help` at the prompt for the commands. With --gdb
+// it waits for gdb instead: `target remote :PORT`, or `target remote | emu6502 --gdb stdio prog`.
+// With --dap it is a Debug Adapter Protocol server on stdin and stdout, for editors. With an
+// ACIA (--acia ADDR) or a system's terminal on stdio the program just runs, Ctrl-C quits.
+
+use emulator6502::acia::Acia;
+use emulator6502::apple1;
+use emulator6502::breadboard;
+use emulator6502::cdl::Layout;
+use emulator6502::dap::DapServer;
+use emulator6502::debugger::{Debugger, StopReason};
+use emulator6502::gdb::GdbStub;
+use emulator6502::kim1;
+use emulator6502::lines::LineTable;
+use emulator6502::loader::{load_file, DEFAULT_LOAD_ADDR};
+use emulator6502::monitor::Monitor;
+use emulator6502::serial::{Host, Stdio, Tcp};
+use emulator6502::symbols::{parse_number, SymbolTable};
+use emulator6502::*;
+use std::net::TcpListener;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+const USAGE: &str = "usage: emu6502 [options] [program]
+
+The program is an llvm-mos ELF, an iNES file or a raw binary.
+
+options:
+  --load ADDR      load address of a raw binary (default $0200)
+  --pc ADDR        start address, instead of the ELF entry or the load address
+  --symbols FILE   VICE, ca65 .dbg, ACME or 64tass symbol file
+  --gdb PORT       serve the gdb remote protocol on localhost:PORT instead of the monitor,
+                   `--gdb stdio` talks to gdb over stdin and stdout
+  --dap            serve the Debug Adapter Protocol on stdin and stdout instead of the monitor
+  --acia ADDR      map a 6551 ACIA at ADDR
+  --system NAME    set up a machine, the program is loaded into it: `apple1`, `kim1` in TTY
+                   mode or `breadboard`, Ben Eater's 6502 with a VIA at $6000 and an LCD on it
+  --rom FILE       the system's ROM, needed for all of them: the Woz Monitor for the Apple-1,
+                   the 6530-003 and -002 ROMs for the KIM-1 (or -002 alone), the EEPROM image of
+                   the breadboard computer
+  --serial HOST    where the ACIA or the system's terminal is connected: `stdio` (default)
+                   runs the program on the terminal without the monitor, `pty` opens a pseudo
+                   terminal, a port number listens on localhost:PORT";
+
+fn fail(message: &str) -> ! {
+    eprintln!("emu6502: {}", message);
+    exit(2)
+}
+
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+// Ctrl-C stops a running `continue` and returns to the prompt
+#[cfg(unix)]
+fn handle_ctrl_c(flag: Arc<AtomicBool>) {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if let Some(flag) = INTERRUPT.get() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+    let _ = INTERRUPT.set(flag);
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn handle_ctrl_c(_flag: Arc<AtomicBool>) {}
+
+fn main() {
+    let mut program = None;
+    let mut load = DEFAULT_LOAD_ADDR;
+    let mut pc = None;
+    let mut symbols = SymbolTable::new();
+    let mut lines = LineTable::new();
+    let mut gdb = None;
+    let mut dap = false;
+    let mut acia = None;
+    let mut serial = None;
+    let mut system = None;
+    let mut rom = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value =
+            |name: &str| args.next().unwrap_or_else(|| fail(&format!("{} needs a value", name)));
+        let mut addr = |name: &str| {
+            let text = value(name);
+            match parse_number(&text) {
+                Some(addr) if addr <= 0xFFFF => addr as u16,
+                _ => fail(&format!("invalid address {}", text)),
+            }
+        };
+        match arg.as_str() {
+            "--load" => load = addr("--load"),
+            "--pc" => pc = Some(addr("--pc")),
+            "--symbols" => {
+                let file = value("--symbols");
+                let table = SymbolTable::load(&file)
+                    .unwrap_or_else(|err| fail(&format!("{}: {}", file, err)));
+                symbols.merge(&table);
+                let table = LineTable::load(&file)
+                    .unwrap_or_else(|err| fail(&format!("{}: {}", file, err)));
+                lines.merge(&table);
+            }
+            "--gdb" => gdb = Some(value("--gdb")),
+            "--dap" => dap = true,
+            "--acia" => acia = Some(addr("--acia")),
+            "--serial" => serial = Some(value("--serial")),
+            "--system" => system = Some(value("--system")),
+            "--rom" => rom = Some(value("--rom")),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => fail(&format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ if program.is_none() => program = Some(arg),
+            _ => fail(&format!("unexpected argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+
+    // the cpu borrows the memory for the rest of the program
+    let mem: &'static mut Mem = Box::leak(Box::new(Mem::new()));
+    mem.reset();
+    let mut entry = None;
+    let mut cdl_layout = Layout::default();
+    if let Some(program) = &program {
+        let loaded = load_file(mem, program, Some(load))
+            .unwrap_or_else(|err| fail(&format!("{}: {}", program, err)));
+        symbols.merge(&loaded.symbols);
+        lines.merge(&loaded.lines);
+        if let Some((prg_size, chr_size)) = loaded.rom_sizes {
+            cdl_layout = Layout::Nes { prg_size, chr_size };
+        }
+        entry = Some(loaded.entry.unwrap_or(load));
+    }
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    let host = serial.as_deref().unwrap_or("stdio");
+    if serial.is_some() && acia.is_none() && system.is_none() {
+        fail("--serial needs --acia or --system");
+    }
+    let on_terminal = (acia.is_some() || system.is_some()) && host == "stdio";
+    if on_terminal && (dap || gdb.as_deref() == Some("stdio")) {
+        fail("the terminal and the debugger can't both use stdin and stdout");
+    }
+    match system.as_deref() {
+        Some("apple1") => {
+            let rom =
+                rom.unwrap_or_else(|| fail("--system apple1 needs --rom with the Woz Monitor"));
+            let data = std::fs::read(&rom).unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+            let mut terminal = apple1::Terminal::new();
+            terminal.connect(open_host(host));
+            apple1::setup(&mut cpu, &data, terminal)
+                .unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+        }
+        Some("kim1") => {
+            let rom = rom.unwrap_or_else(|| fail("--system kim1 needs --rom with the KIM monitor"));
+            let data = std::fs::read(&rom).unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+            let mut tty = kim1::Tty::new();
+            tty.connect(open_host(host));
+            kim1::setup(&mut cpu, &data, tty)
+                .unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+        }
+        Some("breadboard") => {
+            let rom = rom.unwrap_or_else(|| fail("--system breadboard needs --rom"));
+            let data = std::fs::read(&rom).unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+            let mut board = breadboard::Board::new();
+            board.connect(open_host(host));
+            breadboard::setup(&mut cpu, &data, board)
+                .unwrap_or_else(|err| fail(&format!("{}: {}", rom, err)));
+        }
+        Some(name) => fail(&format!("unknown system {}", name)),
+        None => {}
+    }
+    // a system starts in its ROM
+    let start = if system.is_some() { pc } else { pc.or(entry) };
+    if let Some(pc) = start {
+        cpu.pc = pc;
+    }
+    if let Some(addr) = acia {
+        let mut device = Acia::new();
+        device.connect(open_host(host));
+        cpu.devices.map(addr..=addr.saturating_add(3), device);
+    }
+
+    let debugger = Debugger::new(cpu);
+    handle_ctrl_c(debugger.interrupt_handle());
+    if let Some(gdb) = gdb {
+        serve_gdb(GdbStub::new(debugger), &gdb);
+        return;
+    }
+    if dap {
+        let mut server = DapServer::new(debugger);
+        server.symbols = symbols;
+        server.lines = lines;
+        if let Err(err) = server.serve(std::io::stdin(), std::io::stdout()) {
+            fail(&err.to_string());
+        }
+        return;
+    }
+    if on_terminal {
+        run_on_terminal(debugger);
+        return;
+    }
+    let mut monitor = Monitor::new(debugger);
+    monitor.symbols = symbols;
+    monitor.lines = lines;
+    monitor.cdl_layout = cdl_layout;
+    let stdin = std::io::stdin();
+    if let Err(err) = monitor.run(stdin.lock(), &mut std::io::stdout()) {
+        fail(&err.to_string());
+    }
+}
+
+fn serve_gdb(mut stub: GdbStub, target: &str) {
+    let result = if target == "stdio" {
+        stub.serve(std::io::stdin(), std::io::stdout())
+    } else {
+        let port: u16 =
+            target.parse().unwrap_or_else(|_| fail(&format!("invalid port {}", target)));
+        TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+            eprintln!("emu6502: waiting for gdb on localhost:{}", port);
+            let (stream, _) = listener.accept()?;
+            // packets are small and strictly request/reply
+            stream.set_nodelay(true)?;
+            stub.serve(stream.try_clone()?, stream)
+        })
+    };
+    if let Err(err) = result {
+        fail(&err.to_string());
+    }
+}
+
+fn open_host(host: &str) -> Box<dyn Host> {
+    match host {
+        "stdio" => Box::new(Stdio::new()),
+        #[cfg(unix)]
+        "pty" => {
+            let pty = emulator6502::serial::Pty::open()
+                .unwrap_or_else(|err| fail(&format!("pty: {}", err)));
+            eprintln!("emu6502: serial port on {}", pty.path());
+            Box::new(pty)
+        }
+        port => {
+            let port: u16 =
+                port.parse().unwrap_or_else(|_| fail(&format!("invalid port {}", port)));
+            let tcp = Tcp::listen(port).unwrap_or_else(|err| fail(&err.to_string()));
+            eprintln!("emu6502: serial port on localhost:{}", port);
+            Box::new(tcp)
+        }
+    }
+}
+
+// Runs until Ctrl-C, nobody goes back in time here
+fn run_on_terminal(mut debugger: Debugger) {
+    debugger.set_history(None);
+    while debugger.run(None) != StopReason::Interrupted {}
+}
@@ -0,0 +1,83 @@
+// sim65 [options] <program> [arguments]
+//
+// Runs a cc65 program built for sim65 (`cl65 -t sim6502`) the way cc65's sim65 does: its file
+// operations go to the host and the process exits with the program's exit code, so cc65 test
+// suites can run on this emulator.
+
+use emulator6502::sim65::{self, Exit, Paravirt};
+use emulator6502::symbols::parse_number;
+use emulator6502::*;
+use std::process::exit;
+
+const USAGE: &str = "usage: sim65 [options] <program> [arguments]
+
+options:
+  -c, --cycles          print the number of cycles at the end
+  -v, --verbose         tell what is loaded and how the program ended, on stderr
+  -x, --max-cycles N    stop after N cycles with exit code $7E";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("sim65: {}\n\n{}", message, USAGE);
+    exit(sim65::EXIT_ERROR)
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (mut print_cycles, mut verbose, mut max_cycles) = (false, false, None);
+    let program = loop {
+        let arg = args.next().unwrap_or_else(|| usage_error("no program"));
+        match arg.as_str() {
+            "-c" | "--cycles" => print_cycles = true,
+            "-v" | "--verbose" => verbose = true,
+            "-x" | "--max-cycles" => {
+                let value = args.next().unwrap_or_else(|| usage_error("-x needs a value"));
+                let cycles = parse_number(&value)
+                    .unwrap_or_else(|| usage_error(&format!("invalid number {}", value)));
+                max_cycles = Some(cycles as u64);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            }
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option {}", arg)),
+            _ => break arg,
+        }
+    };
+    // the program sees its own name and what comes after it
+    let argv = std::iter::once(program.clone()).chain(args).collect();
+
+    let data = std::fs::read(&program).unwrap_or_else(|err| {
+        eprintln!("sim65: {}: {}", program, err);
+        exit(sim65::EXIT_ERROR)
+    });
+    let mut mem = Mem::new();
+    let header = sim65::load(&mut mem, &data).unwrap_or_else(|err| {
+        eprintln!("sim65: {}: {}", program, err);
+        exit(sim65::EXIT_ERROR)
+    });
+    if verbose {
+        eprintln!("Loaded {} at ${:04X}, starting at ${:04X}", program, header.load, header.reset);
+    }
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = header.reset;
+    let mut paravirt = Paravirt::new(&header, argv);
+    let (end, cycles) = sim65::run(&mut cpu, &mut paravirt, max_cycles);
+    if print_cycles {
+        println!("{} cycles", cycles);
+    }
+    match end {
+        Exit::Code(code) => {
+            if verbose {
+                eprintln!("Exit code {}", code);
+            }
+            exit(code as i32)
+        }
+        Exit::Timeout => {
+            if verbose {
+                eprintln!("No exit after {} cycles", cycles);
+            }
+            exit(sim65::EXIT_TIMEOUT)
+        }
+    }
+}
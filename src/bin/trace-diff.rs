@@ -0,0 +1,150 @@
+// Runs a program and compares every instruction with a reference trace (nestest.log, a log from
+// another emulator or one recorded with trace::Tracer). Stops at the first divergence.
+//
+// trace-diff [options] <program> <reference.log>
+//
+// The program is an iNES file (nestest.nes), an llvm-mos ELF or a raw binary. The cpu starts
+// with the registers and cycle count of the first reference line.
+
+use emulator6502::loader::{load_file, DEFAULT_LOAD_ADDR};
+use emulator6502::symbols::parse_number;
+use emulator6502::trace::{trace_line, TraceRecord};
+use emulator6502::*;
+use std::collections::VecDeque;
+use std::process::exit;
+
+const USAGE: &str = "usage: trace-diff [options] <program> <reference.log>
+
+options:
+  --load ADDR      load address of a raw binary (default $0200)
+  --pc ADDR        start address instead of the PC of the first reference line
+  --context N      lines shown before the divergence (default 5)
+  --max-steps N    stop after N instructions
+  --no-cycles      don't compare cycle counts";
+
+struct Options {
+    program: String,
+    reference: String,
+    load: u16,
+    pc: Option<u16>,
+    context: usize,
+    max_steps: Option<u64>,
+    cycles: bool,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("trace-diff: {}\n\n{}", message, USAGE);
+    exit(2)
+}
+
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut files = Vec::new();
+    let mut options = Options {
+        program: String::new(),
+        reference: String::new(),
+        load: DEFAULT_LOAD_ADDR,
+        pc: None,
+        context: 5,
+        max_steps: None,
+        cycles: true,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            let value =
+                args.next().unwrap_or_else(|| usage_error(&format!("{} needs a value", name)));
+            parse_number(&value)
+                .unwrap_or_else(|| usage_error(&format!("invalid number {}", value)))
+        };
+        let mut addr = |name: &str| match value(name) {
+            addr if addr <= 0xFFFF => addr as u16,
+            addr => usage_error(&format!("invalid address ${:X}", addr)),
+        };
+        match arg.as_str() {
+            "--load" => options.load = addr("--load"),
+            "--pc" => options.pc = Some(addr("--pc")),
+            "--context" => options.context = value("--context") as usize,
+            "--max-steps" => options.max_steps = Some(value("--max-steps") as u64),
+            "--no-cycles" => options.cycles = false,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option {}", arg)),
+            _ => files.push(arg),
+        }
+    }
+    if files.len() != 2 {
+        usage_error("expected a program and a reference log");
+    }
+    options.reference = files.pop().unwrap();
+    options.program = files.pop().unwrap();
+    options
+}
+
+fn main() {
+    let options = parse_args();
+    let reference = std::fs::read_to_string(&options.reference).unwrap_or_else(|err| {
+        eprintln!("trace-diff: {}: {}", options.reference, err);
+        exit(2)
+    });
+    let mut mem = Mem::new();
+    if let Err(err) = load_file(&mut mem, &options.program, Some(options.load)) {
+        eprintln!("trace-diff: {}: {}", options.program, err);
+        exit(2)
+    }
+    let mut cpu = Cpu::new(&mut mem);
+
+    let mut expected_lines = VecDeque::new();
+    let mut our_lines = VecDeque::new();
+    let mut steps = 0u64;
+    for (number, line) in reference.lines().enumerate() {
+        let expected = match TraceRecord::parse(line) {
+            Some(record) => record,
+            None => continue,
+        };
+        if steps == 0 {
+            cpu.pc = options.pc.unwrap_or(expected.pc);
+            cpu.regs[Cpu::REG_A] = expected.a;
+            cpu.regs[Cpu::REG_X] = expected.x;
+            cpu.regs[Cpu::REG_Y] = expected.y;
+            cpu.regs[Cpu::REG_STAT] = expected.p;
+            cpu.regs[Cpu::REG_SP] = expected.sp;
+            cpu.cycles_run = expected.cycles.unwrap_or(0) as u32;
+        }
+        let mut got = TraceRecord::from_cpu(&cpu);
+        if !options.cycles {
+            got.cycles = None;
+        }
+        expected_lines.push_back(line.to_string());
+        our_lines.push_back(trace_line(&cpu));
+        if expected_lines.len() > options.context + 1 {
+            expected_lines.pop_front();
+            our_lines.pop_front();
+        }
+
+        let diffs = got.diff(&expected);
+        if !diffs.is_empty() {
+            println!(
+                "Divergence after {} instructions, reference line {}, cycle {}",
+                steps,
+                number + 1,
+                expected.cycles.map_or("?".to_string(), |cycles| cycles.to_string())
+            );
+            println!("\nreference:");
+            expected_lines.iter().for_each(|line| println!("  {}", line));
+            println!("\nemulator:");
+            our_lines.iter().for_each(|line| println!("  {}", line));
+            println!();
+            diffs.iter().for_each(|diff| println!("{}", diff));
+            exit(1)
+        }
+
+        cpu.step();
+        steps += 1;
+        if options.max_steps == Some(steps) {
+            break;
+        }
+    }
+    println!("{} instructions match", steps);
+}
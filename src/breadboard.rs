@@ -0,0 +1,172 @@
+// The breadboard 6502 computer from Ben Eater's video series: 32K of RAM from $0000, a 6522 VIA
+// at $6000, repeated up to $7FFF, and 32K of ROM at $8000 holding the program and the vectors.
+// `setup` loads the ROM image, the same file that would go into the EEPROM.
+//
+// An HD44780 LCD module hangs off the VIA the way the videos wire it: port B to D7-D0 and the
+// top three bits of port A to the control lines, PA7 = E, PA6 = RW and PA5 = RS. Programs poll the
+// busy flag by turning port B into inputs and pulsing E with RW high, the controller drives the
+// data lines while E is high. Both 8 and 4 bit mode work.
+//
+// The display is drawn on the host as a box of text, again whenever its text changed, checked
+// 50 times a second of 1 MHz time. Custom characters show as `#`.
+//
+// https://eater.net/6502
+
+use crate::device::Device;
+use crate::hd44780::Hd44780;
+use crate::loader::{self, LoadError};
+use crate::serial::{Host, Link, Serial};
+use crate::via::Via;
+use crate::Cpu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const VIA_ADDR: u16 = 0x6000;
+pub const ROM_ADDR: u16 = 0x8000;
+/// The 16x2 module
+pub const COLUMNS: u8 = 16;
+pub const LINES: u8 = 2;
+
+// port A bits
+const E: u8 = 0x80;
+const RW: u8 = 0x40;
+const RS: u8 = 0x20;
+
+const FRAME_CYCLES: u32 = 20_000;
+
+/// The VIA with the LCD on its ports. IRQ goes to the cpu
+#[derive(Clone)]
+pub struct Board {
+    via: Via,
+    lcd: Hd44780,
+    link: Link,
+    // what the host shows, nothing before the first frame
+    shown: Option<Vec<Vec<u8>>>,
+    frame_cycles: u32,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
+impl Board {
+    pub fn new() -> Board {
+        Board {
+            via: Via::new(),
+            lcd: Hd44780::new(),
+            link: Link::default(),
+            shown: None,
+            frame_cycles: 0,
+        }
+    }
+
+    pub fn connect<H: Host + 'static>(&mut self, host: H) -> Rc<RefCell<Serial>> {
+        self.link.connect(host)
+    }
+
+    pub fn via(&self) -> &Via {
+        &self.via
+    }
+
+    pub fn lcd(&self) -> &Hd44780 {
+        &self.lcd
+    }
+
+    /// The text on the display, line by line
+    pub fn text(&self) -> Vec<String> {
+        (0..LINES)
+            .map(|line| self.lcd.line(line, COLUMNS).into_iter().map(glyph).collect())
+            .collect()
+    }
+
+    // The VIA drove the pins, the LCD sees them and drives the data lines back while it is read
+    fn update_lcd(&mut self) {
+        let control = self.via.port_a();
+        let (e, rw, rs) = (control & E != 0, control & RW != 0, control & RS != 0);
+        self.lcd.set_pins(rs, rw, e, self.via.port_b());
+        self.via.set_port_b(if e && rw { self.lcd.data_out(rs) } else { 0xFF });
+    }
+
+    fn frame(&mut self) {
+        let lines: Vec<Vec<u8>> = (0..LINES).map(|line| self.lcd.line(line, COLUMNS)).collect();
+        if self.shown.as_ref() == Some(&lines) {
+            return;
+        }
+        let border = format!("+{}+\r\n", "-".repeat(COLUMNS as usize));
+        let mut text = String::new();
+        if self.shown.is_some() {
+            // back up over the last frame
+            text += &format!("\x1b[{}A", LINES + 2);
+        }
+        text += &border;
+        for line in &lines {
+            text.push('|');
+            text.extend(line.iter().map(|&code| glyph(code)));
+            text += "|\r\n";
+        }
+        text += &border;
+        for byte in text.bytes() {
+            self.link.send(byte);
+        }
+        self.shown = Some(lines);
+    }
+}
+
+// The ASCII part of the character ROM, custom characters and the Japanese half aren't drawn
+fn glyph(code: u8) -> char {
+    match code {
+        0x00..=0x0F => '#',
+        0x20..=0x7D => code as char,
+        _ => '?',
+    }
+}
+
+impl Device for Board {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.via.read(offset);
+        self.update_lcd();
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.via.write(offset, value);
+        self.update_lcd();
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.via.peek(offset)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.via.tick(cycles);
+        self.lcd.tick(cycles);
+        self.link.tick(cycles);
+        // the busy flag may have gone
+        self.update_lcd();
+        self.frame_cycles += cycles;
+        if self.frame_cycles >= FRAME_CYCLES {
+            self.frame_cycles -= FRAME_CYCLES;
+            self.frame();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.via.irq()
+    }
+
+    fn reset(&mut self) {
+        self.via.reset();
+        self.update_lcd();
+    }
+}
+
+/// Loads the ROM image, maps `board` and resets into the ROM. Returns the index of the board in
+/// `Cpu::devices`
+pub fn setup(cpu: &mut Cpu, rom: &[u8], board: Board) -> Result<usize, LoadError> {
+    loader::load(cpu.mem_mut(), rom, Some(ROM_ADDR))?;
+    let index = cpu.devices.map(VIA_ADDR..=ROM_ADDR - 1, board);
+    cpu.reset_to_vector();
+    Ok(index)
+}
@@ -0,0 +1,104 @@
+// Shadow call stack, kept next to the hardware stack by the cpu when `Cpu::call_stack` is set.
+//
+// JSR and interrupts push a frame, RTS and RTI pop the frames whose return address they took off
+// the hardware stack. A frame is identified by the stack pointer it returns to, so frames dropped
+// by stack manipulation (PLA PLA RTS to return two levels at once) are popped together. A return
+// that doesn't go where the frame it pops expects, or that pops none, is recorded as a mismatch.
+// The RTS jump table trick (push the target minus one, then RTS) is the usual cause.
+
+/// More frames than the hardware stack can hold means the program reset the stack pointer
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Call,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl FrameKind {
+    pub fn is_interrupt(self) -> bool {
+        self != FrameKind::Call
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// The JSR or BRK, or the instruction an IRQ or NMI came before
+    pub caller: u16,
+    /// Subroutine or interrupt handler
+    pub target: u16,
+    /// Where the RTS or RTI should go
+    pub return_addr: u16,
+    /// Stack pointer before the call, and after the return
+    pub sp: u8,
+    /// `Cpu::cycles_run` when the subroutine or handler starts
+    pub cycles: u32,
+}
+
+/// A return to an address no JSR or interrupt pushed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch {
+    /// The RTS or RTI
+    pub pc: u16,
+    /// Where it went
+    pub target: u16,
+    /// The top frame before the return, if there was one
+    pub expected: Option<Frame>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    /// Number of returns that didn't match a frame
+    pub mismatches: u64,
+    pub last_mismatch: Option<Mismatch>,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    /// Outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// An RTS or RTI at `pc` went to `target` and left the stack pointer at `sp`. Returns the
+    /// mismatch, if there was one
+    pub fn ret(&mut self, pc: u16, target: u16, sp: u8, interrupt: bool) -> Option<Mismatch> {
+        let expected = self.frames.last().copied();
+        // frames returning to the new stack pointer or above it had their return address popped
+        let live = self.frames.iter().rposition(|frame| frame.sp > sp).map_or(0, |i| i + 1);
+        let popped = self.frames.split_off(live);
+        // the outermost of them is the one this return belongs to
+        let matches = popped.first().is_some_and(|frame| {
+            frame.sp == sp && frame.return_addr == target && frame.kind.is_interrupt() == interrupt
+        });
+        if matches {
+            return None;
+        }
+        let mismatch = Mismatch { pc, target, expected };
+        self.mismatches += 1;
+        self.last_mismatch = Some(mismatch);
+        Some(mismatch)
+    }
+}
@@ -0,0 +1,188 @@
+// Code/Data Logger, runs while `Cpu::cdl` is set. Each byte of memory gets flags for how the
+// program used it: run as an opcode, read as an operand, read as data or read as a pointer by an
+// indirect instruction, BRK or JMP ($nnnn). The flags are looked at before each instruction, from
+// its addressing mode and the registers, so the dummy reads of page crossings don't count.
+//
+// The files use the FCEUX layout, which doesn't tell opcodes from operands and counts pointers as
+// data. For NES programs that is a byte for each byte of PRG ROM followed by one for each byte of
+// CHR ROM:
+//
+//   bit 0  code                          bit 4  target of JMP ($nnnn)
+//   bit 1  data                          bit 5  read with (zp,X) or (zp),Y
+//   bits 2-3  8K window of $8000-$FFFF it was used in
+//
+// https://fceux.com/web/help/CodeDataLogger.html
+
+use crate::disasm::decode;
+use crate::opcodes::AddressingMode;
+use crate::{Cpu, Peek};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// First byte of an instruction that ran
+pub const OPCODE: u8 = 0x01;
+/// The other bytes of an instruction that ran
+pub const OPERAND: u8 = 0x02;
+/// Read by an instruction
+pub const DATA: u8 = 0x04;
+/// Read as an address, by an indirect instruction or from the BRK vector
+pub const POINTER: u8 = 0x08;
+/// Where a JMP ($nnnn) went
+pub const INDIRECT_CODE: u8 = 0x10;
+/// Read through a pointer
+pub const INDIRECT_DATA: u8 = 0x20;
+
+const FCEUX_CODE: u8 = 0x01;
+const FCEUX_DATA: u8 = 0x02;
+const FCEUX_INDIRECT_CODE: u8 = 0x10;
+const FCEUX_INDIRECT_DATA: u8 = 0x20;
+
+// Instructions with a memory operand that don't read it
+const NO_READ: [&str; 10] = ["STA", "STX", "STY", "SAX", "SHA", "SHX", "SHY", "TAS", "JMP", "JSR"];
+
+/// The bytes in a .cdl file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// PRG ROM as mapped from $8000, mirrored up to $FFFF if it's smaller, then CHR ROM, which
+    /// the cpu never reads
+    Nes { prg_size: usize, chr_size: usize },
+    /// A byte for each address from `start` to `end`
+    Memory { start: u16, end: u16 },
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Memory { start: 0, end: 0xFFFF }
+    }
+}
+
+pub struct CodeDataLog {
+    flags: Vec<u8>,
+}
+
+impl Default for CodeDataLog {
+    fn default() -> Self {
+        CodeDataLog { flags: vec![0; 0x10000] }
+    }
+}
+
+impl CodeDataLog {
+    pub fn new() -> CodeDataLog {
+        CodeDataLog::default()
+    }
+
+    /// Called by the cpu before each instruction
+    pub fn record(&mut self, cpu: &Cpu) {
+        let inst = decode(cpu, cpu.pc);
+        self.flags[inst.addr as usize] |= OPCODE;
+        for i in 1..inst.size() {
+            self.flags[inst.addr.wrapping_add(i) as usize] |= OPERAND;
+        }
+        let (x, y) = (cpu.regs[Cpu::REG_X] as u16, cpu.regs[Cpu::REG_Y] as u16);
+        let operand = inst.operand;
+        let (addr, indirect) = match inst.info.mode {
+            AddressingMode::ZeroPage | AddressingMode::Absolute => (operand, false),
+            AddressingMode::ZeroPageX => ((operand + x) & 0xFF, false),
+            AddressingMode::ZeroPageY => ((operand + y) & 0xFF, false),
+            AddressingMode::AbsoluteX => (operand.wrapping_add(x), false),
+            AddressingMode::AbsoluteY => (operand.wrapping_add(y), false),
+            AddressingMode::IndirectX => {
+                let ptr = (operand + x) & 0xFF;
+                (self.pointer(cpu, ptr, (ptr + 1) & 0xFF), true)
+            }
+            AddressingMode::IndirectY => {
+                let ptr = operand & 0xFF;
+                (self.pointer(cpu, ptr, (ptr + 1) & 0xFF).wrapping_add(y), true)
+            }
+            AddressingMode::Indirect => {
+                // the high byte of the pointer comes from the same page, like in the cpu
+                let high = operand & 0xFF00 | operand.wrapping_add(1) & 0xFF;
+                let target = self.pointer(cpu, operand, high);
+                self.flags[target as usize] |= INDIRECT_CODE;
+                return;
+            }
+            _ => {
+                if inst.opcode == Cpu::BRK_IMPLIED {
+                    let vector = Cpu::IRQ_INTERRUPT_VECTOR_ADDR;
+                    self.pointer(cpu, vector, vector + 1);
+                }
+                return;
+            }
+        };
+        if !NO_READ.contains(&inst.info.mnemonic) {
+            self.flags[addr as usize] |= if indirect { DATA | INDIRECT_DATA } else { DATA };
+        }
+    }
+
+    // Marks the two bytes of a pointer and returns it
+    fn pointer(&mut self, cpu: &Cpu, low: u16, high: u16) -> u16 {
+        self.flags[low as usize] |= POINTER;
+        self.flags[high as usize] |= POINTER;
+        cpu.peek8(high) as u16 * 256 + cpu.peek8(low) as u16
+    }
+
+    /// The flags of `addr`, OPCODE, DATA and the others
+    pub fn flags(&self, addr: u16) -> u8 {
+        self.flags[addr as usize]
+    }
+
+    /// Number of bytes with all of `flags`
+    pub fn count(&self, flags: u8) -> usize {
+        self.flags.iter().filter(|&&f| f & flags == flags).count()
+    }
+
+    pub fn merge(&mut self, other: &CodeDataLog) {
+        for (flags, other) in self.flags.iter_mut().zip(&other.flags) {
+            *flags |= other;
+        }
+    }
+
+    /// The flags of `addr` in the FCEUX layout
+    pub fn fceux_flags(&self, addr: u16) -> u8 {
+        let flags = self.flags(addr);
+        let mut fceux = 0;
+        if flags & (OPCODE | OPERAND) != 0 {
+            fceux |= FCEUX_CODE;
+        }
+        if flags & (DATA | POINTER) != 0 {
+            fceux |= FCEUX_DATA;
+        }
+        if flags & INDIRECT_CODE != 0 {
+            fceux |= FCEUX_INDIRECT_CODE;
+        }
+        if flags & INDIRECT_DATA != 0 {
+            fceux |= FCEUX_INDIRECT_DATA;
+        }
+        if fceux != 0 && addr >= 0x8000 {
+            fceux |= (addr >> 11) as u8 & 0x0C;
+        }
+        fceux
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W, layout: Layout) -> io::Result<()> {
+        let bytes: Vec<u8> = match layout {
+            Layout::Nes { prg_size, chr_size } => {
+                let mut bytes = vec![0; prg_size + chr_size];
+                for (offset, byte) in bytes.iter_mut().take(prg_size.min(0x8000)).enumerate() {
+                    // the mirrors share the byte, the bank bits are those of the highest one used
+                    for addr in (0x8000 + offset..0x10000).step_by(prg_size) {
+                        let flags = self.fceux_flags(addr as u16);
+                        if flags != 0 {
+                            *byte = *byte & !0x0C | flags;
+                        }
+                    }
+                }
+                bytes
+            }
+            Layout::Memory { start, end } => (start..=end).map(|a| self.fceux_flags(a)).collect(),
+        };
+        out.write_all(&bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, layout: Layout) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out, layout)?;
+        out.flush()
+    }
+}
@@ -0,0 +1,198 @@
+// Code coverage, recorded while `Cpu::coverage` is set: how often each instruction ran and how
+// often each branch was taken or not.
+//
+// With source lines (ld65 debug files, llvm-mos ELF files) it writes lcov tracefiles for genhtml
+// and editor plugins. A line counts as run as often as its most run instruction, each branch
+// instruction gives two lcov branches, taken and not taken. Without lines there is a report by
+// address.
+//
+// https://manpages.debian.org/unstable/lcov/geninfo.1.en.html#TRACEFILE_FORMAT
+
+use crate::disasm::{decode, Disassembler, Syntax};
+use crate::lines::LineTable;
+use crate::opcodes::AddressingMode;
+use crate::symbols::SymbolTable;
+use crate::{Cpu, Peek};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+pub struct Coverage {
+    // by address of the first byte of the instruction
+    hits: Vec<u64>,
+    branches: HashMap<u16, Branch>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage { hits: vec![0; 0x10000], branches: HashMap::new() }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Called by the cpu after each instruction with its address
+    pub fn record(&mut self, cpu: &Cpu, pc: u16) {
+        self.hits[pc as usize] += 1;
+        let inst = decode(cpu, pc);
+        if inst.info.mode == AddressingMode::Relative {
+            let branch = self.branches.entry(pc).or_default();
+            if cpu.pc == inst.next_addr() {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+            }
+        }
+    }
+
+    /// How often the instruction at `addr` ran
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits[addr as usize]
+    }
+
+    /// None when the branch at `addr` never ran
+    pub fn branch(&self, addr: u16) -> Option<Branch> {
+        self.branches.get(&addr).copied()
+    }
+
+    /// Addresses of the instructions that ran, in order
+    pub fn executed(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=0xFFFF).filter(move |&addr| self.hits[addr as usize] > 0)
+    }
+
+    /// Adds the counts of another run, of the same program
+    pub fn merge(&mut self, other: &Coverage) {
+        for (hits, other) in self.hits.iter_mut().zip(&other.hits) {
+            *hits += other;
+        }
+        for (addr, other) in &other.branches {
+            let branch = self.branches.entry(*addr).or_default();
+            branch.taken += other.taken;
+            branch.not_taken += other.not_taken;
+        }
+    }
+
+    /// One lcov record per source file that has code. `mem` is needed to find the branches that
+    /// never ran, it should still hold the program
+    pub fn write_lcov<W: Write, M: Peek + ?Sized>(
+        &self,
+        out: &mut W,
+        lines: &LineTable,
+        mem: &M,
+    ) -> io::Result<()> {
+        // file -> line -> (hits, branch addresses)
+        let mut files: BTreeMap<usize, BTreeMap<u32, (u64, Vec<u16>)>> = BTreeMap::new();
+        for entry in lines.entries() {
+            let line = files.entry(entry.file).or_default().entry(entry.line).or_default();
+            let end = entry.addr as u32 + entry.size as u32;
+            let mut addr = entry.addr as u32;
+            // the entries of a line are spans of bytes, they hold whole instructions
+            while addr < end {
+                let inst = decode(mem, addr as u16);
+                line.0 = line.0.max(self.hits(addr as u16));
+                if inst.info.mode == AddressingMode::Relative {
+                    line.1.push(inst.addr);
+                }
+                addr += inst.size() as u32;
+            }
+        }
+        for (file, file_lines) in files {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", lines.files()[file])?;
+            let (mut branches_found, mut branches_hit) = (0, 0);
+            for (line, (_, branches)) in &file_lines {
+                for (block, addr) in branches.iter().enumerate() {
+                    let counts = match self.branch(*addr) {
+                        Some(branch) => [branch.taken.to_string(), branch.not_taken.to_string()],
+                        None => ["-".to_string(), "-".to_string()],
+                    };
+                    for (index, count) in counts.iter().enumerate() {
+                        writeln!(out, "BRDA:{},{},{},{}", line, block, index, count)?;
+                        branches_found += 1;
+                        if count != "-" && count != "0" {
+                            branches_hit += 1;
+                        }
+                    }
+                }
+            }
+            if branches_found > 0 {
+                writeln!(out, "BRF:{}", branches_found)?;
+                writeln!(out, "BRH:{}", branches_hit)?;
+            }
+            for (line, (hits, _)) in &file_lines {
+                writeln!(out, "DA:{},{}", line, hits)?;
+            }
+            writeln!(out, "LF:{}", file_lines.len())?;
+            writeln!(out, "LH:{}", file_lines.values().filter(|(hits, _)| *hits > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    pub fn save_lcov<P: AsRef<Path>, M: Peek + ?Sized>(
+        &self,
+        path: P,
+        lines: &LineTable,
+        mem: &M,
+    ) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_lcov(&mut out, lines, mem)?;
+        out.flush()
+    }
+
+    /// Without source lines: the ranges of bytes that ran, then every instruction that ran with
+    /// its count and, for branches, how often it was taken
+    pub fn write_report<W: Write, M: Peek + ?Sized>(
+        &self,
+        out: &mut W,
+        mem: &M,
+        symbols: &SymbolTable,
+    ) -> io::Result<()> {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for addr in self.executed() {
+            let start = addr as u32;
+            let end = start + decode(mem, addr).size() as u32;
+            match ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+        let bytes: u32 = ranges.iter().map(|(start, end)| end - start).sum();
+        writeln!(out, "{} bytes executed", bytes)?;
+        for (start, end) in &ranges {
+            writeln!(out, "${:04X}-${:04X}", start, end - 1)?;
+        }
+        writeln!(out)?;
+        let disasm = Disassembler::new(Syntax::Ca65).with_symbols(symbols);
+        for addr in self.executed() {
+            let (_, text) = disasm.disassemble(mem, addr);
+            write!(out, "{:04X}  {:<24} {:>10}", addr, text, self.hits(addr))?;
+            if let Some(branch) = self.branch(addr) {
+                write!(out, "  taken {}, not taken {}", branch.taken, branch.not_taken)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_report<P: AsRef<Path>, M: Peek + ?Sized>(
+        &self,
+        path: P,
+        mem: &M,
+        symbols: &SymbolTable,
+    ) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_report(&mut out, mem, symbols)?;
+        out.flush()
+    }
+}
@@ -0,0 +1,780 @@
+// Debug Adapter Protocol server, for source level debugging of guest programs in editors
+// https://microsoft.github.io/debug-adapter-protocol/specification
+//
+// Source breakpoints and line stepping use the line information of ld65 debug files or of
+// llvm-mos ELF files. There is a single thread and a "Registers" scope, memory can be read and
+// written by address.
+
+use crate::debugger::{format_flags, Breakpoint, Debugger, StopReason};
+use crate::disasm::{Disassembler, Syntax};
+use crate::expr::Expr;
+use crate::lines::LineTable;
+use crate::loader::load_file;
+use crate::symbols::{parse_number, SymbolTable};
+use crate::{Cpu, Peek};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+// A breakpoint on a line without code moves down to the next line with code, at most this far
+const MAX_LINE_SHIFT: u32 = 20;
+// More than a disassembly view asks for at once, larger offsets and counts are cut down to it
+const MAX_INSTRUCTIONS: i64 = 1000;
+
+pub struct DapServer<'a> {
+    pub debugger: Debugger<'a>,
+    pub symbols: SymbolTable,
+    pub lines: LineTable,
+    source_breakpoints: HashMap<String, Vec<Breakpoint>>,
+    function_breakpoints: Vec<Breakpoint>,
+    instruction_breakpoints: Vec<Breakpoint>,
+    // the breakpoints of the debugger that were set by the requests above
+    active_breakpoints: BTreeMap<u16, Breakpoint>,
+    stop_on_entry: bool,
+}
+
+struct Connection<W: Write> {
+    out: W,
+    seq: u64,
+}
+
+impl<W: Write> Connection<W> {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resume {
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+    NextInstruction,
+    StepInstruction,
+    StepBack,
+    StepBackInstruction,
+    ReverseContinue,
+}
+
+impl<'a> DapServer<'a> {
+    pub fn new(debugger: Debugger<'a>) -> DapServer<'a> {
+        DapServer {
+            debugger,
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            source_breakpoints: HashMap::new(),
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            active_breakpoints: BTreeMap::new(),
+            stop_on_entry: false,
+        }
+    }
+
+    /// Runs a session until the client disconnects. Requests are read on their own thread, so
+    /// that a pause request can stop a running program
+    pub fn serve<R: Read + Send + 'static, W: Write>(
+        &mut self,
+        input: R,
+        output: W,
+    ) -> io::Result<()> {
+        let (sender, receiver) = channel();
+        let interrupt = self.debugger.interrupt_handle();
+        std::thread::spawn(move || read_messages(input, sender, interrupt));
+        let mut conn = Connection { out: output, seq: 0 };
+        while let Ok(request) = receiver.recv() {
+            if request["type"] != "request" {
+                continue;
+            }
+            if !self.handle(&request, &mut conn)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns false when the session ends
+    fn handle<W: Write>(&mut self, request: &Value, conn: &mut Connection<W>) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+        let instruction = args["granularity"] == "instruction";
+        let resume = match command {
+            "continue" => Some(Resume::Continue),
+            "next" if instruction => Some(Resume::NextInstruction),
+            "next" => Some(Resume::Next),
+            "stepIn" if instruction => Some(Resume::StepInstruction),
+            "stepIn" => Some(Resume::StepIn),
+            "stepOut" => Some(Resume::StepOut),
+            "stepBack" if instruction => Some(Resume::StepBackInstruction),
+            "stepBack" => Some(Resume::StepBack),
+            "reverseContinue" => Some(Resume::ReverseContinue),
+            _ => None,
+        };
+        if let Some(resume) = resume {
+            let body = if resume == Resume::Continue {
+                json!({ "allThreadsContinued": true })
+            } else {
+                Value::Null
+            };
+            conn.respond(request, Ok(body))?;
+            let reason = self.resume(resume);
+            return conn.event("stopped", self.stopped(reason)).map(|_| true);
+        }
+        let result = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" | "attach" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" | "pause" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] })),
+            "stackTrace" => Ok(self.stack_trace(args)),
+            "scopes" => Ok(json!({ "scopes": [{
+                "name": "Registers",
+                "presentationHint": "registers",
+                "variablesReference": REGISTERS_REFERENCE,
+                "expensive": false,
+            }] })),
+            "variables" => Ok(self.variables(args)),
+            "setVariable" => self.set_variable(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "evaluate" => self.evaluate(args),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("unsupported request {}", command)),
+        };
+        conn.respond(request, result)?;
+        match command {
+            "initialize" => conn.event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => conn.event(
+                "stopped",
+                json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true }),
+            )?,
+            "configurationDone" => {
+                let reason = self.resume(Resume::Continue);
+                conn.event("stopped", self.stopped(reason))?;
+            }
+            "terminate" => conn.event("terminated", Value::Null)?,
+            "disconnect" => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// `launch` arguments: `program` (ELF, iNES or raw binary), `loadAddress` for raw binaries,
+    /// `symbols` (a file or a list of them, ld65 debug files give source lines too), `pc` and
+    /// `stopOnEntry`. Without a program the memory is used as it is
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let load = match args.get("loadAddress") {
+            Some(addr) => Some(self.address_arg(addr)?),
+            None => None,
+        };
+        let mut pc = None;
+        if let Some(program) = args["program"].as_str() {
+            let loaded = load_file(self.debugger.cpu.mem_mut(), program, load)
+                .map_err(|err| format!("{}: {}", program, err))?;
+            self.symbols.merge(&loaded.symbols);
+            self.lines.merge(&loaded.lines);
+            self.debugger.cpu.reset();
+            pc = loaded.entry.or(load);
+        }
+        let files = match &args["symbols"] {
+            Value::String(file) => vec![file.as_str()],
+            Value::Array(files) => files.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        for file in files {
+            let symbols = SymbolTable::load(file).map_err(|err| format!("{}: {}", file, err))?;
+            let lines = LineTable::load(file).map_err(|err| format!("{}: {}", file, err))?;
+            self.symbols.merge(&symbols);
+            self.lines.merge(&lines);
+        }
+        if let Some(addr) = args.get("pc") {
+            pc = Some(self.address_arg(addr)?);
+        }
+        if let Some(pc) = pc {
+            self.debugger.cpu.pc = pc;
+        }
+        self.debugger.checkpoint();
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let source = &args["source"];
+        let path = source["path"].as_str().or_else(|| source["name"].as_str());
+        let path = path.ok_or("setBreakpoints needs a source path")?;
+        let file = self.lines.find_file(path);
+        let mut added = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            let found = file.and_then(|file| self.code_line(file, line));
+            let result = found.ok_or_else(|| "no code at this line".to_string()).and_then(
+                |(line, addrs)| {
+                    for addr in addrs {
+                        added.push(self.breakpoint(addr, breakpoint)?);
+                    }
+                    Ok(line)
+                },
+            );
+            match result {
+                Ok(line) => breakpoints.push(json!({ "verified": true, "line": line })),
+                Err(message) => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": message,
+                })),
+            }
+        }
+        self.source_breakpoints.insert(path.to_string(), added);
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        self.function_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let name = breakpoint["name"].as_str().unwrap_or("");
+            match self.address(name).and_then(|addr| self.breakpoint(addr, breakpoint)) {
+                Ok(added) => {
+                    self.function_breakpoints.push(added);
+                    breakpoints.push(json!({ "verified": true }));
+                }
+                Err(message) => breakpoints.push(json!({ "verified": false, "message": message })),
+            }
+        }
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"].as_str().unwrap_or("");
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let addr = self.address(reference).map(|addr| (addr as i64 + offset) as u16);
+            match addr.and_then(|addr| self.breakpoint(addr, breakpoint)) {
+                Ok(added) => {
+                    let reference = reference_of(added.addr);
+                    self.instruction_breakpoints.push(added);
+                    breakpoints
+                        .push(json!({ "verified": true, "instructionReference": reference }));
+                }
+                Err(message) => breakpoints.push(json!({ "verified": false, "message": message })),
+            }
+        }
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    // The first line at or after `line` that has code, like other debuggers do for breakpoints on
+    // comments and empty lines
+    fn code_line(&self, file: usize, line: u32) -> Option<(u32, Vec<u16>)> {
+        (line..line.saturating_add(MAX_LINE_SHIFT)).find_map(|line| {
+            let addrs = self.lines.addresses(file, line);
+            if addrs.is_empty() {
+                None
+            } else {
+                Some((line, addrs))
+            }
+        })
+    }
+
+    // A `condition` and a `hitCondition`, the number of the first hit that stops, optionally
+    // prefixed by `>=` like the hit conditions of other adapters
+    fn breakpoint(&self, addr: u16, args: &Value) -> Result<Breakpoint, String> {
+        let mut breakpoint = Breakpoint::new(addr);
+        if let Some(condition) = args["condition"].as_str().filter(|text| !text.trim().is_empty()) {
+            let condition = Expr::parse_with_symbols(condition, &self.symbols)
+                .map_err(|err| err.to_string())?;
+            breakpoint = breakpoint.with_condition(condition);
+        }
+        if let Some(hits) = args["hitCondition"].as_str().filter(|text| !text.trim().is_empty()) {
+            let count = hits.trim().trim_start_matches(">=").trim();
+            let count = count.parse().map_err(|_| format!("invalid hit count {}", hits))?;
+            breakpoint = breakpoint.with_hit_count(count);
+        }
+        Ok(breakpoint)
+    }
+
+    // Breakpoints that didn't change keep their hit counts. With several breakpoints at the same
+    // address the first one wins
+    fn sync_breakpoints(&mut self) {
+        let mut wanted = BTreeMap::new();
+        let all = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.function_breakpoints)
+            .chain(&self.instruction_breakpoints);
+        for breakpoint in all {
+            wanted.entry(breakpoint.addr).or_insert_with(|| breakpoint.clone());
+        }
+        for addr in self.active_breakpoints.keys() {
+            if !wanted.contains_key(addr) {
+                self.debugger.remove_breakpoint(*addr);
+            }
+        }
+        for (addr, breakpoint) in &wanted {
+            if self.active_breakpoints.get(addr) != Some(breakpoint) {
+                self.debugger.set_breakpoint(breakpoint.clone());
+            }
+        }
+        self.active_breakpoints = wanted;
+    }
+
+    fn resume(&mut self, resume: Resume) -> StopReason {
+        match resume {
+            Resume::Continue => self.debugger.run(None),
+            Resume::Next => self.step_line(true),
+            Resume::StepIn => self.step_line(false),
+            Resume::StepOut => self.debugger.step_out(),
+            Resume::NextInstruction => self.debugger.step_over(),
+            Resume::StepInstruction => self.debugger.step(),
+            Resume::StepBack => self.step_line_back(),
+            Resume::StepBackInstruction => self.debugger.step_back(),
+            Resume::ReverseContinue => self.debugger.reverse_continue(),
+        }
+    }
+
+    // Steps until the program reaches another source line. Code without line information (the
+    // ROM, libraries) is run through. Without any line information it is a single instruction
+    fn step_line(&mut self, over: bool) -> StopReason {
+        let interrupt = self.debugger.interrupt_handle();
+        interrupt.store(false, Ordering::Relaxed);
+        let line_of =
+            |lines: &LineTable, pc| lines.entry_at(pc).map(|entry| (entry.file, entry.line));
+        let start = line_of(&self.lines, self.debugger.cpu.pc);
+        loop {
+            let reason = if over { self.debugger.step_over() } else { self.debugger.step() };
+            if reason != StopReason::Step {
+                return reason;
+            }
+            let line = line_of(&self.lines, self.debugger.cpu.pc);
+            if start.is_none() || (line.is_some() && line != start) {
+                return reason;
+            }
+            if interrupt.load(Ordering::Relaxed) {
+                return StopReason::Interrupted;
+            }
+        }
+    }
+
+    // Goes back to the first instruction of the previous source line. Stepping back stops on its
+    // last one, the rest of it is found by going further and then one instruction forward
+    fn step_line_back(&mut self) -> StopReason {
+        let line_of =
+            |lines: &LineTable, pc| lines.entry_at(pc).map(|entry| (entry.file, entry.line));
+        let start = line_of(&self.lines, self.debugger.cpu.pc);
+        let line = loop {
+            let reason = self.debugger.step_back();
+            if reason != StopReason::Step {
+                return reason;
+            }
+            let line = line_of(&self.lines, self.debugger.cpu.pc);
+            if start.is_none() || (line.is_some() && line != start) {
+                break line;
+            }
+        };
+        if line.is_none() {
+            return StopReason::Step;
+        }
+        loop {
+            if self.debugger.step_back() != StopReason::Step {
+                return StopReason::Step;
+            }
+            if line_of(&self.lines, self.debugger.cpu.pc) != line {
+                self.debugger.step();
+                return StopReason::Step;
+            }
+        }
+    }
+
+    fn stopped(&self, reason: StopReason) -> Value {
+        let (reason, description) = match reason {
+            StopReason::Step | StopReason::Limit => ("step", None),
+            StopReason::Breakpoint(addr)
+                if self.instruction_breakpoints.iter().any(|bp| bp.addr == addr) =>
+            {
+                ("instruction breakpoint", None)
+            }
+            StopReason::Breakpoint(addr)
+                if self.function_breakpoints.iter().any(|bp| bp.addr == addr) =>
+            {
+                ("function breakpoint", None)
+            }
+            StopReason::Breakpoint(_) => ("breakpoint", None),
+            StopReason::Watchpoint { hit, .. } => {
+                let access = if hit.write { "write" } else { "read" };
+                let text = format!("{} ${:04X} = ${:02X}", access, hit.addr, hit.value);
+                ("data breakpoint", Some(text))
+            }
+            StopReason::Interrupted => ("pause", None),
+            StopReason::HistoryStart => ("step", Some("start of the history".to_string())),
+            StopReason::InvalidOpcode { pc, opcode } => {
+                ("exception", Some(format!("invalid opcode ${:02X} at ${:04X}", opcode, pc)))
+            }
+        };
+        let mut body =
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(description) = description {
+            body["description"] = json!(description);
+        }
+        body
+    }
+
+    fn stack_trace(&self, args: &Value) -> Value {
+        let frames = self.debugger.backtrace();
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match args["levels"].as_u64() {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => frames.len(),
+        };
+        let stack_frames: Vec<Value> = frames
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(levels)
+            .map(|(id, &addr)| {
+                let name = match self.symbols.symbol_for(addr) {
+                    Some((symbol, _)) => symbol.name.clone(),
+                    None => format!("${:04X}", addr),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": reference_of(addr),
+                });
+                if let Some((file, line)) = self.lines.line_at(addr) {
+                    frame["source"] = source(file);
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": stack_frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, args: &Value) -> Value {
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REFERENCE) {
+            return json!({ "variables": [] });
+        }
+        let cpu = &self.debugger.cpu;
+        let register = |name: &str, reg: usize| variable(name, format!("${:02X}", cpu.regs[reg]));
+        let p = cpu.regs[Cpu::REG_STAT];
+        let mut pc = variable("PC", format!("${:04X}", cpu.pc));
+        pc["memoryReference"] = json!(reference_of(cpu.pc));
+        json!({ "variables": [
+            register("A", Cpu::REG_A),
+            register("X", Cpu::REG_X),
+            register("Y", Cpu::REG_Y),
+            register("SP", Cpu::REG_SP),
+            variable("P", format!("${:02X} {}", p, format_flags(p))),
+            pc,
+            variable("cycles", cpu.cycles_run.to_string()),
+        ] })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REFERENCE) {
+            return Err("only registers can be changed".to_string());
+        }
+        let name = args["name"].as_str().unwrap_or("");
+        let text = args["value"].as_str().unwrap_or("");
+        let reg = match name {
+            "PC" => {
+                let pc = self.address(text)?;
+                self.debugger.cpu.pc = pc;
+                self.debugger.checkpoint();
+                return Ok(json!({ "value": format!("${:04X}", pc) }));
+            }
+            "A" => Cpu::REG_A,
+            "X" => Cpu::REG_X,
+            "Y" => Cpu::REG_Y,
+            "SP" => Cpu::REG_SP,
+            "P" => Cpu::REG_STAT,
+            _ => return Err(format!("{} can't be changed", name)),
+        };
+        let value = match parse_number(text) {
+            Some(value) if value <= 0xFF => value as u8,
+            _ => return Err(format!("{} is not a byte", text)),
+        };
+        self.debugger.cpu.regs[reg] = value;
+        self.debugger.checkpoint();
+        Ok(json!({ "value": format!("${:02X}", value) }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let addr = self.memory_reference(args)?;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let readable = count.min(0x10000 - addr);
+        let data: Vec<u8> =
+            (addr..addr + readable).map(|addr| self.debugger.cpu.peek8(addr as u16)).collect();
+        Ok(json!({
+            "address": reference_of(addr as u16),
+            "data": base64_encode(&data),
+            "unreadableBytes": count - readable,
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = self.memory_reference(args)?;
+        let data =
+            base64_decode(args["data"].as_str().unwrap_or("")).ok_or("invalid base64 data")?;
+        let written = data.len().min(0x10000 - addr);
+        for (i, byte) in data.into_iter().take(written).enumerate() {
+            self.debugger.cpu.mem_mut().write8(addr + i, byte);
+        }
+        self.debugger.checkpoint();
+        Ok(json!({ "bytesWritten": written }))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let addr = self.memory_reference(args)? as u16;
+        let offset = args["instructionOffset"].as_i64().unwrap_or(0);
+        let offset = offset.clamp(-MAX_INSTRUCTIONS, MAX_INSTRUCTIONS);
+        let count = args["instructionCount"].as_u64().unwrap_or(0);
+        let count = count.min(MAX_INSTRUCTIONS as u64) as usize;
+        let mut addr = if offset < 0 {
+            self.instruction_before(addr, offset.unsigned_abs() as usize)
+        } else {
+            addr
+        };
+        let disasm = Disassembler::new(Syntax::Ca65).with_symbols(&self.symbols);
+        if offset > 0 {
+            for _ in 0..offset {
+                addr = disasm.disassemble(&self.debugger.cpu, addr).0.next_addr();
+            }
+        }
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            let (inst, text) = disasm.disassemble(&self.debugger.cpu, addr);
+            let bytes: Vec<String> = inst.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+            let mut instruction = json!({
+                "address": reference_of(addr),
+                "instructionBytes": bytes.join(" "),
+                "instruction": text,
+            });
+            if let Some(name) = self.symbols.name_at(addr) {
+                instruction["symbol"] = json!(name);
+            }
+            if let Some((file, line)) = self.lines.line_at(addr) {
+                instruction["location"] = source(file);
+                instruction["line"] = json!(line);
+            }
+            instructions.push(instruction);
+            addr = inst.next_addr();
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    // Instructions have different sizes, so going back `count` of them means trying start
+    // addresses until one of them decodes into `addr`
+    fn instruction_before(&self, addr: u16, count: usize) -> u16 {
+        let cpu = &self.debugger.cpu;
+        for distance in (count..=count * 3).rev() {
+            let start = addr.wrapping_sub(distance as u16);
+            let mut starts = Vec::new();
+            let mut current = start;
+            while starts.len() <= count * 3 && current.wrapping_sub(start) < distance as u16 {
+                starts.push(current);
+                current = crate::disasm::decode(cpu, current).next_addr();
+            }
+            if current == addr && starts.len() >= count {
+                return starts[starts.len() - count];
+            }
+        }
+        addr.wrapping_sub(count as u16)
+    }
+
+    fn evaluate(&self, args: &Value) -> Result<Value, String> {
+        let text = args["expression"].as_str().unwrap_or("");
+        let expr = Expr::parse_with_symbols(text, &self.symbols).map_err(|err| err.to_string())?;
+        let value = expr.eval(&self.debugger.cpu).map_err(|err| err.to_string())?;
+        let result = match value {
+            0..=0xFF => format!("${:02X} {}", value, value),
+            0x100..=0xFFFF => format!("${:04X} {}", value, value),
+            _ => value.to_string(),
+        };
+        let mut body = json!({ "result": result, "variablesReference": 0 });
+        if (0..=0xFFFF).contains(&value) {
+            body["memoryReference"] = json!(reference_of(value as u16));
+        }
+        Ok(body)
+    }
+
+    fn memory_reference(&self, args: &Value) -> Result<usize, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or("");
+        let addr = self.address(reference)? as i64 + args["offset"].as_i64().unwrap_or(0);
+        if !(0..=0xFFFF).contains(&addr) {
+            return Err(format!("{} is outside of memory", addr));
+        }
+        Ok(addr as usize)
+    }
+
+    fn address_arg(&self, value: &Value) -> Result<u16, String> {
+        match value {
+            Value::Number(number) => match number.as_u64() {
+                Some(addr) if addr <= 0xFFFF => Ok(addr as u16),
+                _ => Err(format!("{} is not an address", number)),
+            },
+            Value::String(text) => self.address(text),
+            _ => Err(format!("{} is not an address", value)),
+        }
+    }
+
+    // Symbol names or numbers with `0x`, `$` or `%` prefix, decimal otherwise
+    fn address(&self, text: &str) -> Result<u16, String> {
+        if let Some(addr) = self.symbols.lookup(text) {
+            return Ok(addr);
+        }
+        match parse_number(text) {
+            Some(addr) if addr <= 0xFFFF => Ok(addr as u16),
+            _ => Err(format!("unknown symbol or invalid address {}", text)),
+        }
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsHitConditionalBreakpoints": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsSteppingGranularity": true,
+        "supportsStepBack": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn reference_of(addr: u16) -> String {
+    format!("0x{:04X}", addr)
+}
+
+fn source(path: &str) -> Value {
+    let name =
+        std::path::Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+    json!({ "name": name, "path": path })
+}
+
+// `Content-Length: n` headers, an empty line and the JSON body. Pause, disconnect and terminate
+// requests also set the interrupt flag right away, the program may be running. So does the end of
+// the input, nothing would stop the program after that
+fn read_messages<R: Read>(input: R, sender: Sender<Value>, interrupt: Arc<AtomicBool>) {
+    let mut input = BufReader::new(input);
+    'messages: loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => break 'messages,
+                Ok(_) => {}
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse().ok();
+            }
+        }
+        let mut body = vec![0; length.unwrap_or(0)];
+        if input.read_exact(&mut body).is_err() {
+            break;
+        }
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+        if matches!(message["command"].as_str(), Some("pause" | "disconnect" | "terminate")) {
+            interrupt.store(true, Ordering::Relaxed);
+        }
+        if sender.send(message).is_err() {
+            return;
+        }
+    }
+    interrupt.store(true, Ordering::Relaxed);
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits =
+            chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = BASE64.iter().position(|&b| b == c)? as u32;
+        bits = (bits << 6 | value) & 0xFFFF;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
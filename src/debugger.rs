@@ -0,0 +1,399 @@
+// Execution control shared by the monitor, the gdb stub and the DAP server
+
+use crate::callstack::CallStack;
+use crate::expr::Expr;
+use crate::history::History;
+use crate::{Cpu, Peek};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Stops execution when the cpu reads or writes data in `start..=end`. Instruction fetches
+/// don't count as reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(addr: u16, kind: WatchKind) -> Watchpoint {
+        Watchpoint { start: addr, end: addr, kind }
+    }
+
+    pub fn matches(&self, addr: u16, write: bool) -> bool {
+        let kind = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::ReadWrite => true,
+        };
+        kind && (self.start..=self.end).contains(&addr)
+    }
+}
+
+/// The first watched access of an instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub addr: u16,
+    /// Value read or written
+    pub value: u8,
+    pub write: bool,
+}
+
+/// Hits only count when the condition holds. The first `ignore_count` hits don't stop and
+/// neither do the ones before hit number `hit_count`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Expr>,
+    pub hit_count: u64,
+    pub ignore_count: u64,
+    /// Hits so far, ignored ones included
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    pub fn new(addr: u16) -> Breakpoint {
+        Breakpoint { addr, condition: None, hit_count: 0, ignore_count: 0, hits: 0 }
+    }
+
+    pub fn with_condition(mut self, condition: Expr) -> Breakpoint {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn with_hit_count(mut self, hit_count: u64) -> Breakpoint {
+        self.hit_count = hit_count;
+        self
+    }
+
+    pub fn with_ignore_count(mut self, ignore_count: u64) -> Breakpoint {
+        self.ignore_count = ignore_count;
+        self
+    }
+
+    /// The condition holds, or there is none. A condition that can't be evaluated holds too, so
+    /// the problem gets noticed
+    pub fn matches(&self, cpu: &Cpu) -> bool {
+        match &self.condition {
+            Some(condition) => condition.eval(cpu) != Ok(0),
+            None => true,
+        }
+    }
+
+    /// Called when the cpu reaches the breakpoint, returns true when it should stop
+    pub fn hit(&mut self, cpu: &Cpu) -> bool {
+        if !self.matches(cpu) {
+            return false;
+        }
+        self.hits += 1;
+        if self.ignore_count > 0 {
+            self.ignore_count -= 1;
+            return false;
+        }
+        self.hits >= self.hit_count
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// Single step or step over finished
+    Step,
+    Breakpoint(u16),
+    /// The instruction at `pc` accessed watched memory, it already ran
+    Watchpoint {
+        pc: u16,
+        hit: WatchHit,
+    },
+    /// The instruction limit given to `run` was reached
+    Limit,
+    /// Stopped from another thread or a signal handler, see `Debugger::interrupt_handle`
+    Interrupted,
+    /// Going back reached the oldest state in the history
+    HistoryStart,
+    /// The instruction at `pc` isn't a 6502 instruction, only its opcode was read
+    InvalidOpcode {
+        pc: u16,
+        opcode: u8,
+    },
+}
+
+pub struct Debugger<'a> {
+    pub cpu: Cpu<'a>,
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    interrupt: Arc<AtomicBool>,
+    history: Option<History>,
+}
+
+impl<'a> Debugger<'a> {
+    /// Turns on the shadow call stack of the cpu, for backtraces, and the history, for going back
+    pub fn new(mut cpu: Cpu<'a>) -> Debugger<'a> {
+        if cpu.call_stack.is_none() {
+            cpu.call_stack = Some(CallStack::new());
+        }
+        let mut history = History::new();
+        history.checkpoint(&cpu);
+        Debugger {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            history: Some(history),
+        }
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// None turns going back off
+    pub fn set_history(&mut self, history: Option<History>) {
+        self.history = history;
+        self.checkpoint();
+    }
+
+    /// Has to be called after changing registers or memory, or going back replays the old values
+    pub fn checkpoint(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.checkpoint(&self.cpu);
+        }
+    }
+
+    /// Adds an unconditional breakpoint, returns false if there was already one at `addr`
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        if self.breakpoints.contains_key(&addr) {
+            return false;
+        }
+        self.set_breakpoint(Breakpoint::new(addr));
+        true
+    }
+
+    /// Adds a breakpoint or replaces the one at the same address
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.insert(breakpoint.addr, breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn breakpoint(&self, addr: u16) -> Option<&Breakpoint> {
+        self.breakpoints.get(&addr)
+    }
+
+    pub fn breakpoint_mut(&mut self, addr: u16) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watch: Watchpoint) {
+        if !self.cpu.watchpoints.contains(&watch) {
+            self.cpu.watchpoints.push(watch);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watch: &Watchpoint) -> bool {
+        let len = self.cpu.watchpoints.len();
+        self.cpu.watchpoints.retain(|other| other != watch);
+        len != self.cpu.watchpoints.len()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.cpu.watchpoints
+    }
+
+    /// The current pc and the JSR or interrupted instruction of each frame, innermost first
+    pub fn backtrace(&self) -> Vec<u16> {
+        let frames = self.cpu.call_stack.iter().flat_map(|call_stack| call_stack.frames());
+        std::iter::once(self.cpu.pc).chain(frames.rev().map(|frame| frame.caller)).collect()
+    }
+
+    /// Setting the flag makes a running `run` or `step_over` return `StopReason::Interrupted`
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    pub fn step(&mut self) -> StopReason {
+        let pc = self.cpu.pc;
+        self.cpu.watch_hit = None;
+        self.cpu.invalid_op = None;
+        if let Some(history) = &mut self.history {
+            history.record(&self.cpu);
+        }
+        self.cpu.step();
+        self.stop_reason(pc).unwrap_or(StopReason::Step)
+    }
+
+    /// Like `step`, but runs a whole subroutine when the instruction is a JSR
+    pub fn step_over(&mut self) -> StopReason {
+        if self.cpu.peek8(self.cpu.pc) != Cpu::JSR_ABSOLUTE {
+            return self.step();
+        }
+        let ret = self.cpu.pc.wrapping_add(3);
+        let sp = self.cpu.regs[Cpu::REG_SP];
+        // the stack check keeps recursive calls from stopping at the wrong level
+        self.run_until(None, |cpu, _| cpu.pc == ret && cpu.regs[Cpu::REG_SP] >= sp)
+    }
+
+    /// Runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.cpu.regs[Cpu::REG_SP];
+        self.run_until(None, |cpu, pc| {
+            let opcode = cpu.peek8(pc);
+            (opcode == Cpu::RTS_IMPLIED || opcode == Cpu::RTI_IMPLIED) && cpu.regs[Cpu::REG_SP] > sp
+        })
+    }
+
+    /// Runs until a breakpoint, a watchpoint or an interrupt. The current instruction always
+    /// runs, so continuing from a breakpoint doesn't stop right away
+    pub fn run(&mut self, limit: Option<u64>) -> StopReason {
+        self.run_until(limit, |_, _| false)
+    }
+
+    /// Goes back one instruction
+    pub fn step_back(&mut self) -> StopReason {
+        let (start, position) = match &self.history {
+            Some(history) => (history.start(), history.position()),
+            None => return StopReason::HistoryStart,
+        };
+        if position <= start {
+            return StopReason::HistoryStart;
+        }
+        self.replay(position - 1, position - 1, |_, _, _| {});
+        StopReason::Step
+    }
+
+    /// Goes back to the last breakpoint or watchpoint hit before the current position. Hit and
+    /// ignore counts don't apply, conditions do
+    pub fn reverse_continue(&mut self) -> StopReason {
+        let (start, end) = match &self.history {
+            Some(history) => (history.start(), history.position()),
+            None => return StopReason::HistoryStart,
+        };
+        // each pass replays from the checkpoint before the part already searched
+        let mut searched = end;
+        while searched > start {
+            let mut last = None;
+            let from = self.replay(searched - 1, searched, |debugger, position, reason| {
+                if position >= end {
+                    return;
+                }
+                let reason = match reason {
+                    Some(reason) => reason,
+                    None => match debugger.breakpoints.get(&debugger.cpu.pc) {
+                        Some(breakpoint) if breakpoint.matches(&debugger.cpu) => {
+                            StopReason::Breakpoint(debugger.cpu.pc)
+                        }
+                        _ => return,
+                    },
+                };
+                last = Some((position, reason));
+            });
+            if let Some((position, reason)) = last {
+                self.replay(position, position, |_, _, _| {});
+                return reason;
+            }
+            searched = from;
+        }
+        self.replay(start, start, |_, _, _| {});
+        StopReason::HistoryStart
+    }
+
+    // Restores the last checkpoint at or before `from` and runs up to `target`, with the tools of
+    // the cpu off. `visit` gets the position after each instruction and the watchpoint it hit.
+    // Returns the position of the checkpoint
+    fn replay<F>(&mut self, from: u64, target: u64, mut visit: F) -> u64
+    where
+        F: FnMut(&Debugger<'a>, u64, Option<StopReason>),
+    {
+        let history = self.history.as_ref().expect("replay needs a history");
+        let (from, snapshot) = history.checkpoint_at(from).expect("no checkpoint to replay from");
+        self.cpu.restore(snapshot);
+        let tools = (
+            self.cpu.tracer.take(),
+            self.cpu.profiler.take(),
+            self.cpu.coverage.take(),
+            self.cpu.cdl.take(),
+            self.cpu.write_history.take(),
+        );
+        let mut position = from;
+        while position < target {
+            let pc = self.cpu.pc;
+            self.cpu.step();
+            position += 1;
+            let reason = self.stop_reason(pc);
+            visit(self, position, reason);
+        }
+        (
+            self.cpu.tracer,
+            self.cpu.profiler,
+            self.cpu.coverage,
+            self.cpu.cdl,
+            self.cpu.write_history,
+        ) = tools;
+        // the writes that were undone
+        if let Some(write_history) = &mut self.cpu.write_history {
+            write_history.forget_after(self.cpu.cycles_run);
+        }
+        if let Some(history) = &mut self.history {
+            history.set_position(target);
+        }
+        from
+    }
+
+    // Why to stop after the instruction at `pc`, if it ran into something
+    fn stop_reason(&mut self, pc: u16) -> Option<StopReason> {
+        let hit = self.cpu.watch_hit.take();
+        match self.cpu.invalid_op.take() {
+            Some(opcode) => Some(StopReason::InvalidOpcode { pc, opcode }),
+            None => hit.map(|hit| StopReason::Watchpoint { pc, hit }),
+        }
+    }
+
+    // `done` gets the cpu and the address of the instruction that just ran
+    fn run_until<F: Fn(&Cpu, u16) -> bool>(&mut self, limit: Option<u64>, done: F) -> StopReason {
+        self.interrupt.store(false, Ordering::Relaxed);
+        let mut count = 0;
+        loop {
+            let pc = self.cpu.pc;
+            let reason = self.step();
+            if reason != StopReason::Step {
+                return reason;
+            }
+            if done(&self.cpu, pc) {
+                return StopReason::Step;
+            }
+            if let Some(breakpoint) = self.breakpoints.get_mut(&self.cpu.pc) {
+                if breakpoint.hit(&self.cpu) {
+                    return StopReason::Breakpoint(self.cpu.pc);
+                }
+            }
+            if self.interrupt.load(Ordering::Relaxed) {
+                return StopReason::Interrupted;
+            }
+            count += 1;
+            if limit == Some(count) {
+                return StopReason::Limit;
+            }
+        }
+    }
+}
+
+/// `Nv-bdizC`, set flags in upper case
+pub fn format_flags(p: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, flag)| if p & (0x80 >> i) != 0 { flag } else { flag.to_ascii_lowercase() })
+        .collect()
+}
@@ -0,0 +1,177 @@
+// Memory mapped peripherals. A device claims a range of addresses, the cpu's reads and writes
+// there go to it instead of memory, with the offset into the range as the register. Devices that
+// have fewer registers than addresses mask the offset, which mirrors them like the partial address
+// decoding on real boards does.
+//
+// Devices are clocked lazily: before each access and after each instruction they catch up on the
+// cycles the cpu ran since the last time. A register read in the middle of an instruction sees
+// the state of the cycle it happens in, and nothing runs while no device is mapped.
+
+use std::any::Any;
+use std::ops::RangeInclusive;
+
+pub trait Device: AsAny + 'static {
+    /// Read with side effects, like clearing an interrupt flag
+    fn read(&mut self, offset: u16) -> u8;
+    fn write(&mut self, offset: u16, value: u8);
+    /// Read without side effects, for the debugger and the other tools
+    fn peek(&self, offset: u16) -> u8;
+    /// Advances the device by `cycles` clock cycles
+    fn tick(&mut self, _cycles: u32) {}
+    /// Level of the device's IRQ output, true pulls the line low
+    fn irq(&self) -> bool {
+        false
+    }
+    /// Level of the device's NMI output, the cpu reacts to it going active
+    fn nmi(&self) -> bool {
+        false
+    }
+    /// The RESET line
+    fn reset(&mut self) {}
+}
+
+/// Implemented for every `Device` that is `Clone`, to take devices into snapshots and to get the
+/// concrete type back out of the cpu
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn clone_device(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone + 'static> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn clone_device(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+struct Mapped {
+    range: RangeInclusive<u16>,
+    device: Box<dyn Device>,
+}
+
+impl Clone for Mapped {
+    fn clone(&self) -> Self {
+        Mapped { range: self.range.clone(), device: self.device.clone_device() }
+    }
+}
+
+/// The devices mapped on a cpu, see `Cpu::devices`
+#[derive(Clone, Default)]
+pub struct Devices {
+    mapped: Vec<Mapped>,
+    // `Cpu::cycles_run` the devices are at
+    cycles: u32,
+    // NMI level after the last instruction, an interrupt happens when it goes active
+    nmi: bool,
+}
+
+impl Devices {
+    pub fn new() -> Devices {
+        Devices::default()
+    }
+
+    /// Maps `device` over `range` and returns its index. Devices mapped later hide earlier ones
+    /// where they overlap
+    pub fn map<D: Device>(&mut self, range: RangeInclusive<u16>, device: D) -> usize {
+        self.mapped.push(Mapped { range, device: Box::new(device) });
+        self.mapped.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mapped.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.mapped.len()
+    }
+
+    /// Address range of the device at `index`
+    pub fn range(&self, index: usize) -> Option<RangeInclusive<u16>> {
+        self.mapped.get(index).map(|mapped| mapped.range.clone())
+    }
+
+    /// The device at `index`, if it is a `D`
+    pub fn get<D: Device>(&self, index: usize) -> Option<&D> {
+        self.mapped.get(index)?.device.as_any().downcast_ref()
+    }
+
+    pub fn get_mut<D: Device>(&mut self, index: usize) -> Option<&mut D> {
+        self.mapped.get_mut(index)?.device.as_any_mut().downcast_mut()
+    }
+
+    /// The first device of type `D`
+    pub fn find<D: Device>(&self) -> Option<&D> {
+        self.mapped.iter().find_map(|mapped| mapped.device.as_any().downcast_ref())
+    }
+
+    pub fn find_mut<D: Device>(&mut self) -> Option<&mut D> {
+        self.mapped.iter_mut().find_map(|mapped| mapped.device.as_any_mut().downcast_mut())
+    }
+
+    fn lookup(&self, addr: u16) -> Option<usize> {
+        self.mapped.iter().rposition(|mapped| mapped.range.contains(&addr))
+    }
+
+    /// Runs the devices up to `cycle`
+    pub fn sync(&mut self, cycle: u32) {
+        // the cycle counter going back (reset, restore) only moves the devices' time base
+        if cycle > self.cycles {
+            let cycles = cycle - self.cycles;
+            self.mapped.iter_mut().for_each(|mapped| mapped.device.tick(cycles));
+        }
+        self.cycles = cycle;
+    }
+
+    /// A read by the cpu in the cycle after `cycle`, `None` if no device is mapped there
+    pub(crate) fn read(&mut self, addr: u16, cycle: u32) -> Option<u8> {
+        let index = self.lookup(addr)?;
+        self.sync(cycle);
+        let mapped = &mut self.mapped[index];
+        Some(mapped.device.read(addr - mapped.range.start()))
+    }
+
+    /// A write by the cpu in the cycle after `cycle`, false if no device is mapped there
+    pub(crate) fn write(&mut self, addr: u16, value: u8, cycle: u32) -> bool {
+        match self.lookup(addr) {
+            Some(index) => {
+                self.sync(cycle);
+                let mapped = &mut self.mapped[index];
+                mapped.device.write(addr - mapped.range.start(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        let mapped = &self.mapped[self.lookup(addr)?];
+        Some(mapped.device.peek(addr - mapped.range.start()))
+    }
+
+    /// Whether any device holds the IRQ line low
+    pub fn irq(&self) -> bool {
+        self.mapped.iter().any(|mapped| mapped.device.irq())
+    }
+
+    /// Whether the NMI line went active since the last call
+    pub(crate) fn nmi_edge(&mut self) -> bool {
+        let nmi = self.mapped.iter().any(|mapped| mapped.device.nmi());
+        let edge = nmi && !self.nmi;
+        self.nmi = nmi;
+        edge
+    }
+
+    pub fn reset(&mut self) {
+        self.mapped.iter_mut().for_each(|mapped| mapped.device.reset());
+        self.cycles = 0;
+        self.nmi = false;
+    }
+}
@@ -0,0 +1,166 @@
+use crate::opcodes::{opcode_info, AddressingMode, OpcodeInfo, OpcodeKind};
+use crate::symbols::SymbolTable;
+use crate::Peek;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    // 8 or 16 bit operand, as stored after the opcode
+    pub operand: u16,
+    pub info: OpcodeInfo,
+}
+
+impl Instruction {
+    pub fn size(&self) -> u16 {
+        self.info.mode.size()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode, self.operand as u8, (self.operand >> 8) as u8];
+        bytes.truncate(self.size() as usize);
+        bytes
+    }
+
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.size())
+    }
+
+    /// The address the operand points to when it doesn't depend on registers: branch and jump
+    /// destinations, zero page and absolute operands
+    pub fn target(&self) -> Option<u16> {
+        match self.info.mode {
+            AddressingMode::Relative => {
+                Some(self.next_addr().wrapping_add(self.operand as u8 as i8 as u16))
+            }
+            AddressingMode::ZeroPage | AddressingMode::Absolute => Some(self.operand),
+            _ => None,
+        }
+    }
+}
+
+pub fn decode<M: Peek + ?Sized>(mem: &M, addr: u16) -> Instruction {
+    let opcode = mem.peek8(addr);
+    let info = opcode_info(opcode);
+    let operand = match info.mode.size() {
+        1 => 0,
+        2 => mem.peek8(addr.wrapping_add(1)) as u16,
+        _ => mem.peek16(addr.wrapping_add(1)),
+    };
+    Instruction { addr, opcode, operand, info }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// Lower case, assembles back to the same bytes with ca65 (`.setcpu "6502X"`)
+    Ca65,
+    /// Upper case, illegal opcodes marked with `*` like in nestest.log
+    Generic,
+}
+
+pub struct Disassembler<'a> {
+    pub syntax: Syntax,
+    symbols: Option<&'a SymbolTable>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(syntax: Syntax) -> Disassembler<'a> {
+        Disassembler { syntax, symbols: None }
+    }
+
+    pub fn with_symbols(mut self, symbols: &'a SymbolTable) -> Disassembler<'a> {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    pub fn disassemble<M: Peek + ?Sized>(&self, mem: &M, addr: u16) -> (Instruction, String) {
+        let inst = decode(mem, addr);
+        let text = self.format(&inst);
+        (inst, text)
+    }
+
+    pub fn format(&self, inst: &Instruction) -> String {
+        if self.syntax == Syntax::Ca65 && inst.info.kind == OpcodeKind::Unstable {
+            let bytes: Vec<String> = inst.bytes().iter().map(|b| format!("${:02X}", b)).collect();
+            return format!(".byte {}", bytes.join(","));
+        }
+        let mnemonic = match self.syntax {
+            Syntax::Ca65 => inst.info.mnemonic.to_lowercase(),
+            Syntax::Generic => {
+                let name = if inst.info.mnemonic == "ISC" { "ISB" } else { inst.info.mnemonic };
+                if inst.info.is_official() {
+                    name.to_string()
+                } else {
+                    format!("*{}", name)
+                }
+            }
+        };
+        let operand = self.format_operand(inst);
+        if operand.is_empty() {
+            mnemonic
+        } else {
+            format!("{} {}", mnemonic, operand)
+        }
+    }
+
+    fn format_operand(&self, inst: &Instruction) -> String {
+        let zp = || self.address(inst.operand, 2);
+        let abs = || {
+            let text = self.address(inst.operand, 4);
+            // ca65 would pick the zero page encoding for these
+            if self.syntax == Syntax::Ca65
+                && inst.operand < 0x100
+                && inst.info.mnemonic != "JMP"
+                && inst.info.mnemonic != "JSR"
+            {
+                format!("a:{}", text)
+            } else {
+                text
+            }
+        };
+        match inst.info.mode {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => {
+                if self.syntax == Syntax::Ca65 {
+                    "a".to_string()
+                } else {
+                    "A".to_string()
+                }
+            }
+            AddressingMode::Immediate => format!("#${:02X}", inst.operand),
+            AddressingMode::ZeroPage => zp(),
+            AddressingMode::ZeroPageX => format!("{},X", zp()),
+            AddressingMode::ZeroPageY => format!("{},Y", zp()),
+            AddressingMode::Absolute => abs(),
+            AddressingMode::AbsoluteX => format!("{},X", abs()),
+            AddressingMode::AbsoluteY => format!("{},Y", abs()),
+            AddressingMode::Indirect => format!("({})", self.address(inst.operand, 4)),
+            AddressingMode::IndirectX => format!("({},X)", zp()),
+            AddressingMode::IndirectY => format!("({}),Y", zp()),
+            AddressingMode::Relative => self.address(inst.target().unwrap_or(0), 4),
+        }
+    }
+
+    fn address(&self, addr: u16, digits: usize) -> String {
+        self.symbols
+            .and_then(|symbols| symbols.symbolize(addr))
+            .unwrap_or_else(|| format!("${:0width$X}", addr, width = digits))
+    }
+
+    /// `count` instructions starting at `addr`, one per line with address and raw bytes.
+    /// Symbols defined at an address get their own `label:` line
+    pub fn listing<M: Peek + ?Sized>(&self, mem: &M, addr: u16, count: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut addr = addr;
+        for _ in 0..count {
+            if let Some(name) = self.symbols.and_then(|symbols| symbols.name_at(addr)) {
+                lines.push(format!("{}:", name));
+            }
+            let (inst, text) = self.disassemble(mem, addr);
+            let bytes: Vec<String> = inst.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+            lines.push(format!("{:04X}  {:<8}  {}", addr, bytes.join(" "), text));
+            addr = inst.next_addr();
+        }
+        lines
+    }
+}
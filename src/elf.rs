@@ -0,0 +1,275 @@
+// https://refspecs.linuxfoundation.org/elf/elf.pdf
+// https://llvm-mos.org/wiki/ELF_specification
+
+use crate::Mem;
+use std::fmt;
+
+pub const EM_MOS: u16 = 6502;
+const EM_NONE: u16 = 0;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHN_UNDEF: u16 = 0;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum ElfError {
+    NotElf,
+    Unsupported(&'static str),
+    UnsupportedMachine(u16),
+    Truncated,
+    AddressOutOfRange(u32),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+            ElfError::UnsupportedMachine(machine) => {
+                write!(f, "unsupported ELF machine {}", machine)
+            }
+            ElfError::Truncated => write!(f, "ELF file is truncated"),
+            ElfError::AddressOutOfRange(addr) => {
+                write!(f, "address {:#X} does not fit in the 6502 address space", addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub addr: u16,
+    pub size: u32,
+    pub kind: SymbolKind,
+}
+
+/// A PT_LOAD segment. `mem_size` can be bigger than `data`, the rest is zero filled (.bss)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub addr: u16,
+    pub data: Vec<u8>,
+    pub mem_size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfFile {
+    pub entry: u16,
+    pub segments: Vec<Segment>,
+    // sorted by address
+    pub symbols: Vec<ElfSymbol>,
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.len() >= 4 && data[0..4] == [0x7F, b'E', b'L', b'F']
+}
+
+fn read16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn slice(data: &[u8], offset: u32, size: u32) -> Result<&[u8], ElfError> {
+    let start = offset as usize;
+    let end = start.checked_add(size as usize).ok_or(ElfError::Truncated)?;
+    data.get(start..end).ok_or(ElfError::Truncated)
+}
+
+fn addr16(addr: u32) -> Result<u16, ElfError> {
+    if addr > 0xFFFF {
+        return Err(ElfError::AddressOutOfRange(addr));
+    }
+    Ok(addr as u16)
+}
+
+fn c_string(strtab: &[u8], offset: u32) -> String {
+    let start = (offset as usize).min(strtab.len());
+    let end = strtab[start..].iter().position(|b| *b == 0).map_or(strtab.len(), |p| start + p);
+    String::from_utf8_lossy(&strtab[start..end]).into_owned()
+}
+
+impl ElfFile {
+    pub fn parse(data: &[u8]) -> Result<ElfFile, ElfError> {
+        if !is_elf(data) {
+            return Err(ElfError::NotElf);
+        }
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[4] != 1 {
+            return Err(ElfError::Unsupported("only 32 bit files are supported"));
+        }
+        if data[5] != 1 {
+            return Err(ElfError::Unsupported("only little endian files are supported"));
+        }
+        let machine = read16(data, 18)?;
+        if machine != EM_MOS && machine != EM_NONE {
+            return Err(ElfError::UnsupportedMachine(machine));
+        }
+        let entry = addr16(read32(data, 24)?)?;
+        let segments = ElfFile::parse_segments(data)?;
+        let mut symbols = ElfFile::parse_symbols(data)?;
+        symbols.sort_by_key(|sym| sym.addr);
+        Ok(ElfFile { entry, segments, symbols })
+    }
+
+    fn parse_segments(data: &[u8]) -> Result<Vec<Segment>, ElfError> {
+        let phoff = read32(data, 28)? as usize;
+        let phentsize = read16(data, 42)? as usize;
+        let phnum = read16(data, 44)? as usize;
+        if phnum > 0 && phentsize < PHDR_SIZE {
+            return Err(ElfError::Unsupported("program header entries are too small"));
+        }
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            if read32(data, ph)? != PT_LOAD {
+                continue;
+            }
+            let offset = read32(data, ph + 4)?;
+            // The 6502 sees the virtual address. llvm-mos puts the bank into the upper bits of
+            // the physical one
+            let vaddr = read32(data, ph + 8)?;
+            let file_size = read32(data, ph + 16)?;
+            let mem_size = read32(data, ph + 20)?;
+            if mem_size == 0 {
+                continue;
+            }
+            let addr = addr16(vaddr)?;
+            addr16(vaddr.saturating_add(mem_size - 1))?;
+            let data = slice(data, offset, file_size.min(mem_size))?.to_vec();
+            segments.push(Segment { addr, data, mem_size });
+        }
+        Ok(segments)
+    }
+
+    fn parse_symbols(data: &[u8]) -> Result<Vec<ElfSymbol>, ElfError> {
+        let shoff = read32(data, 32)? as usize;
+        let shentsize = read16(data, 46)? as usize;
+        let shnum = read16(data, 48)? as usize;
+        if shnum > 0 && shentsize < SHDR_SIZE {
+            return Err(ElfError::Unsupported("section header entries are too small"));
+        }
+        let section = |index: usize| shoff + index * shentsize;
+        let mut symbols = Vec::new();
+        for i in 0..shnum {
+            let sh = section(i);
+            if read32(data, sh + 4)? != SHT_SYMTAB {
+                continue;
+            }
+            let symtab = slice(data, read32(data, sh + 16)?, read32(data, sh + 20)?)?;
+            let link = read32(data, sh + 24)? as usize;
+            if link >= shnum {
+                return Err(ElfError::Truncated);
+            }
+            let strtab =
+                slice(data, read32(data, section(link) + 16)?, read32(data, section(link) + 20)?)?;
+            // the first entry is always the undefined symbol
+            for sym in symtab.chunks_exact(SYM_SIZE).skip(1) {
+                let kind = sym[12] & 0xF;
+                let shndx = read16(sym, 14)?;
+                if kind == STT_SECTION || kind == STT_FILE || shndx == SHN_UNDEF {
+                    continue;
+                }
+                let name = c_string(strtab, read32(sym, 0)?);
+                let value = read32(sym, 4)?;
+                // Symbols outside the 64K window (banked code, debug sections) can't be reached
+                // by the cpu, so they are not interesting here
+                if name.is_empty() || value > 0xFFFF {
+                    continue;
+                }
+                let kind = match kind {
+                    1 => SymbolKind::Object,
+                    2 => SymbolKind::Function,
+                    _ => SymbolKind::Other,
+                };
+                symbols.push(ElfSymbol { name, addr: value as u16, size: read32(sym, 8)?, kind });
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Copies all the loadable segments into memory, zero filling the .bss part of them
+    pub fn load(&self, mem: &mut Mem) {
+        for segment in &self.segments {
+            mem.load_programm_at(segment.addr, &segment.data);
+            for i in segment.data.len() as u32..segment.mem_size {
+                mem.write8(segment.addr as usize + i as usize, 0);
+            }
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&ElfSymbol> {
+        self.symbols.iter().find(|sym| sym.name == name)
+    }
+
+    /// The contents of the section called `name`, None if the file doesn't have one. SHT_NOBITS
+    /// sections have no data in the file and come back empty
+    pub fn section<'d>(data: &'d [u8], name: &str) -> Result<Option<&'d [u8]>, ElfError> {
+        let shoff = read32(data, 32)? as usize;
+        let shentsize = read16(data, 46)? as usize;
+        let shnum = read16(data, 48)? as usize;
+        let shstrndx = read16(data, 50)? as usize;
+        if shnum == 0 || shstrndx >= shnum {
+            return Ok(None);
+        }
+        if shentsize < SHDR_SIZE {
+            return Err(ElfError::Unsupported("section header entries are too small"));
+        }
+        let section = |index: usize| shoff + index * shentsize;
+        let names = slice(
+            data,
+            read32(data, section(shstrndx) + 16)?,
+            read32(data, section(shstrndx) + 20)?,
+        )?;
+        for i in 0..shnum {
+            let sh = section(i);
+            if c_string(names, read32(data, sh)?) != name {
+                continue;
+            }
+            if read32(data, sh + 4)? == SHT_NOBITS {
+                return Ok(Some(&[]));
+            }
+            return Ok(Some(slice(data, read32(data, sh + 16)?, read32(data, sh + 20)?)?));
+        }
+        Ok(None)
+    }
+
+    /// Finds the symbol that covers `addr` and the offset of `addr` inside of it.
+    /// Symbols without a size only match their exact address.
+    pub fn symbol_at(&self, addr: u16) -> Option<(&ElfSymbol, u16)> {
+        let end = self.symbols.partition_point(|sym| sym.addr <= addr);
+        self.symbols[..end]
+            .iter()
+            .rev()
+            .filter(|sym| {
+                let offset = (addr - sym.addr) as u32;
+                offset == 0 || offset < sym.size
+            })
+            // prefer functions and objects over plain labels at the same address
+            .min_by_key(|sym| (addr - sym.addr, sym.kind == SymbolKind::Other))
+            .map(|sym| (sym, addr - sym.addr))
+    }
+}
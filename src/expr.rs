@@ -0,0 +1,330 @@
+// Expressions over the cpu state, for conditional breakpoints and the monitor:
+//
+//     A == $40 && [$D012] > 100
+//     [ptr].w + Y >= $C000 || cycles > 100000
+//
+// `A X Y SP P PC`, the flags `N V B D I Z C` (0 or 1) and `cycles` can be written in any case
+// and hide symbols with the same name. `[addr]` reads a byte and `[addr].w` a little endian word,
+// without the side effects a read of an I/O register has. Numbers are decimal unless they have a
+// `$`, `0x` or `%` prefix.
+// Operators from the highest precedence: the unary `! ~ -`, then `* / %`, `+ -`, `<< >>`,
+// `< <= > >=`, `== !=`, `&`, `^`, `|`, `&&` and `||`. Comparisons and logic give 0 or 1.
+
+use crate::symbols::SymbolTable;
+use crate::{Cpu, Peek};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl From<String> for ExprError {
+    fn from(message: String) -> Self {
+        ExprError { message }
+    }
+}
+
+impl From<&str> for ExprError {
+    fn from(message: &str) -> Self {
+        ExprError { message: message.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOp {
+    Neg,
+    Not,
+    LogicNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicAnd,
+    LogicOr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Num(i64),
+    Reg(usize),
+    Pc,
+    Flag(u8),
+    Cycles,
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// A parsed expression, symbols are replaced by their value when parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    node: Node,
+    text: String,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, ExprError> {
+        Expr::parse_with_symbols(text, &SymbolTable::new())
+    }
+
+    pub fn parse_with_symbols(text: &str, symbols: &SymbolTable) -> Result<Expr, ExprError> {
+        let mut parser = ExprParser { chars: text.trim().chars().collect(), pos: 0, symbols };
+        if parser.chars.is_empty() {
+            return Err("missing expression".into());
+        }
+        let node = parser.binary(0)?;
+        parser.skip_spaces();
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected '{}' in expression", c).into());
+        }
+        Ok(Expr { node, text: text.trim().to_string() })
+    }
+
+    /// Division by zero is the only error
+    pub fn eval(&self, cpu: &Cpu) -> Result<i64, ExprError> {
+        eval(&self.node, cpu)
+    }
+}
+
+/// The expression as it was written
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn eval(node: &Node, cpu: &Cpu) -> Result<i64, ExprError> {
+    Ok(match node {
+        Node::Num(value) => *value,
+        Node::Reg(reg) => cpu.regs[*reg] as i64,
+        Node::Pc => cpu.pc as i64,
+        Node::Flag(mask) => (cpu.regs[Cpu::REG_STAT] & mask != 0) as i64,
+        Node::Cycles => cpu.cycles_run as i64,
+        Node::Byte(addr) => cpu.peek8(eval(addr, cpu)? as u16) as i64,
+        Node::Word(addr) => cpu.peek16(eval(addr, cpu)? as u16) as i64,
+        Node::Unary(op, node) => {
+            let value = eval(node, cpu)?;
+            match op {
+                UnaryOp::Neg => value.wrapping_neg(),
+                UnaryOp::Not => !value,
+                UnaryOp::LogicNot => (value == 0) as i64,
+            }
+        }
+        // no need to evaluate the right side, it may divide by zero
+        Node::Binary(BinaryOp::LogicAnd, left, right) => {
+            (eval(left, cpu)? != 0 && eval(right, cpu)? != 0) as i64
+        }
+        Node::Binary(BinaryOp::LogicOr, left, right) => {
+            (eval(left, cpu)? != 0 || eval(right, cpu)? != 0) as i64
+        }
+        Node::Binary(op, left, right) => {
+            let (left, right) = (eval(left, cpu)?, eval(right, cpu)?);
+            match op {
+                BinaryOp::Mul => left.wrapping_mul(right),
+                BinaryOp::Div | BinaryOp::Mod if right == 0 => {
+                    return Err("division by zero".into())
+                }
+                BinaryOp::Div => left.wrapping_div(right),
+                BinaryOp::Mod => left.wrapping_rem(right),
+                BinaryOp::Add => left.wrapping_add(right),
+                BinaryOp::Sub => left.wrapping_sub(right),
+                BinaryOp::Shl => left.wrapping_shl(right as u32),
+                BinaryOp::Shr => left.wrapping_shr(right as u32),
+                BinaryOp::Lt => (left < right) as i64,
+                BinaryOp::Le => (left <= right) as i64,
+                BinaryOp::Gt => (left > right) as i64,
+                BinaryOp::Ge => (left >= right) as i64,
+                BinaryOp::Eq => (left == right) as i64,
+                BinaryOp::Ne => (left != right) as i64,
+                BinaryOp::And => left & right,
+                BinaryOp::Xor => left ^ right,
+                BinaryOp::Or => left | right,
+                BinaryOp::LogicAnd | BinaryOp::LogicOr => unreachable!(),
+            }
+        }
+    })
+}
+
+// Lowest precedence first, longer operators before their prefixes
+const PRECEDENCE: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::LogicOr)],
+    &[("&&", BinaryOp::LogicAnd)],
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[("<=", BinaryOp::Le), (">=", BinaryOp::Ge), ("<", BinaryOp::Lt), (">", BinaryOp::Gt)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Mod)],
+];
+
+const FLAGS: &str = "NV-BDIZC";
+
+struct ExprParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> ExprParser<'a> {
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    // `&` and `|` must not match the first half of `&&` and `||`
+    fn starts_with_op(&self, op: &str) -> bool {
+        let doubled =
+            (op == "&" || op == "|") && self.chars.get(self.pos + 1) == self.peek().as_ref();
+        self.starts_with(op) && !doubled
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            self.skip_spaces();
+            for (text, op) in PRECEDENCE[level] {
+                if self.starts_with_op(text) {
+                    self.pos += text.len();
+                    let right = self.binary(level + 1)?;
+                    left = Node::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        self.skip_spaces();
+        let op = match self.peek() {
+            Some('-') => UnaryOp::Neg,
+            Some('~') => UnaryOp::Not,
+            Some('!') => UnaryOp::LogicNot,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        self.skip_spaces();
+        if self.starts_with("0x") || self.starts_with("0X") {
+            self.pos += 2;
+            return self.number(16);
+        }
+        let c = self.peek().ok_or("missing operand in expression")?;
+        self.pos += 1;
+        match c {
+            '(' => {
+                let node = self.binary(0)?;
+                self.close(')')?;
+                Ok(node)
+            }
+            '[' => {
+                let addr = Box::new(self.binary(0)?);
+                self.close(']')?;
+                if self.starts_with(".w") || self.starts_with(".W") {
+                    self.pos += 2;
+                    Ok(Node::Word(addr))
+                } else {
+                    Ok(Node::Byte(addr))
+                }
+            }
+            '$' => self.number(16),
+            '%' => self.number(2),
+            c if c.is_ascii_digit() => {
+                self.pos -= 1;
+                self.number(10)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == '@' || c == '.' => {
+                let start = self.pos - 1;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || "_@.:".contains(c)) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                self.name(&name)
+            }
+            c => Err(format!("unexpected '{}' in expression", c)),
+        }
+    }
+
+    fn close(&mut self, bracket: char) -> Result<(), String> {
+        self.skip_spaces();
+        if self.peek() != Some(bracket) {
+            return Err(format!("missing {}", bracket));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn name(&self, name: &str) -> Result<Node, String> {
+        let upper = name.to_ascii_uppercase();
+        Ok(match upper.as_str() {
+            "A" => Node::Reg(Cpu::REG_A),
+            "X" => Node::Reg(Cpu::REG_X),
+            "Y" => Node::Reg(Cpu::REG_Y),
+            "SP" => Node::Reg(Cpu::REG_SP),
+            "P" => Node::Reg(Cpu::REG_STAT),
+            "PC" => Node::Pc,
+            "CYCLES" => Node::Cycles,
+            flag if flag.len() == 1 && FLAGS.contains(flag) => {
+                Node::Flag(0x80 >> FLAGS.find(flag).unwrap())
+            }
+            _ => match self.symbols.lookup(name) {
+                Some(addr) => Node::Num(addr as i64),
+                None => return Err(format!("unknown symbol {}", name)),
+            },
+        })
+    }
+
+    fn number(&mut self, radix: u32) -> Result<Node, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_digit(radix)) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        i64::from_str_radix(&digits, radix)
+            .map(Node::Num)
+            .map_err(|_| format!("invalid number '{}'", digits))
+    }
+}
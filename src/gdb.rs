@@ -0,0 +1,444 @@
+// GDB remote serial protocol stub, lets gdb or the llvm-mos lldb debug a program running in `Cpu`.
+// The register layout is sent as target.xml: a, x, y, p, sp (8 bit) and pc (16 bit, little endian).
+// Both breakpoint kinds map to debugger breakpoints, watchpoints (Z2-Z4) to debugger watchpoints.
+// reverse-stepi and reverse-continue (bs and bc) go back through the debugger history.
+
+use crate::debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+use crate::{Cpu, Peek};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.emulator6502.core">
+    <reg name="a" bitsize="8" regnum="0" type="uint8"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// The register numbers of the target description, in `g` packet order
+const REGISTERS: [usize; 5] = [Cpu::REG_A, Cpu::REG_X, Cpu::REG_Y, Cpu::REG_STAT, Cpu::REG_SP];
+const REG_PC: usize = 5;
+
+const SUPPORTED: &str =
+    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+;vContSupported+;\
+     ReverseStep+;ReverseContinue+";
+
+const INTERRUPT: u8 = 0x03;
+
+pub struct GdbStub<'a> {
+    pub debugger: Debugger<'a>,
+    sw_breakpoints: BTreeSet<u16>,
+    hw_breakpoints: BTreeSet<u16>,
+}
+
+struct Connection<W: Write> {
+    input: Receiver<u8>,
+    out: W,
+    ack: bool,
+}
+
+impl<W: Write> Connection<W> {
+    /// Returns None when the client closed the connection
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            // everything before the start of a packet is an ack, a nack or a stray interrupt
+            match self.input.recv() {
+                Ok(b'$') => {}
+                Ok(_) => continue,
+                Err(_) => return Ok(None),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.input.recv() {
+                    Ok(b'#') => break,
+                    Ok(byte) => data.push(byte),
+                    Err(_) => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in checksum.iter_mut() {
+                match self.input.recv() {
+                    Ok(byte) => *digit = byte,
+                    Err(_) => return Ok(None),
+                }
+            }
+            let expected =
+                std::str::from_utf8(&checksum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+            let valid = expected == Some(checksum_of(&data));
+            if self.ack {
+                self.out.write_all(if valid { b"+" } else { b"-" })?;
+                self.out.flush()?;
+            }
+            if valid || !self.ack {
+                return Ok(Some(unescape(&data)));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for &byte in data.as_bytes() {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = checksum_of(&packet[1..]);
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        loop {
+            self.out.write_all(&packet)?;
+            self.out.flush()?;
+            if !self.ack {
+                return Ok(());
+            }
+            // resend on a nack, anything else is taken as an ack
+            match self.input.recv() {
+                Ok(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => result.push(bytes.next().map_or(0, |byte| byte ^ 0x20)),
+            _ => result.push(byte),
+        }
+    }
+    result
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn parse_addr(text: &str) -> Option<u16> {
+    parse_hex(text).filter(|&addr| addr <= 0xFFFF).map(|addr| addr as u16)
+}
+
+/// Parses `addr,len`
+fn parse_range(text: &str) -> Option<(u16, u32)> {
+    let (addr, len) = text.split_once(',')?;
+    let addr = parse_addr(addr)?;
+    let len = parse_hex(len)?;
+    if (addr as u32).checked_add(len)? > 0x10000 {
+        return None;
+    }
+    Some((addr, len))
+}
+
+enum Reply {
+    Send(String),
+    /// Resume execution, the stop reply is sent when it stops
+    Resume(StopReason),
+    /// The session ends after the reply
+    Exit(Option<String>),
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(debugger: Debugger<'a>) -> GdbStub<'a> {
+        GdbStub { debugger, sw_breakpoints: BTreeSet::new(), hw_breakpoints: BTreeSet::new() }
+    }
+
+    /// Runs a session until gdb detaches, kills the target or closes the connection. `input` is
+    /// read on its own thread, so that a Ctrl-C from gdb can stop a running `continue`
+    pub fn serve<R: Read + Send + 'static, W: Write>(
+        &mut self,
+        input: R,
+        output: W,
+    ) -> io::Result<()> {
+        let (sender, receiver) = channel();
+        let interrupt = self.debugger.interrupt_handle();
+        std::thread::spawn(move || forward_input(input, sender, interrupt));
+        let mut conn = Connection { input: receiver, out: output, ack: true };
+        while let Some(packet) = conn.read_packet()? {
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            match self.handle(&packet) {
+                Reply::Send(reply) => conn.send(&reply)?,
+                Reply::Resume(reason) => conn.send(&self.stop_reply(reason))?,
+                Reply::Exit(reply) => {
+                    if let Some(reply) = reply {
+                        conn.send(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+            if packet == "QStartNoAckMode" {
+                conn.ack = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        let ok = || Reply::Send("OK".to_string());
+        let error = || Reply::Send("E01".to_string());
+        let (command, args) = packet.split_at(packet.len().min(1));
+        match command {
+            "?" => Reply::Send("S05".to_string()),
+            "g" => Reply::Send(self.read_registers()),
+            "G" => match parse_hex_bytes(args) {
+                Some(values) if values.len() == REGISTERS.len() + 2 => {
+                    for (&reg, &value) in REGISTERS.iter().zip(&values) {
+                        self.debugger.cpu.regs[reg] = value;
+                    }
+                    self.debugger.cpu.pc = u16::from_le_bytes([values[5], values[6]]);
+                    self.debugger.checkpoint();
+                    ok()
+                }
+                _ => error(),
+            },
+            "p" => match parse_hex(args) {
+                Some(reg) if (reg as usize) < REGISTERS.len() => {
+                    Reply::Send(hex(&[self.debugger.cpu.regs[REGISTERS[reg as usize]]]))
+                }
+                Some(reg) if reg as usize == REG_PC => {
+                    Reply::Send(hex(&self.debugger.cpu.pc.to_le_bytes()))
+                }
+                _ => error(),
+            },
+            "P" => {
+                let value = args
+                    .split_once('=')
+                    .and_then(|(reg, value)| Some((parse_hex(reg)?, parse_hex_bytes(value)?)));
+                match value {
+                    Some((reg, value)) if (reg as usize) < REGISTERS.len() && value.len() == 1 => {
+                        self.debugger.cpu.regs[REGISTERS[reg as usize]] = value[0];
+                        self.debugger.checkpoint();
+                        ok()
+                    }
+                    Some((reg, value)) if reg as usize == REG_PC && value.len() == 2 => {
+                        self.debugger.cpu.pc = u16::from_le_bytes([value[0], value[1]]);
+                        self.debugger.checkpoint();
+                        ok()
+                    }
+                    _ => error(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let bytes: Vec<u8> =
+                        (0..len).map(|i| self.debugger.cpu.peek8(addr + i as u16)).collect();
+                    Reply::Send(hex(&bytes))
+                }
+                None => error(),
+            },
+            "M" => {
+                let write = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, parse_hex_bytes(data)?)));
+                match write {
+                    Some(((addr, len), data)) if data.len() == len as usize => {
+                        for (i, byte) in data.into_iter().enumerate() {
+                            self.debugger.cpu.mem_mut().write8(addr as usize + i, byte);
+                        }
+                        self.debugger.checkpoint();
+                        ok()
+                    }
+                    _ => error(),
+                }
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_addr(args) {
+                        Some(addr) => self.debugger.cpu.pc = addr,
+                        None => return error(),
+                    }
+                    self.debugger.checkpoint();
+                }
+                self.resume(command == "s")
+            }
+            "b" => match args {
+                "s" => Reply::Resume(self.debugger.step_back()),
+                "c" => Reply::Resume(self.debugger.reverse_continue()),
+                _ => error(),
+            },
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => ok(),
+            "T" => ok(),
+            "k" => Reply::Exit(None),
+            "D" => Reply::Exit(Some("OK".to_string())),
+            _ => self.query(packet),
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> Reply {
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let range =
+                range.split_once(',').and_then(|(o, l)| Some((parse_hex(o)?, parse_hex(l)?)));
+            let (offset, len) = match range {
+                Some(range) => range,
+                None => return Reply::Send("E01".to_string()),
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = (start + len as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { "l" } else { "m" };
+            return Reply::Send(format!("{}{}", marker, &TARGET_XML[start..end]));
+        }
+        // only the action of the first thread matters, there is just one
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            return match actions.bytes().next() {
+                Some(b's') | Some(b'S') => self.resume(true),
+                Some(b'c') | Some(b'C') => self.resume(false),
+                _ => Reply::Send("E01".to_string()),
+            };
+        }
+        let reply = match packet {
+            _ if packet.starts_with("qSupported") => SUPPORTED,
+            "QStartNoAckMode" => "OK",
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            "vCont?" => "vCont;c;C;s;S",
+            _ => "",
+        };
+        Reply::Send(reply.to_string())
+    }
+
+    fn resume(&mut self, step: bool) -> Reply {
+        Reply::Resume(if step { self.debugger.step() } else { self.debugger.run(None) })
+    }
+
+    /// `Z type,addr,kind` adds and `z type,addr,kind` removes a breakpoint or watchpoint. For
+    /// watchpoints the kind is the length in bytes
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Reply {
+        let mut fields = args.splitn(3, ',');
+        let kind = fields.next().and_then(parse_hex);
+        let addr = fields.next().and_then(parse_addr);
+        let len = fields.next().and_then(|len| parse_hex(len.split(';').next().unwrap_or("")));
+        let (kind, addr, len) = match (kind, addr, len) {
+            (Some(kind), Some(addr), Some(len)) => (kind, addr, len),
+            _ => return Reply::Send("E01".to_string()),
+        };
+        match kind {
+            0 | 1 => {
+                let (own, other) = if kind == 0 {
+                    (&mut self.sw_breakpoints, &self.hw_breakpoints)
+                } else {
+                    (&mut self.hw_breakpoints, &self.sw_breakpoints)
+                };
+                if insert {
+                    own.insert(addr);
+                    self.debugger.add_breakpoint(addr);
+                } else if own.remove(&addr) && !other.contains(&addr) {
+                    self.debugger.remove_breakpoint(addr);
+                }
+            }
+            2..=4 => {
+                let end = match (addr as u32).checked_add(len.max(1) - 1) {
+                    Some(end) if end <= 0xFFFF => end,
+                    _ => return Reply::Send("E01".to_string()),
+                };
+                let kind = match kind {
+                    2 => WatchKind::Write,
+                    3 => WatchKind::Read,
+                    _ => WatchKind::ReadWrite,
+                };
+                let watch = Watchpoint { start: addr, end: end as u16, kind };
+                if insert {
+                    self.debugger.add_watchpoint(watch);
+                } else {
+                    self.debugger.remove_watchpoint(&watch);
+                }
+            }
+            _ => return Reply::Send(String::new()),
+        }
+        Reply::Send("OK".to_string())
+    }
+
+    fn read_registers(&self) -> String {
+        let cpu = &self.debugger.cpu;
+        let mut values: Vec<u8> = REGISTERS.iter().map(|&reg| cpu.regs[reg]).collect();
+        values.extend_from_slice(&cpu.pc.to_le_bytes());
+        hex(&values)
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Step | StopReason::Limit => "S05".to_string(),
+            StopReason::Breakpoint(addr)
+                if self.hw_breakpoints.contains(&addr) && !self.sw_breakpoints.contains(&addr) =>
+            {
+                "T05hwbreak:;".to_string()
+            }
+            StopReason::Breakpoint(_) => "T05swbreak:;".to_string(),
+            StopReason::Watchpoint { hit, .. } => {
+                let kind = self
+                    .debugger
+                    .watchpoints()
+                    .iter()
+                    .find(|watch| watch.matches(hit.addr, hit.write))
+                    .map(|watch| watch.kind);
+                let name = match kind {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::ReadWrite) => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:x};", name, hit.addr)
+            }
+            StopReason::Interrupted => "S02".to_string(),
+            StopReason::HistoryStart => "T05replaylog:begin;".to_string(),
+            // SIGILL
+            StopReason::InvalidOpcode { .. } => "S04".to_string(),
+        }
+    }
+}
+
+// A Ctrl-C from gdb is a single byte outside of a packet, it sets the interrupt flag instead of
+// being queued behind the packets
+fn forward_input<R: Read>(
+    mut input: R,
+    sender: std::sync::mpsc::Sender<u8>,
+    interrupt: Arc<AtomicBool>,
+) {
+    let mut buffer = [0; 1024];
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(len) => len,
+        };
+        for &byte in &buffer[..len] {
+            if byte == INTERRUPT {
+                interrupt.store(true, Ordering::Relaxed);
+            } else if sender.send(byte).is_err() {
+                return;
+            }
+        }
+    }
+}
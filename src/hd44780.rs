@@ -0,0 +1,257 @@
+// Hitachi HD44780 character LCD controller, as on the common 16x2 modules. It isn't on the cpu
+// bus, a board drives its pins from a port: RS selects instruction or data, RW reads, and the
+// transfer happens on the falling edge of E. With E high and RW high the controller drives the
+// data lines, with the busy flag and the address counter (RS low) or a byte of RAM (RS high).
+//
+// 80 bytes of display RAM hold the text, lines start at $00 and $40 in two line mode. The 64 bytes
+// of character generator RAM define the custom characters 0-7. Both are reached through the one
+// address counter, whichever was set last. In 4 bit mode only D7-D4 are used, high nibble first.
+//
+// Instructions take their time and the controller ignores what comes while it is busy, a program
+// has to poll the busy flag or wait. Times are in cycles of the 1 MHz clock the breadboard
+// computers run at.
+//
+// https://www.sparkfun.com/datasheets/LCD/HD44780.pdf
+
+/// Clear display and return home
+const LONG_CYCLES: u32 = 1520;
+const SHORT_CYCLES: u32 = 37;
+
+#[derive(Debug, Clone)]
+pub struct Hd44780 {
+    ddram: [u8; 0x80],
+    cgram: [u8; 0x40],
+    address: u8,
+    // the address counter points into CGRAM
+    cgram_selected: bool,
+    increment: bool,
+    shift_display: bool,
+    display_on: bool,
+    cursor_on: bool,
+    blink: bool,
+    eight_bit: bool,
+    two_lines: bool,
+    // first visible column, the display shift
+    shift: u8,
+    busy: u32,
+    e: bool,
+    // 4 bit mode: the high nibble of a transfer came already
+    high_nibble: Option<u8>,
+    read_low: bool,
+}
+
+impl Default for Hd44780 {
+    fn default() -> Self {
+        Hd44780::new()
+    }
+}
+
+impl Hd44780 {
+    /// Characters per line
+    pub const LINE_LEN: u8 = 40;
+
+    pub fn new() -> Hd44780 {
+        Hd44780 {
+            ddram: [b' '; 0x80],
+            cgram: [0; 0x40],
+            address: 0,
+            cgram_selected: false,
+            increment: true,
+            shift_display: false,
+            display_on: false,
+            cursor_on: false,
+            blink: false,
+            eight_bit: true,
+            two_lines: false,
+            shift: 0,
+            busy: 0,
+            e: false,
+            high_nibble: None,
+            read_low: false,
+        }
+    }
+
+    /// Sets the control and data lines, a transfer happens when E falls
+    pub fn set_pins(&mut self, rs: bool, rw: bool, e: bool, data: u8) {
+        let falling = self.e && !e;
+        self.e = e;
+        if !falling {
+            return;
+        }
+        if rw {
+            // the read itself happened while E was high, a data read moves the address
+            if (self.eight_bit || self.read_low) && rs && self.busy == 0 {
+                self.advance();
+            }
+            if !self.eight_bit {
+                self.read_low = !self.read_low;
+            }
+            return;
+        }
+        let value = if self.eight_bit {
+            data
+        } else {
+            match self.high_nibble.take() {
+                Some(high) => high | data >> 4,
+                None => {
+                    self.high_nibble = Some(data & 0xF0);
+                    return;
+                }
+            }
+        };
+        if self.busy > 0 {
+            return;
+        }
+        if rs {
+            self.write_data(value);
+        } else {
+            self.instruction(value);
+        }
+    }
+
+    /// What the controller drives on D7-D0, while E and RW are high
+    pub fn data_out(&self, rs: bool) -> u8 {
+        let busy = if self.busy > 0 { 0x80 } else { 0 };
+        let value = if rs { self.read_data() } else { busy | self.address };
+        // D3-D0 aren't driven in 4 bit mode
+        match (self.eight_bit, self.read_low) {
+            (true, _) => value,
+            (false, false) => value & 0xF0,
+            (false, true) => value << 4,
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.busy = self.busy.saturating_sub(cycles);
+    }
+
+    pub fn busy(&self) -> bool {
+        self.busy > 0
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn ddram(&self) -> &[u8] {
+        &self.ddram
+    }
+
+    pub fn cgram(&self) -> &[u8] {
+        &self.cgram
+    }
+
+    pub fn display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Whether the cursor is shown and whether it blinks
+    pub fn cursor(&self) -> (bool, bool) {
+        (self.cursor_on, self.blink)
+    }
+
+    /// The `columns` characters visible on line `line` of the module, the codes from display RAM
+    pub fn line(&self, line: u8, columns: u8) -> Vec<u8> {
+        if !self.display_on || line > 1 || line == 1 && !self.two_lines {
+            return vec![b' '; columns as usize];
+        }
+        let len = if self.two_lines { Hd44780::LINE_LEN } else { 2 * Hd44780::LINE_LEN };
+        (0..columns)
+            .map(|column| self.ddram[(line * 0x40 + (self.shift + column) % len) as usize])
+            .collect()
+    }
+
+    fn read_data(&self) -> u8 {
+        if self.cgram_selected {
+            self.cgram[(self.address & 0x3F) as usize]
+        } else {
+            self.ddram[self.address as usize]
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        if self.cgram_selected {
+            self.cgram[(self.address & 0x3F) as usize] = value;
+        } else {
+            self.ddram[self.address as usize] = value;
+            if self.shift_display {
+                self.shift_by(self.increment);
+            }
+        }
+        self.advance();
+        self.busy = SHORT_CYCLES;
+    }
+
+    // Moves the address counter on, display RAM wraps from the end of one line to the start of
+    // the other
+    fn advance(&mut self) {
+        self.move_address(self.increment);
+    }
+
+    fn move_address(&mut self, forward: bool) {
+        if self.cgram_selected {
+            let address = if forward { self.address + 1 } else { self.address.wrapping_sub(1) };
+            self.address = address & 0x3F;
+            return;
+        }
+        self.address = match (self.two_lines, forward, self.address) {
+            (true, true, 0x27) => 0x40,
+            (true, true, 0x67) => 0x00,
+            (true, false, 0x00) => 0x67,
+            (true, false, 0x40) => 0x27,
+            (false, true, 0x4F) => 0x00,
+            (false, false, 0x00) => 0x4F,
+            // addresses past the end of a line aren't shown, the counter has 7 bits
+            (_, true, address) => (address + 1) & 0x7F,
+            (_, false, address) => address.wrapping_sub(1) & 0x7F,
+        };
+    }
+
+    // a shift to the left moves the text left, the first visible column goes up
+    fn shift_by(&mut self, left: bool) {
+        let len = if self.two_lines { Hd44780::LINE_LEN } else { 2 * Hd44780::LINE_LEN };
+        self.shift = if left { (self.shift + 1) % len } else { (self.shift + len - 1) % len };
+    }
+
+    fn instruction(&mut self, value: u8) {
+        self.busy = SHORT_CYCLES;
+        if value & 0x80 != 0 {
+            self.address = value & 0x7F;
+            self.cgram_selected = false;
+        } else if value & 0x40 != 0 {
+            self.address = value & 0x3F;
+            self.cgram_selected = true;
+        } else if value & 0x20 != 0 {
+            self.eight_bit = value & 0x10 != 0;
+            self.two_lines = value & 0x08 != 0;
+            self.high_nibble = None;
+            self.read_low = false;
+        } else if value & 0x10 != 0 {
+            let right = value & 0x04 != 0;
+            if value & 0x08 != 0 {
+                self.shift_by(!right);
+            } else {
+                self.move_address(right);
+            }
+        } else if value & 0x08 != 0 {
+            self.display_on = value & 0x04 != 0;
+            self.cursor_on = value & 0x02 != 0;
+            self.blink = value & 0x01 != 0;
+        } else if value & 0x04 != 0 {
+            self.increment = value & 0x02 != 0;
+            self.shift_display = value & 0x01 != 0;
+        } else if value & 0x02 != 0 {
+            self.address = 0;
+            self.cgram_selected = false;
+            self.shift = 0;
+            self.busy = LONG_CYCLES;
+        } else if value & 0x01 != 0 {
+            self.ddram = [b' '; 0x80];
+            self.address = 0;
+            self.cgram_selected = false;
+            self.increment = true;
+            self.shift = 0;
+            self.busy = LONG_CYCLES;
+        }
+    }
+}
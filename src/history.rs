@@ -0,0 +1,95 @@
+// Checkpoints for reverse execution. The debugger counts the instructions it runs and keeps a
+// snapshot of the cpu every `interval` of them, going back means restoring the last checkpoint
+// before the target and running forward to it. That only works because the cpu is deterministic,
+// so the debugger takes a new checkpoint whenever a frontend changes registers or memory.
+//
+// When there are too many checkpoints every other one is dropped and the interval doubles, the
+// history goes back to the first checkpoint but replays get longer.
+
+use crate::{Cpu, Snapshot};
+
+/// 64K of memory each
+const MAX_CHECKPOINTS: usize = 256;
+const DEFAULT_INTERVAL: u64 = 10_000;
+
+pub struct History {
+    // ordered by position
+    checkpoints: Vec<(u64, Snapshot)>,
+    interval: u64,
+    position: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::with_interval(DEFAULT_INTERVAL)
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn with_interval(interval: u64) -> History {
+        History { checkpoints: Vec::new(), interval: interval.max(1), position: 0 }
+    }
+
+    /// Instructions run since the history started
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The oldest position that can be gone back to
+    pub fn start(&self) -> u64 {
+        self.checkpoints.first().map_or(self.position, |(position, _)| *position)
+    }
+
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Called before each instruction the debugger runs
+    pub fn record(&mut self, cpu: &Cpu) {
+        // after going back the checkpoints ahead still hold, the same instructions run again
+        let due = match self.checkpoints.last() {
+            Some((last, _)) => *last <= self.position && self.position - last >= self.interval,
+            None => true,
+        };
+        if due {
+            self.push(cpu);
+        }
+        self.position += 1;
+    }
+
+    /// Starts over from the current state of `cpu`, for changes that didn't come from running
+    /// instructions. Checkpoints at and after the current position are dropped, the ones before
+    /// it still lead up to it
+    pub fn checkpoint(&mut self, cpu: &Cpu) {
+        let position = self.position;
+        self.checkpoints.retain(|(p, _)| *p < position);
+        self.push(cpu);
+    }
+
+    fn push(&mut self, cpu: &Cpu) {
+        self.checkpoints.push((self.position, cpu.snapshot()));
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            let mut index = 0;
+            self.checkpoints.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            self.interval *= 2;
+        }
+    }
+
+    /// The last checkpoint at or before `position`
+    pub(crate) fn checkpoint_at(&self, position: u64) -> Option<(u64, &Snapshot)> {
+        let index = self.checkpoints.iter().rposition(|(p, _)| *p <= position)?;
+        let (p, snapshot) = &self.checkpoints[index];
+        Some((*p, snapshot))
+    }
+
+    pub(crate) fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+}
@@ -0,0 +1,218 @@
+// KIM-1: 1K of RAM from $0000 and two 6530 RRIOTs. The 6530-003 has its I/O and timer at $1700
+// and the tape routines in ROM at $1800, the 6530-002 its I/O and timer at $1740 and the monitor
+// in ROM at $1C00. Their 64 bytes of RAM each are at $1780 and $17C0. The ROMs aren't included,
+// `setup` takes them from the user, both in a 2K image or the monitor alone in 1K.
+//
+// Only A0-A12 are decoded, so the board repeats every 8K and the cpu finds the vectors at $FFFA in
+// the copy of the monitor at $FC00. `setup` puts a copy of the ROMs there.
+//
+// `Tty` is the 6530-002 with the teletype on it, the keypad and the LED display aren't emulated.
+// The jumper for TTY mode pulls PA0 low. The monitor sends and receives the serial bits itself:
+// input on PA7, output on PB0, both high while idle. The input loops through to the output, so
+// the teletype prints what is typed without the program echoing it, unless PB5 is an output and
+// high. Bytes from the host go in at 1200 baud. After a reset the monitor measures the baud rate
+// on a RUBOUT, `Tty` types that one itself.
+//
+// http://www.6502.org/trainers/buildkim/kim.htm
+
+use crate::device::Device;
+use crate::loader::{self, LoadError};
+use crate::rriot::Rriot;
+use crate::serial::{Host, Link, Serial};
+use crate::Cpu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The 6530-003's I/O and timer
+pub const RRIOT_003_ADDR: u16 = 0x1700;
+/// The 6530-002's, with the teletype on it
+pub const RRIOT_002_ADDR: u16 = 0x1740;
+/// The tape routines, the monitor follows at $1C00
+pub const ROM_ADDR: u16 = 0x1800;
+pub const ROM_END: u16 = 0x1FFF;
+
+/// 1200 baud at 1 MHz
+const BIT_CYCLES: u32 = 833;
+/// The monitor sets up its ports before it waits for the RUBOUT
+const RESET_CYCLES: u32 = 10_000;
+const RUBOUT: u8 = 0x7F;
+
+// port bits
+const PA_TTY: u8 = 0x01;
+const PA_IN: u8 = 0x80;
+const PB_OUT: u8 = 0x01;
+const PB_NO_ECHO: u8 = 0x20;
+
+#[derive(Clone)]
+pub struct Tty {
+    rriot: Rriot,
+    link: Link,
+    // cycles before the input line can be used
+    quiet: u32,
+    rubout: bool,
+    // the character going out on the input line, start bit first, with the bits and the cycles
+    // of the current one left
+    rx_bits: u16,
+    rx_count: u8,
+    rx_cycles: u32,
+    // the output line in the last cycle
+    line: bool,
+    // the character coming in on the output line: bits so far, the bits and the cycles to the
+    // next sample
+    tx: Option<(u8, u8, u32)>,
+}
+
+impl Default for Tty {
+    fn default() -> Self {
+        Tty::new()
+    }
+}
+
+impl Tty {
+    pub fn new() -> Tty {
+        let mut tty = Tty {
+            rriot: Rriot::new(),
+            link: Link::default(),
+            quiet: RESET_CYCLES,
+            rubout: true,
+            rx_bits: 0,
+            rx_count: 0,
+            rx_cycles: 0,
+            line: true,
+            tx: None,
+        };
+        // the echo stays on while PB5 isn't driven
+        tty.rriot.set_port_b(!PB_NO_ECHO);
+        tty.drive_input();
+        tty
+    }
+
+    pub fn connect<H: Host + 'static>(&mut self, host: H) -> Rc<RefCell<Serial>> {
+        self.link.connect(host)
+    }
+
+    pub fn rriot(&self) -> &Rriot {
+        &self.rriot
+    }
+
+    fn input(&self) -> bool {
+        self.rx_count == 0 || self.rx_bits & 1 != 0
+    }
+
+    // PA0 on the jumper, the other keypad rows not pressed
+    fn drive_input(&mut self) {
+        let pins = !(PA_TTY | PA_IN) | if self.input() { PA_IN } else { 0 };
+        self.rriot.set_port_a(pins);
+    }
+
+    fn output(&self) -> bool {
+        let port = self.rriot.port_b();
+        port & PB_OUT != 0 && (self.input() || port & PB_NO_ECHO != 0)
+    }
+
+    // a start bit, 8 data bits and two stop bits
+    fn type_key(&mut self, byte: u8) {
+        let key = match byte {
+            b'\n' => b'\r',
+            _ => byte.to_ascii_uppercase(),
+        };
+        self.rx_bits = 0x600 | (key as u16) << 1;
+        self.rx_count = 11;
+        self.rx_cycles = BIT_CYCLES;
+    }
+
+    fn cycle(&mut self) {
+        if self.quiet > 0 {
+            self.quiet -= 1;
+        } else if self.rx_count > 0 {
+            self.rx_cycles -= 1;
+            if self.rx_cycles == 0 {
+                self.rx_bits >>= 1;
+                self.rx_count -= 1;
+                self.rx_cycles = BIT_CYCLES;
+                self.drive_input();
+            }
+        }
+        let line = self.output();
+        match &mut self.tx {
+            // start bit, the data bits are sampled in their middle
+            None if self.line && !line => self.tx = Some((0, 0, BIT_CYCLES + BIT_CYCLES / 2)),
+            None => {}
+            Some((bits, byte, cycles)) => {
+                *cycles -= 1;
+                if *cycles == 0 {
+                    if *bits < 8 {
+                        *byte |= (line as u8) << *bits;
+                        *bits += 1;
+                        *cycles = BIT_CYCLES;
+                    } else {
+                        // without the parity bit
+                        let byte = *byte & 0x7F;
+                        self.tx = None;
+                        self.link.send(byte);
+                    }
+                }
+            }
+        }
+        self.line = line;
+    }
+}
+
+impl Device for Tty {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.rriot.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.rriot.write(offset, value);
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.rriot.peek(offset)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.rriot.tick(cycles);
+        self.link.tick(cycles);
+        if self.quiet == 0 && self.rx_count == 0 {
+            if self.rubout {
+                self.rubout = false;
+                self.type_key(RUBOUT);
+            } else if let Some(byte) = self.link.poll() {
+                self.type_key(byte);
+            }
+            self.drive_input();
+        }
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.rriot.irq()
+    }
+
+    fn reset(&mut self) {
+        self.rriot.reset();
+        self.quiet = RESET_CYCLES;
+        self.rubout = true;
+        self.rx_count = 0;
+        self.drive_input();
+    }
+}
+
+/// Loads the ROMs, maps both RRIOTs and resets into the monitor. Returns the index of `tty` in
+/// `Cpu::devices`
+pub fn setup(cpu: &mut Cpu, rom: &[u8], tty: Tty) -> Result<usize, LoadError> {
+    let size = ROM_END - ROM_ADDR + 1;
+    if rom.len() > size as usize {
+        return Err(LoadError::TooLarge { addr: ROM_ADDR, len: rom.len() });
+    }
+    let addr = ROM_END + 1 - rom.len() as u16;
+    loader::load(cpu.mem_mut(), rom, Some(addr))?;
+    loader::load(cpu.mem_mut(), rom, Some(addr | 0xE000))?;
+    cpu.devices.map(RRIOT_003_ADDR..=RRIOT_003_ADDR + 0x3F, Rriot::new());
+    let index = cpu.devices.map(RRIOT_002_ADDR..=RRIOT_002_ADDR + 0x3F, tty);
+    cpu.reset_to_vector();
+    Ok(index)
+}
@@ -7,23 +7,59 @@
 // http://www.righto.com/2012/12/the-6502-overflow-flag-explained.html
 // https://www.middle-engine.com/blog/posts/2020/06/23/programming-the-nes-the-6502-in-detail
 
+pub mod acia;
+pub mod apple1;
+pub mod asm;
+pub mod breadboard;
+pub mod callstack;
+pub mod cdl;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod device;
+pub mod disasm;
+pub mod elf;
+pub mod expr;
+pub mod gdb;
+pub mod hd44780;
+pub mod history;
+pub mod kim1;
+pub mod lines;
+pub mod loader;
+pub mod monitor;
+pub mod opcodes;
+pub mod pia;
+pub mod profile;
+pub mod rriot;
+pub mod serial;
+pub mod sim65;
+pub mod symbols;
+pub mod trace;
+pub mod trap;
+pub mod via;
+pub mod writes;
+
 // No decimal mode here
 
 struct OpImm {
     reg_index: usize,
 }
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct OpRead {
     reg_index: usize,
 }
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct OpReadAnd {
     reg_index: usize,
 }
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct OpReadEor {
     reg_index: usize,
 }
 struct OpReadOra {
     reg_index: usize,
 }
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct OpWrite {
     reg_index: usize,
 }
@@ -82,15 +118,20 @@ impl Operation for OpInv {
 
 struct AddrModeImm;
 struct AddrModeZero;
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct AddrModeZeroX;
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct AddrModeAbs;
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct AddrModeAbsX {
     is_read_op: bool,
 }
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct AddrModeAbsY {
     is_read_op: bool,
 }
 struct AddrModeIndX;
+#[allow(dead_code)] // not wired into INST_VEC yet
 struct AddrModeIndY {
     is_read_op: bool,
 }
@@ -179,11 +220,79 @@ static INST_VEC: [(&dyn AddrMode, &dyn Operation); 7] = [
     (&AddrModeImm, &OpImm { reg_index: Cpu::REG_A }),
 ];
 
+/// The state a program continues from: registers, memory, devices and the shadow call stack. Tracer,
+/// profiler and the other tools aren't part of it
+#[derive(Clone)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub regs: [u8; 5],
+    pub cycles_run: u32,
+    mem: Box<[u8; MEM_SIZE]>,
+    call_stack: Option<callstack::CallStack>,
+    devices: device::Devices,
+}
+
+impl Snapshot {
+    pub fn mem(&self) -> &[u8] {
+        &self.mem[..]
+    }
+}
+
+/// The registers a subroutine returned with from `Cpu::call`, and the cycles it took, the JSR
+/// and RTS included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallResult {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub cycles: u64,
+}
+
+/// A subroutine run with `Cpu::call` didn't return within its cycles. The cpu is left where it
+/// stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallTimeout {
+    pub pc: u16,
+    pub cycles: u64,
+}
+
+impl std::fmt::Display for CallTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "no return after {} cycles, at ${:04X}", self.cycles, self.pc)
+    }
+}
+
+impl std::error::Error for CallTimeout {}
+
 // lifetime anotation <'b>
 pub struct Cpu<'a> {
     pub pc: u16,
     pub regs: [u8; 5],
     pub cycles_run: u32,
+    /// Logs every instruction before it runs, see `trace::Tracer`
+    pub tracer: Option<trace::Tracer>,
+    /// Follows JSR, RTS, interrupts and RTI, see `callstack::CallStack`
+    pub call_stack: Option<callstack::CallStack>,
+    /// Counts the cycles of each instruction, see `profile::Profiler`
+    pub profiler: Option<profile::Profiler>,
+    /// Counts the runs of each instruction and branch, see `coverage::Coverage`
+    pub coverage: Option<coverage::Coverage>,
+    /// Marks the bytes used as code, data and pointers, see `cdl::CodeDataLog`
+    pub cdl: Option<cdl::CodeDataLog>,
+    /// Remembers the last writes to each address, see `writes::WriteHistory`
+    pub write_history: Option<writes::WriteHistory>,
+    /// Peripherals mapped into the address space, see `device::Devices`
+    pub devices: device::Devices,
+    /// Host routines run in place of the code at their address, see `trap::Traps`
+    pub traps: trap::Traps,
+    // the instruction being run, for the write history
+    instruction_pc: u16,
+    // checked on data reads and writes, see `debugger::Debugger`
+    pub(crate) watchpoints: Vec<debugger::Watchpoint>,
+    pub(crate) watch_hit: Option<debugger::WatchHit>,
+    // the opcode of the last instruction when it wasn't a 6502 one
+    pub(crate) invalid_op: Option<u8>,
     mem: &'a mut Mem,
 }
 
@@ -385,8 +494,30 @@ impl<'a> Cpu<'a> {
     pub const IRQ_INTERRUPT_VECTOR_ADDR: u16 = 0xFFFE;
     pub const NMI_INTERRUPT_VECTOR_ADDR: u16 = 0xFFFA;
 
+    /// Where `call` has the subroutine return to
+    pub const CALL_SENTINEL: u16 = 0xFFFF;
+    /// The cycles `call` gives a subroutine, 10 seconds at 1 MHz
+    pub const CALL_CYCLES: u64 = 10_000_000;
+
     pub fn new(mem: &'a mut Mem) -> Self {
-        Cpu { pc: 0, regs: [0; 5], cycles_run: 0, mem }
+        Cpu {
+            pc: 0,
+            regs: [0; 5],
+            cycles_run: 0,
+            tracer: None,
+            call_stack: None,
+            profiler: None,
+            coverage: None,
+            cdl: None,
+            write_history: None,
+            devices: device::Devices::new(),
+            traps: trap::Traps::new(),
+            instruction_pc: 0,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            invalid_op: None,
+            mem,
+        }
     }
     pub fn reset(&mut self) {
         self.pc = (self.mem.read8(RESET_VECTOR_ADDR) as u16) << 8
@@ -394,13 +525,103 @@ impl<'a> Cpu<'a> {
         self.regs = [0; 5];
         self.regs[Cpu::REG_SP] = STACK_OFFSET_START;
         self.cycles_run = 0;
+        self.devices.reset();
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.clear();
+        }
+    }
+
+    /// Resets and starts at the reset vector the way ROMs store it, low byte first. `reset` reads
+    /// it in the order `Mem::reset` stores it in
+    pub fn reset_to_vector(&mut self) {
+        self.reset();
+        self.pc = self.mem.peek16(RESET_VECTOR_ADDR as u16);
+    }
+
+    pub fn mem(&self) -> &Mem {
+        self.mem
+    }
+
+    /// Returns from the subroutine the way an RTS at the pc would, for routines the host runs in
+    /// place of 6502 code. Takes no cycles
+    pub fn return_from_subroutine(&mut self) {
+        let (pc, cycles) = (self.pc, self.cycles_run);
+        self.pc = self.read_from_stack_16().wrapping_add(1);
+        self.cycles_run = cycles;
+        self.pop_frame(pc, false);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            regs: self.regs,
+            cycles_run: self.cycles_run,
+            mem: Box::new(self.mem.mem),
+            call_stack: self.call_stack.clone(),
+            devices: self.devices.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.pc = snapshot.pc;
+        self.regs = snapshot.regs;
+        self.cycles_run = snapshot.cycles_run;
+        self.mem.mem = *snapshot.mem;
+        self.call_stack = snapshot.call_stack.clone();
+        self.devices = snapshot.devices.clone();
+        self.watch_hit = None;
+        self.invalid_op = None;
+    }
+
+    pub fn mem_mut(&mut self) -> &mut Mem {
+        self.mem
+    }
+
+    fn check_watchpoints(&mut self, addr: u16, val: u8, write: bool) {
+        if self.watch_hit.is_none()
+            && self.watchpoints.iter().any(|watch| watch.matches(addr, write))
+        {
+            self.watch_hit = Some(debugger::WatchHit { addr, value: val, write });
+        }
+    }
+
+    // `sp` is the stack pointer before the return address was pushed
+    fn push_frame(&mut self, kind: callstack::FrameKind, caller: u16, return_addr: u16, sp: u8) {
+        if let Some(call_stack) = &mut self.call_stack {
+            let target = self.pc;
+            let cycles = self.cycles_run;
+            call_stack.push(callstack::Frame { kind, caller, target, return_addr, sp, cycles });
+        }
+    }
+
+    fn pop_frame(&mut self, pc: u16, interrupt: bool) {
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.ret(pc, self.pc, self.regs[Cpu::REG_SP], interrupt);
+        }
     }
 
     // The methods below cost some cycles to run.
     // Try to use them when processing instructions instead of incrementing the cycles counter on each instruction
+    // Reads and writes go to the device mapped at the address, memory if there is none
+    fn bus_read(&mut self, addr: u16) -> u8 {
+        match self.devices.read(addr, self.cycles_run) {
+            Some(val) => val,
+            None => self.mem.read8(addr as usize),
+        }
+    }
+
+    fn bus_write(&mut self, addr: u16, val: u8) {
+        if !self.devices.write(addr, val, self.cycles_run) {
+            self.mem.write8(addr as usize, val);
+        }
+    }
+
     fn read8(&mut self, addr: u16) -> u8 {
-        let val = self.mem.read8(addr as usize);
+        let val = self.bus_read(addr);
         self.cycles_run += 1;
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, false);
+        }
         val
     }
 
@@ -411,8 +632,16 @@ impl<'a> Cpu<'a> {
     }
 
     fn write8(&mut self, addr: u16, val: u8) {
-        self.mem.write8(addr as usize, val);
+        self.bus_write(addr, val);
         self.cycles_run += 1;
+        if let Some(history) = &mut self.write_history {
+            let write =
+                writes::Write { pc: self.instruction_pc, cycle: self.cycles_run, value: val };
+            history.record(addr, write);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, true);
+        }
     }
 
     fn write_to_stack(&mut self, val: u8) {
@@ -437,9 +666,11 @@ impl<'a> Cpu<'a> {
         val
     }
 
+    // Instruction fetches don't trigger read watchpoints
     fn read_pc(&mut self) -> u8 {
-        let val = self.read8(self.pc);
-        self.pc += 1;
+        let val = self.bus_read(self.pc);
+        self.cycles_run += 1;
+        self.pc = self.pc.wrapping_add(1);
         val
     }
 
@@ -615,788 +846,933 @@ impl<'a> Cpu<'a> {
 
     pub fn process(&mut self, cycles: u32) {
         let init_cycles = self.cycles_run;
-        // TODO: For testing only
-        println!("PC {:X}", self.pc);
         loop {
-            let instruction = self.read_pc();
-            match instruction {
-                Cpu::LDA_IMMEDIATE => {
-                    self.regs[Cpu::REG_A] = self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_A] = self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDA_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    self.regs[Cpu::REG_A] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LDX_IMMEDIATE => {
-                    self.regs[Cpu::REG_X] = self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::LDX_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_X] = self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::LDX_ZERO_Y => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_Y]);
-                    self.regs[Cpu::REG_X] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::LDX_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_X] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::LDX_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    self.regs[Cpu::REG_X] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::LDY_IMMEDIATE => {
-                    self.regs[Cpu::REG_Y] = self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::LDY_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_Y] = self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::LDY_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.regs[Cpu::REG_Y] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::LDY_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_Y] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::LDY_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    self.regs[Cpu::REG_Y] = self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::STA_ZERO => {
-                    let addr = self.read_pc();
-                    self.write8(addr as u16, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], false);
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STA_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(false);
-                    self.write8(addr, self.regs[Cpu::REG_A]);
-                }
-                Cpu::STX_ZERO => {
-                    let addr = self.read_pc();
-                    self.write8(addr as u16, self.regs[Cpu::REG_X]);
-                }
-                Cpu::STX_ZERO_Y => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_Y]);
-                    self.write8(addr, self.regs[Cpu::REG_X]);
-                }
-                Cpu::STX_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.write8(addr, self.regs[Cpu::REG_X]);
-                }
-                Cpu::STY_ZERO => {
-                    let addr = self.read_pc();
-                    self.write8(addr as u16, self.regs[Cpu::REG_Y]);
-                }
-                Cpu::STY_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.write8(addr, self.regs[Cpu::REG_Y]);
-                }
-                Cpu::STY_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.write8(addr, self.regs[Cpu::REG_Y]);
-                }
-                Cpu::TRANS_A_TO_X => {
-                    self.regs[Cpu::REG_X] = self.regs[Cpu::REG_A];
-                    self.cycles_run += 1;
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::TRANS_A_TO_Y => {
-                    self.regs[Cpu::REG_Y] = self.regs[Cpu::REG_A];
-                    self.cycles_run += 1;
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::TRANS_X_TO_A => {
-                    self.regs[Cpu::REG_A] = self.regs[Cpu::REG_X];
-                    self.cycles_run += 1;
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::TRANS_Y_TO_A => {
-                    self.regs[Cpu::REG_A] = self.regs[Cpu::REG_Y];
-                    self.cycles_run += 1;
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::TRANS_SP_TO_X => {
-                    self.regs[Cpu::REG_X] = self.regs[Cpu::REG_SP];
-                    self.cycles_run += 1;
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::TRANS_X_TO_SP => {
-                    self.regs[Cpu::REG_SP] = self.regs[Cpu::REG_X];
-                    self.cycles_run += 1;
-                }
-                Cpu::PUSH_A_TO_SP => {
-                    self.write_to_stack(self.regs[Cpu::REG_A]);
-                    self.cycles_run += 1;
-                }
-                Cpu::PUSH_STAT_TO_SP => {
-                    self.write_to_stack(self.regs[Cpu::REG_STAT]);
-                    self.cycles_run += 1;
-                }
-                Cpu::PULL_SP_TO_A => {
-                    self.regs[Cpu::REG_A] = self.read_from_stack();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                    self.cycles_run += 2;
-                }
-                Cpu::PULL_SP_TO_STAT => {
-                    self.regs[Cpu::REG_STAT] = self.read_from_stack();
-                    self.cycles_run += 2;
-                }
-                Cpu::AND_IMMEDIATE => {
-                    self.regs[Cpu::REG_A] &= self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_A] &= self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::AND_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    self.regs[Cpu::REG_A] &= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
+            self.step();
+            if cycles > 0 && self.cycles_run - init_cycles >= cycles {
+                break;
+            }
+        }
+    }
 
-                Cpu::EOR_IMMEDIATE => {
-                    self.regs[Cpu::REG_A] ^= self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_A] ^= self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::EOR_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    self.regs[Cpu::REG_A] ^= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_IMMEDIATE => {
-                    self.regs[Cpu::REG_A] |= self.read_pc();
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_ZERO => {
-                    let addr = self.read_pc();
-                    self.regs[Cpu::REG_A] |= self.read8(addr as u16);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ORA_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    self.regs[Cpu::REG_A] |= self.read8(addr);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::BIT_TEST_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let masked = self.regs[Cpu::REG_A] & val;
-                    if masked == 0 {
-                        self.regs[Cpu::REG_STAT] |= Cpu::FLAG_ZERO;
-                    } else {
-                        self.regs[Cpu::REG_STAT] &= !Cpu::FLAG_ZERO;
-                    }
-                    self.regs[Cpu::REG_STAT] &= !(Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
-                    self.regs[Cpu::REG_STAT] |= val & (Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
-                }
-                Cpu::BIT_TEST_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr);
-                    let masked = self.regs[Cpu::REG_A] & val;
-                    if masked == 0 {
-                        self.regs[Cpu::REG_STAT] |= Cpu::FLAG_ZERO;
-                    } else {
-                        self.regs[Cpu::REG_STAT] &= !Cpu::FLAG_ZERO;
-                    }
-                    self.regs[Cpu::REG_STAT] &= !(Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
-                    self.regs[Cpu::REG_STAT] |= val & (Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
-                }
-                Cpu::ADC_IMMEDIATE => {
-                    let val = self.read_pc();
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ADC_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    let val = self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_IMMEDIATE => {
-                    let val = 255 - self.read_pc();
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_ZERO => {
-                    let addr = self.read_pc();
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::SBC_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    let val = 255 - self.read8(addr as u16);
-                    self.regs[Cpu::REG_A] = self.adc(val);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::CMP_IMMEDIATE => {
-                    let val = self.read_pc();
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_ABSOLUTE_Y => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_INDIRECT_X => {
-                    let addr = self.fetch_indirect_x_addr();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CMP_INDIRECT_Y => {
-                    let addr = self.fetch_indirect_y_addr(true);
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_A, val);
-                }
-                Cpu::CPX_IMMEDIATE => {
-                    let val = self.read_pc();
-                    self.set_compare_flags(Cpu::REG_X, val);
-                }
-                Cpu::CPX_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_X, val);
-                }
-                Cpu::CPX_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_X, val);
-                }
-                Cpu::CPY_IMMEDIATE => {
-                    let val = self.read_pc();
-                    self.set_compare_flags(Cpu::REG_Y, val);
-                }
-                Cpu::CPY_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_Y, val);
-                }
-                Cpu::CPY_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    self.set_compare_flags(Cpu::REG_Y, val);
-                }
-                Cpu::INC_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sum(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::INC_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sum(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::INC_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sum(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::INC_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sum(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::INX_IMPLIED => {
-                    self.regs[Cpu::REG_X] = self.sum(self.regs[Cpu::REG_X], 1);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::INY_IMPLIED => {
-                    self.regs[Cpu::REG_Y] = self.sum(self.regs[Cpu::REG_Y], 1);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::DEC_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sub(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::DEC_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sub(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::DEC_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sub(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::DEC_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let inc_val = self.sub(val, 1);
-                    self.write8(addr as u16, inc_val);
-                    self.set_zero_negative_flags(inc_val);
-                }
-                Cpu::DEX_IMPLIED => {
-                    self.regs[Cpu::REG_X] = self.sub(self.regs[Cpu::REG_X], 1);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
-                }
-                Cpu::DEY_IMPLIED => {
-                    self.regs[Cpu::REG_Y] = self.sub(self.regs[Cpu::REG_Y], 1);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
-                }
-                Cpu::ASL_IMPLIED => {
-                    self.regs[Cpu::REG_A] = self.shift_left(self.regs[Cpu::REG_A]);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ASL_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ASL_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ASL_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ASL_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::LSR_IMPLIED => {
-                    self.regs[Cpu::REG_A] = self.shift_right(self.regs[Cpu::REG_A]);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::LSR_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::LSR_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::LSR_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::LSR_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.shift_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROL_IMPLIED => {
-                    self.regs[Cpu::REG_A] = self.rotate_left(self.regs[Cpu::REG_A]);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ROL_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROL_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROL_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROL_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_left(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROR_IMPLIED => {
-                    self.regs[Cpu::REG_A] = self.rotate_right(self.regs[Cpu::REG_A]);
-                    self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
-                }
-                Cpu::ROR_ZERO => {
-                    let addr = self.read_pc();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROR_ZERO_X => {
-                    let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROR_ABSOLUTE => {
-                    let addr = self.fetch_absolute_addr();
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::ROR_ABSOLUTE_X => {
-                    let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
-                    let val = self.read8(addr as u16);
-                    let shift_val = self.rotate_right(val);
-                    self.write8(addr as u16, shift_val);
-                    self.set_zero_negative_flags(shift_val);
-                }
-                Cpu::BCC_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_CARRY == 0 {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BCS_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_CARRY == Cpu::FLAG_CARRY {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BEQ_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_ZERO == Cpu::FLAG_ZERO {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BMI_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_NEGATIVE == Cpu::FLAG_NEGATIVE {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BNE_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_ZERO == 0 {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BPL_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_NEGATIVE == 0 {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BVC_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_OVERFLOW == 0 {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::BVS_RELATIVE => {
-                    let offset = self.read_pc();
-                    if self.regs[Cpu::REG_STAT] & Cpu::FLAG_OVERFLOW == Cpu::FLAG_OVERFLOW {
-                        self.branch(offset as i8);
-                    }
-                }
-                Cpu::CLC_IMPLIED => {
-                    self.clear_status_flag(Cpu::FLAG_CARRY);
-                }
-                Cpu::CLD_IMPLIED => {
-                    self.clear_status_flag(Cpu::FLAG_DECIMAL);
-                }
-                Cpu::CLI_IMPLIED => {
-                    self.clear_status_flag(Cpu::FLAG_INTERRUPT);
+    /// Calls the subroutine at `addr` with the registers set to `a`, `x` and `y` and runs it until
+    /// it returns, for at most `Cpu::CALL_CYCLES`. See `call_with_limit`
+    pub fn call(&mut self, addr: u16, a: u8, x: u8, y: u8) -> Result<CallResult, CallTimeout> {
+        self.call_with_limit(addr, a, x, y, Cpu::CALL_CYCLES)
+    }
+
+    /// Calls the subroutine at `addr` as a JSR from the pc would, with a return address of
+    /// `Cpu::CALL_SENTINEL`, and runs it until its RTS gets there with the stack pointer back
+    /// where it was. The pc goes back to where it was then. Devices, traps and the tools run as
+    /// usual. Getting to the sentinel with the stack pointer off stops it like a timeout, at the
+    /// sentinel
+    pub fn call_with_limit(
+        &mut self,
+        addr: u16,
+        a: u8,
+        x: u8,
+        y: u8,
+        max_cycles: u64,
+    ) -> Result<CallResult, CallTimeout> {
+        let (pc, sp) = (self.pc, self.regs[Cpu::REG_SP]);
+        self.regs[Cpu::REG_A] = a;
+        self.regs[Cpu::REG_X] = x;
+        self.regs[Cpu::REG_Y] = y;
+        let before = self.cycles_run;
+        self.write_to_stack_16(Cpu::CALL_SENTINEL.wrapping_sub(1));
+        self.cycles_run = before.wrapping_add(6);
+        self.pc = addr;
+        self.push_frame(callstack::FrameKind::Call, pc, Cpu::CALL_SENTINEL, sp);
+        let mut cycles = 6u64;
+        while self.pc != Cpu::CALL_SENTINEL || self.regs[Cpu::REG_SP] != sp {
+            if cycles >= max_cycles || self.pc == Cpu::CALL_SENTINEL {
+                return Err(CallTimeout { pc: self.pc, cycles });
+            }
+            let before = self.cycles_run;
+            self.step();
+            cycles += self.cycles_run.wrapping_sub(before) as u64;
+        }
+        self.pc = pc;
+        Ok(CallResult {
+            a: self.regs[Cpu::REG_A],
+            x: self.regs[Cpu::REG_X],
+            y: self.regs[Cpu::REG_Y],
+            status: self.regs[Cpu::REG_STAT],
+            cycles,
+        })
+    }
+
+    /// Runs a single instruction, or the trap at the pc and its return
+    pub fn step(&mut self) {
+        if !self.traps.is_empty() && self.traps.contains(self.pc) {
+            self.run_trap();
+            return;
+        }
+        if let Some(mut tracer) = self.tracer.take() {
+            // a failing writer turns tracing off
+            if tracer.trace(self).is_ok() {
+                self.tracer = Some(tracer);
+            }
+        }
+        if let Some(mut cdl) = self.cdl.take() {
+            cdl.record(self);
+            self.cdl = Some(cdl);
+        }
+        let (pc, cycles) = (self.pc, self.cycles_run);
+        self.instruction_pc = pc;
+        self.execute_instruction();
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.record(self, pc, cycles);
+            self.profiler = Some(profiler);
+        }
+        if let Some(mut coverage) = self.coverage.take() {
+            coverage.record(self, pc);
+            self.coverage = Some(coverage);
+        }
+        if !self.devices.is_empty() {
+            self.devices.sync(self.cycles_run);
+            self.poll_interrupts();
+        }
+    }
+
+    fn run_trap(&mut self) {
+        let pc = self.pc;
+        if let Some(mut trap) = self.traps.take(pc) {
+            trap(self);
+            self.traps.put_back(pc, trap);
+            self.return_from_subroutine();
+        }
+    }
+
+    // Interrupts are taken between instructions, NMI on the edge and IRQ while the line is low
+    // and the I flag is clear
+    fn poll_interrupts(&mut self) {
+        if self.devices.nmi_edge() {
+            self.interrupt(Cpu::NMI_INTERRUPT_VECTOR_ADDR, callstack::FrameKind::Nmi);
+        } else if self.devices.irq() && self.regs[Cpu::REG_STAT] & Cpu::FLAG_INTERRUPT == 0 {
+            self.interrupt(Cpu::IRQ_INTERRUPT_VECTOR_ADDR, callstack::FrameKind::Irq);
+        }
+    }
+
+    // Same as BRK without the break flag, the pc pushed is the next instruction
+    fn interrupt(&mut self, vector: u16, kind: callstack::FrameKind) {
+        let (return_addr, sp) = (self.pc, self.regs[Cpu::REG_SP]);
+        self.cycles_run += 2;
+        self.write_to_stack_16(return_addr);
+        self.write_to_stack(self.regs[Cpu::REG_STAT] & !Cpu::FLAG_BREAK);
+        self.regs[Cpu::REG_STAT] |= Cpu::FLAG_INTERRUPT;
+        self.pc = self.read16(vector);
+        self.push_frame(kind, return_addr, return_addr, sp);
+        self.devices.sync(self.cycles_run);
+    }
+
+    fn execute_instruction(&mut self) {
+        let instruction = self.read_pc();
+        match instruction {
+            Cpu::LDA_IMMEDIATE => {
+                self.regs[Cpu::REG_A] = self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_A] = self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDA_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                self.regs[Cpu::REG_A] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LDX_IMMEDIATE => {
+                self.regs[Cpu::REG_X] = self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::LDX_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_X] = self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::LDX_ZERO_Y => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_Y]);
+                self.regs[Cpu::REG_X] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::LDX_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_X] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::LDX_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                self.regs[Cpu::REG_X] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::LDY_IMMEDIATE => {
+                self.regs[Cpu::REG_Y] = self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::LDY_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_Y] = self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::LDY_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.regs[Cpu::REG_Y] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::LDY_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_Y] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::LDY_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                self.regs[Cpu::REG_Y] = self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::STA_ZERO => {
+                let addr = self.read_pc();
+                self.write8(addr as u16, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], false);
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STA_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(false);
+                self.write8(addr, self.regs[Cpu::REG_A]);
+            }
+            Cpu::STX_ZERO => {
+                let addr = self.read_pc();
+                self.write8(addr as u16, self.regs[Cpu::REG_X]);
+            }
+            Cpu::STX_ZERO_Y => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_Y]);
+                self.write8(addr, self.regs[Cpu::REG_X]);
+            }
+            Cpu::STX_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.write8(addr, self.regs[Cpu::REG_X]);
+            }
+            Cpu::STY_ZERO => {
+                let addr = self.read_pc();
+                self.write8(addr as u16, self.regs[Cpu::REG_Y]);
+            }
+            Cpu::STY_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.write8(addr, self.regs[Cpu::REG_Y]);
+            }
+            Cpu::STY_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.write8(addr, self.regs[Cpu::REG_Y]);
+            }
+            Cpu::TRANS_A_TO_X => {
+                self.regs[Cpu::REG_X] = self.regs[Cpu::REG_A];
+                self.cycles_run += 1;
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::TRANS_A_TO_Y => {
+                self.regs[Cpu::REG_Y] = self.regs[Cpu::REG_A];
+                self.cycles_run += 1;
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::TRANS_X_TO_A => {
+                self.regs[Cpu::REG_A] = self.regs[Cpu::REG_X];
+                self.cycles_run += 1;
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::TRANS_Y_TO_A => {
+                self.regs[Cpu::REG_A] = self.regs[Cpu::REG_Y];
+                self.cycles_run += 1;
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::TRANS_SP_TO_X => {
+                self.regs[Cpu::REG_X] = self.regs[Cpu::REG_SP];
+                self.cycles_run += 1;
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::TRANS_X_TO_SP => {
+                self.regs[Cpu::REG_SP] = self.regs[Cpu::REG_X];
+                self.cycles_run += 1;
+            }
+            Cpu::PUSH_A_TO_SP => {
+                self.write_to_stack(self.regs[Cpu::REG_A]);
+                self.cycles_run += 1;
+            }
+            Cpu::PUSH_STAT_TO_SP => {
+                self.write_to_stack(self.regs[Cpu::REG_STAT]);
+                self.cycles_run += 1;
+            }
+            Cpu::PULL_SP_TO_A => {
+                self.regs[Cpu::REG_A] = self.read_from_stack();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+                self.cycles_run += 2;
+            }
+            Cpu::PULL_SP_TO_STAT => {
+                self.regs[Cpu::REG_STAT] = self.read_from_stack();
+                self.cycles_run += 2;
+            }
+            Cpu::AND_IMMEDIATE => {
+                self.regs[Cpu::REG_A] &= self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_A] &= self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::AND_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                self.regs[Cpu::REG_A] &= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+
+            Cpu::EOR_IMMEDIATE => {
+                self.regs[Cpu::REG_A] ^= self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_A] ^= self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::EOR_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                self.regs[Cpu::REG_A] ^= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_IMMEDIATE => {
+                self.regs[Cpu::REG_A] |= self.read_pc();
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_ZERO => {
+                let addr = self.read_pc();
+                self.regs[Cpu::REG_A] |= self.read8(addr as u16);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ORA_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                self.regs[Cpu::REG_A] |= self.read8(addr);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::BIT_TEST_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let masked = self.regs[Cpu::REG_A] & val;
+                if masked == 0 {
+                    self.regs[Cpu::REG_STAT] |= Cpu::FLAG_ZERO;
+                } else {
+                    self.regs[Cpu::REG_STAT] &= !Cpu::FLAG_ZERO;
+                }
+                self.regs[Cpu::REG_STAT] &= !(Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
+                self.regs[Cpu::REG_STAT] |= val & (Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
+            }
+            Cpu::BIT_TEST_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let masked = self.regs[Cpu::REG_A] & val;
+                if masked == 0 {
+                    self.regs[Cpu::REG_STAT] |= Cpu::FLAG_ZERO;
+                } else {
+                    self.regs[Cpu::REG_STAT] &= !Cpu::FLAG_ZERO;
+                }
+                self.regs[Cpu::REG_STAT] &= !(Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
+                self.regs[Cpu::REG_STAT] |= val & (Cpu::FLAG_NEGATIVE | Cpu::FLAG_OVERFLOW);
+            }
+            Cpu::ADC_IMMEDIATE => {
+                let val = self.read_pc();
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ADC_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                let val = self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_IMMEDIATE => {
+                let val = 255 - self.read_pc();
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_ZERO => {
+                let addr = self.read_pc();
+                let val = 255 - self.read8(addr as u16);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::SBC_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                let val = 255 - self.read8(addr);
+                self.regs[Cpu::REG_A] = self.adc(val);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::CMP_IMMEDIATE => {
+                let val = self.read_pc();
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], true);
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_ABSOLUTE_Y => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_Y], true);
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_INDIRECT_X => {
+                let addr = self.fetch_indirect_x_addr();
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CMP_INDIRECT_Y => {
+                let addr = self.fetch_indirect_y_addr(true);
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_A, val);
+            }
+            Cpu::CPX_IMMEDIATE => {
+                let val = self.read_pc();
+                self.set_compare_flags(Cpu::REG_X, val);
+            }
+            Cpu::CPX_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                self.set_compare_flags(Cpu::REG_X, val);
+            }
+            Cpu::CPX_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_X, val);
+            }
+            Cpu::CPY_IMMEDIATE => {
+                let val = self.read_pc();
+                self.set_compare_flags(Cpu::REG_Y, val);
+            }
+            Cpu::CPY_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                self.set_compare_flags(Cpu::REG_Y, val);
+            }
+            Cpu::CPY_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                self.set_compare_flags(Cpu::REG_Y, val);
+            }
+            Cpu::INC_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let inc_val = self.sum(val, 1);
+                self.write8(addr as u16, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::INC_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let inc_val = self.sum(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::INC_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let inc_val = self.sum(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::INC_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let inc_val = self.sum(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::INX_IMPLIED => {
+                self.regs[Cpu::REG_X] = self.sum(self.regs[Cpu::REG_X], 1);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::INY_IMPLIED => {
+                self.regs[Cpu::REG_Y] = self.sum(self.regs[Cpu::REG_Y], 1);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::DEC_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let inc_val = self.sub(val, 1);
+                self.write8(addr as u16, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::DEC_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let inc_val = self.sub(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::DEC_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let inc_val = self.sub(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::DEC_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let inc_val = self.sub(val, 1);
+                self.write8(addr, inc_val);
+                self.set_zero_negative_flags(inc_val);
+            }
+            Cpu::DEX_IMPLIED => {
+                self.regs[Cpu::REG_X] = self.sub(self.regs[Cpu::REG_X], 1);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_X]);
+            }
+            Cpu::DEY_IMPLIED => {
+                self.regs[Cpu::REG_Y] = self.sub(self.regs[Cpu::REG_Y], 1);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_Y]);
+            }
+            Cpu::ASL_IMPLIED => {
+                self.regs[Cpu::REG_A] = self.shift_left(self.regs[Cpu::REG_A]);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ASL_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let shift_val = self.shift_left(val);
+                self.write8(addr as u16, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ASL_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let shift_val = self.shift_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ASL_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let shift_val = self.shift_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ASL_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let shift_val = self.shift_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::LSR_IMPLIED => {
+                self.regs[Cpu::REG_A] = self.shift_right(self.regs[Cpu::REG_A]);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::LSR_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let shift_val = self.shift_right(val);
+                self.write8(addr as u16, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::LSR_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let shift_val = self.shift_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::LSR_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let shift_val = self.shift_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::LSR_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let shift_val = self.shift_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROL_IMPLIED => {
+                self.regs[Cpu::REG_A] = self.rotate_left(self.regs[Cpu::REG_A]);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ROL_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let shift_val = self.rotate_left(val);
+                self.write8(addr as u16, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROL_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let shift_val = self.rotate_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROL_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let shift_val = self.rotate_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROL_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let shift_val = self.rotate_left(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROR_IMPLIED => {
+                self.regs[Cpu::REG_A] = self.rotate_right(self.regs[Cpu::REG_A]);
+                self.set_zero_negative_flags(self.regs[Cpu::REG_A]);
+            }
+            Cpu::ROR_ZERO => {
+                let addr = self.read_pc();
+                let val = self.read8(addr as u16);
+                let shift_val = self.rotate_right(val);
+                self.write8(addr as u16, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROR_ZERO_X => {
+                let addr = self.fetch_zero_page_addr(self.regs[Cpu::REG_X]);
+                let val = self.read8(addr);
+                let shift_val = self.rotate_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROR_ABSOLUTE => {
+                let addr = self.fetch_absolute_addr();
+                let val = self.read8(addr);
+                let shift_val = self.rotate_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::ROR_ABSOLUTE_X => {
+                let addr = self.fetch_absolute_indexed_addr(self.regs[Cpu::REG_X], false);
+                let val = self.read8(addr);
+                let shift_val = self.rotate_right(val);
+                self.write8(addr, shift_val);
+                self.set_zero_negative_flags(shift_val);
+            }
+            Cpu::BCC_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_CARRY == 0 {
+                    self.branch(offset as i8);
                 }
-                Cpu::CLV_IMPLIED => {
-                    self.clear_status_flag(Cpu::FLAG_OVERFLOW);
+            }
+            Cpu::BCS_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_CARRY == Cpu::FLAG_CARRY {
+                    self.branch(offset as i8);
                 }
-                Cpu::SEC_IMPLIED => {
-                    self.set_status_flag(Cpu::FLAG_CARRY);
+            }
+            Cpu::BEQ_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_ZERO == Cpu::FLAG_ZERO {
+                    self.branch(offset as i8);
                 }
-                Cpu::SED_IMPLIED => {
-                    self.set_status_flag(Cpu::FLAG_DECIMAL);
+            }
+            Cpu::BMI_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_NEGATIVE == Cpu::FLAG_NEGATIVE {
+                    self.branch(offset as i8);
                 }
-                Cpu::SEI_IMPLIED => {
-                    self.set_status_flag(Cpu::FLAG_INTERRUPT);
+            }
+            Cpu::BNE_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_ZERO == 0 {
+                    self.branch(offset as i8);
                 }
-                Cpu::NOP_IMPLIED => {
-                    self.cycles_run += 1;
+            }
+            Cpu::BPL_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_NEGATIVE == 0 {
+                    self.branch(offset as i8);
                 }
-                // https://www.pagetable.com/?p=410
-                Cpu::BRK_IMPLIED => {
-                    self.write_to_stack_16(self.pc);
-                    self.write_to_stack(self.regs[Cpu::REG_STAT] | Cpu::FLAG_BREAK);
-                    self.pc = self.read16(Cpu::IRQ_INTERRUPT_VECTOR_ADDR);
-                    self.cycles_run += 1;
+            }
+            Cpu::BVC_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_OVERFLOW == 0 {
+                    self.branch(offset as i8);
                 }
-                Cpu::RTI_IMPLIED => {
-                    self.regs[Cpu::REG_STAT] = self.read_from_stack();
-                    self.pc = self.read_from_stack_16();
-                    self.cycles_run += 2;
+            }
+            Cpu::BVS_RELATIVE => {
+                let offset = self.read_pc();
+                if self.regs[Cpu::REG_STAT] & Cpu::FLAG_OVERFLOW == Cpu::FLAG_OVERFLOW {
+                    self.branch(offset as i8);
                 }
-                _ => println!("Invalid OP: {:X}", instruction),
             }
-            if cycles > 0 && self.cycles_run - init_cycles >= cycles {
-                break;
+            Cpu::CLC_IMPLIED => {
+                self.clear_status_flag(Cpu::FLAG_CARRY);
+            }
+            Cpu::CLD_IMPLIED => {
+                self.clear_status_flag(Cpu::FLAG_DECIMAL);
+            }
+            Cpu::CLI_IMPLIED => {
+                self.clear_status_flag(Cpu::FLAG_INTERRUPT);
+            }
+            Cpu::CLV_IMPLIED => {
+                self.clear_status_flag(Cpu::FLAG_OVERFLOW);
+            }
+            Cpu::SEC_IMPLIED => {
+                self.set_status_flag(Cpu::FLAG_CARRY);
+            }
+            Cpu::SED_IMPLIED => {
+                self.set_status_flag(Cpu::FLAG_DECIMAL);
             }
+            Cpu::SEI_IMPLIED => {
+                self.set_status_flag(Cpu::FLAG_INTERRUPT);
+            }
+            Cpu::NOP_IMPLIED => {
+                self.cycles_run += 1;
+            }
+            Cpu::JMP_ABSOLUTE => {
+                self.pc = self.fetch_absolute_addr();
+            }
+            Cpu::JMP_INDIRECT => {
+                let ptr = self.fetch_absolute_addr();
+                // The high byte comes from the same page, the pointer doesn't carry
+                let low = self.read8(ptr) as u16;
+                let high = self.read8(ptr & 0xFF00 | (ptr as u8).wrapping_add(1) as u16) as u16;
+                self.pc = high << 8 | low;
+            }
+            Cpu::JSR_ABSOLUTE => {
+                let (caller, sp) = (self.pc.wrapping_sub(1), self.regs[Cpu::REG_SP]);
+                let addr = self.fetch_absolute_addr();
+                // The address pushed is the last byte of the JSR, RTS adds 1
+                self.write_to_stack_16(self.pc.wrapping_sub(1));
+                let return_addr = self.pc;
+                self.pc = addr;
+                self.cycles_run += 1;
+                self.push_frame(callstack::FrameKind::Call, caller, return_addr, sp);
+            }
+            Cpu::RTS_IMPLIED => {
+                let pc = self.pc.wrapping_sub(1);
+                self.pc = self.read_from_stack_16().wrapping_add(1);
+                self.cycles_run += 3;
+                self.pop_frame(pc, false);
+            }
+            // https://www.pagetable.com/?p=410
+            Cpu::BRK_IMPLIED => {
+                let (caller, sp) = (self.pc.wrapping_sub(1), self.regs[Cpu::REG_SP]);
+                let return_addr = self.pc;
+                self.write_to_stack_16(self.pc);
+                self.write_to_stack(self.regs[Cpu::REG_STAT] | Cpu::FLAG_BREAK);
+                self.pc = self.read16(Cpu::IRQ_INTERRUPT_VECTOR_ADDR);
+                self.cycles_run += 1;
+                self.push_frame(callstack::FrameKind::Brk, caller, return_addr, sp);
+            }
+            Cpu::RTI_IMPLIED => {
+                let pc = self.pc.wrapping_sub(1);
+                self.regs[Cpu::REG_STAT] = self.read_from_stack();
+                self.pc = self.read_from_stack_16();
+                self.cycles_run += 2;
+                self.pop_frame(pc, true);
+            }
+            // skipped, the debugger stops on it
+            _ => self.invalid_op = Some(instruction),
         }
     }
 }
@@ -1464,3 +1840,37 @@ impl Mem {
         self.mem[RESET_VECTOR_ADDR + 1] = (RESET_EXEC_ADDRESS & 0x00FF) as u8;
     }
 }
+
+/// Side effect free read access to memory, for the tools that only inspect it (disassembler, ...)
+pub trait Peek {
+    fn peek8(&self, addr: u16) -> u8;
+
+    fn peek16(&self, addr: u16) -> u16 {
+        (self.peek8(addr.wrapping_add(1)) as u16) << 8 | self.peek8(addr) as u16
+    }
+}
+
+impl Peek for Mem {
+    fn peek8(&self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+}
+
+impl Peek for Cpu<'_> {
+    fn peek8(&self, addr: u16) -> u8 {
+        self.devices.peek(addr).unwrap_or_else(|| self.mem.peek8(addr))
+    }
+}
+
+// A slice is seen as memory starting at address 0
+impl Peek for [u8] {
+    fn peek8(&self, addr: u16) -> u8 {
+        self.get(addr as usize).copied().unwrap_or(0)
+    }
+}
+
+impl Peek for Vec<u8> {
+    fn peek8(&self, addr: u16) -> u8 {
+        self.as_slice().peek8(addr)
+    }
+}
@@ -0,0 +1,559 @@
+// Source line information for source level debugging: the line and span records of ld65
+// --dbgfile and the DWARF line tables llvm-mos writes into its ELF files
+// https://cc65.github.io/doc/debugging.html
+// https://dwarfstd.org/doc/DWARF5.pdf (6.2 Line Number Information)
+
+use crate::elf::{ElfError, ElfFile};
+use crate::symbols::{parse_dbg_attributes, parse_error, parse_number, SymbolError, SymbolFormat};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// `size` bytes at `addr` were generated from `line` of `LineTable::files()[file]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineEntry {
+    pub addr: u16,
+    pub size: u16,
+    pub file: usize,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LineTable {
+    files: Vec<String>,
+    entries: Vec<LineEntry>,
+    // the smallest entry that covers an address, index into `entries`
+    by_addr: BTreeMap<u16, usize>,
+}
+
+impl LineTable {
+    pub fn new() -> LineTable {
+        LineTable::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn entries(&self) -> &[LineEntry] {
+        &self.entries
+    }
+
+    /// The entry that generated the byte at `addr`
+    pub fn entry_at(&self, addr: u16) -> Option<&LineEntry> {
+        self.by_addr.get(&addr).map(|&index| &self.entries[index])
+    }
+
+    /// File name and line number of the code at `addr`
+    pub fn line_at(&self, addr: u16) -> Option<(&str, u32)> {
+        self.entry_at(addr).map(|entry| (self.files[entry.file].as_str(), entry.line))
+    }
+
+    /// Start addresses of the code generated by a line, a line can have several (macros, loops
+    /// unrolled by the compiler)
+    pub fn addresses(&self, file: usize, line: u32) -> Vec<u16> {
+        let mut addrs: Vec<u16> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.file == file && entry.line == line)
+            .map(|entry| entry.addr)
+            .collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        addrs
+    }
+
+    /// Finds a file by its path. Editors send absolute paths while the debug information often
+    /// has relative ones, so a path also matches when one of them ends with the other
+    pub fn find_file(&self, path: &str) -> Option<usize> {
+        self.files.iter().position(|file| file == path).or_else(|| {
+            let path = Path::new(path);
+            self.files
+                .iter()
+                .position(|file| path.ends_with(file) || Path::new(file).ends_with(path))
+        })
+    }
+
+    pub fn merge(&mut self, other: &LineTable) {
+        for entry in &other.entries {
+            let file = self.add_file(&other.files[entry.file]);
+            self.entries.push(LineEntry { file, ..*entry });
+        }
+        self.index();
+    }
+
+    /// Reads the lines of an ld65 debug file. Other symbol file formats have no line
+    /// information, they give an empty table. Relative file names are taken as relative to the
+    /// directory of the debug file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<LineTable, SymbolError> {
+        let text = fs::read_to_string(&path)?;
+        if SymbolFormat::detect(&text) != SymbolFormat::Ca65Dbg {
+            return Ok(LineTable::new());
+        }
+        let mut table = LineTable::parse_ca65_dbg(&text)?;
+        if let Some(dir) = path.as_ref().parent() {
+            for file in table.files.iter_mut() {
+                *file = join(&dir.to_string_lossy(), file);
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn parse_ca65_dbg(text: &str) -> Result<LineTable, SymbolError> {
+        let mut files = HashMap::new();
+        let mut segs = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let (kind, attrs) = match line.find(char::is_whitespace) {
+                Some(pos) => (&line[..pos], parse_dbg_attributes(&line[pos..])),
+                None => continue,
+            };
+            let number = |name: &str| attrs.get(name).and_then(|value| parse_number(value));
+            match kind {
+                "file" => match (number("id"), attrs.get("name")) {
+                    (Some(id), Some(name)) => {
+                        files.insert(id, name.clone());
+                    }
+                    _ => return parse_error(i, "invalid file record"),
+                },
+                "seg" => match (number("id"), number("start")) {
+                    (Some(id), Some(start)) => {
+                        segs.insert(id, start);
+                    }
+                    _ => return parse_error(i, "invalid seg record"),
+                },
+                "span" => match (number("id"), number("seg"), number("start"), number("size")) {
+                    (Some(id), Some(seg), Some(start), Some(size)) => {
+                        spans.insert(id, (seg, start, size));
+                    }
+                    _ => return parse_error(i, "invalid span record"),
+                },
+                "line" => {
+                    // type 2 lines are inside of macro definitions, the code belongs to the
+                    // line that invoked the macro
+                    if number("type") == Some(2) {
+                        continue;
+                    }
+                    // lines without code have no spans
+                    let span_list = match attrs.get("span") {
+                        Some(span_list) => span_list,
+                        None => continue,
+                    };
+                    match (number("file"), number("line")) {
+                        (Some(file), Some(line)) => lines.push((i, file, line, span_list.clone())),
+                        _ => return parse_error(i, "invalid line record"),
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut table = LineTable::new();
+        for (i, file, line, span_list) in lines {
+            let file = match files.get(&file) {
+                Some(name) => table.add_file(name),
+                None => return parse_error(i, "line record with an unknown file"),
+            };
+            for span in span_list.split('+') {
+                let (seg, start, size) = match parse_number(span).and_then(|id| spans.get(&id)) {
+                    Some(span) => *span,
+                    None => return parse_error(i, "line record with an unknown span"),
+                };
+                let addr = match segs.get(&seg) {
+                    Some(seg_start) => seg_start + start,
+                    None => return parse_error(i, "span with an unknown segment"),
+                };
+                if size > 0 && addr + size <= 0x10000 {
+                    table.entries.push(LineEntry {
+                        addr: addr as u16,
+                        size: size as u16,
+                        file,
+                        line,
+                    });
+                }
+            }
+        }
+        table.index();
+        Ok(table)
+    }
+
+    /// The DWARF line tables (.debug_line) of an ELF file, empty if it has none
+    pub fn from_elf(data: &[u8]) -> Result<LineTable, ElfError> {
+        let mut table = LineTable::new();
+        let debug_line = match ElfFile::section(data, ".debug_line")? {
+            Some(section) => section,
+            None => return Ok(table),
+        };
+        let strings = Strings {
+            line_str: ElfFile::section(data, ".debug_line_str")?.unwrap_or(&[]),
+            str: ElfFile::section(data, ".debug_str")?.unwrap_or(&[]),
+        };
+        let mut offset = 0;
+        while offset < debug_line.len() {
+            offset = table.parse_line_unit(debug_line, offset, &strings)?;
+        }
+        table.index();
+        Ok(table)
+    }
+
+    fn add_file(&mut self, name: &str) -> usize {
+        match self.files.iter().position(|file| file == name) {
+            Some(index) => index,
+            None => {
+                self.files.push(name.to_string());
+                self.files.len() - 1
+            }
+        }
+    }
+
+    fn index(&mut self) {
+        self.by_addr.clear();
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        // bigger entries first, so that the smaller ones inside of them win
+        order.sort_by_key(|&index| std::cmp::Reverse(self.entries[index].size));
+        for index in order {
+            let entry = self.entries[index];
+            for addr in entry.addr as u32..entry.addr as u32 + entry.size as u32 {
+                self.by_addr.insert(addr as u16, index);
+            }
+        }
+    }
+
+    // One unit of .debug_line, returns the offset of the next one
+    fn parse_line_unit(
+        &mut self,
+        data: &[u8],
+        offset: usize,
+        strings: &Strings,
+    ) -> Result<usize, ElfError> {
+        let mut r = Reader { data, pos: offset };
+        let unit_length = r.u32()?;
+        if unit_length >= 0xFFFF_FFF0 {
+            return Err(ElfError::Unsupported("64 bit DWARF"));
+        }
+        let end = r.pos.checked_add(unit_length as usize).ok_or(ElfError::Truncated)?;
+        if end > data.len() {
+            return Err(ElfError::Truncated);
+        }
+        r.data = &data[..end];
+        let version = r.u16()?;
+        if !(2..=5).contains(&version) {
+            return Err(ElfError::Unsupported("DWARF line table version"));
+        }
+        if version >= 5 {
+            r.u8()?; // address_size
+            r.u8()?; // segment_selector_size
+        }
+        let header_length = r.u32()? as usize;
+        let program = r.pos + header_length;
+        let min_inst_length = r.u8()? as u64;
+        if version >= 4 {
+            r.u8()?; // maximum_operations_per_instruction, only for VLIW
+        }
+        let default_is_stmt = r.u8()? != 0;
+        let line_base = r.u8()? as i8 as i64;
+        let line_range = r.u8()?;
+        let opcode_base = r.u8()?;
+        if line_range == 0 || opcode_base == 0 {
+            return Err(ElfError::Unsupported("invalid DWARF line table header"));
+        }
+        let mut opcode_lengths = Vec::new();
+        for _ in 1..opcode_base {
+            opcode_lengths.push(r.u8()?);
+        }
+
+        // file numbers start at 1 before DWARF 5, at 0 from then on
+        let mut files: Vec<Option<usize>> = Vec::new();
+        let mut dirs: Vec<String> = Vec::new();
+        if version >= 5 {
+            // directory 0 is the compilation directory, the others can be relative to it
+            for (path, _) in r.entry_list(strings)? {
+                let path = match dirs.first() {
+                    Some(comp_dir) => join(comp_dir, &path),
+                    None => path,
+                };
+                dirs.push(path);
+            }
+            for (path, dir) in r.entry_list(strings)? {
+                let dir = dirs.get(dir as usize).map_or("", String::as_str);
+                files.push(Some(self.add_file(&join(dir, &path))));
+            }
+        } else {
+            // directory 0 is the compilation directory, which is only in .debug_info
+            dirs.push(String::new());
+            loop {
+                let dir = r.c_string()?;
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(dir);
+            }
+            files.push(None);
+            loop {
+                let path = r.c_string()?;
+                if path.is_empty() {
+                    break;
+                }
+                let dir = r.uleb()? as usize;
+                r.uleb()?; // modification time
+                r.uleb()?; // length
+                files.push(Some(
+                    self.add_file(&join(dirs.get(dir).map_or("", String::as_str), &path)),
+                ));
+            }
+        }
+
+        r.pos = program;
+        let mut rows: Vec<Row> = Vec::new();
+        let new_row = || Row { addr: 0, file: 1, line: 1, is_stmt: default_is_stmt, end: false };
+        let mut row = new_row();
+        while r.pos < end {
+            let opcode = r.u8()?;
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                row.addr += (adjusted / line_range) as u64 * min_inst_length;
+                row.line += line_base + (adjusted % line_range) as i64;
+                rows.push(row);
+                continue;
+            }
+            match opcode {
+                // extended opcodes
+                0 => {
+                    let len = r.uleb()? as usize;
+                    let next = r.pos + len;
+                    match r.u8()? {
+                        1 => {
+                            rows.push(Row { end: true, ..row });
+                            self.add_sequence(&rows, &files);
+                            rows.clear();
+                            row = new_row();
+                        }
+                        2 => row.addr = r.address(len.saturating_sub(1))?,
+                        3 => {
+                            let path = r.c_string()?;
+                            let dir = r.uleb()? as usize;
+                            let path = join(dirs.get(dir).map_or("", String::as_str), &path);
+                            files.push(Some(self.add_file(&path)));
+                        }
+                        _ => {}
+                    }
+                    r.pos = next;
+                }
+                1 => rows.push(row),
+                2 => row.addr += r.uleb()? * min_inst_length,
+                3 => row.line += r.sleb()?,
+                4 => row.file = r.uleb()?,
+                5 => {
+                    r.uleb()?; // column
+                }
+                6 => row.is_stmt = !row.is_stmt,
+                8 => row.addr += ((255 - opcode_base) / line_range) as u64 * min_inst_length,
+                9 => row.addr += r.u16()? as u64,
+                _ => {
+                    for _ in 0..opcode_lengths[opcode as usize - 1] {
+                        r.uleb()?;
+                    }
+                }
+            }
+        }
+        Ok(end)
+    }
+
+    // Each row of a sequence covers the addresses up to the next row
+    fn add_sequence(&mut self, rows: &[Row], files: &[Option<usize>]) {
+        for pair in rows.windows(2) {
+            let (row, next) = (pair[0], pair[1]);
+            let file = files.get(row.file as usize).copied().flatten();
+            if row.end
+                || !row.is_stmt
+                || row.line <= 0
+                || row.addr > 0xFFFF
+                || next.addr <= row.addr
+            {
+                continue;
+            }
+            if let Some(file) = file {
+                let size = next.addr.min(0x10000) - row.addr;
+                let line = row.line as u32;
+                self.entries.push(LineEntry {
+                    addr: row.addr as u16,
+                    size: size as u16,
+                    file,
+                    line,
+                });
+            }
+        }
+    }
+}
+
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() || Path::new(path).is_absolute() {
+        path.to_string()
+    } else {
+        Path::new(dir).join(path).to_string_lossy().into_owned()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    addr: u64,
+    file: u64,
+    line: i64,
+    is_stmt: bool,
+    end: bool,
+}
+
+// The string sections DWARF 5 line table headers can point into
+struct Strings<'d> {
+    line_str: &'d [u8],
+    str: &'d [u8],
+}
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0B;
+const DW_FORM_STRP: u64 = 0x0E;
+const DW_FORM_UDATA: u64 = 0x0F;
+const DW_FORM_DATA16: u64 = 0x1E;
+const DW_FORM_LINE_STRP: u64 = 0x1F;
+
+struct Reader<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn bytes(&mut self, len: usize) -> Result<&'d [u8], ElfError> {
+        let end = self.pos.checked_add(len).ok_or(ElfError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(ElfError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ElfError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ElfError> {
+        Ok(self.address(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ElfError> {
+        Ok(self.address(4)? as u32)
+    }
+
+    // little endian value of 1 to 8 bytes
+    fn address(&mut self, len: usize) -> Result<u64, ElfError> {
+        if len > 8 {
+            return Err(ElfError::Unsupported("address size"));
+        }
+        Ok(self.bytes(len)?.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    fn uleb(&mut self) -> Result<u64, ElfError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64, ElfError> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn c_string(&mut self) -> Result<String, ElfError> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest.iter().position(|&b| b == 0).ok_or(ElfError::Truncated)?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+
+    // The directory and file name lists of DWARF 5, as (path, directory index)
+    fn entry_list(&mut self, strings: &Strings) -> Result<Vec<(String, u64)>, ElfError> {
+        let mut formats = Vec::new();
+        for _ in 0..self.u8()? {
+            formats.push((self.uleb()?, self.uleb()?));
+        }
+        let mut entries = Vec::new();
+        for _ in 0..self.uleb()? {
+            let mut path = String::new();
+            let mut dir = 0;
+            for &(content, form) in &formats {
+                let mut number = None;
+                let string = match form {
+                    DW_FORM_STRING => Some(self.c_string()?),
+                    DW_FORM_LINE_STRP => Some(string_at(strings.line_str, self.u32()?)?),
+                    DW_FORM_STRP => Some(string_at(strings.str, self.u32()?)?),
+                    DW_FORM_DATA1 | DW_FORM_DATA2 | DW_FORM_DATA4 | DW_FORM_DATA8 => {
+                        let len = match form {
+                            DW_FORM_DATA1 => 1,
+                            DW_FORM_DATA2 => 2,
+                            DW_FORM_DATA4 => 4,
+                            _ => 8,
+                        };
+                        number = Some(self.address(len)?);
+                        None
+                    }
+                    DW_FORM_UDATA => {
+                        number = Some(self.uleb()?);
+                        None
+                    }
+                    DW_FORM_DATA16 => {
+                        self.bytes(16)?;
+                        None
+                    }
+                    DW_FORM_BLOCK => {
+                        let len = self.uleb()? as usize;
+                        self.bytes(len)?;
+                        None
+                    }
+                    _ => return Err(ElfError::Unsupported("DWARF form in the line table header")),
+                };
+                match content {
+                    DW_LNCT_PATH => path = string.unwrap_or_default(),
+                    DW_LNCT_DIRECTORY_INDEX => dir = number.unwrap_or(0),
+                    _ => {}
+                }
+            }
+            entries.push((path, dir));
+        }
+        Ok(entries)
+    }
+}
+
+fn string_at(section: &[u8], offset: u32) -> Result<String, ElfError> {
+    Reader { data: section, pos: offset as usize }.c_string()
+}
@@ -0,0 +1,131 @@
+// Loads the program files the tools accept: llvm-mos ELF, iNES and raw binaries
+// https://www.nesdev.org/wiki/INES
+
+use crate::elf::{is_elf, ElfError, ElfFile};
+use crate::lines::LineTable;
+use crate::symbols::SymbolTable;
+use crate::{Mem, MEM_SIZE};
+use std::fmt;
+use std::path::Path;
+
+/// Where the tools load raw binaries unless told otherwise
+pub const DEFAULT_LOAD_ADDR: u16 = 0x0200;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Elf(ElfError),
+    Ines(String),
+    Sim65(String),
+    /// Raw binaries need an address to load them at
+    NoAddress,
+    TooLarge {
+        addr: u16,
+        len: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Elf(err) => write!(f, "{}", err),
+            LoadError::Ines(message) => write!(f, "iNES: {}", message),
+            LoadError::Sim65(message) => write!(f, "sim65: {}", message),
+            LoadError::NoAddress => write!(f, "raw binaries need a load address"),
+            LoadError::TooLarge { addr, len } => {
+                write!(f, "{} bytes don't fit in memory at ${:04X}", len, addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ElfError> for LoadError {
+    fn from(err: ElfError) -> Self {
+        LoadError::Elf(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    /// ELF entry point or the reset vector of an iNES file, None for raw binaries
+    pub entry: Option<u16>,
+    pub symbols: SymbolTable,
+    /// DWARF line information of an ELF file
+    pub lines: LineTable,
+    /// PRG and CHR ROM sizes of an iNES file
+    pub rom_sizes: Option<(usize, usize)>,
+}
+
+pub fn load_file<P: AsRef<Path>>(
+    mem: &mut Mem,
+    path: P,
+    addr: Option<u16>,
+) -> Result<Loaded, LoadError> {
+    load(mem, &std::fs::read(path)?, addr)
+}
+
+/// `addr` is only used for raw binaries
+pub fn load(mem: &mut Mem, data: &[u8], addr: Option<u16>) -> Result<Loaded, LoadError> {
+    if data.starts_with(b"NES\x1A") && data.len() >= 16 {
+        load_ines(mem, data)?;
+        let entry = mem.read8(0xFFFC) as u16 | (mem.read8(0xFFFD) as u16) << 8;
+        let rom_sizes = Some((data[4] as usize * 0x4000, data[5] as usize * 0x2000));
+        Ok(Loaded {
+            entry: Some(entry),
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            rom_sizes,
+        })
+    } else if is_elf(data) {
+        let elf = ElfFile::parse(data)?;
+        elf.load(mem);
+        // broken or unusual debug information shouldn't keep the program from running
+        let lines = LineTable::from_elf(data).unwrap_or_default();
+        Ok(Loaded {
+            entry: Some(elf.entry),
+            symbols: SymbolTable::from(&elf),
+            lines,
+            rom_sizes: None,
+        })
+    } else {
+        let addr = addr.ok_or(LoadError::NoAddress)?;
+        if addr as usize + data.len() > MEM_SIZE {
+            return Err(LoadError::TooLarge { addr, len: data.len() });
+        }
+        mem.load_programm_at(addr, data);
+        Ok(Loaded {
+            entry: None,
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            rom_sizes: None,
+        })
+    }
+}
+
+// 16 byte header, optional 512 byte trainer, then the PRG ROM banks. A single 16K bank is
+// mirrored at $8000 and $C000
+fn load_ines(mem: &mut Mem, data: &[u8]) -> Result<(), LoadError> {
+    let banks = data[4] as usize;
+    let start = if data[6] & 0x04 != 0 { 16 + 512 } else { 16 };
+    let prg = data
+        .get(start..start + banks * 0x4000)
+        .ok_or_else(|| LoadError::Ines("file is truncated".to_string()))?;
+    match banks {
+        1 => {
+            mem.load_programm_at(0x8000, prg);
+            mem.load_programm_at(0xC000, prg);
+        }
+        2 => mem.load_programm_at(0x8000, prg),
+        _ => return Err(LoadError::Ines(format!("{} PRG banks need a mapper", banks))),
+    }
+    Ok(())
+}
@@ -0,0 +1,682 @@
+// Machine language monitor, command names loosely follow the VICE monitor.
+// Numbers are hex with an optional `$`, symbol names can be used wherever an address is expected.
+
+use crate::callstack::FrameKind;
+use crate::cdl::{self, CodeDataLog, Layout};
+use crate::coverage::Coverage;
+use crate::debugger::{format_flags, Breakpoint, Debugger, StopReason, WatchKind, Watchpoint};
+use crate::disasm::{Disassembler, Syntax};
+use crate::expr::Expr;
+use crate::lines::LineTable;
+use crate::loader::load_file;
+use crate::profile::Profiler;
+use crate::symbols::{parse_number, SymbolTable};
+use crate::writes::WriteHistory;
+use crate::{Cpu, Peek};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+step (s) [count]            run count instructions (default 1)
+next (n) [count]            like step, but runs whole subroutines
+continue (c) [count]        run until a breakpoint, watchpoint or count instructions
+reverse-step (rs) [count]   go back count instructions (default 1)
+reverse-continue (rc)       go back to the last breakpoint or watchpoint hit
+registers (r) [reg=value]   show or set A X Y SP P PC
+mem (m) [start] [end]       hex dump, continues where the last one ended
+edit (e, >) addr bytes...   write bytes to memory
+disasm (d) [addr] [count]   disassemble, continues where the last one ended
+load (l) file [addr]        load an ELF, iNES or raw binary (raw needs addr)
+symbols (sym) file          load a VICE, ca65 .dbg, ACME or 64tass symbol file
+pc addr                     set the program counter
+reset                       reset the cpu
+break (b) [addr] [if expr]  add a breakpoint or list them
+cond addr [expr]            set or clear the condition of a breakpoint
+ignore addr count           don't stop at the next count hits of a breakpoint
+hits addr count             only stop from hit number count on
+delete (del) addr           remove a breakpoint
+watch (w) [r|w|rw] addr [end]  add a watchpoint (default rw) or list them
+unwatch addr                remove the watchpoints starting at addr
+print (p) expr              evaluate an expression
+backtrace (bt)              show the subroutines and interrupt handlers being run
+profile on|off              start counting cycles by instruction and function, or stop
+profile [count]             report the functions and the count (default 20) slowest instructions
+profile save file           write the profile in callgrind format, for kcachegrind
+coverage on|off             start counting the runs of instructions and branches, or stop
+coverage                    show the code that ran and the branches taken
+coverage save file          write lcov with source lines (ca65 .dbg or ELF), else the report
+cdl on|off                  start marking the bytes used as code, data and pointers, or stop
+cdl                         count the bytes marked
+cdl save file               write the marks in FCEUX .cdl format
+writes on|off               start remembering the last writes to each address, or stop
+writes addr                 show who wrote to addr and when, the last write first
+quit (q, x)                 leave the monitor
+An empty line repeats step, next, mem and disasm.
+Expressions use registers (A X Y SP P PC), flags (N V B D I Z C), cycles, symbols, [addr] for a
+byte and [addr].w for a word, with C operators. Their numbers are decimal unless prefixed by $.";
+
+enum CmdError {
+    Io(io::Error),
+    Usage(String),
+}
+
+impl From<io::Error> for CmdError {
+    fn from(err: io::Error) -> Self {
+        CmdError::Io(err)
+    }
+}
+
+impl From<String> for CmdError {
+    fn from(message: String) -> Self {
+        CmdError::Usage(message)
+    }
+}
+
+impl From<&str> for CmdError {
+    fn from(message: &str) -> Self {
+        CmdError::Usage(message.to_string())
+    }
+}
+
+type CmdResult = Result<bool, CmdError>;
+
+pub struct Monitor<'a> {
+    pub debugger: Debugger<'a>,
+    pub symbols: SymbolTable,
+    /// Source lines, for coverage in lcov format
+    pub lines: LineTable,
+    /// Bytes written by `cdl save`, PRG and CHR ROM after loading an iNES file
+    pub cdl_layout: Layout,
+    pub syntax: Syntax,
+    last_command: String,
+    next_disasm: Option<u16>,
+    next_dump: Option<u16>,
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(debugger: Debugger<'a>) -> Monitor<'a> {
+        Monitor {
+            debugger,
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            cdl_layout: Layout::default(),
+            syntax: Syntax::Ca65,
+            last_command: String::new(),
+            next_disasm: None,
+            next_dump: None,
+        }
+    }
+
+    /// Reads commands until `quit` or the end of the input
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", self.status_line())?;
+        let mut lines = input.lines();
+        loop {
+            write!(out, "(6502) ")?;
+            out.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            if !self.execute(&line, out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs one command, returns false when the monitor should exit
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line =
+            if line.trim().is_empty() { self.last_command.clone() } else { line.to_string() };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+        let args: Vec<&str> = words.collect();
+        self.last_command = match command {
+            "s" | "step" | "n" | "next" | "m" | "mem" | "d" | "disasm" => command.to_string(),
+            _ => String::new(),
+        };
+        match self.command(command, &args, out) {
+            Ok(cont) => Ok(cont),
+            Err(CmdError::Io(err)) => Err(err),
+            Err(CmdError::Usage(message)) => {
+                writeln!(out, "error: {}", message)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn command<W: Write>(&mut self, command: &str, args: &[&str], out: &mut W) -> CmdResult {
+        match command {
+            "h" | "help" | "?" => writeln!(out, "{}", HELP)?,
+            "q" | "quit" | "x" => return Ok(false),
+            "s" | "step" | "z" => {
+                let count = self.count(args.first(), 1)?;
+                self.report(out, |debugger| {
+                    (0..count).map(|_| debugger.step()).find(|reason| *reason != StopReason::Step)
+                })?;
+            }
+            "n" | "next" => {
+                let count = self.count(args.first(), 1)?;
+                self.report(out, |debugger| {
+                    (0..count)
+                        .map(|_| debugger.step_over())
+                        .find(|reason| *reason != StopReason::Step)
+                })?;
+            }
+            "c" | "continue" => {
+                let limit = match args.first() {
+                    Some(arg) => Some(self.count(Some(arg), 0)?),
+                    None => None,
+                };
+                self.report(out, |debugger| Some(debugger.run(limit)))?;
+            }
+            "rs" | "reverse-step" => {
+                let count = self.count(args.first(), 1)?;
+                self.report(out, |debugger| {
+                    (0..count)
+                        .map(|_| debugger.step_back())
+                        .find(|reason| *reason != StopReason::Step)
+                })?;
+            }
+            "rc" | "reverse-continue" => {
+                self.report(out, |debugger| Some(debugger.reverse_continue()))?;
+            }
+            "r" | "registers" => {
+                for arg in args {
+                    self.set_register(arg)?;
+                }
+                if !args.is_empty() {
+                    self.debugger.checkpoint();
+                }
+                writeln!(out, "{}", self.registers())?;
+            }
+            "m" | "mem" => self.dump(args, out)?,
+            "e" | "edit" | ">" => {
+                let (addr, bytes) = args.split_first().ok_or("edit needs an address")?;
+                let addr = self.address(addr)?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    let value = self.number(byte)?;
+                    if value > 0xFF {
+                        return Err(format!("{} is not a byte", byte).into());
+                    }
+                    let addr = addr.wrapping_add(i as u16) as usize;
+                    self.debugger.cpu.mem_mut().write8(addr, value as u8);
+                }
+                self.debugger.checkpoint();
+            }
+            "d" | "disasm" => {
+                let addr = match args.first() {
+                    Some(arg) => self.address(arg)?,
+                    None => self.next_disasm.unwrap_or(self.debugger.cpu.pc),
+                };
+                let count = self.count(args.get(1), 10)?;
+                let disasm = Disassembler::new(self.syntax).with_symbols(&self.symbols);
+                for line in disasm.listing(&self.debugger.cpu, addr, count as usize) {
+                    writeln!(out, "{}", line)?;
+                }
+                let mut next = addr;
+                for _ in 0..count {
+                    next = disasm.disassemble(&self.debugger.cpu, next).0.next_addr();
+                }
+                self.next_disasm = Some(next);
+            }
+            "l" | "load" => {
+                let file = args.first().ok_or("load needs a file name")?;
+                let addr = match args.get(1) {
+                    Some(arg) => Some(self.address(arg)?),
+                    None => None,
+                };
+                let loaded = load_file(self.debugger.cpu.mem_mut(), file, addr)
+                    .map_err(|err| format!("{}: {}", file, err))?;
+                self.symbols.merge(&loaded.symbols);
+                self.lines.merge(&loaded.lines);
+                if let Some((prg_size, chr_size)) = loaded.rom_sizes {
+                    self.cdl_layout = Layout::Nes { prg_size, chr_size };
+                }
+                if let Some(pc) = loaded.entry.or(addr) {
+                    self.debugger.cpu.pc = pc;
+                }
+                self.debugger.checkpoint();
+                writeln!(out, "{}", self.status_line())?;
+            }
+            "sym" | "symbols" => {
+                let file = args.first().ok_or("symbols needs a file name")?;
+                let symbols =
+                    SymbolTable::load(file).map_err(|err| format!("{}: {}", file, err))?;
+                writeln!(out, "{} symbols loaded", symbols.len())?;
+                self.symbols.merge(&symbols);
+                let lines = LineTable::load(file).map_err(|err| format!("{}: {}", file, err))?;
+                self.lines.merge(&lines);
+            }
+            "pc" => {
+                let addr = args.first().ok_or("pc needs an address")?;
+                self.debugger.cpu.pc = self.address(addr)?;
+                self.debugger.checkpoint();
+                writeln!(out, "{}", self.status_line())?;
+            }
+            "reset" => {
+                self.debugger.cpu.reset_to_vector();
+                self.debugger.checkpoint();
+                writeln!(out, "{}", self.status_line())?;
+            }
+            "b" | "break" => match args.split_first() {
+                Some((arg, rest)) => {
+                    let mut breakpoint = Breakpoint::new(self.address(arg)?);
+                    match rest.split_first() {
+                        Some((&"if", condition)) => {
+                            breakpoint = breakpoint.with_condition(self.expr(condition)?)
+                        }
+                        Some(_) => return Err("expected if and a condition".into()),
+                        None => {}
+                    }
+                    writeln!(out, "{}", self.format_breakpoint(&breakpoint))?;
+                    self.debugger.set_breakpoint(breakpoint);
+                }
+                None => {
+                    for addr in self.debugger.breakpoints() {
+                        let breakpoint = self.debugger.breakpoint(addr).unwrap();
+                        writeln!(out, "{}", self.format_breakpoint(breakpoint))?;
+                    }
+                }
+            },
+            "cond" => {
+                let (addr, condition) = args.split_first().ok_or("cond needs an address")?;
+                let condition =
+                    if condition.is_empty() { None } else { Some(self.expr(condition)?) };
+                self.existing_breakpoint(addr)?.condition = condition;
+            }
+            "ignore" | "hits" => {
+                let addr = args.first().ok_or_else(|| format!("{} needs an address", command))?;
+                let count = self.count(Some(args.get(1).ok_or("missing count")?), 0)?;
+                let breakpoint = self.existing_breakpoint(addr)?;
+                if command == "ignore" {
+                    breakpoint.ignore_count = count;
+                } else {
+                    breakpoint.hit_count = count;
+                }
+            }
+            "bt" | "backtrace" => self.backtrace(out)?,
+            "profile" => self.profile(args, out)?,
+            "coverage" => self.coverage(args, out)?,
+            "cdl" => self.cdl(args, out)?,
+            "writes" => self.writes(args, out)?,
+            "p" | "print" => {
+                let expr = self.expr(args)?;
+                let value = expr.eval(&self.debugger.cpu).map_err(|err| err.to_string())?;
+                match value {
+                    0..=0xFFFF => writeln!(out, "${:04X} {}", value, value)?,
+                    _ => writeln!(out, "{}", value)?,
+                }
+            }
+            "del" | "delete" => {
+                let addr = self.address(args.first().ok_or("delete needs an address")?)?;
+                if !self.debugger.remove_breakpoint(addr) {
+                    return Err(format!("no breakpoint at ${:04X}", addr).into());
+                }
+            }
+            "w" | "watch" => self.watch(args, out)?,
+            "unwatch" => {
+                let addr = self.address(args.first().ok_or("unwatch needs an address")?)?;
+                let watches: Vec<Watchpoint> = self.debugger.watchpoints().to_vec();
+                let mut found = false;
+                for watch in watches.iter().filter(|watch| watch.start == addr) {
+                    found |= self.debugger.remove_watchpoint(watch);
+                }
+                if !found {
+                    return Err(format!("no watchpoint at ${:04X}", addr).into());
+                }
+            }
+            _ => return Err(format!("unknown command {}, try help", command).into()),
+        }
+        Ok(true)
+    }
+
+    // Runs the debugger and prints why it stopped and where
+    fn report<W, F>(&mut self, out: &mut W, run: F) -> Result<(), CmdError>
+    where
+        W: Write,
+        F: FnOnce(&mut Debugger<'a>) -> Option<StopReason>,
+    {
+        self.next_disasm = None;
+        match run(&mut self.debugger) {
+            Some(StopReason::Breakpoint(addr)) => {
+                writeln!(out, "Breakpoint at {}", self.symbols.format_addr(addr))?
+            }
+            Some(StopReason::Watchpoint { pc, hit }) => writeln!(
+                out,
+                "Watchpoint: {} {} = ${:02X} at {}",
+                if hit.write { "write" } else { "read" },
+                self.symbols.format_addr(hit.addr),
+                hit.value,
+                self.symbols.format_addr(pc)
+            )?,
+            Some(StopReason::Interrupted) => writeln!(out, "Interrupted")?,
+            Some(StopReason::HistoryStart) => writeln!(out, "Start of the history")?,
+            Some(StopReason::InvalidOpcode { pc, opcode }) => {
+                writeln!(out, "Invalid opcode ${:02X} at {}", opcode, self.symbols.format_addr(pc))?
+            }
+            Some(StopReason::Limit) | Some(StopReason::Step) | None => {}
+        }
+        writeln!(out, "{}", self.status_line())?;
+        Ok(())
+    }
+
+    fn dump<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let start = match args.first() {
+            Some(arg) => self.address(arg)?,
+            None => self.next_dump.unwrap_or(self.debugger.cpu.pc),
+        };
+        let end = match args.get(1) {
+            Some(arg) => self.address(arg)?,
+            None => start.saturating_add(0x7F),
+        };
+        if end < start {
+            return Err(format!("${:04X} is before ${:04X}", end, start).into());
+        }
+        let cpu = &self.debugger.cpu;
+        let mut addr = start as u32;
+        while addr <= end as u32 {
+            let row_end = (addr + 15).min(end as u32);
+            let bytes: Vec<u8> = (addr..=row_end).map(|addr| cpu.peek8(addr as u16)).collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' })
+                .collect();
+            writeln!(out, "{:04X}  {:<47}  |{}|", addr, hex.join(" "), text)?;
+            addr += 16;
+        }
+        self.next_dump = Some(end.wrapping_add(1));
+        Ok(())
+    }
+
+    fn watch<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let (kind, args) = match args.first() {
+            Some(&"r") => (WatchKind::Read, &args[1..]),
+            Some(&"w") => (WatchKind::Write, &args[1..]),
+            Some(&"rw") => (WatchKind::ReadWrite, &args[1..]),
+            _ => (WatchKind::ReadWrite, args),
+        };
+        match args.first() {
+            Some(arg) => {
+                let start = self.address(arg)?;
+                let end = match args.get(1) {
+                    Some(arg) => self.address(arg)?,
+                    None => start,
+                };
+                if end < start {
+                    return Err(format!("${:04X} is before ${:04X}", end, start).into());
+                }
+                self.debugger.add_watchpoint(Watchpoint { start, end, kind });
+            }
+            None => {
+                for watch in self.debugger.watchpoints() {
+                    let kind = match watch.kind {
+                        WatchKind::Read => "read",
+                        WatchKind::Write => "write",
+                        WatchKind::ReadWrite => "read/write",
+                    };
+                    write!(
+                        out,
+                        "Watchpoint ({}) at {}",
+                        kind,
+                        self.symbols.format_addr(watch.start)
+                    )?;
+                    if watch.end != watch.start {
+                        write!(out, " to {}", self.symbols.format_addr(watch.end))?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Innermost first, each line has the pc or the call in that frame
+    fn backtrace<W: Write>(&self, out: &mut W) -> Result<(), CmdError> {
+        let call_stack = self.debugger.cpu.call_stack.as_ref().ok_or("no call stack")?;
+        let frames = call_stack.frames().iter().rev();
+        // the kind of the frame the address is in, the outermost one is main code
+        let kinds = frames.clone().map(|frame| Some(frame.kind)).chain(Some(None));
+        let addrs = std::iter::once(self.debugger.cpu.pc).chain(frames.map(|frame| frame.caller));
+        for (i, (addr, kind)) in addrs.zip(kinds).enumerate() {
+            let kind = match kind {
+                Some(FrameKind::Brk) => " (brk handler)",
+                Some(FrameKind::Irq) => " (irq handler)",
+                Some(FrameKind::Nmi) => " (nmi handler)",
+                Some(FrameKind::Call) | None => "",
+            };
+            writeln!(out, "#{:<2} {:04X}  {}{}", i, addr, self.symbols.format_addr(addr), kind)?;
+        }
+        if let Some(mismatch) = call_stack.last_mismatch {
+            writeln!(
+                out,
+                "Returns that didn't match a call: {}, the last one at {} went to {}",
+                call_stack.mismatches,
+                self.symbols.format_addr(mismatch.pc),
+                self.symbols.format_addr(mismatch.target)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn profile<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let cpu = &mut self.debugger.cpu;
+        match args.first() {
+            Some(&"on") => {
+                cpu.profiler = Some(Profiler::new());
+                writeln!(out, "Profiling")?;
+            }
+            Some(&"off") => cpu.profiler = None,
+            Some(&"save") => {
+                let file = args.get(1).ok_or("profile save needs a file name")?;
+                let profiler = cpu.profiler.as_ref().ok_or("not profiling, try profile on")?;
+                profiler
+                    .save_callgrind(file, &self.symbols)
+                    .map_err(|err| format!("{}: {}", file, err))?;
+            }
+            arg => {
+                let count = self.count(arg, 20)?;
+                let cpu = &self.debugger.cpu;
+                let profiler = cpu.profiler.as_ref().ok_or("not profiling, try profile on")?;
+                profiler.report(out, cpu, &self.symbols, count as usize)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn coverage<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let cpu = &mut self.debugger.cpu;
+        match args.first() {
+            Some(&"on") => {
+                cpu.coverage = Some(Coverage::new());
+                writeln!(out, "Recording coverage")?;
+            }
+            Some(&"off") => cpu.coverage = None,
+            Some(&"save") => {
+                let file = args.get(1).ok_or("coverage save needs a file name")?;
+                let cpu = &self.debugger.cpu;
+                let coverage = cpu.coverage.as_ref().ok_or("no coverage, try coverage on")?;
+                let result = if self.lines.is_empty() {
+                    coverage.save_report(file, cpu, &self.symbols)
+                } else {
+                    coverage.save_lcov(file, &self.lines, cpu)
+                };
+                result.map_err(|err| format!("{}: {}", file, err))?;
+            }
+            None => {
+                let cpu = &self.debugger.cpu;
+                let coverage = cpu.coverage.as_ref().ok_or("no coverage, try coverage on")?;
+                coverage.write_report(out, cpu, &self.symbols)?;
+            }
+            Some(arg) => return Err(format!("unknown coverage command {}", arg).into()),
+        }
+        Ok(())
+    }
+
+    fn cdl<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let cpu = &mut self.debugger.cpu;
+        match args.first() {
+            Some(&"on") => {
+                cpu.cdl = Some(CodeDataLog::new());
+                writeln!(out, "Logging code and data")?;
+            }
+            Some(&"off") => cpu.cdl = None,
+            Some(&"save") => {
+                let file = args.get(1).ok_or("cdl save needs a file name")?;
+                let log = cpu.cdl.as_ref().ok_or("not logging, try cdl on")?;
+                log.save(file, self.cdl_layout).map_err(|err| format!("{}: {}", file, err))?;
+            }
+            None => {
+                let log = cpu.cdl.as_ref().ok_or("not logging, try cdl on")?;
+                writeln!(
+                    out,
+                    "{} opcode, {} operand, {} data and {} pointer bytes",
+                    log.count(cdl::OPCODE),
+                    log.count(cdl::OPERAND),
+                    log.count(cdl::DATA),
+                    log.count(cdl::POINTER)
+                )?;
+            }
+            Some(arg) => return Err(format!("unknown cdl command {}", arg).into()),
+        }
+        Ok(())
+    }
+
+    fn writes<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), CmdError> {
+        let cpu = &mut self.debugger.cpu;
+        match args.first() {
+            Some(&"on") => {
+                cpu.write_history = Some(WriteHistory::new());
+                writeln!(out, "Recording writes")?;
+            }
+            Some(&"off") => cpu.write_history = None,
+            Some(arg) => {
+                let addr = self.address(arg)?;
+                let history = self.debugger.cpu.write_history.as_ref();
+                let history = history.ok_or("not recording writes, try writes on")?;
+                let mut writes = history.writes(addr).peekable();
+                if writes.peek().is_none() {
+                    writeln!(out, "No writes to {}", self.symbols.format_addr(addr))?;
+                }
+                for write in writes {
+                    writeln!(
+                        out,
+                        "${:02X} by {} at cycle {}",
+                        write.value,
+                        self.symbols.format_addr(write.pc),
+                        write.cycle
+                    )?;
+                }
+            }
+            None => return Err("writes needs an address, on or off".into()),
+        }
+        Ok(())
+    }
+
+    fn set_register(&mut self, arg: &str) -> Result<(), String> {
+        let mut parts = arg.splitn(2, '=');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name.to_lowercase(), value),
+            _ => return Err(format!("expected register=value, got {}", arg)),
+        };
+        let reg = match name.as_str() {
+            "pc" => {
+                self.debugger.cpu.pc = self.address(value)?;
+                return Ok(());
+            }
+            "a" => Cpu::REG_A,
+            "x" => Cpu::REG_X,
+            "y" => Cpu::REG_Y,
+            "sp" => Cpu::REG_SP,
+            "p" => Cpu::REG_STAT,
+            _ => return Err(format!("unknown register {}", name)),
+        };
+        let value = self.number(value)?;
+        if value > 0xFF {
+            return Err(format!("{} doesn't fit in {}", value, name));
+        }
+        self.debugger.cpu.regs[reg] = value as u8;
+        Ok(())
+    }
+
+    fn expr(&self, words: &[&str]) -> Result<Expr, String> {
+        Expr::parse_with_symbols(&words.join(" "), &self.symbols).map_err(|err| err.to_string())
+    }
+
+    fn existing_breakpoint(&mut self, arg: &str) -> Result<&mut Breakpoint, String> {
+        let addr = self.address(arg)?;
+        self.debugger.breakpoint_mut(addr).ok_or_else(|| format!("no breakpoint at ${:04X}", addr))
+    }
+
+    fn format_breakpoint(&self, breakpoint: &Breakpoint) -> String {
+        let mut text = format!("Breakpoint at {}", self.symbols.format_addr(breakpoint.addr));
+        if let Some(condition) = &breakpoint.condition {
+            text += &format!(" if {}", condition);
+        }
+        if breakpoint.hits > 0 {
+            text += &format!(", {} hits", breakpoint.hits);
+        }
+        if breakpoint.hit_count > 1 {
+            text += &format!(", stops from hit {}", breakpoint.hit_count);
+        }
+        if breakpoint.ignore_count > 0 {
+            text += &format!(", ignores {} more", breakpoint.ignore_count);
+        }
+        text
+    }
+
+    fn count(&self, arg: Option<&&str>, default: u64) -> Result<u64, String> {
+        match arg {
+            Some(arg) => arg.parse().map_err(|_| format!("invalid count {}", arg)),
+            None => Ok(default),
+        }
+    }
+
+    // Hex by default, `$`, `0x` and `%` prefixes work too
+    fn number(&self, text: &str) -> Result<u32, String> {
+        let value = if text.starts_with(|c: char| c.is_ascii_hexdigit()) && !text.starts_with("0x")
+        {
+            u32::from_str_radix(text, 16).ok()
+        } else {
+            parse_number(text)
+        };
+        value.ok_or_else(|| format!("invalid number {}", text))
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        if let Some(addr) = self.symbols.lookup(text) {
+            return Ok(addr);
+        }
+        match self.number(text) {
+            Ok(value) if value <= 0xFFFF => Ok(value as u16),
+            Ok(_) => Err(format!("{} is not an address", text)),
+            Err(_) => Err(format!("unknown symbol or invalid address {}", text)),
+        }
+    }
+
+    pub fn registers(&self) -> String {
+        let cpu = &self.debugger.cpu;
+        let p = cpu.regs[Cpu::REG_STAT];
+        format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} {} CYC:{}",
+            cpu.pc,
+            cpu.regs[Cpu::REG_A],
+            cpu.regs[Cpu::REG_X],
+            cpu.regs[Cpu::REG_Y],
+            cpu.regs[Cpu::REG_SP],
+            p,
+            format_flags(p),
+            cpu.cycles_run
+        )
+    }
+
+    /// The next instruction and the registers
+    pub fn status_line(&self) -> String {
+        let disasm = Disassembler::new(self.syntax).with_symbols(&self.symbols);
+        let line = disasm.listing(&self.debugger.cpu, self.debugger.cpu.pc, 1).pop().unwrap();
+        format!("{:<36}{}", line, self.registers())
+    }
+}
@@ -0,0 +1,173 @@
+// https://www.masswerk.at/6502/6502_instruction_set.html
+// https://csdb.dk/release/?id=212346 (No More Secrets, illegal opcodes)
+// https://cc65.github.io/doc/ca65.html#ss4.4 (6502X mnemonics)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl AddressingMode {
+    /// Instruction size in bytes, opcode included
+    pub fn size(self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 3,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeKind {
+    Official,
+    /// Undocumented, but stable and with an unique encoding (ca65 6502X can assemble it)
+    Illegal,
+    /// Undocumented and either unstable or a duplicate of another encoding
+    Unstable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub kind: OpcodeKind,
+}
+
+impl OpcodeInfo {
+    pub fn is_official(&self) -> bool {
+        self.kind == OpcodeKind::Official
+    }
+}
+
+use AddressingMode::{
+    Absolute as ABS, AbsoluteX as ABX, AbsoluteY as ABY, Accumulator as ACC, Immediate as IMM,
+    Implied as IMP, Indirect as IND, IndirectX as IZX, IndirectY as IZY, Relative as REL,
+    ZeroPage as ZP, ZeroPageX as ZPX, ZeroPageY as ZPY,
+};
+use OpcodeKind::{Illegal as I, Official as O, Unstable as U};
+
+#[rustfmt::skip]
+static OPCODES: [(&str, AddressingMode, OpcodeKind); 256] = [
+    // 0x00
+    ("BRK", IMP, O), ("ORA", IZX, O), ("JAM", IMP, I), ("SLO", IZX, I),
+    ("NOP", ZP, I), ("ORA", ZP, O), ("ASL", ZP, O), ("SLO", ZP, I),
+    ("PHP", IMP, O), ("ORA", IMM, O), ("ASL", ACC, O), ("ANC", IMM, I),
+    ("NOP", ABS, I), ("ORA", ABS, O), ("ASL", ABS, O), ("SLO", ABS, I),
+    // 0x10
+    ("BPL", REL, O), ("ORA", IZY, O), ("JAM", IMP, U), ("SLO", IZY, I),
+    ("NOP", ZPX, I), ("ORA", ZPX, O), ("ASL", ZPX, O), ("SLO", ZPX, I),
+    ("CLC", IMP, O), ("ORA", ABY, O), ("NOP", IMP, U), ("SLO", ABY, I),
+    ("NOP", ABX, I), ("ORA", ABX, O), ("ASL", ABX, O), ("SLO", ABX, I),
+    // 0x20
+    ("JSR", ABS, O), ("AND", IZX, O), ("JAM", IMP, U), ("RLA", IZX, I),
+    ("BIT", ZP, O), ("AND", ZP, O), ("ROL", ZP, O), ("RLA", ZP, I),
+    ("PLP", IMP, O), ("AND", IMM, O), ("ROL", ACC, O), ("ANC", IMM, U),
+    ("BIT", ABS, O), ("AND", ABS, O), ("ROL", ABS, O), ("RLA", ABS, I),
+    // 0x30
+    ("BMI", REL, O), ("AND", IZY, O), ("JAM", IMP, U), ("RLA", IZY, I),
+    ("NOP", ZPX, U), ("AND", ZPX, O), ("ROL", ZPX, O), ("RLA", ZPX, I),
+    ("SEC", IMP, O), ("AND", ABY, O), ("NOP", IMP, U), ("RLA", ABY, I),
+    ("NOP", ABX, U), ("AND", ABX, O), ("ROL", ABX, O), ("RLA", ABX, I),
+    // 0x40
+    ("RTI", IMP, O), ("EOR", IZX, O), ("JAM", IMP, U), ("SRE", IZX, I),
+    ("NOP", ZP, U), ("EOR", ZP, O), ("LSR", ZP, O), ("SRE", ZP, I),
+    ("PHA", IMP, O), ("EOR", IMM, O), ("LSR", ACC, O), ("ALR", IMM, I),
+    ("JMP", ABS, O), ("EOR", ABS, O), ("LSR", ABS, O), ("SRE", ABS, I),
+    // 0x50
+    ("BVC", REL, O), ("EOR", IZY, O), ("JAM", IMP, U), ("SRE", IZY, I),
+    ("NOP", ZPX, U), ("EOR", ZPX, O), ("LSR", ZPX, O), ("SRE", ZPX, I),
+    ("CLI", IMP, O), ("EOR", ABY, O), ("NOP", IMP, U), ("SRE", ABY, I),
+    ("NOP", ABX, U), ("EOR", ABX, O), ("LSR", ABX, O), ("SRE", ABX, I),
+    // 0x60
+    ("RTS", IMP, O), ("ADC", IZX, O), ("JAM", IMP, U), ("RRA", IZX, I),
+    ("NOP", ZP, U), ("ADC", ZP, O), ("ROR", ZP, O), ("RRA", ZP, I),
+    ("PLA", IMP, O), ("ADC", IMM, O), ("ROR", ACC, O), ("ARR", IMM, I),
+    ("JMP", IND, O), ("ADC", ABS, O), ("ROR", ABS, O), ("RRA", ABS, I),
+    // 0x70
+    ("BVS", REL, O), ("ADC", IZY, O), ("JAM", IMP, U), ("RRA", IZY, I),
+    ("NOP", ZPX, U), ("ADC", ZPX, O), ("ROR", ZPX, O), ("RRA", ZPX, I),
+    ("SEI", IMP, O), ("ADC", ABY, O), ("NOP", IMP, U), ("RRA", ABY, I),
+    ("NOP", ABX, U), ("ADC", ABX, O), ("ROR", ABX, O), ("RRA", ABX, I),
+    // 0x80
+    ("NOP", IMM, I), ("STA", IZX, O), ("NOP", IMM, U), ("SAX", IZX, I),
+    ("STY", ZP, O), ("STA", ZP, O), ("STX", ZP, O), ("SAX", ZP, I),
+    ("DEY", IMP, O), ("NOP", IMM, U), ("TXA", IMP, O), ("ANE", IMM, U),
+    ("STY", ABS, O), ("STA", ABS, O), ("STX", ABS, O), ("SAX", ABS, I),
+    // 0x90
+    ("BCC", REL, O), ("STA", IZY, O), ("JAM", IMP, U), ("SHA", IZY, U),
+    ("STY", ZPX, O), ("STA", ZPX, O), ("STX", ZPY, O), ("SAX", ZPY, I),
+    ("TYA", IMP, O), ("STA", ABY, O), ("TXS", IMP, O), ("TAS", ABY, U),
+    ("SHY", ABX, U), ("STA", ABX, O), ("SHX", ABY, U), ("SHA", ABY, U),
+    // 0xA0
+    ("LDY", IMM, O), ("LDA", IZX, O), ("LDX", IMM, O), ("LAX", IZX, I),
+    ("LDY", ZP, O), ("LDA", ZP, O), ("LDX", ZP, O), ("LAX", ZP, I),
+    ("TAY", IMP, O), ("LDA", IMM, O), ("TAX", IMP, O), ("LXA", IMM, U),
+    ("LDY", ABS, O), ("LDA", ABS, O), ("LDX", ABS, O), ("LAX", ABS, I),
+    // 0xB0
+    ("BCS", REL, O), ("LDA", IZY, O), ("JAM", IMP, U), ("LAX", IZY, I),
+    ("LDY", ZPX, O), ("LDA", ZPX, O), ("LDX", ZPY, O), ("LAX", ZPY, I),
+    ("CLV", IMP, O), ("LDA", ABY, O), ("TSX", IMP, O), ("LAS", ABY, I),
+    ("LDY", ABX, O), ("LDA", ABX, O), ("LDX", ABY, O), ("LAX", ABY, I),
+    // 0xC0
+    ("CPY", IMM, O), ("CMP", IZX, O), ("NOP", IMM, U), ("DCP", IZX, I),
+    ("CPY", ZP, O), ("CMP", ZP, O), ("DEC", ZP, O), ("DCP", ZP, I),
+    ("INY", IMP, O), ("CMP", IMM, O), ("DEX", IMP, O), ("AXS", IMM, I),
+    ("CPY", ABS, O), ("CMP", ABS, O), ("DEC", ABS, O), ("DCP", ABS, I),
+    // 0xD0
+    ("BNE", REL, O), ("CMP", IZY, O), ("JAM", IMP, U), ("DCP", IZY, I),
+    ("NOP", ZPX, U), ("CMP", ZPX, O), ("DEC", ZPX, O), ("DCP", ZPX, I),
+    ("CLD", IMP, O), ("CMP", ABY, O), ("NOP", IMP, U), ("DCP", ABY, I),
+    ("NOP", ABX, U), ("CMP", ABX, O), ("DEC", ABX, O), ("DCP", ABX, I),
+    // 0xE0
+    ("CPX", IMM, O), ("SBC", IZX, O), ("NOP", IMM, U), ("ISC", IZX, I),
+    ("CPX", ZP, O), ("SBC", ZP, O), ("INC", ZP, O), ("ISC", ZP, I),
+    ("INX", IMP, O), ("SBC", IMM, O), ("NOP", IMP, O), ("SBC", IMM, U),
+    ("CPX", ABS, O), ("SBC", ABS, O), ("INC", ABS, O), ("ISC", ABS, I),
+    // 0xF0
+    ("BEQ", REL, O), ("SBC", IZY, O), ("JAM", IMP, U), ("ISC", IZY, I),
+    ("NOP", ZPX, U), ("SBC", ZPX, O), ("INC", ZPX, O), ("ISC", ZPX, I),
+    ("SED", IMP, O), ("SBC", ABY, O), ("NOP", IMP, U), ("ISC", ABY, I),
+    ("NOP", ABX, U), ("SBC", ABX, O), ("INC", ABX, O), ("ISC", ABX, I),
+];
+
+pub fn opcode_info(opcode: u8) -> OpcodeInfo {
+    let (mnemonic, mode, kind) = OPCODES[opcode as usize];
+    OpcodeInfo { mnemonic, mode, kind }
+}
+
+/// The encoding an assembler should use. Official opcodes win over the illegal ones and
+/// unstable or duplicated encodings are never picked
+pub fn find_opcode(mnemonic: &str, mode: AddressingMode, allow_illegal: bool) -> Option<u8> {
+    let mut found = None;
+    for (opcode, (name, op_mode, kind)) in OPCODES.iter().enumerate() {
+        if *op_mode != mode || !name.eq_ignore_ascii_case(mnemonic) {
+            continue;
+        }
+        match kind {
+            OpcodeKind::Official => return Some(opcode as u8),
+            OpcodeKind::Illegal if allow_illegal && found.is_none() => found = Some(opcode as u8),
+            _ => {}
+        }
+    }
+    found
+}
+
+pub fn is_mnemonic(name: &str) -> bool {
+    OPCODES.iter().any(|(mnemonic, _, _)| mnemonic.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,267 @@
+// MOS 6520 / Motorola 6821 Peripheral Interface Adapter, the I/O chip of the Apple-1. Two 8 bit
+// ports, each with a data direction register, a control register and two control lines. C1 is an
+// interrupt input, C2 an interrupt input or an output. IRQA and IRQB are both wired to the cpu's
+// IRQ line here. The four registers repeat over the range the chip is mapped at.
+//
+// Bit 2 of a control register selects whether the port address reaches the data direction
+// register (0) or the peripheral register (1), bits 6 and 7 are the C2 and C1 interrupt flags,
+// cleared by reading the peripheral register.
+//
+// http://archive.6502.org/datasheets/mos_6520.pdf
+// https://www.cpcwiki.eu/imgs/6/6b/MC6821.pdf
+
+use crate::device::Device;
+
+#[derive(Debug, Clone)]
+pub struct Pia {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    cra: u8,
+    crb: u8,
+    // levels driven on the port pins from outside
+    pins_a: u8,
+    pins_b: u8,
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    // CA2 and CB2 as outputs in handshake and pulse mode
+    ca2_out: bool,
+    cb2_out: bool,
+    ca2_pulse: bool,
+    cb2_pulse: bool,
+}
+
+impl Default for Pia {
+    fn default() -> Self {
+        Pia::new()
+    }
+}
+
+impl Pia {
+    // registers
+    /// Peripheral register A or DDRA
+    pub const PA: u16 = 0;
+    pub const CRA: u16 = 1;
+    /// Peripheral register B or DDRB
+    pub const PB: u16 = 2;
+    pub const CRB: u16 = 3;
+
+    // control register bits
+    pub const CR_C1_IRQ: u8 = 0x01;
+    /// C1 reacts to rising edges instead of falling ones
+    pub const CR_C1_RISING: u8 = 0x02;
+    /// The port address reaches the peripheral register, not the DDR
+    pub const CR_PORT: u8 = 0x04;
+    pub const CR_C2_MASK: u8 = 0x38;
+    pub const CR_IRQ2: u8 = 0x40;
+    pub const CR_IRQ1: u8 = 0x80;
+
+    pub fn new() -> Pia {
+        Pia {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            cra: 0,
+            crb: 0,
+            pins_a: 0xFF,
+            pins_b: 0xFF,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: true,
+            cb2_out: true,
+            ca2_pulse: false,
+            cb2_pulse: false,
+        }
+    }
+
+    /// Levels on the port A pins, ORA where DDRA makes them outputs
+    pub fn port_a(&self) -> u8 {
+        self.ora & self.ddra | self.pins_a & !self.ddra
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.orb & self.ddrb | self.pins_b & !self.ddrb
+    }
+
+    /// Drives the port A pins, only the ones that are inputs are seen
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.pins_b = pins;
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        if level != self.ca1 {
+            self.ca1 = level;
+            if c1_edge(self.cra, level) {
+                self.cra |= Pia::CR_IRQ1;
+                if self.cra & 0x38 == 0x20 {
+                    self.ca2_out = true;
+                }
+            }
+        }
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        if level != self.ca2 {
+            self.ca2 = level;
+            if c2_edge(self.cra, level) {
+                self.cra |= Pia::CR_IRQ2;
+            }
+        }
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        if level != self.cb1 {
+            self.cb1 = level;
+            if c1_edge(self.crb, level) {
+                self.crb |= Pia::CR_IRQ1;
+                if self.crb & 0x38 == 0x20 {
+                    self.cb2_out = true;
+                }
+            }
+        }
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        if level != self.cb2 {
+            self.cb2 = level;
+            if c2_edge(self.crb, level) {
+                self.crb |= Pia::CR_IRQ2;
+            }
+        }
+    }
+
+    /// Level of CA2, the input when it isn't an output
+    pub fn ca2(&self) -> bool {
+        c2_level(self.cra, self.ca2_out, self.ca2)
+    }
+
+    pub fn cb2(&self) -> bool {
+        c2_level(self.crb, self.cb2_out, self.cb2)
+    }
+
+    /// Whether IRQA is active
+    pub fn irq_a(&self) -> bool {
+        irq(self.cra)
+    }
+
+    pub fn irq_b(&self) -> bool {
+        irq(self.crb)
+    }
+}
+
+fn c1_edge(cr: u8, level: bool) -> bool {
+    level == (cr & Pia::CR_C1_RISING != 0)
+}
+
+// only while C2 is an input, bit 4 selects the rising edge
+fn c2_edge(cr: u8, level: bool) -> bool {
+    cr & 0x20 == 0 && level == (cr & 0x10 != 0)
+}
+
+fn c2_level(cr: u8, out: bool, input: bool) -> bool {
+    match cr & 0x38 {
+        0x20 | 0x28 => out,
+        0x30 => false,
+        0x38 => true,
+        _ => input,
+    }
+}
+
+fn irq(cr: u8) -> bool {
+    cr & Pia::CR_IRQ1 != 0 && cr & Pia::CR_C1_IRQ != 0
+        || cr & Pia::CR_IRQ2 != 0 && cr & 0x28 == 0x08
+}
+
+impl Device for Pia {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.peek(offset);
+        match offset & 3 {
+            Pia::PA if self.cra & Pia::CR_PORT != 0 => {
+                self.cra &= !(Pia::CR_IRQ1 | Pia::CR_IRQ2);
+                // CA2 handshakes on reads of port A
+                match self.cra & 0x38 {
+                    0x20 => self.ca2_out = false,
+                    0x28 => {
+                        self.ca2_out = false;
+                        self.ca2_pulse = true;
+                    }
+                    _ => {}
+                }
+            }
+            Pia::PB if self.crb & Pia::CR_PORT != 0 => {
+                self.crb &= !(Pia::CR_IRQ1 | Pia::CR_IRQ2);
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 3 {
+            Pia::PA if self.cra & Pia::CR_PORT != 0 => self.ora = value,
+            Pia::PA => self.ddra = value,
+            Pia::PB if self.crb & Pia::CR_PORT != 0 => {
+                self.orb = value;
+                // and CB2 on writes of port B
+                match self.crb & 0x38 {
+                    0x20 => self.cb2_out = false,
+                    0x28 => {
+                        self.cb2_out = false;
+                        self.cb2_pulse = true;
+                    }
+                    _ => {}
+                }
+            }
+            Pia::PB => self.ddrb = value,
+            Pia::CRA => self.cra = self.cra & 0xC0 | value & 0x3F,
+            _ => self.crb = self.crb & 0xC0 | value & 0x3F,
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset & 3 {
+            Pia::PA if self.cra & Pia::CR_PORT != 0 => self.port_a(),
+            Pia::PA => self.ddra,
+            Pia::PB if self.crb & Pia::CR_PORT != 0 => self.port_b(),
+            Pia::PB => self.ddrb,
+            Pia::CRA => self.cra,
+            _ => self.crb,
+        }
+    }
+
+    fn tick(&mut self, _cycles: u32) {
+        if self.ca2_pulse {
+            self.ca2_pulse = false;
+            self.ca2_out = true;
+        }
+        if self.cb2_pulse {
+            self.cb2_pulse = false;
+            self.cb2_out = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_a() || self.irq_b()
+    }
+
+    fn reset(&mut self) {
+        self.ora = 0;
+        self.orb = 0;
+        self.ddra = 0;
+        self.ddrb = 0;
+        self.cra = 0;
+        self.crb = 0;
+        self.ca2_out = true;
+        self.cb2_out = true;
+    }
+}
@@ -0,0 +1,289 @@
+// Cycle profiler, runs while `Cpu::profiler` is set. The cycles each instruction takes are added
+// to its address and to the function it runs in. Functions come from the shadow call stack
+// (`Cpu::call_stack`), without one everything counts as top level code.
+//
+// A function is known by its entry address, `None` is the code outside of any subroutine or
+// interrupt handler. Inclusive counts of a call run from the first instruction of the callee to
+// the return, the JSR itself counts for the caller.
+//
+// https://valgrind.org/docs/manual/cl-format.html
+
+use crate::disasm::{Disassembler, Syntax};
+use crate::symbols::SymbolTable;
+use crate::{Cpu, Peek};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counts {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+
+    fn since(self, start: Counts) -> Counts {
+        Counts {
+            instructions: self.instructions - start.instructions,
+            cycles: self.cycles - start.cycles,
+        }
+    }
+}
+
+/// The calls from one call site to a function
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CallCounts {
+    pub calls: u64,
+    /// Everything that ran in the callee and in the functions it called
+    pub inclusive: Counts,
+}
+
+// Caller, call site and callee
+type CallKey = (Option<u16>, u16, u16);
+
+// A call that didn't return yet
+struct OpenCall {
+    caller: Option<u16>,
+    site: u16,
+    callee: u16,
+    // `Frame::cycles` of the frame on the call stack
+    frame_cycles: u32,
+    start: Counts,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    // by function and address
+    costs: HashMap<(Option<u16>, u16), Counts>,
+    calls: HashMap<CallKey, CallCounts>,
+    open: Vec<OpenCall>,
+    total: Counts,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// The function the cpu is in, as far as the profiler knows
+    pub fn current_function(&self) -> Option<u16> {
+        self.open.last().map(|call| call.callee)
+    }
+
+    /// Called by the cpu after each instruction, with its address and the cycle count before it
+    pub fn record(&mut self, cpu: &Cpu, pc: u16, start_cycles: u32) {
+        let cost =
+            Counts { instructions: 1, cycles: cpu.cycles_run.wrapping_sub(start_cycles) as u64 };
+        self.costs.entry((self.current_function(), pc)).or_default().add(cost);
+        self.total.add(cost);
+        let frames = cpu.call_stack.as_ref().map_or(&[][..], |call_stack| call_stack.frames());
+        // returns, the frames of the calls still open are at the same depth
+        while let Some(call) = self.open.last() {
+            let depth = self.open.len() - 1;
+            let frame = frames.get(depth);
+            if frame.is_some_and(|frame| {
+                frame.target == call.callee && frame.cycles == call.frame_cycles
+            }) {
+                break;
+            }
+            let call = self.open.pop().unwrap();
+            let counts = self.calls.entry((call.caller, call.site, call.callee)).or_default();
+            counts.calls += 1;
+            counts.inclusive.add(self.total.since(call.start));
+        }
+        for frame in &frames[self.open.len().min(frames.len())..] {
+            let caller = self.current_function();
+            self.open.push(OpenCall {
+                caller,
+                site: frame.caller,
+                callee: frame.target,
+                frame_cycles: frame.cycles,
+                start: self.total,
+            });
+        }
+    }
+
+    pub fn total(&self) -> Counts {
+        self.total
+    }
+
+    /// Everything that ran at `addr`
+    pub fn address(&self, addr: u16) -> Counts {
+        let mut counts = Counts::default();
+        for (_, cost) in self.costs.iter().filter(|((_, pc), _)| *pc == addr) {
+            counts.add(*cost);
+        }
+        counts
+    }
+
+    /// The counts by address, ordered by address
+    pub fn addresses(&self) -> Vec<(u16, Counts)> {
+        let mut by_addr: HashMap<u16, Counts> = HashMap::new();
+        for ((_, pc), cost) in &self.costs {
+            by_addr.entry(*pc).or_default().add(*cost);
+        }
+        let mut addrs: Vec<(u16, Counts)> = by_addr.into_iter().collect();
+        addrs.sort_by_key(|(addr, _)| *addr);
+        addrs
+    }
+
+    /// What ran in the function itself, not in the ones it called
+    pub fn exclusive(&self, function: Option<u16>) -> Counts {
+        let mut counts = Counts::default();
+        for (_, cost) in self.costs.iter().filter(|((f, _), _)| *f == function) {
+            counts.add(*cost);
+        }
+        counts
+    }
+
+    /// The calls to `function` from anywhere. Calls that didn't return yet count up to now
+    pub fn calls_to(&self, function: u16) -> CallCounts {
+        let mut counts = CallCounts::default();
+        for ((_, _, callee), call) in self.all_calls() {
+            if callee == function {
+                counts.calls += call.calls;
+                counts.inclusive.add(call.inclusive);
+            }
+        }
+        counts
+    }
+
+    // Open calls are closed as if they returned now
+    fn all_calls(&self) -> HashMap<CallKey, CallCounts> {
+        let mut calls = self.calls.clone();
+        for call in &self.open {
+            let counts = calls.entry((call.caller, call.site, call.callee)).or_default();
+            counts.calls += 1;
+            counts.inclusive.add(self.total.since(call.start));
+        }
+        calls
+    }
+
+    fn functions(&self) -> Vec<Option<u16>> {
+        let mut functions: Vec<Option<u16>> =
+            self.costs.keys().map(|(function, _)| *function).collect();
+        functions.extend(self.open.iter().map(|call| Some(call.callee)));
+        functions.sort();
+        functions.dedup();
+        functions
+    }
+
+    /// Functions sorted by exclusive cycles, then the `limit` instructions that took the most
+    pub fn report<W: Write, M: Peek + ?Sized>(
+        &self,
+        out: &mut W,
+        mem: &M,
+        symbols: &SymbolTable,
+        limit: usize,
+    ) -> io::Result<()> {
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.total.cycles.max(1) as f64;
+        writeln!(out, "{} cycles, {} instructions", self.total.cycles, self.total.instructions)?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:<24} {:>12} {:>6} {:>12} {:>6} {:>8}",
+            "function", "cycles", "%", "inclusive", "%", "calls"
+        )?;
+        let mut functions: Vec<(Option<u16>, Counts, CallCounts)> = self
+            .functions()
+            .into_iter()
+            .map(|function| {
+                let calls = match function {
+                    Some(function) => self.calls_to(function),
+                    None => CallCounts { calls: 0, inclusive: self.total },
+                };
+                (function, self.exclusive(function), calls)
+            })
+            .collect();
+        functions.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        for (function, exclusive, calls) in functions {
+            writeln!(
+                out,
+                "{:<24} {:>12} {:>5.1}% {:>12} {:>5.1}% {:>8}",
+                function_name(function, symbols),
+                exclusive.cycles,
+                percent(exclusive.cycles),
+                calls.inclusive.cycles,
+                percent(calls.inclusive.cycles),
+                calls.calls
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "{:<44} {:>12} {:>6} {:>10}", "instruction", "cycles", "%", "count")?;
+        let mut addrs = self.addresses();
+        addrs.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        let disasm = Disassembler::new(Syntax::Ca65).with_symbols(symbols);
+        for (addr, counts) in addrs.into_iter().take(limit) {
+            let (_, text) = disasm.disassemble(mem, addr);
+            let line = format!("{:04X}  {}", addr, text);
+            writeln!(
+                out,
+                "{:<44} {:>12} {:>5.1}% {:>10}",
+                line,
+                counts.cycles,
+                percent(counts.cycles),
+                counts.instructions
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Callgrind format, for kcachegrind and callgrind_annotate. Positions are addresses
+    pub fn write_callgrind<W: Write>(&self, out: &mut W, symbols: &SymbolTable) -> io::Result<()> {
+        writeln!(out, "# callgrind format")?;
+        writeln!(out, "version: 1")?;
+        writeln!(out, "creator: emulator6502")?;
+        writeln!(out, "positions: instr")?;
+        writeln!(out, "events: Cycles Instructions")?;
+        writeln!(out, "summary: {} {}", self.total.cycles, self.total.instructions)?;
+        let mut calls: Vec<(CallKey, CallCounts)> = self.all_calls().into_iter().collect();
+        calls.sort_by_key(|(key, _)| *key);
+        for function in self.functions() {
+            writeln!(out)?;
+            writeln!(out, "fn={}", function_name(function, symbols))?;
+            let mut costs: Vec<(u16, Counts)> = self
+                .costs
+                .iter()
+                .filter(|((f, _), _)| *f == function)
+                .map(|((_, pc), cost)| (*pc, *cost))
+                .collect();
+            costs.sort_by_key(|(pc, _)| *pc);
+            for (pc, cost) in costs {
+                writeln!(out, "0x{:04X} {} {}", pc, cost.cycles, cost.instructions)?;
+            }
+            for ((_, site, callee), call) in
+                calls.iter().filter(|((caller, _, _), _)| *caller == function)
+            {
+                writeln!(out, "cfn={}", function_name(Some(*callee), symbols))?;
+                writeln!(out, "calls={} 0x{:04X}", call.calls, callee)?;
+                writeln!(
+                    out,
+                    "0x{:04X} {} {}",
+                    site, call.inclusive.cycles, call.inclusive.instructions
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_callgrind<P: AsRef<Path>>(&self, path: P, symbols: &SymbolTable) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_callgrind(&mut out, symbols)?;
+        out.flush()
+    }
+}
+
+fn function_name(function: Option<u16>, symbols: &SymbolTable) -> String {
+    match function {
+        Some(addr) => {
+            symbols.name_at(addr).map_or_else(|| format!("${:04X}", addr), str::to_string)
+        }
+        None => "(top level)".to_string(),
+    }
+}
@@ -0,0 +1,172 @@
+// MOS 6530 ROM-RAM-I/O-Timer, two of them make up the KIM-1. The ROM and the 64 bytes of RAM are
+// plain memory, this is the I/O and timer part: two 8 bit ports with data direction registers
+// and an 8 bit interval timer. The registers repeat over the 64 byte range the part is mapped at.
+//
+// A2 clear selects the ports. A2 set selects the timer: writes start it with the divider in A1-A0
+// (1, 8, 64 or 1024 cycles) and the interrupt enabled by A3, reads with A0 clear return the count
+// and set the interrupt enable from A3, reads with A0 set return the interrupt flag in bit 7.
+// After passing zero the timer sets the flag and goes on counting down every cycle until it is
+// read or written again.
+//
+// On the real part PB7 doubles as the IRQ output, here the flag with the interrupt enabled drives
+// the cpu's IRQ line directly.
+//
+// http://archive.6502.org/datasheets/mos_6530_rriot.pdf
+
+use crate::device::Device;
+
+const DIVIDERS: [u32; 4] = [1, 8, 64, 1024];
+
+#[derive(Debug, Clone)]
+pub struct Rriot {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    // levels driven on the port pins from outside, inputs read high when nothing drives them
+    pins_a: u8,
+    pins_b: u8,
+    timer: u8,
+    divider: u32,
+    // cycles to the next count
+    prescaler: u32,
+    // passed zero, counting every cycle
+    timed_out: bool,
+    flag: bool,
+    irq_enabled: bool,
+}
+
+impl Default for Rriot {
+    fn default() -> Self {
+        Rriot::new()
+    }
+}
+
+impl Rriot {
+    // registers
+    pub const PAD: u16 = 0x0;
+    pub const PADD: u16 = 0x1;
+    pub const PBD: u16 = 0x2;
+    pub const PBDD: u16 = 0x3;
+    /// Start the timer counting every cycle, add the divider's index and `TIMER_IRQ`
+    pub const TIMER: u16 = 0x4;
+    /// The count when read, the interrupt flag at `TIMER + 1`
+    pub const TIMER_IRQ: u16 = 0x8;
+
+    pub fn new() -> Rriot {
+        Rriot {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            pins_a: 0xFF,
+            pins_b: 0xFF,
+            timer: 0,
+            divider: 1,
+            prescaler: 1,
+            timed_out: false,
+            flag: false,
+            irq_enabled: false,
+        }
+    }
+
+    /// Levels on the port A pins, the data register where the DDR makes them outputs
+    pub fn port_a(&self) -> u8 {
+        self.ora & self.ddra | self.pins_a & !self.ddra
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.orb & self.ddrb | self.pins_b & !self.ddrb
+    }
+
+    /// Drives the port A pins, only the ones that are inputs are seen
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.pins_b = pins;
+    }
+
+    /// Whether the timer passed zero since it was last read or written
+    pub fn timer_flag(&self) -> bool {
+        self.flag
+    }
+
+    fn count(&mut self) {
+        self.prescaler -= 1;
+        if self.prescaler > 0 {
+            return;
+        }
+        if self.timer == 0 {
+            self.flag = true;
+            self.timed_out = true;
+        }
+        self.timer = self.timer.wrapping_sub(1);
+        self.prescaler = if self.timed_out { 1 } else { self.divider };
+    }
+}
+
+impl Device for Rriot {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.peek(offset);
+        if offset & 0x05 == Rriot::TIMER {
+            self.irq_enabled = offset & Rriot::TIMER_IRQ != 0;
+            self.flag = false;
+            if self.timed_out {
+                self.timed_out = false;
+                self.prescaler = self.divider;
+            }
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset & Rriot::TIMER != 0 {
+            self.timer = value;
+            self.divider = DIVIDERS[(offset & 3) as usize];
+            self.prescaler = self.divider;
+            self.timed_out = false;
+            self.flag = false;
+            self.irq_enabled = offset & Rriot::TIMER_IRQ != 0;
+            return;
+        }
+        match offset & 3 {
+            Rriot::PAD => self.ora = value,
+            Rriot::PADD => self.ddra = value,
+            Rriot::PBD => self.orb = value,
+            _ => self.ddrb = value,
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        if offset & Rriot::TIMER != 0 {
+            return if offset & 1 == 0 { self.timer } else { (self.flag as u8) << 7 };
+        }
+        match offset & 3 {
+            Rriot::PAD => self.port_a(),
+            Rriot::PADD => self.ddra,
+            Rriot::PBD => self.port_b(),
+            _ => self.ddrb,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.count();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.flag && self.irq_enabled
+    }
+
+    /// Clears the ports and the interrupt enable, the timer keeps counting
+    fn reset(&mut self) {
+        self.ora = 0;
+        self.orb = 0;
+        self.ddra = 0;
+        self.ddrb = 0;
+        self.irq_enabled = false;
+    }
+}
@@ -0,0 +1,344 @@
+// The host end of a serial device: the terminal, a pseudo terminal or a TCP connection. Devices
+// keep a `Link` to it, the copies the debugger keeps in its checkpoints share the `Serial`.
+//
+// Bytes from the host are logged with the device cycle they were received in. After going back
+// in time the device runs forward again from a checkpoint and takes its input from the log at the
+// same cycles, so the replay is the same as the first run. Output is counted the same way and a
+// replay doesn't send it to the host a second time.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+/// About one character at 9600 baud with a 1 MHz clock
+const POLL_CYCLES: u64 = 1000;
+
+pub trait Host {
+    /// The next byte from the host, `None` when there is none yet. Doesn't block
+    fn read(&mut self) -> io::Result<Option<u8>>;
+    fn write(&mut self, byte: u8) -> io::Result<()>;
+}
+
+impl<H: Host + ?Sized> Host for Box<H> {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        (**self).read()
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        (**self).write(byte)
+    }
+}
+
+pub struct Serial {
+    host: Option<Box<dyn Host>>,
+    // oldest first
+    received: Vec<(u64, u8)>,
+    sent: u64,
+}
+
+impl Serial {
+    pub fn new<H: Host + 'static>(host: H) -> Rc<RefCell<Serial>> {
+        Rc::new(RefCell::new(Serial { host: Some(Box::new(host)), received: Vec::new(), sent: 0 }))
+    }
+
+    /// False once the host failed, the device goes on without it
+    pub fn connected(&self) -> bool {
+        self.host.is_some()
+    }
+
+    /// Byte number `index` of the input if it arrived by `cycle`. Past the end of the log the host
+    /// is asked, what it has counts as arrived at `cycle`
+    pub fn receive(&mut self, index: usize, cycle: u64) -> Option<u8> {
+        if let Some(&(at, byte)) = self.received.get(index) {
+            return if at <= cycle { Some(byte) } else { None };
+        }
+        let host = self.host.as_mut()?;
+        match host.read() {
+            Ok(Some(byte)) => {
+                self.received.push((cycle, byte));
+                Some(byte)
+            }
+            Ok(None) => None,
+            Err(_) => {
+                self.host = None;
+                None
+            }
+        }
+    }
+
+    /// Whether byte number `index` of the input is from the log
+    pub fn logged(&self, index: usize) -> bool {
+        index < self.received.len()
+    }
+
+    /// Byte number `index` of the output, sent unless it already was
+    pub fn send(&mut self, index: u64, byte: u8) {
+        if index < self.sent {
+            return;
+        }
+        self.sent = index + 1;
+        if let Some(host) = &mut self.host {
+            if host.write(byte).is_err() {
+                self.host = None;
+            }
+        }
+    }
+}
+
+/// A device's end of a `Serial`, with the device cycles and the bytes that went back and forth.
+/// It is part of the device's state and goes into checkpoints with it
+#[derive(Clone, Default)]
+pub struct Link {
+    serial: Option<Rc<RefCell<Serial>>>,
+    cycles: u64,
+    next_poll: u64,
+    received: usize,
+    transmitted: u64,
+}
+
+impl Link {
+    pub fn connect<H: Host + 'static>(&mut self, host: H) -> Rc<RefCell<Serial>> {
+        let serial = Serial::new(host);
+        self.serial = Some(serial.clone());
+        serial
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    /// The next byte of input, for a device that is ready to take one. The log is checked every
+    /// time, the host only every `POLL_CYCLES` so a program waiting for a key doesn't ask it on
+    /// every instruction
+    pub fn poll(&mut self) -> Option<u8> {
+        let mut serial = self.serial.as_ref()?.borrow_mut();
+        if !serial.logged(self.received) {
+            if self.cycles < self.next_poll {
+                return None;
+            }
+            self.next_poll = self.cycles + POLL_CYCLES;
+        }
+        let byte = serial.receive(self.received, self.cycles)?;
+        self.received += 1;
+        Some(byte)
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        if let Some(serial) = &self.serial {
+            serial.borrow_mut().send(self.transmitted, byte);
+        }
+        self.transmitted += 1;
+    }
+}
+
+/// The terminal emu6502 runs in. Input comes from a thread reading stdin, a terminal is put into
+/// raw mode for as long as this lives so keys go to the program as they are typed and without
+/// echo, Return as CR like on the old terminals
+pub struct Stdio {
+    input: Receiver<u8>,
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl Stdio {
+    pub fn new() -> Stdio {
+        let (sender, input) = mpsc::channel();
+        std::thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        #[cfg(unix)]
+        let saved = raw_mode(libc::STDIN_FILENO, false);
+        Stdio {
+            input,
+            #[cfg(unix)]
+            saved,
+        }
+    }
+}
+
+impl Default for Stdio {
+    fn default() -> Self {
+        Stdio::new()
+    }
+}
+
+impl Host for Stdio {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        match self.input.try_recv() {
+            Ok(b'\n') => Ok(Some(b'\r')),
+            Ok(byte) => Ok(Some(byte)),
+            Err(TryRecvError::Empty) => Ok(None),
+            // end of input, nothing comes anymore
+            Err(TryRecvError::Disconnected) => Ok(None),
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        stdout.write_all(&[byte])?;
+        stdout.flush()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Stdio {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+// Turns off line editing, echo and CR to LF translation, returns the old settings. Fully raw
+// also turns off Ctrl-C and output processing
+#[cfg(unix)]
+fn raw_mode(fd: libc::c_int, full: bool) -> Option<libc::termios> {
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return None;
+        }
+        let saved = termios;
+        if full {
+            libc::cfmakeraw(&mut termios);
+        } else {
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_iflag &= !libc::ICRNL;
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+        }
+        libc::tcsetattr(fd, libc::TCSANOW, &termios);
+        Some(saved)
+    }
+}
+
+/// A pseudo terminal, for `screen`, `minicom` or `picocom` on the path `Pty::path` returns. Input
+/// is lost while nothing has the other end open
+#[cfg(unix)]
+pub struct Pty {
+    master: std::fs::File,
+    path: String,
+}
+
+#[cfg(unix)]
+impl Pty {
+    pub fn open() -> io::Result<Pty> {
+        use std::os::unix::io::FromRawFd;
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = std::fs::File::from_raw_fd(fd);
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut name = [0 as libc::c_char; 128];
+            if libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let path = std::ffi::CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+            raw_mode(fd, true);
+            Ok(Pty { master, path })
+        }
+    }
+
+    /// The device the terminal program opens
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+// Without anything on the other end Linux fails reads with EIO
+#[cfg(unix)]
+fn no_peer(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.raw_os_error() == Some(libc::EIO)
+}
+
+#[cfg(unix)]
+impl Host for Pty {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.master.read(&mut byte) {
+            Ok(1) => Ok(Some(byte[0])),
+            Ok(_) => Ok(None),
+            Err(err) if no_peer(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        match self.master.write(&[byte]) {
+            Err(err) if !no_peer(&err) => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Listens on localhost for `telnet` or `nc`, one client at a time. Output is dropped while
+/// nobody is connected
+pub struct Tcp {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+}
+
+impl Tcp {
+    pub fn listen(port: u16) -> io::Result<Tcp> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Tcp { listener, stream: None })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    fn stream(&mut self) -> Option<&mut TcpStream> {
+        if self.stream.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    let _ = stream.set_nodelay(true);
+                    self.stream = Some(stream);
+                }
+            }
+        }
+        self.stream.as_mut()
+    }
+}
+
+impl Host for Tcp {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        let stream = match self.stream() {
+            Some(stream) => stream,
+            None => return Ok(None),
+        };
+        let mut byte = [0];
+        match stream.read(&mut byte) {
+            Ok(1) => Ok(Some(byte[0])),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            // the client left, wait for the next one
+            _ => {
+                self.stream = None;
+                Ok(None)
+            }
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        if let Some(stream) = self.stream() {
+            if stream.write_all(&[byte]).is_err() {
+                self.stream = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+// Programs for cc65's simulator sim65, linked with `cl65 -t sim6502`. A 12 byte header comes
+// first: "sim65", the version (2), the cpu (0 for the 6502), the zero page address of the C stack
+// pointer and the load and reset addresses, low byte first. Version 1 files only have the magic,
+// the version and the cpu, they load at $0200, start there and keep the stack pointer at $00.
+//
+// The runtime calls the host through `JSR`s to the paravirtualization hooks at $FFF4-$FFF9:
+// open, close, read, write, args and exit. `Paravirt::service` runs the hook at the pc on the
+// host and returns as the RTS would. The arguments come the cc65 way, the last one in A and X and
+// the others on the C stack, `open` with the number of bytes pushed in Y. Results go back in A
+// and X, -1 for errors. File descriptors 0, 1 and 2 are the host's stdin, stdout and stderr.
+//
+// https://cc65.github.io/doc/sim65.html
+
+use crate::loader::LoadError;
+use crate::{Cpu, Mem};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8] = b"sim65";
+/// Open, the first of the hooks
+pub const HOOKS: u16 = 0xFFF4;
+/// Exit, the last one
+pub const HOOKS_END: u16 = 0xFFF9;
+/// The exit code sim65 gives for a program that can't be loaded or run
+pub const EXIT_ERROR: i32 = 0x7F;
+/// And for one that ran out of cycles
+pub const EXIT_TIMEOUT: i32 = 0x7E;
+
+const CPU_6502: u8 = 0;
+const CPU_65C02: u8 = 1;
+
+// open flags
+const O_RDONLY: u16 = 0x01;
+const O_WRONLY: u16 = 0x02;
+const O_RDWR: u16 = 0x03;
+const O_CREAT: u16 = 0x10;
+const O_TRUNC: u16 = 0x20;
+const O_APPEND: u16 = 0x40;
+const O_EXCL: u16 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub cpu: u8,
+    /// Zero page address of the C stack pointer
+    pub sp: u8,
+    pub load: u16,
+    pub reset: u16,
+}
+
+pub fn is_sim65(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Loads the program after the header into memory
+pub fn load(mem: &mut Mem, data: &[u8]) -> Result<Header, LoadError> {
+    let error = |message: &str| Err(LoadError::Sim65(message.to_string()));
+    if !is_sim65(data) {
+        return error("not a sim65 program");
+    }
+    let byte = |index: usize| data.get(index).copied();
+    let word = |index: usize| Some(byte(index)? as u16 | (byte(index + 1)? as u16) << 8);
+    let (header, body) = match byte(5) {
+        Some(1) => {
+            let cpu = byte(6).ok_or_else(|| LoadError::Sim65("truncated header".to_string()))?;
+            (Header { version: 1, cpu, sp: 0x00, load: 0x0200, reset: 0x0200 }, &data[7..])
+        }
+        Some(2) => match (byte(6), byte(7), word(8), word(10)) {
+            (Some(cpu), Some(sp), Some(load), Some(reset)) => {
+                (Header { version: 2, cpu, sp, load, reset }, &data[12..])
+            }
+            _ => return error("truncated header"),
+        },
+        Some(version) => return error(&format!("unknown version {}", version)),
+        None => return error("truncated header"),
+    };
+    match header.cpu {
+        CPU_6502 => {}
+        CPU_65C02 => return error("65C02 programs aren't supported"),
+        cpu => return error(&format!("unknown cpu {}", cpu)),
+    }
+    if header.load as usize + body.len() > HOOKS as usize {
+        return Err(LoadError::TooLarge { addr: header.load, len: body.len() });
+    }
+    mem.load_programm_at(header.load, body);
+    Ok(header)
+}
+
+enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// The host side of the hooks: the program's arguments and open files
+pub struct Paravirt {
+    sp: u8,
+    args: Vec<String>,
+    // by file descriptor
+    files: Vec<Option<Stream>>,
+    exit: Option<u8>,
+}
+
+impl Paravirt {
+    /// `args` are the program's `argv`, its name first
+    pub fn new(header: &Header, args: Vec<String>) -> Paravirt {
+        Paravirt {
+            sp: header.sp,
+            args,
+            files: vec![Some(Stream::Stdin), Some(Stream::Stdout), Some(Stream::Stderr)],
+            exit: None,
+        }
+    }
+
+    /// The code the program passed to `exit`, or returned from `main`
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit
+    }
+
+    /// Runs the hook the pc is at and returns to the caller, false when it isn't at one
+    pub fn service(&mut self, cpu: &mut Cpu) -> bool {
+        let result = match cpu.pc {
+            HOOKS => self.open(cpu),
+            0xFFF5 => self.close(ax(cpu)),
+            0xFFF6 => self.read(cpu),
+            0xFFF7 => self.write(cpu),
+            0xFFF8 => self.set_args(cpu),
+            HOOKS_END => {
+                self.exit = Some(cpu.regs[Cpu::REG_A]);
+                return true;
+            }
+            _ => return false,
+        };
+        cpu.regs[Cpu::REG_A] = result as u8;
+        cpu.regs[Cpu::REG_X] = (result >> 8) as u8;
+        cpu.return_from_subroutine();
+        true
+    }
+
+    // takes a word off the C stack and moves the stack pointer up by `size`
+    fn pop(&self, cpu: &mut Cpu, size: u16) -> u16 {
+        let sp = read16(cpu.mem(), self.sp as u16);
+        let value = read16(cpu.mem(), sp);
+        write16(cpu.mem_mut(), self.sp as u16, sp.wrapping_add(size));
+        value
+    }
+
+    // int open(const char* name, int flags, ...), the mode is ignored like sim65 does
+    fn open(&mut self, cpu: &mut Cpu) -> u16 {
+        let extra = cpu.regs[Cpu::REG_Y].wrapping_sub(4) as u16;
+        self.pop(cpu, extra);
+        let flags = self.pop(cpu, 2);
+        let name = self.pop(cpu, 2);
+        let path = read_string(cpu.mem(), name);
+        let mut options = OpenOptions::new();
+        match flags & O_RDWR {
+            O_RDONLY => options.read(true),
+            O_WRONLY => options.write(true),
+            O_RDWR => options.read(true).write(true),
+            _ => return 0xFFFF,
+        };
+        options.append(flags & O_APPEND != 0).truncate(flags & O_TRUNC != 0);
+        if flags & O_CREAT != 0 {
+            if flags & O_EXCL != 0 {
+                options.create_new(true);
+            } else {
+                options.create(true);
+            }
+        }
+        let file = match options.open(&path) {
+            Ok(file) => Stream::File(file),
+            Err(_) => return 0xFFFF,
+        };
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                fd as u16
+            }
+            None => {
+                self.files.push(Some(file));
+                (self.files.len() - 1) as u16
+            }
+        }
+    }
+
+    fn close(&mut self, fd: u16) -> u16 {
+        match self.files.get_mut(fd as usize).and_then(Option::take) {
+            Some(_) => 0,
+            None => 0xFFFF,
+        }
+    }
+
+    // int read(int fd, void* buf, unsigned count)
+    fn read(&mut self, cpu: &mut Cpu) -> u16 {
+        let count = ax(cpu);
+        let buf = self.pop(cpu, 2);
+        let fd = self.pop(cpu, 2);
+        let mut data = vec![0; count as usize];
+        let result = match self.files.get_mut(fd as usize) {
+            Some(Some(Stream::Stdin)) => io::stdin().read(&mut data),
+            Some(Some(Stream::File(file))) => file.read(&mut data),
+            _ => return 0xFFFF,
+        };
+        match result {
+            Ok(len) => {
+                for (i, &byte) in data[..len].iter().enumerate() {
+                    cpu.mem_mut().write8(buf.wrapping_add(i as u16) as usize, byte);
+                }
+                len as u16
+            }
+            Err(_) => 0xFFFF,
+        }
+    }
+
+    // int write(int fd, const void* buf, unsigned count)
+    fn write(&mut self, cpu: &mut Cpu) -> u16 {
+        let count = ax(cpu);
+        let buf = self.pop(cpu, 2);
+        let fd = self.pop(cpu, 2);
+        let data: Vec<u8> =
+            (0..count).map(|i| cpu.mem().read8(buf.wrapping_add(i) as usize)).collect();
+        let result = match self.files.get_mut(fd as usize) {
+            Some(Some(Stream::Stdout)) => write_all(&mut io::stdout(), &data),
+            Some(Some(Stream::Stderr)) => write_all(&mut io::stderr(), &data),
+            Some(Some(Stream::File(file))) => file.write_all(&data),
+            _ => return 0xFFFF,
+        };
+        match result {
+            Ok(()) => count,
+            Err(_) => 0xFFFF,
+        }
+    }
+
+    // Puts the arguments on the C stack below the stack pointer, stores argv at the address in A
+    // and X and returns argc
+    fn set_args(&mut self, cpu: &mut Cpu) -> u16 {
+        let argv = ax(cpu);
+        let mut sp = read16(cpu.mem(), self.sp as u16);
+        let mut pointer = sp.wrapping_sub((self.args.len() as u16 + 1) * 2);
+        write16(cpu.mem_mut(), argv, pointer);
+        sp = pointer;
+        for arg in &self.args {
+            let bytes = arg.as_bytes();
+            sp = sp.wrapping_sub(bytes.len() as u16 + 1);
+            for (i, &byte) in bytes.iter().chain(&[0]).enumerate() {
+                cpu.mem_mut().write8(sp.wrapping_add(i as u16) as usize, byte);
+            }
+            write16(cpu.mem_mut(), pointer, sp);
+            pointer = pointer.wrapping_add(2);
+        }
+        write16(cpu.mem_mut(), pointer, 0);
+        write16(cpu.mem_mut(), self.sp as u16, sp);
+        self.args.len() as u16
+    }
+}
+
+/// How a program run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Code(u8),
+    /// It didn't exit within the cycles it was given
+    Timeout,
+}
+
+/// Runs the program until it exits, or for `max_cycles`. Returns with the cpu at the exit hook
+pub fn run(cpu: &mut Cpu, paravirt: &mut Paravirt, max_cycles: Option<u64>) -> (Exit, u64) {
+    let mut cycles = 0u64;
+    loop {
+        if paravirt.service(cpu) {
+            if let Some(code) = paravirt.exit_code() {
+                return (Exit::Code(code), cycles);
+            }
+            continue;
+        }
+        if max_cycles.is_some_and(|max| cycles >= max) {
+            return (Exit::Timeout, cycles);
+        }
+        let before = cpu.cycles_run;
+        cpu.step();
+        cycles += cpu.cycles_run.wrapping_sub(before) as u64;
+    }
+}
+
+fn ax(cpu: &Cpu) -> u16 {
+    cpu.regs[Cpu::REG_A] as u16 | (cpu.regs[Cpu::REG_X] as u16) << 8
+}
+
+fn read16(mem: &Mem, addr: u16) -> u16 {
+    mem.read8(addr as usize) as u16 | (mem.read8(addr.wrapping_add(1) as usize) as u16) << 8
+}
+
+fn write16(mem: &mut Mem, addr: u16, value: u16) {
+    mem.write8(addr as usize, value as u8);
+    mem.write8(addr.wrapping_add(1) as usize, (value >> 8) as u8);
+}
+
+fn read_string(mem: &Mem, addr: u16) -> String {
+    let bytes: Vec<u8> = (0..=0xFFFF)
+        .map(|i: u16| mem.read8(addr.wrapping_add(i) as usize))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// unbuffered like sim65's, the output is there before the program exits
+fn write_all(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    writer.write_all(data)?;
+    writer.flush()
+}
@@ -0,0 +1,364 @@
+// https://vice-emu.sourceforge.io/vice_12.html (label files)
+// https://cc65.github.io/doc/debugging.html (ld65 --dbgfile)
+// https://sourceforge.net/p/acme-crossass/code-0/HEAD/tree/trunk/docs/QuickRef.txt
+// https://tass64.sourceforge.net/#commandline-options (--labels)
+
+use crate::elf::ElfFile;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// How far after a symbol without a known size an address is still shown as symbol+offset
+pub const MAX_SYMBOL_OFFSET: u16 = 0xFF;
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolError::Io(err) => write!(f, "{}", err),
+            SymbolError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl From<io::Error> for SymbolError {
+    fn from(err: io::Error) -> Self {
+        SymbolError::Io(err)
+    }
+}
+
+pub(crate) fn parse_error<T>(line: usize, message: &str) -> Result<T, SymbolError> {
+    Err(SymbolError::Parse { line: line + 1, message: message.to_string() })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolFormat {
+    /// `al C:c000 .main`, written by VICE, ld65 -Ln, ACME --vicelabels and 64tass --vice-labels
+    Vice,
+    /// ld65 --dbgfile
+    Ca65Dbg,
+    /// ACME --symbollist: `main = $c000 ; ?`
+    Acme,
+    /// 64tass --labels: `main = $c000`
+    Tass64,
+}
+
+impl SymbolFormat {
+    pub fn detect(text: &str) -> SymbolFormat {
+        let first = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+        if first.starts_with("version") && first.contains("major=") {
+            SymbolFormat::Ca65Dbg
+        } else if first.starts_with("al ") {
+            SymbolFormat::Vice
+        } else if text.contains("; ?") || text.contains("!addr") {
+            SymbolFormat::Acme
+        } else {
+            SymbolFormat::Tass64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+    pub size: Option<u16>,
+}
+
+/// Two way map between addresses and names
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    by_addr: BTreeMap<u16, Vec<Symbol>>,
+    by_name: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.insert_sized(name, addr, None);
+    }
+
+    pub fn insert_sized(&mut self, name: &str, addr: u16, size: Option<u16>) {
+        if let Some(old) = self.by_name.insert(name.to_string(), addr) {
+            self.remove_from_addr(name, old);
+        }
+        let symbol = Symbol { name: name.to_string(), addr, size };
+        self.by_addr.entry(addr).or_default().push(symbol);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<u16> {
+        let addr = self.by_name.remove(name)?;
+        self.remove_from_addr(name, addr);
+        Some(addr)
+    }
+
+    fn remove_from_addr(&mut self, name: &str, addr: u16) {
+        if let Some(symbols) = self.by_addr.get_mut(&addr) {
+            symbols.retain(|sym| sym.name != name);
+            if symbols.is_empty() {
+                self.by_addr.remove(&addr);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &SymbolTable) {
+        for sym in other.iter() {
+            self.insert_sized(&sym.name, sym.addr, sym.size);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// All symbols, ordered by address
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.by_addr.values().flatten()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The preferred name for exactly this address. Local labels are only used when there is
+    /// nothing else
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        let symbols = self.by_addr.get(&addr)?;
+        symbols
+            .iter()
+            .find(|sym| !is_local(&sym.name))
+            .or_else(|| symbols.first())
+            .map(|sym| sym.name.as_str())
+    }
+
+    pub fn names_at(&self, addr: u16) -> impl Iterator<Item = &str> {
+        self.by_addr.get(&addr).into_iter().flatten().map(|sym| sym.name.as_str())
+    }
+
+    /// The closest symbol at or before `addr` and the offset from it
+    pub fn symbol_for(&self, addr: u16) -> Option<(&Symbol, u16)> {
+        let (sym_addr, symbols) = self.by_addr.range(..=addr).next_back()?;
+        let offset = addr - sym_addr;
+        let symbol = symbols.iter().find(|sym| !is_local(&sym.name)).unwrap_or(&symbols[0]);
+        let max_offset = symbol.size.map_or(MAX_SYMBOL_OFFSET, |size| size.saturating_sub(1));
+        if offset > max_offset {
+            return None;
+        }
+        Some((symbol, offset))
+    }
+
+    /// `main`, `main+3` or None when no symbol is close enough
+    pub fn symbolize(&self, addr: u16) -> Option<String> {
+        self.symbol_for(addr).map(|(sym, offset)| {
+            if offset == 0 {
+                sym.name.clone()
+            } else {
+                format!("{}+{}", sym.name, offset)
+            }
+        })
+    }
+
+    /// Like symbolize, but falls back to `$FCE5`
+    pub fn format_addr(&self, addr: u16) -> String {
+        self.symbolize(addr).unwrap_or_else(|| format!("${:04X}", addr))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SymbolTable, SymbolError> {
+        let text = fs::read_to_string(path)?;
+        SymbolTable::parse(&text, SymbolFormat::detect(&text))
+    }
+
+    pub fn parse(text: &str, format: SymbolFormat) -> Result<SymbolTable, SymbolError> {
+        let mut table = SymbolTable::new();
+        match format {
+            SymbolFormat::Vice => table.parse_vice(text)?,
+            SymbolFormat::Ca65Dbg => table.parse_ca65_dbg(text)?,
+            SymbolFormat::Acme | SymbolFormat::Tass64 => table.parse_assignments(text)?,
+        }
+        Ok(table)
+    }
+
+    fn parse_vice(&mut self, text: &str) -> Result<(), SymbolError> {
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("al") => {}
+                // other monitor commands (break, ...) can be part of the file too
+                _ => continue,
+            }
+            let (addr, name) = match (words.next(), words.next()) {
+                (Some(addr), Some(name)) => (addr, name),
+                _ => return parse_error(i, "expected 'al <address> .<label>'"),
+            };
+            // optional memory space prefix (C:, 8:, ...)
+            let addr = addr.rsplit(':').next().unwrap_or(addr);
+            let addr = match u32::from_str_radix(addr, 16) {
+                Ok(addr) if addr <= 0xFFFF => addr as u16,
+                _ => return parse_error(i, "invalid address"),
+            };
+            self.insert(name.trim_start_matches('.'), addr);
+        }
+        Ok(())
+    }
+
+    fn parse_assignments(&mut self, text: &str) -> Result<(), SymbolError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let line = line.strip_prefix("!addr").unwrap_or(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return parse_error(i, "expected '<label> = <value>'"),
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return parse_error(i, "invalid label name");
+            }
+            match parse_number(value) {
+                Some(addr) if addr <= 0xFFFF => self.insert(name, addr as u16),
+                // constants bigger than the address space are not addresses
+                Some(_) => {}
+                None => return parse_error(i, "invalid value"),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_ca65_dbg(&mut self, text: &str) -> Result<(), SymbolError> {
+        let mut scopes: HashMap<String, (String, Option<String>)> = HashMap::new();
+        let mut syms = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let (kind, attrs) = match line.find(char::is_whitespace) {
+                Some(pos) => (&line[..pos], parse_dbg_attributes(&line[pos..])),
+                None => continue,
+            };
+            match kind {
+                "scope" => {
+                    let id = attrs.get("id").cloned().unwrap_or_default();
+                    let name = attrs.get("name").cloned().unwrap_or_default();
+                    scopes.insert(id, (name, attrs.get("parent").cloned()));
+                }
+                "sym" => {
+                    if attrs.get("type").map(String::as_str) != Some("lab") {
+                        continue;
+                    }
+                    // imports have no value
+                    let val = match attrs.get("val") {
+                        Some(val) => val,
+                        None => continue,
+                    };
+                    let addr = match parse_number(val) {
+                        Some(addr) if addr <= 0xFFFF => addr as u16,
+                        _ => return parse_error(i, "invalid symbol value"),
+                    };
+                    let size = attrs
+                        .get("size")
+                        .and_then(|size| parse_number(size))
+                        .map(|size| size.min(0xFFFF) as u16);
+                    syms.push((i, attrs, addr, size));
+                }
+                _ => {}
+            }
+        }
+        let ids: HashMap<String, String> = syms
+            .iter()
+            .filter_map(|(_, attrs, _, _)| {
+                Some((attrs.get("id")?.clone(), attrs.get("name")?.clone()))
+            })
+            .collect();
+        for (i, attrs, addr, size) in syms {
+            let mut name = match attrs.get("name") {
+                Some(name) => name.clone(),
+                None => return parse_error(i, "symbol without name"),
+            };
+            // cheap locals (@loop) belong to the previous normal label
+            if let Some(parent) = attrs.get("parent").and_then(|parent| ids.get(parent)) {
+                name = format!("{}{}", parent, name);
+            }
+            let mut scope = attrs.get("scope").cloned();
+            let mut depth = 0;
+            while let Some((scope_name, parent)) = scope.and_then(|id| scopes.get(&id)) {
+                // a chain longer than the number of scopes goes round in a cycle
+                depth += 1;
+                if depth > scopes.len() {
+                    return parse_error(i, "scope parents form a cycle");
+                }
+                if !scope_name.is_empty() {
+                    name = format!("{}::{}", scope_name, name);
+                }
+                scope = parent.clone();
+            }
+            self.insert_sized(&name, addr, size);
+        }
+        Ok(())
+    }
+}
+
+impl From<&ElfFile> for SymbolTable {
+    fn from(elf: &ElfFile) -> Self {
+        let mut table = SymbolTable::new();
+        for sym in &elf.symbols {
+            let size = if sym.size > 0 { Some(sym.size.min(0xFFFF) as u16) } else { None };
+            table.insert_sized(&sym.name, sym.addr, size);
+        }
+        table
+    }
+}
+
+fn is_local(name: &str) -> bool {
+    name.starts_with('@') || name.starts_with('.') || name.contains('@')
+}
+
+/// `$c000`, `0xc000`, `%1010`, `&777` (octal) or decimal
+pub fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text.strip_prefix('%') {
+        u32::from_str_radix(bin, 2).ok()
+    } else if let Some(oct) = text.strip_prefix('&') {
+        u32::from_str_radix(oct, 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// id=0,name="main",val=0xC000 -> map. Values can be quoted and contain commas
+pub(crate) fn parse_dbg_attributes(text: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut chars = text.trim().chars().peekable();
+    loop {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() {
+            break;
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            value = chars.by_ref().take_while(|c| *c != '"').collect();
+            chars.next(); // the comma
+        } else {
+            value.extend(chars.by_ref().take_while(|c| *c != ','));
+        }
+        attrs.insert(key.trim().to_string(), value);
+    }
+    attrs
+}
@@ -0,0 +1,193 @@
+// https://www.qmtpro.com/~nes/misc/nestest.log
+// https://www.nesdev.org/wiki/Emulator_tests (nestest)
+//
+// One line per instruction, logged before it runs, in the nestest.log layout:
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+
+use crate::disasm::{decode, Disassembler, Instruction, Syntax};
+use crate::opcodes::AddressingMode;
+use crate::{Cpu, Peek};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Tracer {
+        Tracer { out: Box::new(out) }
+    }
+
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn trace(&mut self, cpu: &Cpu) -> io::Result<()> {
+        writeln!(self.out, "{}", trace_line(cpu))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The trace line for the instruction at `cpu.pc`, without a line break
+pub fn trace_line(cpu: &Cpu) -> String {
+    let inst = decode(cpu, cpu.pc);
+    let bytes: Vec<String> = inst.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+    let mut text = Disassembler::new(Syntax::Generic).format(&inst);
+    if !text.starts_with('*') {
+        text.insert(0, ' ');
+    }
+    text.push_str(&annotation(cpu, &inst));
+    // nestest runs the NES PPU at three dots per cpu cycle, 341 dots per scanline
+    let dots = cpu.cycles_run as u64 * 3;
+    format!(
+        "{:04X}  {:<8} {:<33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        inst.addr,
+        bytes.join(" "),
+        text,
+        cpu.regs[Cpu::REG_A],
+        cpu.regs[Cpu::REG_X],
+        cpu.regs[Cpu::REG_Y],
+        cpu.regs[Cpu::REG_STAT],
+        cpu.regs[Cpu::REG_SP],
+        dots / 341 % 262,
+        dots % 341,
+        cpu.cycles_run
+    )
+}
+
+// The effective address and the memory contents before the instruction runs, like nestest
+fn annotation(cpu: &Cpu, inst: &Instruction) -> String {
+    let x = cpu.regs[Cpu::REG_X];
+    let y = cpu.regs[Cpu::REG_Y];
+    let zp_pointer = |addr: u8| {
+        cpu.peek8(addr as u16) as u16 | (cpu.peek8(addr.wrapping_add(1) as u16) as u16) << 8
+    };
+    let operand = inst.operand;
+    match inst.info.mode {
+        AddressingMode::ZeroPage => format!(" = {:02X}", cpu.peek8(operand)),
+        AddressingMode::Absolute if inst.info.mnemonic != "JMP" && inst.info.mnemonic != "JSR" => {
+            format!(" = {:02X}", cpu.peek8(operand))
+        }
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let index = if inst.info.mode == AddressingMode::ZeroPageX { x } else { y };
+            let addr = (operand as u8).wrapping_add(index) as u16;
+            format!(" @ {:02X} = {:02X}", addr, cpu.peek8(addr))
+        }
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let index = if inst.info.mode == AddressingMode::AbsoluteX { x } else { y };
+            let addr = operand.wrapping_add(index as u16);
+            format!(" @ {:04X} = {:02X}", addr, cpu.peek8(addr))
+        }
+        AddressingMode::IndirectX => {
+            let pointer = (operand as u8).wrapping_add(x);
+            let addr = zp_pointer(pointer);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, addr, cpu.peek8(addr))
+        }
+        AddressingMode::IndirectY => {
+            let base = zp_pointer(operand as u8);
+            let addr = base.wrapping_add(y as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, addr, cpu.peek8(addr))
+        }
+        AddressingMode::Indirect => {
+            // the 6502 doesn't carry into the high byte of the pointer
+            let high = (operand & 0xFF00) | (operand as u8).wrapping_add(1) as u16;
+            format!(" = {:04X}", cpu.peek8(operand) as u16 | (cpu.peek8(high) as u16) << 8)
+        }
+        _ => String::new(),
+    }
+}
+
+/// The cpu state of one trace line, parsed from our own log, nestest.log or any emulator log
+/// with a leading PC and `A:` `X:` `Y:` `P:` `SP:` fields
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub cycles: Option<u64>,
+}
+
+impl TraceRecord {
+    pub fn from_cpu(cpu: &Cpu) -> TraceRecord {
+        TraceRecord {
+            pc: cpu.pc,
+            a: cpu.regs[Cpu::REG_A],
+            x: cpu.regs[Cpu::REG_X],
+            y: cpu.regs[Cpu::REG_Y],
+            p: cpu.regs[Cpu::REG_STAT],
+            sp: cpu.regs[Cpu::REG_SP],
+            cycles: Some(cpu.cycles_run as u64),
+        }
+    }
+
+    /// None for lines that are not instructions, like comments or headers
+    pub fn parse(line: &str) -> Option<TraceRecord> {
+        let pc = u16::from_str_radix(line.get(..4)?, 16).ok()?;
+        let field = |name: &str| {
+            let start = line.find(name)? + name.len();
+            let digits = line[start..].split(|c: char| !c.is_ascii_hexdigit()).next()?;
+            u8::from_str_radix(digits, 16).ok()
+        };
+        let cycles = line.find("CYC:").and_then(|start| {
+            line[start + 4..].split_whitespace().next().and_then(|cyc| cyc.parse().ok())
+        });
+        Some(TraceRecord {
+            pc,
+            a: field(" A:")?,
+            x: field(" X:")?,
+            y: field(" Y:")?,
+            p: field(" P:")?,
+            sp: field(" SP:")?,
+            cycles,
+        })
+    }
+
+    /// A description of each field that differs from `expected`, empty when they match.
+    /// Cycles are only compared when both records have them
+    pub fn diff(&self, expected: &TraceRecord) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if self.pc != expected.pc {
+            diffs.push(format!("PC: expected {:04X}, got {:04X}", expected.pc, self.pc));
+        }
+        let regs = [
+            ("A", expected.a, self.a),
+            ("X", expected.x, self.x),
+            ("Y", expected.y, self.y),
+            ("SP", expected.sp, self.sp),
+        ];
+        for (name, expected, got) in regs.iter() {
+            if expected != got {
+                diffs.push(format!("{}: expected {:02X}, got {:02X}", name, expected, got));
+            }
+        }
+        if self.p != expected.p {
+            let changed = self.p ^ expected.p;
+            let flags: Vec<String> = "NV-BDIZC"
+                .chars()
+                .enumerate()
+                .filter(|(i, _)| changed & (0x80 >> i) != 0)
+                .map(|(_, flag)| flag.to_string())
+                .collect();
+            diffs.push(format!(
+                "P: expected {:02X}, got {:02X} (flags {} differ)",
+                expected.p,
+                self.p,
+                flags.join(" ")
+            ));
+        }
+        if let (Some(expected), Some(got)) = (expected.cycles, self.cycles) {
+            if expected != got {
+                diffs.push(format!("CYC: expected {}, got {}", expected, got));
+            }
+        }
+        diffs
+    }
+}
@@ -0,0 +1,84 @@
+// Host routines in place of 6502 code. A trap is a closure registered on an address, when the cpu
+// is about to run the instruction there it calls the closure instead and returns as an RTS would.
+// The closure gets the cpu with its registers and memory, arguments come in and results go out
+// the way the routine it stands in for passes them. That stubs out KERNAL or BIOS routines like
+// CHROUT and GETIN without writing ROM code for them.
+//
+// A trap and its return take a step of their own and no cycles. Traps aren't part of snapshots,
+// running forward again after going back in the debugger calls them again.
+
+use crate::Cpu;
+use std::collections::HashMap;
+
+pub type Trap = Box<dyn FnMut(&mut Cpu)>;
+
+#[derive(Default)]
+pub struct Traps {
+    traps: HashMap<u16, Trap>,
+    // the traps taken out to run, and whether they removed themselves. A trap that runs 6502 code
+    // can reach other traps, the innermost one is last
+    running: Vec<(u16, bool)>,
+}
+
+impl Traps {
+    pub fn new() -> Traps {
+        Traps::default()
+    }
+
+    /// Registers `trap` on `addr`, in place of the one there
+    pub fn set<F: FnMut(&mut Cpu) + 'static>(&mut self, addr: u16, trap: F) {
+        self.traps.insert(addr, Box::new(trap));
+    }
+
+    /// Whether there was a trap on `addr`
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let running =
+            self.running.iter_mut().rev().find(|(running, removed)| *running == addr && !*removed);
+        if let Some((_, removed)) = running {
+            *removed = true;
+            return true;
+        }
+        self.traps.remove(&addr).is_some()
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.traps.contains_key(&addr) || self.taken().any(|taken| taken == addr)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.traps.len() + self.taken().count()
+    }
+
+    /// The trapped addresses, in order
+    pub fn addresses(&self) -> Vec<u16> {
+        let mut addresses: Vec<u16> = self.traps.keys().copied().chain(self.taken()).collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    // The running traps that go back in when they return, unless one was set in their place
+    fn taken(&self) -> impl Iterator<Item = u16> + '_ {
+        self.running
+            .iter()
+            .filter(move |(addr, removed)| !removed && !self.traps.contains_key(addr))
+            .map(|&(addr, _)| addr)
+    }
+
+    // Taken out for the call so the closure can have the cpu, put back unless it removed or
+    // replaced itself
+    pub(crate) fn take(&mut self, addr: u16) -> Option<Trap> {
+        let trap = self.traps.remove(&addr)?;
+        self.running.push((addr, false));
+        Some(trap)
+    }
+
+    pub(crate) fn put_back(&mut self, addr: u16, trap: Trap) {
+        if let Some((_, false)) = self.running.pop() {
+            self.traps.entry(addr).or_insert(trap);
+        }
+    }
+}
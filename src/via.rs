@@ -0,0 +1,547 @@
+// MOS 6522 Versatile Interface Adapter: two 8 bit ports with data direction registers, the
+// CA1/CA2 and CB1/CB2 control lines, two 16 bit timers, a shift register and an interrupt flag
+// register that drives IRQ. The 16 registers repeat over whatever range the chip is mapped at.
+//
+// Timing follows the data sheet to the cycle: a timer written in one cycle shows the loaded value
+// in the next and counts down from there, through 0 to $FFFF, where it times out. That puts the
+// interrupt N + 1.5 cycles after the write, seen by the cpu after N + 2, and gives free running
+// T1 a period of N + 2.
+//
+// The pins are set from outside with `set_port_a`, `set_ca1` and friends, and what the chip
+// drives is read back with `port_a`, `ca2`, ...
+//
+// http://archive.6502.org/datasheets/mos_6522_preliminary_nov_1977.pdf
+// http://archive.6502.org/datasheets/wdc_w65c22_sep_2008.pdf
+
+use crate::device::Device;
+
+#[derive(Debug, Clone)]
+pub struct Via {
+    orb: u8,
+    ora: u8,
+    ddrb: u8,
+    ddra: u8,
+    // levels driven on the port pins from outside, inputs read high when nothing drives them
+    pins_a: u8,
+    pins_b: u8,
+    // inputs captured on an active CA1 / CB1 edge when latching is on
+    latch_a: u8,
+    latch_b: u8,
+    t1_counter: u16,
+    t1_latch: u16,
+    // the counter was just loaded and doesn't count in this cycle
+    t1_load: bool,
+    t1_armed: bool,
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch_low: u8,
+    t2_load: bool,
+    t2_armed: bool,
+    sr: u8,
+    sr_running: bool,
+    sr_bits: u8,
+    // cycles to the next edge of the shift clock when it comes from T2
+    sr_divider: u16,
+    sr_clock: bool,
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    // CA2 and CB2 as outputs, in handshake and pulse mode or when shifting out
+    ca2_out: bool,
+    cb2_out: bool,
+    ca2_pulse: bool,
+    cb2_pulse: bool,
+}
+
+impl Default for Via {
+    fn default() -> Self {
+        Via::new()
+    }
+}
+
+impl Via {
+    // registers
+    pub const ORB: u16 = 0x0;
+    pub const ORA: u16 = 0x1;
+    pub const DDRB: u16 = 0x2;
+    pub const DDRA: u16 = 0x3;
+    pub const T1C_L: u16 = 0x4;
+    pub const T1C_H: u16 = 0x5;
+    pub const T1L_L: u16 = 0x6;
+    pub const T1L_H: u16 = 0x7;
+    pub const T2C_L: u16 = 0x8;
+    pub const T2C_H: u16 = 0x9;
+    pub const SR: u16 = 0xA;
+    pub const ACR: u16 = 0xB;
+    pub const PCR: u16 = 0xC;
+    pub const IFR: u16 = 0xD;
+    pub const IER: u16 = 0xE;
+    /// ORA without the handshake
+    pub const ORA_NH: u16 = 0xF;
+
+    // interrupt flags, IFR and IER
+    pub const IRQ_CA2: u8 = 0x01;
+    pub const IRQ_CA1: u8 = 0x02;
+    pub const IRQ_SR: u8 = 0x04;
+    pub const IRQ_CB2: u8 = 0x08;
+    pub const IRQ_CB1: u8 = 0x10;
+    pub const IRQ_T2: u8 = 0x20;
+    pub const IRQ_T1: u8 = 0x40;
+    /// Set in IFR when any enabled flag is, the IRQ line
+    pub const IRQ_ANY: u8 = 0x80;
+
+    // auxiliary control register
+    pub const ACR_LATCH_A: u8 = 0x01;
+    pub const ACR_LATCH_B: u8 = 0x02;
+    pub const ACR_SR_MASK: u8 = 0x1C;
+    /// T2 counts pulses on PB6 instead of cycles
+    pub const ACR_T2_PULSES: u8 = 0x20;
+    pub const ACR_T1_FREE_RUN: u8 = 0x40;
+    /// T1 drives PB7
+    pub const ACR_T1_PB7: u8 = 0x80;
+
+    pub fn new() -> Via {
+        Via {
+            orb: 0,
+            ora: 0,
+            ddrb: 0,
+            ddra: 0,
+            pins_a: 0xFF,
+            pins_b: 0xFF,
+            latch_a: 0,
+            latch_b: 0,
+            t1_counter: 0,
+            t1_latch: 0,
+            t1_load: false,
+            t1_armed: false,
+            pb7: true,
+            t2_counter: 0,
+            t2_latch_low: 0,
+            t2_load: false,
+            t2_armed: false,
+            sr: 0,
+            sr_running: false,
+            sr_bits: 0,
+            sr_divider: 0,
+            sr_clock: true,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: true,
+            cb2_out: true,
+            ca2_pulse: false,
+            cb2_pulse: false,
+        }
+    }
+
+    /// Levels on the port A pins, ORA where DDRA makes them outputs
+    pub fn port_a(&self) -> u8 {
+        self.ora & self.ddra | self.pins_a & !self.ddra
+    }
+
+    /// Levels on the port B pins, PB7 comes from T1 when ACR says so
+    pub fn port_b(&self) -> u8 {
+        self.port_b_with(self.pins_b)
+    }
+
+    fn port_b_with(&self, input: u8) -> u8 {
+        let pins = self.orb & self.ddrb | input & !self.ddrb;
+        if self.acr & Via::ACR_T1_PB7 != 0 {
+            pins & 0x7F | (self.pb7 as u8) << 7
+        } else {
+            pins
+        }
+    }
+
+    /// Drives the port A pins, only the ones that are inputs are seen
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    /// Drives the port B pins. Falling edges on PB6 count down T2 in pulse counting mode
+    pub fn set_port_b(&mut self, pins: u8) {
+        let falling = self.pins_b & !pins & 0x40 != 0;
+        self.pins_b = pins;
+        if falling && self.acr & Via::ACR_T2_PULSES != 0 {
+            self.t2_count();
+        }
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        if level == self.ca1 {
+            return;
+        }
+        self.ca1 = level;
+        if level == (self.pcr & 0x01 != 0) {
+            self.ifr |= Via::IRQ_CA1;
+            if self.acr & Via::ACR_LATCH_A != 0 {
+                self.latch_a = self.port_a();
+            }
+            if self.ca2_control() == 0b100 {
+                self.ca2_out = true;
+            }
+        }
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        if level == self.ca2 {
+            return;
+        }
+        self.ca2 = level;
+        let control = self.ca2_control();
+        if control & 0b100 == 0 && level == (control & 0b010 != 0) {
+            self.ifr |= Via::IRQ_CA2;
+        }
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        if level == self.cb1 {
+            return;
+        }
+        self.cb1 = level;
+        if self.sr_mode() & 0b011 == 0b011 {
+            self.shift_edge(level);
+        }
+        if level == (self.pcr & 0x10 != 0) {
+            self.ifr |= Via::IRQ_CB1;
+            if self.acr & Via::ACR_LATCH_B != 0 {
+                self.latch_b = self.pins_b;
+            }
+            if self.cb2_control() == 0b100 {
+                self.cb2_out = true;
+            }
+        }
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        if level == self.cb2 {
+            return;
+        }
+        self.cb2 = level;
+        let control = self.cb2_control();
+        if control & 0b100 == 0 && level == (control & 0b010 != 0) {
+            self.ifr |= Via::IRQ_CB2;
+        }
+    }
+
+    /// Level of CA2, the input when it isn't an output
+    pub fn ca2(&self) -> bool {
+        match self.ca2_control() {
+            0b100 | 0b101 => self.ca2_out,
+            0b110 => false,
+            0b111 => true,
+            _ => self.ca2,
+        }
+    }
+
+    /// Level of CB1, the shift clock when the shift register makes it
+    pub fn cb1(&self) -> bool {
+        match self.sr_mode() {
+            0b001 | 0b010 | 0b100 | 0b101 | 0b110 => self.sr_clock,
+            _ => self.cb1,
+        }
+    }
+
+    /// Level of CB2, the data when shifting out
+    pub fn cb2(&self) -> bool {
+        if self.sr_mode() & 0b100 != 0 {
+            return self.cb2_out;
+        }
+        match self.cb2_control() {
+            0b100 | 0b101 => self.cb2_out,
+            0b110 => false,
+            0b111 => true,
+            _ => self.cb2,
+        }
+    }
+
+    fn ca2_control(&self) -> u8 {
+        (self.pcr >> 1) & 0b111
+    }
+
+    fn cb2_control(&self) -> u8 {
+        (self.pcr >> 5) & 0b111
+    }
+
+    fn sr_mode(&self) -> u8 {
+        (self.acr & Via::ACR_SR_MASK) >> 2
+    }
+
+    // Reading or writing a port clears its interrupt flags, CA2 / CB2 only when they aren't an
+    // independent interrupt input
+    fn clear_port_flags(&mut self, control: u8, flag1: u8, flag2: u8) {
+        self.ifr &= !flag1;
+        if control & 0b101 != 0b001 {
+            self.ifr &= !flag2;
+        }
+    }
+
+    fn port_a_access(&mut self) {
+        let control = self.ca2_control();
+        self.clear_port_flags(control, Via::IRQ_CA1, Via::IRQ_CA2);
+        if control == 0b100 || control == 0b101 {
+            self.ca2_out = false;
+            self.ca2_pulse = control == 0b101;
+        }
+    }
+
+    fn start_shift(&mut self) {
+        self.ifr &= !Via::IRQ_SR;
+        self.sr_running = self.sr_mode() != 0;
+        self.sr_bits = 0;
+        self.sr_divider = self.t2_latch_low as u16 + 1;
+    }
+
+    // One edge of the shift clock. Bits go out on CB2 on the falling edge and come in from it on
+    // the rising one, shifting out rotates the register so it ends where it started
+    fn shift_edge(&mut self, level: bool) {
+        if !self.sr_running {
+            return;
+        }
+        self.sr_clock = level;
+        let out = self.sr_mode() & 0b100 != 0;
+        if !level {
+            if out {
+                self.cb2_out = self.sr & 0x80 != 0;
+                self.sr = self.sr.rotate_left(1);
+            }
+            return;
+        }
+        if !out {
+            self.sr = self.sr << 1 | self.cb2 as u8;
+        }
+        self.sr_bits += 1;
+        if self.sr_bits == 8 {
+            self.sr_bits = 0;
+            // free running shift out goes on forever
+            if self.sr_mode() != 0b100 {
+                self.sr_running = false;
+                self.ifr |= Via::IRQ_SR;
+            }
+        }
+    }
+
+    fn t1_timeout(&mut self) {
+        let free_run = self.acr & Via::ACR_T1_FREE_RUN != 0;
+        if self.t1_armed {
+            self.ifr |= Via::IRQ_T1;
+            self.pb7 = !free_run || !self.pb7;
+            self.t1_armed = free_run;
+        }
+        if free_run {
+            self.t1_load = true;
+        }
+    }
+
+    fn t2_count(&mut self) {
+        self.t2_counter = self.t2_counter.wrapping_sub(1);
+        if self.t2_counter == 0xFFFF && self.t2_armed {
+            self.ifr |= Via::IRQ_T2;
+            self.t2_armed = false;
+        }
+    }
+
+    fn cycle(&mut self) {
+        if self.t1_load {
+            self.t1_counter = self.t1_latch;
+            self.t1_load = false;
+        } else {
+            self.t1_counter = self.t1_counter.wrapping_sub(1);
+            if self.t1_counter == 0xFFFF {
+                self.t1_timeout();
+            }
+        }
+        if self.t2_load {
+            self.t2_load = false;
+        } else if self.acr & Via::ACR_T2_PULSES == 0 {
+            self.t2_count();
+        }
+        if self.sr_running {
+            match self.sr_mode() {
+                0b010 | 0b110 => self.shift_edge(!self.sr_clock),
+                0b001 | 0b100 | 0b101 => {
+                    if self.sr_divider == 0 {
+                        self.sr_divider = self.t2_latch_low as u16 + 1;
+                        self.shift_edge(!self.sr_clock);
+                    } else {
+                        self.sr_divider -= 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.ca2_pulse {
+            self.ca2_pulse = false;
+            self.ca2_out = true;
+        }
+        if self.cb2_pulse {
+            self.cb2_pulse = false;
+            self.cb2_out = true;
+        }
+    }
+
+    fn ifr_with_irq(&self) -> u8 {
+        if self.irq() {
+            self.ifr | Via::IRQ_ANY
+        } else {
+            self.ifr
+        }
+    }
+
+    fn port_b_in(&self) -> u8 {
+        if self.acr & Via::ACR_LATCH_B != 0 {
+            self.port_b_with(self.latch_b)
+        } else {
+            self.port_b()
+        }
+    }
+
+    fn port_a_in(&self) -> u8 {
+        if self.acr & Via::ACR_LATCH_A != 0 {
+            self.latch_a
+        } else {
+            self.port_a()
+        }
+    }
+}
+
+impl Device for Via {
+    fn read(&mut self, offset: u16) -> u8 {
+        let value = self.peek(offset);
+        match offset & 0x0F {
+            Via::ORB => {
+                let control = self.cb2_control();
+                self.clear_port_flags(control, Via::IRQ_CB1, Via::IRQ_CB2);
+            }
+            Via::ORA => self.port_a_access(),
+            Via::T1C_L => self.ifr &= !Via::IRQ_T1,
+            Via::T2C_L => self.ifr &= !Via::IRQ_T2,
+            Via::SR => self.start_shift(),
+            _ => {}
+        }
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x0F {
+            Via::ORB => {
+                self.orb = value;
+                let control = self.cb2_control();
+                self.clear_port_flags(control, Via::IRQ_CB1, Via::IRQ_CB2);
+                // port B only handshakes on writes
+                if control == 0b100 || control == 0b101 {
+                    self.cb2_out = false;
+                    self.cb2_pulse = control == 0b101;
+                }
+            }
+            Via::ORA => {
+                self.ora = value;
+                self.port_a_access();
+            }
+            Via::DDRB => self.ddrb = value,
+            Via::DDRA => self.ddra = value,
+            Via::T1C_L | Via::T1L_L => self.t1_latch = self.t1_latch & 0xFF00 | value as u16,
+            Via::T1C_H => {
+                self.t1_latch = self.t1_latch & 0x00FF | (value as u16) << 8;
+                self.t1_counter = self.t1_latch;
+                self.t1_load = true;
+                self.t1_armed = true;
+                self.ifr &= !Via::IRQ_T1;
+                if self.acr & Via::ACR_T1_PB7 != 0 {
+                    self.pb7 = false;
+                }
+            }
+            Via::T1L_H => {
+                self.t1_latch = self.t1_latch & 0x00FF | (value as u16) << 8;
+                self.ifr &= !Via::IRQ_T1;
+            }
+            Via::T2C_L => self.t2_latch_low = value,
+            Via::T2C_H => {
+                self.t2_counter = (value as u16) << 8 | self.t2_latch_low as u16;
+                self.t2_load = true;
+                self.t2_armed = true;
+                self.ifr &= !Via::IRQ_T2;
+            }
+            Via::SR => {
+                self.sr = value;
+                self.start_shift();
+            }
+            Via::ACR => {
+                self.acr = value;
+                if self.sr_mode() == 0 {
+                    self.sr_running = false;
+                }
+            }
+            Via::PCR => self.pcr = value,
+            Via::IFR => self.ifr &= !value,
+            Via::IER => {
+                if value & 0x80 != 0 {
+                    self.ier |= value & 0x7F;
+                } else {
+                    self.ier &= !value;
+                }
+            }
+            // ORA_NH
+            _ => self.ora = value,
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset & 0x0F {
+            Via::ORB => self.port_b_in(),
+            Via::ORA => self.port_a_in(),
+            Via::DDRB => self.ddrb,
+            Via::DDRA => self.ddra,
+            Via::T1C_L => self.t1_counter as u8,
+            Via::T1C_H => (self.t1_counter >> 8) as u8,
+            Via::T1L_L => self.t1_latch as u8,
+            Via::T1L_H => (self.t1_latch >> 8) as u8,
+            Via::T2C_L => self.t2_counter as u8,
+            Via::T2C_H => (self.t2_counter >> 8) as u8,
+            Via::SR => self.sr,
+            Via::ACR => self.acr,
+            Via::PCR => self.pcr,
+            Via::IFR => self.ifr_with_irq(),
+            Via::IER => self.ier | 0x80,
+            _ => self.port_a_in(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.ifr & self.ier & 0x7F != 0
+    }
+
+    /// Clears the registers, timers, latches and the shift register keep their values
+    fn reset(&mut self) {
+        self.orb = 0;
+        self.ora = 0;
+        self.ddrb = 0;
+        self.ddra = 0;
+        self.acr = 0;
+        self.pcr = 0;
+        self.ifr = 0;
+        self.ier = 0;
+        self.t1_armed = false;
+        self.t2_armed = false;
+        self.sr_running = false;
+        self.pb7 = true;
+        self.ca2_out = true;
+        self.cb2_out = true;
+    }
+}
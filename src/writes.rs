@@ -0,0 +1,70 @@
+// Write history, kept while `Cpu::write_history` is set: for each address the last few writes
+// with the instruction that made them and the cycle they happened in. Answers "who wrote $0200
+// and when" without having to set a watchpoint and run again.
+
+use std::collections::VecDeque;
+
+const DEFAULT_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Write {
+    /// The instruction that wrote, for interrupts the one they came after
+    pub pc: u16,
+    /// `Cpu::cycles_run` after the write
+    pub cycle: u32,
+    pub value: u8,
+}
+
+pub struct WriteHistory {
+    depth: usize,
+    // oldest first
+    writes: Vec<VecDeque<Write>>,
+}
+
+impl Default for WriteHistory {
+    fn default() -> Self {
+        WriteHistory::with_depth(DEFAULT_DEPTH)
+    }
+}
+
+impl WriteHistory {
+    pub fn new() -> WriteHistory {
+        WriteHistory::default()
+    }
+
+    /// Keeps the last `depth` writes of each address
+    pub fn with_depth(depth: usize) -> WriteHistory {
+        WriteHistory { depth: depth.max(1), writes: vec![VecDeque::new(); 0x10000] }
+    }
+
+    pub fn record(&mut self, addr: u16, write: Write) {
+        let writes = &mut self.writes[addr as usize];
+        if writes.len() == self.depth {
+            writes.pop_front();
+        }
+        writes.push_back(write);
+    }
+
+    /// The last write to `addr`
+    pub fn last(&self, addr: u16) -> Option<Write> {
+        self.writes[addr as usize].back().copied()
+    }
+
+    /// The writes to `addr` that are kept, newest first
+    pub fn writes(&self, addr: u16) -> impl Iterator<Item = &Write> + '_ {
+        self.writes[addr as usize].iter().rev()
+    }
+
+    /// Drops the writes after `cycle`, for going back in time
+    pub fn forget_after(&mut self, cycle: u32) {
+        for writes in self.writes.iter_mut() {
+            while writes.back().is_some_and(|write| write.cycle > cycle) {
+                writes.pop_back();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.iter_mut().for_each(VecDeque::clear);
+    }
+}
@@ -0,0 +1,126 @@
+use emulator6502::acia::Acia;
+use emulator6502::asm::assemble;
+use emulator6502::debugger::*;
+use emulator6502::device::Device;
+use emulator6502::serial::{Host, Tcp};
+use emulator6502::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+struct Loopback {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Host for Loopback {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.borrow_mut().pop_front())
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        self.output.borrow_mut().push(byte);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_acia_registers() {
+    let mut acia = Acia::new();
+    assert_eq!(Acia::STATUS_TDRE, acia.peek(Acia::STATUS));
+    acia.write(Acia::COMMAND, Acia::COMMAND_DTR);
+    acia.write(Acia::CONTROL, 0x1F);
+    assert_eq!(0x1F, acia.read(Acia::CONTROL + 4), "mirrored");
+    acia.receive(b'A');
+    assert!(acia.irq());
+    assert_eq!(Acia::STATUS_IRQ | Acia::STATUS_RDRF | Acia::STATUS_TDRE, acia.read(Acia::STATUS));
+    assert!(!acia.irq(), "reading the status acknowledges");
+    acia.receive(b'B');
+    assert_eq!(Acia::STATUS_OVERRUN, acia.peek(Acia::STATUS) & Acia::STATUS_OVERRUN);
+    assert_eq!(b'A', acia.read(Acia::DATA));
+    assert_eq!(Acia::STATUS_TDRE, acia.peek(Acia::STATUS));
+
+    // receive interrupts off, transmit interrupts and echo
+    let host = Loopback::default();
+    acia.connect(host.clone());
+    acia.write(Acia::COMMAND, Acia::COMMAND_DTR | Acia::COMMAND_RX_IRQ_OFF | Acia::COMMAND_TX_IRQ);
+    acia.write(Acia::DATA, b'x');
+    assert!(acia.irq());
+    acia.read(Acia::STATUS);
+    acia.write(Acia::COMMAND, Acia::COMMAND_DTR | Acia::COMMAND_RX_IRQ_OFF | Acia::COMMAND_ECHO);
+    acia.receive(b'y');
+    assert!(!acia.irq());
+    assert_eq!(b"xy".to_vec(), *host.output.borrow());
+    acia.write(Acia::STATUS, 0);
+    assert_eq!(0, acia.peek(Acia::COMMAND), "programmed reset");
+}
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  lda #$09        ; receiver and its interrupts on
+            sta $8002
+            cli
+    loop:   jmp loop
+    irq:    pha
+            lda $8001       ; acknowledge
+            lda $8000
+            sta $8000
+            inc $10
+            pla
+            rti
+    .org $FFFE
+    .word irq
+";
+
+#[test]
+fn test_acia_host_and_going_back() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let host = Loopback::default();
+    host.input.borrow_mut().extend(b"hi");
+    let mut acia = Acia::new();
+    acia.connect(host.clone());
+    cpu.devices.map(0x8000..=0x8003, acia);
+    let mut debugger = Debugger::new(cpu);
+    while debugger.cpu.mem().read8(0x10) != 2 {
+        debugger.step();
+    }
+    assert_eq!(b"hi".to_vec(), *host.output.borrow());
+    let cycles = debugger.cpu.cycles_run;
+
+    while debugger.cpu.mem().read8(0x10) != 0 {
+        debugger.step_back();
+    }
+    host.input.borrow_mut().push_back(b'!');
+    while debugger.cpu.mem().read8(0x10) != 2 {
+        debugger.step();
+    }
+    assert_eq!(cycles, debugger.cpu.cycles_run, "the input comes at the same cycles again");
+    assert_eq!(b"hi".to_vec(), *host.output.borrow(), "and isn't echoed twice");
+    while debugger.cpu.mem().read8(0x10) != 3 {
+        debugger.step();
+    }
+    assert_eq!(b"hi!".to_vec(), *host.output.borrow());
+}
+
+#[test]
+fn test_acia_tcp_host() {
+    let mut tcp = Tcp::listen(0).unwrap();
+    let mut client = TcpStream::connect(("127.0.0.1", tcp.port().unwrap())).unwrap();
+    client.write_all(b"k").unwrap();
+    let mut byte = None;
+    while byte.is_none() {
+        byte = tcp.read().unwrap();
+    }
+    assert_eq!(Some(b'k'), byte);
+    tcp.write(b'!').unwrap();
+    let mut reply = [0];
+    client.read_exact(&mut reply).unwrap();
+    assert_eq!(b'!', reply[0]);
+}
@@ -0,0 +1,66 @@
+use emulator6502::apple1::{self, Terminal};
+use emulator6502::asm::assemble;
+use emulator6502::serial::Host;
+use emulator6502::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+struct Keyboard {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Host for Keyboard {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.borrow_mut().pop_front())
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        self.output.borrow_mut().push(byte);
+        Ok(())
+    }
+}
+
+// echoes the keys like the Woz Monitor does
+const ROM: &str = "
+    .org $FF00
+    reset:  ldy #$7F
+            sty $D012       ; DDRB
+            lda #$A7
+            sta $D011
+            sta $D013
+    next:   lda $D011
+            bpl next
+            lda $D010
+    echo:   bit $D012
+            bmi echo
+            sta $D012
+            jmp next
+    .org $FFFC
+    .word reset
+    .word $0000
+";
+
+#[test]
+fn test_apple1_terminal() {
+    let mut mem = Mem::new();
+    let mut cpu = Cpu::new(&mut mem);
+    let keyboard = Keyboard::default();
+    keyboard.input.borrow_mut().extend(b"8000.80ff\r\nabc\x7f");
+    keyboard.input.borrow_mut().extend([b'x'; 45].iter());
+    let mut terminal = Terminal::new();
+    terminal.connect(keyboard.clone());
+    let rom = assemble(ROM).unwrap().bytes();
+    assert_eq!(256, rom.len());
+    let index = apple1::setup(&mut cpu, &rom, terminal).unwrap();
+    assert_eq!(0xFF00, cpu.pc);
+    cpu.process(200_000);
+    let output = String::from_utf8(keyboard.output.borrow().clone()).unwrap();
+    assert_eq!(format!("8000.80FF\r\nABC_{}\r\n{}", "X".repeat(36), "X".repeat(9)), output, "upper case, 40 columns, nothing lost");
+    assert!(keyboard.input.borrow().is_empty());
+    assert_eq!(0xD8, cpu.devices.get::<Terminal>(index).unwrap().pia().port_a(), "the last key");
+    assert!(!cpu.devices.irq(), "IRQ isn't connected");
+}
@@ -0,0 +1,164 @@
+use emulator6502::asm::*;
+use emulator6502::*;
+use rstest::*;
+
+fn asm_bytes(source: &str) -> Vec<u8> {
+    assemble(source).unwrap().bytes()
+}
+
+#[rstest]
+#[case::implied("clc", &[0x18])]
+#[case::accumulator("asl a", &[0x0A])]
+#[case::accumulator_no_operand("lsr", &[0x4A])]
+#[case::immediate("lda #$CA", &[Cpu::LDA_IMMEDIATE, 0xCA])]
+#[case::immediate_negative("ldx #-1", &[Cpu::LDX_IMMEDIATE, 0xFF])]
+#[case::immediate_char("lda #'A'", &[Cpu::LDA_IMMEDIATE, 0x41])]
+#[case::zero("lda $80", &[Cpu::LDA_ZERO, 0x80])]
+#[case::zero_x("lda $80,x", &[Cpu::LDA_ZERO_X, 0x80])]
+#[case::zero_y("ldx $80, Y", &[Cpu::LDX_ZERO_Y, 0x80])]
+#[case::forced_abs("lda a:$80", &[Cpu::LDA_ABSOLUTE, 0x80, 0x00])]
+#[case::abs("LDA $1234", &[Cpu::LDA_ABSOLUTE, 0x34, 0x12])]
+#[case::abs_x("lda $1234,x", &[Cpu::LDA_ABSOLUTE_X, 0x34, 0x12])]
+#[case::abs_y("lda $1234,y", &[Cpu::LDA_ABSOLUTE_Y, 0x34, 0x12])]
+#[case::abs_y_without_zero_y("lda $12,y", &[Cpu::LDA_ABSOLUTE_Y, 0x12, 0x00])]
+#[case::ind_x("lda ($25,x)", &[Cpu::LDA_INDIRECT_X, 0x25])]
+#[case::ind_y("lda ($25),y", &[Cpu::LDA_INDIRECT_Y, 0x25])]
+#[case::indirect("jmp ($1234)", &[Cpu::JMP_INDIRECT, 0x34, 0x12])]
+#[case::parenthesized_expression("lda ($10+2)*2,x", &[Cpu::LDA_ZERO_X, 0x24])]
+#[case::illegal("lax $80", &[0xA7, 0x80])]
+fn test_asm_addressing_modes(#[case] source: &str, #[case] expected: &[u8]) {
+    assert_eq!(expected, &asm_bytes(source)[..]);
+}
+
+#[rstest]
+#[case::hex_bin_dec("$10 + %101 + 10", 31)]
+#[case::precedence("2 + 3 * 4", 14)]
+#[case::parens("(2 + 3) * 4", 20)]
+#[case::shift_and_mask("1 << 4 | 3 & 1", 17)]
+#[case::modulo("17 % 5", 2)]
+#[case::low_byte("<$1234", 0x34)]
+#[case::high_byte(">$1234", 0x12)]
+#[case::complement("~0 & $FF", 0xFF)]
+#[case::current_address("* + 1", 0x0301)]
+fn test_asm_expressions(#[case] expr: &str, #[case] expected: u16) {
+    let source = format!(".org $0300\n.word {}", expr);
+    assert_eq!(expected.to_le_bytes().to_vec(), asm_bytes(&source));
+}
+
+#[test]
+fn test_asm_labels_and_data() {
+    let asm = assemble(
+        "
+        .org $0800
+        count = 3
+        start:  ldx #count      ; forward and backward references
+        @loop:  dex
+                bne @loop
+                jmp end
+        table:  .byte 1, \"ab;\", <start, >start
+                .word start, table
+        end:    rts
+        ",
+    )
+    .unwrap();
+    assert_eq!(0x0800, asm.origin());
+    #[rustfmt::skip]
+    let expected = vec![
+        Cpu::LDX_IMMEDIATE, 3,
+        Cpu::DEX_IMPLIED,
+        Cpu::BNE_RELATIVE, 0xFD,
+        Cpu::JMP_ABSOLUTE, 0x12, 0x08,
+        1, b'a', b'b', b';', 0x00, 0x08,
+        0x00, 0x08, 0x08, 0x08,
+        Cpu::RTS_IMPLIED,
+    ];
+    assert_eq!(expected, asm.bytes());
+    assert_eq!(Some(0x0800), asm.symbols.lookup("start"));
+    assert_eq!(Some(0x0802), asm.symbols.lookup("start@loop"));
+    assert_eq!(Some(0x0812), asm.symbols.lookup("end"));
+    assert_eq!(None, asm.symbols.lookup("count"), "constants are not labels");
+}
+
+#[test]
+fn test_asm_local_labels_are_scoped() {
+    let bytes = asm_bytes(
+        "
+        first:  nop
+        @skip:  bne @skip
+        second: nop
+        @skip:  bne @skip
+        ",
+    );
+    assert_eq!(vec![Cpu::NOP_IMPLIED, Cpu::BNE_RELATIVE, 0xFE, Cpu::NOP_IMPLIED, Cpu::BNE_RELATIVE, 0xFE], bytes);
+}
+
+#[test]
+fn test_asm_forward_reference_keeps_absolute_size() {
+    let bytes = asm_bytes(
+        "
+        lda zp
+        zp = $10
+        lda zp
+        ",
+    );
+    assert_eq!(vec![Cpu::LDA_ABSOLUTE, 0x10, 0x00, Cpu::LDA_ZERO, 0x10], bytes);
+}
+
+#[test]
+fn test_asm_org_chunks_and_res() {
+    let asm = assemble(
+        "
+        .org $FFFC
+        .word reset, 0
+        .org $0200
+        reset: .res 3, $EA
+        ",
+    )
+    .unwrap();
+    assert_eq!(2, asm.chunks.len());
+    assert_eq!(Chunk { addr: 0xFFFC, data: vec![0x00, 0x02, 0x00, 0x00] }, asm.chunks[0]);
+    assert_eq!(Chunk { addr: 0x0200, data: vec![0xEA; 3] }, asm.chunks[1]);
+    assert_eq!(0xFFFC, asm.origin());
+}
+
+#[test]
+fn test_asm_program_runs() {
+    let asm = assemble(
+        "
+        .org $FCE2
+        start:
+            ldx #$05
+            lda #0
+        @add:
+            adc #$03
+            dex
+            bne @add
+            sta $0200
+        ",
+    );
+    let mut mem = Mem::new();
+    mem.reset();
+    asm.unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.process(2 + 2 + 5 * (2 + 2 + 3) - 1 + 4);
+    assert_eq!(15, cpu.regs[Cpu::REG_A]);
+    assert_eq!(15, mem.read8(0x0200));
+}
+
+#[rstest]
+#[case::unknown_instruction("nop\nfoo #1", 2, "unknown instruction foo")]
+#[case::unknown_directive(".bogus 1", 1, "unknown directive .bogus")]
+#[case::undefined_symbol("lda missing", 1, "undefined symbol missing")]
+#[case::duplicate_label("x1: nop\nx1: nop", 2, "x1 is already defined")]
+#[case::bad_mode("stx $1234,x", 1, "STX does not support this addressing mode")]
+#[case::immediate_range("lda #256", 1, "value 256 is out of range")]
+#[case::branch_range(".org $1000\nbeq $2000", 2, "branch target is out of range (4094 bytes)")]
+#[case::local_without_scope("@loop: nop", 1, "local label @loop without a previous label")]
+#[case::org_forward(".org later\nlater = 1", 1, "later must be defined before it is used here")]
+#[case::division_by_zero(".byte 1/0", 1, "division by zero")]
+#[case::overflow(".org $FFFF\nnop\nnop", 3, "program goes beyond $FFFF")]
+fn test_asm_errors(#[case] source: &str, #[case] line: usize, #[case] message: &str) {
+    let err = assemble(source).unwrap_err();
+    assert_eq!(AsmError { line, message: message.to_string() }, err);
+}
@@ -0,0 +1,128 @@
+use emulator6502::asm::assemble;
+use emulator6502::breadboard::{self, Board};
+use emulator6502::serial::Host;
+use emulator6502::*;
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+struct Screen {
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Host for Screen {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        Ok(None)
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        self.output.borrow_mut().push(byte);
+        Ok(())
+    }
+}
+
+// hello world from the videos, waiting for the busy flag
+const ROM: &str = "
+    PORTB = $6000
+    PORTA = $6001
+    DDRB = $6002
+    DDRA = $6003
+    E  = %10000000
+    RW = %01000000
+    RS = %00100000
+
+    .org $8000
+    reset:  ldx #$FF
+            txs
+            lda #%11111111
+            sta DDRB
+            lda #%11100000
+            sta DDRA
+            lda #%00111000      ; 8 bit, 2 lines
+            jsr lcd_instruction
+            lda #%00001110      ; display and cursor on
+            jsr lcd_instruction
+            lda #%00000110      ; increment
+            jsr lcd_instruction
+            lda #%00000001      ; clear
+            jsr lcd_instruction
+            ldx #0
+    print:  lda message,x
+            beq loop
+            jsr print_char
+            inx
+            jmp print
+    loop:   jmp loop
+
+    message: .byte \"Hello, world!\", 0
+
+    lcd_wait:
+            pha
+            lda #%00000000
+            sta DDRB
+    lcdbusy:
+            lda #RW
+            sta PORTA
+            lda #(RW | E)
+            sta PORTA
+            lda PORTB
+            and #%10000000
+            bne lcdbusy
+            lda #RW
+            sta PORTA
+            lda #%11111111
+            sta DDRB
+            pla
+            rts
+
+    lcd_instruction:
+            jsr lcd_wait
+            sta PORTB
+            lda #0
+            sta PORTA
+            lda #E
+            sta PORTA
+            lda #0
+            sta PORTA
+            rts
+
+    print_char:
+            jsr lcd_wait
+            sta PORTB
+            lda #RS
+            sta PORTA
+            lda #(RS | E)
+            sta PORTA
+            lda #RS
+            sta PORTA
+            rts
+
+    .org $FFFC
+    .word reset
+    .word $0000
+";
+
+#[test]
+fn test_breadboard_lcd() {
+    let mut mem = Mem::new();
+    let mut cpu = Cpu::new(&mut mem);
+    let screen = Screen::default();
+    let mut board = Board::new();
+    board.connect(screen.clone());
+    let rom = assemble(ROM).unwrap().bytes();
+    assert_eq!(0x8000, rom.len());
+    let index = breadboard::setup(&mut cpu, &rom, board).unwrap();
+    assert_eq!(0x8000, cpu.pc);
+    cpu.process(30_000);
+    let board = cpu.devices.get::<Board>(index).unwrap();
+    assert_eq!(vec!["Hello, world!   ", "                "], board.text());
+    assert_eq!(13, board.lcd().address());
+    let frame = "+----------------+\r\n|Hello, world!   |\r\n|                |\r\n+----------------+\r\n";
+    let output = String::from_utf8(screen.output.borrow().clone()).unwrap();
+    assert_eq!(frame, output);
+
+    cpu.process(50_000);
+    assert_eq!(frame.len(), screen.output.borrow().len(), "drawn again only when the text changes");
+    assert_eq!(Some(0x6000..=0x7FFF), cpu.devices.range(index), "the VIA repeats up to the ROM");
+}
@@ -0,0 +1,95 @@
+use emulator6502::asm::assemble;
+use emulator6502::callstack::CallStack;
+use emulator6502::*;
+use rstest::rstest;
+
+// A * X, the product in A (low) and Y (high), shift and add
+const MULTIPLY: &str = "
+    factor = $10
+    result = $11
+
+    .org $1000
+    multiply:
+            stx factor
+            ldx #8
+            sta $12
+            lda #0
+            sta result
+    next:   lsr $12
+            bcc skip
+            clc
+            adc factor
+    skip:   ror a
+            ror result
+            dex
+            bne next
+            tay
+            jsr get_low
+            rts
+    get_low: lda result
+            rts
+";
+
+fn setup(mem: &mut Mem) {
+    assemble(MULTIPLY).unwrap().load(mem);
+    // lda #1 / sec / rts, and a loop
+    mem.load_programm_at(0x2000, &[0xA9, 0x01, 0x38, 0x60]);
+    mem.load_programm_at(0x2100, &[0x4C, 0x00, 0x21]);
+    // pha / lda #$FE / pha / rts, returning to the sentinel with the real return address left
+    mem.load_programm_at(0x2200, &[0x48, 0xA9, 0xFE, 0x48, 0x60]);
+}
+
+#[rstest]
+#[case(0, 0)]
+#[case(1, 200)]
+#[case(12, 34)]
+#[case(255, 255)]
+#[case(128, 2)]
+fn test_call_multiply(#[case] a: u8, #[case] x: u8) {
+    let mut mem = Mem::new();
+    setup(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0400;
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    let result = cpu.call(0x1000, a, x, 0).unwrap();
+    assert_eq!(a as u16 * x as u16, result.a as u16 | (result.y as u16) << 8);
+    assert_eq!(0x0400, cpu.pc, "back where it was");
+    assert_eq!(0xFF, cpu.regs[Cpu::REG_SP]);
+}
+
+#[test]
+fn test_call_result() {
+    let mut mem = Mem::new();
+    setup(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    cpu.call_stack = Some(CallStack::new());
+    let result = cpu.call(0x2000, 0x80, 2, 3).unwrap();
+    assert_eq!(CallResult { a: 1, x: 2, y: 3, status: Cpu::FLAG_CARRY, cycles: 6 + 2 + 2 + 6 }, result);
+    assert_eq!(16, cpu.cycles_run);
+    assert_eq!(0, cpu.call_stack.as_ref().unwrap().depth());
+
+    // a trap in the routine
+    cpu.traps.set(0x2000, |cpu| cpu.regs[Cpu::REG_X] = 9);
+    assert_eq!(9, cpu.call(0x2000, 0, 0, 0).unwrap().x);
+
+    let timeout = cpu.call_with_limit(0x2100, 0, 0, 0, 1000).unwrap_err();
+    assert_eq!(0x2100, timeout.pc);
+    assert!(timeout.cycles >= 1000 && timeout.cycles < 1010);
+    assert_eq!(0xFD, cpu.regs[Cpu::REG_SP], "left where it stopped");
+}
+
+#[test]
+fn test_call_unbalanced_stack() {
+    let mut mem = Mem::new();
+    setup(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    let timeout = cpu.call(0x2200, 0xFF, 0, 0).unwrap_err();
+    assert_eq!(0xFFFF, timeout.pc);
+    assert_eq!(6 + 3 + 2 + 3 + 6, timeout.cycles);
+    assert_eq!(0xFD, cpu.regs[Cpu::REG_SP], "left where it stopped");
+}
@@ -0,0 +1,115 @@
+use emulator6502::asm::assemble;
+use emulator6502::callstack::*;
+use emulator6502::debugger::Debugger;
+use emulator6502::*;
+
+// outer calls inner, inner returns to main directly, then a jump through the RTS trick and a BRK
+const PROGRAM: &str = "
+    .org $0200
+    start:  jsr outer
+    after:  lda #>(target - 1)
+            pha
+            lda #<(target - 1)
+            pha
+    trick:  rts
+    target: brk
+            nop
+    done:   jmp done
+    outer:  jsr inner
+            rts
+    inner:  pla
+            pla
+    skip:   rts
+    handler: rti
+            .org $FFFE
+            .word handler
+";
+
+fn new_cpu(mem: &mut Mem) -> Cpu<'_> {
+    assemble(PROGRAM).unwrap().load(mem);
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.call_stack = Some(CallStack::new());
+    cpu
+}
+
+fn run_to(cpu: &mut Cpu, addr: u16) {
+    while cpu.pc != addr {
+        cpu.step();
+    }
+}
+
+fn call_stack<'a>(cpu: &'a Cpu) -> &'a CallStack {
+    cpu.call_stack.as_ref().unwrap()
+}
+
+#[test]
+fn test_callstack_calls_and_stack_tricks() {
+    let mut mem = Mem::new();
+    let mut cpu = new_cpu(&mut mem);
+    let symbols = assemble(PROGRAM).unwrap().symbols;
+    let addr = |name: &str| symbols.lookup(name).unwrap();
+    run_to(&mut cpu, addr("inner"));
+    let frames = call_stack(&cpu).frames().to_vec();
+    assert_eq!(Frame { kind: FrameKind::Call, caller: 0x0200, target: addr("outer"), return_addr: addr("after"), sp: 0xFF, cycles: 6 }, frames[0]);
+    assert_eq!((addr("outer"), addr("inner"), addr("outer") + 3, 0xFD), (frames[1].caller, frames[1].target, frames[1].return_addr, frames[1].sp));
+
+    // PLA PLA RTS returns from both subroutines at once, that's not a mismatch
+    run_to(&mut cpu, addr("after"));
+    assert_eq!(0, call_stack(&cpu).depth());
+    assert_eq!(None, call_stack(&cpu).last_mismatch);
+
+    run_to(&mut cpu, addr("target"));
+    assert_eq!(Some(Mismatch { pc: addr("trick"), target: addr("target"), expected: None }), call_stack(&cpu).last_mismatch);
+
+    cpu.step();
+    assert_eq!(FrameKind::Brk, call_stack(&cpu).frames()[0].kind);
+    assert_eq!((addr("target"), addr("handler")), (call_stack(&cpu).frames()[0].caller, call_stack(&cpu).frames()[0].target));
+    run_to(&mut cpu, addr("done"));
+    assert_eq!(0, call_stack(&cpu).depth());
+    assert_eq!(1, call_stack(&cpu).mismatches);
+}
+
+#[test]
+fn test_callstack_return_kinds_and_reset() {
+    let mut stack = CallStack::new();
+    let frame = Frame { kind: FrameKind::Irq, caller: 0x1000, target: 0x2000, return_addr: 0x1000, sp: 0xFF, cycles: 0 };
+    stack.push(frame);
+    let mismatch = stack.ret(0x2005, 0x1000, 0xFF, false).unwrap();
+    assert_eq!(Some(frame), mismatch.expected, "an RTS out of an interrupt handler");
+    assert_eq!(0, stack.depth());
+    stack.push(frame);
+    assert_eq!(None, stack.ret(0x2005, 0x1000, 0xFF, true));
+
+    let mut mem = Mem::new();
+    let mut cpu = new_cpu(&mut mem);
+    cpu.step();
+    assert_eq!(1, call_stack(&cpu).depth());
+    cpu.reset();
+    assert_eq!(0, call_stack(&cpu).depth());
+}
+
+#[test]
+fn test_callstack_depth_is_bounded() {
+    let mut stack = CallStack::new();
+    for i in 0..300 {
+        stack.push(Frame { kind: FrameKind::Call, caller: i, target: 0, return_addr: i + 3, sp: 0xFF - (i as u8), cycles: 0 });
+    }
+    assert_eq!(256, stack.depth());
+    assert_eq!(44, stack.frames()[0].caller);
+}
+
+#[test]
+fn test_callstack_debugger_backtrace() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let mut debugger = Debugger::new(cpu);
+    let symbols = assemble(PROGRAM).unwrap().symbols;
+    debugger.add_breakpoint(symbols.lookup("inner").unwrap());
+    debugger.run(None);
+    assert_eq!(vec![symbols.lookup("inner").unwrap(), symbols.lookup("outer").unwrap(), 0x0200], debugger.backtrace());
+}
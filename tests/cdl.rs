@@ -0,0 +1,79 @@
+use emulator6502::asm::assemble;
+use emulator6502::cdl::*;
+use emulator6502::loader::load;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #1
+            lda table,x
+            sta $10
+            ldy #0
+            lda ($20),y
+            jmp ($30)
+    done:   jmp done
+    table:  .byte 1, 2, 3
+";
+
+#[test]
+fn test_cdl_flags() {
+    let asm = assemble(PROGRAM).unwrap();
+    let addr = |name: &str| asm.symbols.lookup(name).unwrap();
+    let mut mem = Mem::new();
+    asm.load(&mut mem);
+    mem.write16(0x20, addr("table") + 2);
+    mem.write16(0x30, addr("done"));
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.cdl = Some(CodeDataLog::new());
+    while cpu.pc != addr("done") {
+        cpu.step();
+    }
+    let log = cpu.cdl.take().unwrap();
+    assert_eq!(OPCODE, log.flags(0x0200));
+    assert_eq!(OPERAND, log.flags(0x0201));
+    assert_eq!(0, log.flags(addr("table")));
+    assert_eq!(DATA, log.flags(addr("table") + 1));
+    assert_eq!(DATA | INDIRECT_DATA, log.flags(addr("table") + 2));
+    assert_eq!(0, log.flags(0x10), "stores aren't reads");
+    assert_eq!(POINTER, log.flags(0x20));
+    assert_eq!(POINTER, log.flags(0x21));
+    assert_eq!(POINTER, log.flags(0x31));
+    assert_eq!(INDIRECT_CODE, log.flags(addr("done")), "done hasn't run yet");
+    assert_eq!(6, log.count(OPCODE));
+    assert_eq!(0x01, log.fceux_flags(0x0200));
+    assert_eq!(0x22, log.fceux_flags(addr("table") + 2));
+    assert_eq!(0x10, log.fceux_flags(addr("done")));
+    let mut out = Vec::new();
+    log.write(&mut out, Layout::Memory { start: 0x0200, end: 0x020F }).unwrap();
+    assert_eq!(vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0x10, 0], out);
+}
+
+#[test]
+fn test_cdl_nes_layout() {
+    // one 16K PRG bank, mirrored at $8000 and $C000, and one 8K CHR bank
+    let mut rom = vec![0; 16 + 0x4000 + 0x2000];
+    rom[..6].copy_from_slice(b"NES\x1A\x01\x01");
+    let prg = &mut rom[16..16 + 0x4000];
+    // lda $8010, jmp $C003
+    prg[..6].copy_from_slice(&[0xAD, 0x10, 0x80, 0x4C, 0x03, 0xC0]);
+    prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+    let mut mem = Mem::new();
+    let loaded = load(&mut mem, &rom, None).unwrap();
+    assert_eq!(Some((0x4000, 0x2000)), loaded.rom_sizes);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = loaded.entry.unwrap();
+    cpu.cdl = Some(CodeDataLog::new());
+    cpu.step();
+    cpu.step();
+    let log = cpu.cdl.take().unwrap();
+    let mut out = Vec::new();
+    log.write(&mut out, Layout::Nes { prg_size: 0x4000, chr_size: 0x2000 }).unwrap();
+    assert_eq!(0x6000, out.len());
+    // code in $C000-$DFFF has bank bits 10, data in $8000-$9FFF 00
+    assert_eq!(vec![0x09; 6], out[..6]);
+    assert_eq!(0x02, out[0x10]);
+    assert_eq!(vec![0; 0x2000], out[0x4000..]);
+}
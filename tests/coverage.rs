@@ -0,0 +1,118 @@
+use emulator6502::asm::{assemble, Assembly};
+use emulator6502::coverage::*;
+use emulator6502::lines::LineTable;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   dex
+            bne loop
+            beq done
+            bcs start
+    done:   jmp done
+";
+
+// the lines of PROGRAM, with dex and bne on one line
+const DBG: &str = r#"version	major=2,minor=0
+file	id=0,name="main.s",size=120,mtime=0x60000000,mod=0
+line	id=0,file=0,line=2,span=0
+line	id=1,file=0,line=3,span=1
+line	id=2,file=0,line=5,span=2
+line	id=3,file=0,line=6,span=3
+line	id=4,file=0,line=7,span=4
+seg	id=0,name="CODE",start=0x000200,size=0x00000C,addrsize=absolute,type=ro,oname="main.bin",ooffs=0
+span	id=0,seg=0,start=0,size=2
+span	id=1,seg=0,start=2,size=3
+span	id=2,seg=0,start=5,size=2
+span	id=3,seg=0,start=7,size=2
+span	id=4,seg=0,start=9,size=3
+"#;
+
+fn cover(mem: &mut Mem, asm: &Assembly) -> Coverage {
+    asm.load(mem);
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.coverage = Some(Coverage::new());
+    while cpu.pc != asm.symbols.lookup("done").unwrap() {
+        cpu.step();
+    }
+    cpu.coverage.take().unwrap()
+}
+
+#[test]
+fn test_coverage_counts() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let coverage = cover(&mut mem, &asm);
+    assert_eq!(1, coverage.hits(0x0200));
+    assert_eq!(3, coverage.hits(0x0202));
+    assert_eq!(0, coverage.hits(0x0201), "only the first byte of an instruction counts");
+    assert_eq!(0, coverage.hits(0x0207));
+    assert_eq!(Some(Branch { taken: 2, not_taken: 1 }), coverage.branch(0x0203));
+    assert_eq!(Some(Branch { taken: 1, not_taken: 0 }), coverage.branch(0x0205));
+    assert_eq!(None, coverage.branch(0x0207));
+    assert_eq!(vec![0x0200, 0x0202, 0x0203, 0x0205], coverage.executed().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_coverage_merge() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let mut coverage = cover(&mut mem, &asm);
+    let other = cover(&mut mem, &asm);
+    coverage.merge(&other);
+    assert_eq!(6, coverage.hits(0x0202));
+    assert_eq!(Some(Branch { taken: 4, not_taken: 2 }), coverage.branch(0x0203));
+}
+
+#[test]
+fn test_coverage_lcov() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let coverage = cover(&mut mem, &asm);
+    let lines = LineTable::parse_ca65_dbg(DBG).unwrap();
+    let mut out = Vec::new();
+    coverage.write_lcov(&mut out, &lines, &mem).unwrap();
+    let expected = "\
+TN:
+SF:main.s
+BRDA:3,0,0,2
+BRDA:3,0,1,1
+BRDA:5,0,0,1
+BRDA:5,0,1,0
+BRDA:6,0,0,-
+BRDA:6,0,1,-
+BRF:6
+BRH:3
+DA:2,1
+DA:3,3
+DA:5,1
+DA:6,0
+DA:7,0
+LF:5
+LH:3
+end_of_record
+";
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn test_coverage_report() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let coverage = cover(&mut mem, &asm);
+    let mut out = Vec::new();
+    coverage.write_report(&mut out, &mem, &asm.symbols).unwrap();
+    let expected = "\
+7 bytes executed
+$0200-$0206
+
+0200  ldx #$03                          1
+0202  dex                               3
+0203  bne loop                          3  taken 2, not taken 1
+0205  beq done                          1  taken 1, not taken 0
+";
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+}
@@ -0,0 +1,362 @@
+use emulator6502::asm::assemble;
+use emulator6502::dap::DapServer;
+use emulator6502::debugger::Debugger;
+use emulator6502::*;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::Duration;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  inc $10
+            rts
+";
+
+// main.s has a comment on line 3 and an empty line 7
+const DBG: &str = r#"version	major=2,minor=0
+file	id=0,name="main.s",size=100,mtime=0x60000000,mod=0
+line	id=0,file=0,line=1,span=0
+line	id=1,file=0,line=2,span=1
+line	id=2,file=0,line=4,span=2
+line	id=3,file=0,line=5,span=3
+line	id=4,file=0,line=6,span=4
+line	id=5,file=0,line=8,span=5
+line	id=6,file=0,line=9,span=6
+line	id=7,file=0,line=3
+mod	id=0,name="main.o",file=0
+scope	id=0,name="",mod=0,size=14
+seg	id=0,name="CODE",start=0x000200,size=0x00000E,addrsize=absolute,type=ro,oname="main.bin",ooffs=0
+span	id=0,seg=0,start=0,size=2
+span	id=1,seg=0,start=2,size=3
+span	id=2,seg=0,start=5,size=1
+span	id=3,seg=0,start=6,size=2
+span	id=4,seg=0,start=8,size=3
+span	id=5,seg=0,start=11,size=2
+span	id=6,seg=0,start=13,size=1
+sym	id=0,name="start",addrsize=absolute,scope=0,def=0,val=0x200,seg=0,type=lab
+sym	id=1,name="loop",addrsize=absolute,scope=0,def=1,val=0x202,seg=0,type=lab
+sym	id=2,name="done",addrsize=absolute,scope=0,def=4,val=0x208,seg=0,type=lab
+sym	id=3,name="count",addrsize=absolute,scope=0,def=5,val=0x20B,seg=0,type=lab
+"#;
+
+// A scripted editor, events that arrive before the response of a request are kept for later
+struct Client<R: Read, W: Write> {
+    input: BufReader<R>,
+    output: W,
+    seq: u64,
+    events: VecDeque<Value>,
+}
+
+impl<R: Read, W: Write> Client<R, W> {
+    fn new(input: R, output: W) -> Client<R, W> {
+        Client { input: BufReader::new(input), output, seq: 0, events: VecDeque::new() }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) -> u64 {
+        self.seq += 1;
+        let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.output.flush().unwrap();
+        self.seq
+    }
+
+    fn read(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            assert!(self.input.read_line(&mut line).unwrap() > 0, "connection closed");
+            if line.trim().is_empty() {
+                break;
+            }
+            length = line.trim().strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        }
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let seq = self.send(command, arguments);
+        loop {
+            let message = self.read();
+            if message["type"] == "event" {
+                self.events.push_back(message);
+            } else {
+                assert_eq!(seq, message["request_seq"]);
+                return message;
+            }
+        }
+    }
+
+    fn body(&mut self, command: &str, arguments: Value) -> Value {
+        let response = self.request(command, arguments);
+        assert_eq!(true, response["success"], "{}", response);
+        response["body"].clone()
+    }
+
+    fn event(&mut self, event: &str) -> Value {
+        let message = match self.events.pop_front() {
+            Some(message) => message,
+            None => self.read(),
+        };
+        assert_eq!(event, message["event"], "{}", message);
+        message["body"].clone()
+    }
+}
+
+fn dbg_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("emulator6502-dap-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.dbg"), DBG).unwrap();
+    dir
+}
+
+fn start_server() -> (Client<TcpStream, TcpStream>, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // the cpu isn't Send, so the server gets built on its own thread
+    let server = spawn(move || {
+        let mut mem = Mem::new();
+        assemble(PROGRAM).unwrap().load(&mut mem);
+        let mut cpu = Cpu::new(&mut mem);
+        cpu.reset();
+        cpu.pc = 0x0200;
+        let mut server = DapServer::new(Debugger::new(cpu));
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        server.serve(stream.try_clone().unwrap(), stream).unwrap();
+    });
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client::new(stream.try_clone().unwrap(), stream), server)
+}
+
+fn top_frame<R: Read, W: Write>(client: &mut Client<R, W>) -> Value {
+    client.body("stackTrace", json!({ "threadId": 1 }))["stackFrames"][0].clone()
+}
+
+#[test]
+fn test_dap_source_breakpoints_and_stepping() {
+    let dir = dbg_file("steps");
+    let source = dir.join("main.s").to_string_lossy().into_owned();
+    let (mut client, server) = start_server();
+    assert_eq!(true, client.body("initialize", json!({ "adapterID": "emu6502" }))["supportsDisassembleRequest"]);
+    client.event("initialized");
+    client.body("launch", json!({ "symbols": dir.join("main.dbg"), "stopOnEntry": true }));
+    let breakpoints = client.body("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": 3 }, { "line": 8 }, { "line": 30 }] }));
+    assert_eq!(json!([{ "verified": true, "line": 4 }, { "verified": true, "line": 8 }, { "verified": false, "line": 30, "message": "no code at this line" }]), breakpoints["breakpoints"]);
+    let breakpoints = client.body("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": u32::MAX }] }));
+    assert_eq!(false, breakpoints["breakpoints"][0]["verified"]);
+    client.body("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": 3 }, { "line": 8 }] }));
+    client.body("configurationDone", json!({}));
+    assert_eq!("entry", client.event("stopped")["reason"]);
+
+    client.body("continue", json!({ "threadId": 1 }));
+    assert_eq!("breakpoint", client.event("stopped")["reason"]);
+    let frames = client.body("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(2, frames["totalFrames"]);
+    let frame = &frames["stackFrames"][0];
+    assert_eq!(("count", 8, "0x020B"), (frame["name"].as_str().unwrap(), frame["line"].as_u64().unwrap(), frame["instructionPointerReference"].as_str().unwrap()));
+    assert_eq!(json!({ "name": "main.s", "path": source }), frame["source"]);
+    let caller = &frames["stackFrames"][1];
+    assert_eq!(("loop", 2), (caller["name"].as_str().unwrap(), caller["line"].as_u64().unwrap()));
+
+    client.body("continue", json!({ "threadId": 1 }));
+    assert_eq!("breakpoint", client.event("stopped")["reason"]);
+    assert_eq!(4, top_frame(&mut client)["line"]);
+    client.body("next", json!({ "threadId": 1 }));
+    assert_eq!("step", client.event("stopped")["reason"]);
+    assert_eq!(5, top_frame(&mut client)["line"]);
+    client.body("next", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(2, top_frame(&mut client)["line"], "the branch goes back to loop");
+    client.body("next", json!({ "threadId": 1 }));
+    assert_eq!("breakpoint", client.event("stopped")["reason"], "next stops at breakpoints inside the call");
+    client.body("stepOut", json!({ "threadId": 1 }));
+    assert_eq!("step", client.event("stopped")["reason"]);
+    assert_eq!("0x0205", top_frame(&mut client)["instructionPointerReference"]);
+    client.body("stepIn", json!({ "threadId": 1, "granularity": "instruction" }));
+    client.event("stopped");
+    assert_eq!("0x0206", top_frame(&mut client)["instructionPointerReference"]);
+
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dap_registers_and_memory() {
+    let (mut client, server) = start_server();
+    client.body("launch", json!({}));
+    let scopes = client.body("scopes", json!({ "frameId": 0 }));
+    let reference = scopes["scopes"][0]["variablesReference"].clone();
+    assert_eq!("Registers", scopes["scopes"][0]["name"]);
+    assert_eq!(json!({ "value": "$5A" }), client.body("setVariable", json!({ "variablesReference": reference, "name": "A", "value": "$5a" })));
+    client.body("setVariable", json!({ "variablesReference": reference, "name": "P", "value": "0x81" }));
+    assert_eq!(false, client.request("setVariable", json!({ "variablesReference": reference, "name": "A", "value": "256" }))["success"]);
+    let variables = client.body("variables", json!({ "variablesReference": reference }))["variables"].clone();
+    let value = |name: &str| variables.as_array().unwrap().iter().find(|var| var["name"] == name).unwrap()["value"].clone();
+    assert_eq!(("$5A", "$81 Nv-bdizC", "$0200"), (value("A").as_str().unwrap(), value("P").as_str().unwrap(), value("PC").as_str().unwrap()));
+
+    assert_eq!(json!({ "address": "0x0200", "data": "ogMgCw==", "unreadableBytes": 0 }), client.body("readMemory", json!({ "memoryReference": "0x0200", "count": 4 })));
+    assert_eq!(json!({ "bytesWritten": 3 }), client.body("writeMemory", json!({ "memoryReference": "0x02FF", "offset": 1, "data": "SGkh" })));
+    assert_eq!("SGkh", client.body("readMemory", json!({ "memoryReference": "0x0300", "count": 3 }))["data"]);
+    assert_eq!(2, client.body("readMemory", json!({ "memoryReference": "0xFFFE", "count": 4 }))["unreadableBytes"]);
+
+    let instructions = client.body("disassemble", json!({ "memoryReference": "0x0205", "instructionOffset": -2, "instructionCount": 3 }))["instructions"].clone();
+    let text: Vec<(&str, &str)> = instructions.as_array().unwrap().iter().map(|inst| (inst["address"].as_str().unwrap(), inst["instruction"].as_str().unwrap())).collect();
+    assert_eq!(vec![("0x0200", "ldx #$03"), ("0x0202", "jsr $020B"), ("0x0205", "dex")], text);
+    let instructions = client.body("disassemble", json!({ "memoryReference": "0x0205", "instructionOffset": i64::MIN, "instructionCount": u64::MAX }))["instructions"].clone();
+    assert_eq!(1000, instructions.as_array().unwrap().len(), "huge offsets and counts are cut down");
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_dap_instruction_breakpoints_and_pause() {
+    let (mut client, server) = start_server();
+    client.body("launch", json!({ "stopOnEntry": true }));
+    let breakpoints = client.body("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x0206", "offset": 2 }] }));
+    assert_eq!(json!([{ "verified": true, "instructionReference": "0x0208" }]), breakpoints["breakpoints"]);
+    let breakpoints = client.body("setFunctionBreakpoints", json!({ "breakpoints": [{ "name": "nowhere" }] }));
+    assert_eq!(false, breakpoints["breakpoints"][0]["verified"]);
+    client.body("configurationDone", json!({}));
+    client.event("stopped");
+    client.body("continue", json!({ "threadId": 1 }));
+    assert_eq!("instruction breakpoint", client.event("stopped")["reason"]);
+    assert_eq!(json!(1), client.body("threads", json!({}))["threads"][0]["id"]);
+
+    client.body("setInstructionBreakpoints", json!({ "breakpoints": [] }));
+    client.body("continue", json!({ "threadId": 1 }));
+    sleep(Duration::from_millis(50));
+    client.body("pause", json!({ "threadId": 1 }));
+    assert_eq!("pause", client.event("stopped")["reason"]);
+    assert_eq!("0x0208", top_frame(&mut client)["instructionPointerReference"]);
+    assert_eq!("$0208", top_frame(&mut client)["name"], "no symbols were loaded");
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_dap_disconnect_and_end_of_input_while_running() {
+    let (mut client, server) = start_server();
+    client.body("launch", json!({}));
+    client.body("configurationDone", json!({}));
+    sleep(Duration::from_millis(50));
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+
+    let (mut client, server) = start_server();
+    client.body("launch", json!({}));
+    client.body("configurationDone", json!({}));
+    sleep(Duration::from_millis(50));
+    client.output.shutdown(std::net::Shutdown::Write).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_dap_conditional_breakpoints_and_evaluate() {
+    let (mut client, server) = start_server();
+    let capabilities = client.body("initialize", json!({}));
+    assert_eq!(true, capabilities["supportsConditionalBreakpoints"]);
+    assert_eq!(true, capabilities["supportsHitConditionalBreakpoints"]);
+    client.event("initialized");
+    client.body("launch", json!({ "stopOnEntry": true }));
+    let breakpoints =
+        client.body("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x020B", "condition": "X == 2" }, { "instructionReference": "0x0206", "condition": "nowhere" }] }));
+    assert_eq!(json!({ "verified": false, "message": "unknown symbol nowhere" }), breakpoints["breakpoints"][1]);
+    client.body("configurationDone", json!({}));
+    client.event("stopped");
+    client.body("continue", json!({ "threadId": 1 }));
+    assert_eq!("instruction breakpoint", client.event("stopped")["reason"]);
+    assert_eq!(json!({ "result": "$02 2", "variablesReference": 0, "memoryReference": "0x0002" }), client.body("evaluate", json!({ "expression": "X" })));
+    assert_eq!("$020C 524", client.body("evaluate", json!({ "expression": "pc + [$10]" }))["result"]);
+    assert_eq!("division by zero", client.request("evaluate", json!({ "expression": "1 / 0" }))["message"]);
+
+    client.body("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x0205", "hitCondition": ">= 2" }] }));
+    client.body("continue", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!("$01 1", client.body("evaluate", json!({ "expression": "X" }))["result"], "the first hit doesn't stop");
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_dap_step_back_and_reverse_continue() {
+    let dir = dbg_file("reverse");
+    let source = dir.join("main.s").to_string_lossy().into_owned();
+    let (mut client, server) = start_server();
+    assert_eq!(true, client.body("initialize", json!({}))["supportsStepBack"]);
+    client.event("initialized");
+    client.body("launch", json!({ "symbols": dir.join("main.dbg"), "stopOnEntry": true }));
+    client.body("setBreakpoints", json!({ "source": { "path": source }, "breakpoints": [{ "line": 8 }] }));
+    client.body("configurationDone", json!({}));
+    client.event("stopped");
+    client.body("continue", json!({ "threadId": 1 }));
+    client.event("stopped");
+    client.body("continue", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!("$01 1", client.body("evaluate", json!({ "expression": "[$10]" }))["result"]);
+
+    client.body("stepBack", json!({ "threadId": 1 }));
+    assert_eq!("step", client.event("stopped")["reason"]);
+    assert_eq!((2, "0x0202"), (top_frame(&mut client)["line"].as_u64().unwrap(), top_frame(&mut client)["instructionPointerReference"].as_str().unwrap()));
+    client.body("stepBack", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(5, top_frame(&mut client)["line"]);
+    client.body("stepBack", json!({ "threadId": 1, "granularity": "instruction" }));
+    client.event("stopped");
+    assert_eq!("0x0205", top_frame(&mut client)["instructionPointerReference"]);
+    client.body("reverseContinue", json!({ "threadId": 1 }));
+    assert_eq!("breakpoint", client.event("stopped")["reason"]);
+    assert_eq!(8, top_frame(&mut client)["line"]);
+    assert_eq!("$00 0", client.body("evaluate", json!({ "expression": "[$10]" }))["result"]);
+    client.body("reverseContinue", json!({ "threadId": 1 }));
+    let stopped = client.event("stopped");
+    assert_eq!(("step", "start of the history"), (stopped["reason"].as_str().unwrap(), stopped["description"].as_str().unwrap()));
+    assert_eq!("0x0200", top_frame(&mut client)["instructionPointerReference"]);
+
+    client.body("disconnect", json!({}));
+    server.join().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dap_over_stdio() {
+    let dir = dbg_file("stdio");
+    let program = dir.join("main.bin");
+    std::fs::write(&program, assemble(PROGRAM).unwrap().bytes()).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_emu6502"))
+        .args(["--dap", "--load", "$0200", "--symbols"])
+        .arg(dir.join("main.dbg"))
+        .arg(&program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client::new(child.stdout.take().unwrap(), child.stdin.take().unwrap());
+    client.body("initialize", json!({}));
+    client.event("initialized");
+    client.body("launch", json!({ "stopOnEntry": true }));
+    let breakpoints = client.body("setBreakpoints", json!({ "source": { "path": dir.join("main.s") }, "breakpoints": [{ "line": 9 }] }));
+    assert_eq!(true, breakpoints["breakpoints"][0]["verified"]);
+    client.body("configurationDone", json!({}));
+    client.event("stopped");
+    client.body("continue", json!({ "threadId": 1 }));
+    client.event("stopped");
+    let frame = top_frame(&mut client);
+    assert_eq!(("count", 9), (frame["name"].as_str().unwrap(), frame["line"].as_u64().unwrap()));
+    client.body("disconnect", json!({}));
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
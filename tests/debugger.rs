@@ -0,0 +1,217 @@
+use emulator6502::asm::assemble;
+use emulator6502::debugger::*;
+use emulator6502::expr::Expr;
+use emulator6502::history::History;
+use emulator6502::*;
+use std::sync::atomic::Ordering;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  lda $10
+            adc #1
+            sta $10
+            rts
+";
+
+fn mem_program() -> Mem {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    mem
+}
+
+fn new_debugger(mem: &mut Mem) -> Debugger<'_> {
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    Debugger::new(cpu)
+}
+
+#[test]
+fn test_debugger_breakpoint() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    assert!(debugger.add_breakpoint(0x0205));
+    assert!(!debugger.add_breakpoint(0x0205));
+    assert_eq!(StopReason::Breakpoint(0x0205), debugger.run(None));
+    assert_eq!(3, debugger.cpu.regs[Cpu::REG_X]);
+    // continuing runs the instruction under the breakpoint first
+    assert_eq!(StopReason::Breakpoint(0x0205), debugger.run(None));
+    assert_eq!(2, debugger.cpu.regs[Cpu::REG_X]);
+    assert!(debugger.remove_breakpoint(0x0205));
+    assert_eq!(Vec::<u16>::new(), debugger.breakpoints().collect::<Vec<_>>());
+    assert_eq!(StopReason::Limit, debugger.run(Some(100)));
+    assert_eq!(0x0208, debugger.cpu.pc);
+}
+
+#[test]
+fn test_debugger_step_and_next() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    assert_eq!(StopReason::Step, debugger.step());
+    assert_eq!(0x0202, debugger.cpu.pc);
+    assert_eq!(StopReason::Step, debugger.step());
+    assert_eq!(0x020B, debugger.cpu.pc, "step enters the subroutine");
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    debugger.step();
+    assert_eq!(StopReason::Step, debugger.step_over());
+    assert_eq!(0x0205, debugger.cpu.pc, "next runs the whole subroutine");
+    assert_eq!(1, mem_value(&debugger, 0x10));
+    assert_eq!(StopReason::Step, debugger.step_over());
+    assert_eq!(0x0206, debugger.cpu.pc);
+}
+
+#[test]
+fn test_debugger_next_stops_at_breakpoint_inside_call() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    debugger.step();
+    debugger.add_breakpoint(0x020F);
+    assert_eq!(StopReason::Breakpoint(0x020F), debugger.step_over());
+}
+
+#[test]
+fn test_debugger_watchpoints() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    debugger.add_watchpoint(Watchpoint::new(0x10, WatchKind::Write));
+    let hit = WatchHit { addr: 0x10, value: 1, write: true };
+    assert_eq!(StopReason::Watchpoint { pc: 0x020F, hit }, debugger.run(None));
+    assert_eq!(0x0211, debugger.cpu.pc, "the instruction completes");
+    assert!(debugger.remove_watchpoint(&Watchpoint::new(0x10, WatchKind::Write)));
+    debugger.add_watchpoint(Watchpoint { start: 0x0F, end: 0x11, kind: WatchKind::Read });
+    let hit = WatchHit { addr: 0x10, value: 1, write: false };
+    assert_eq!(StopReason::Watchpoint { pc: 0x020B, hit }, debugger.run(None));
+    assert_eq!(1, debugger.watchpoints().len());
+}
+
+#[test]
+fn test_debugger_fetch_is_not_a_read() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    debugger.add_watchpoint(Watchpoint { start: 0x0200, end: 0x02FF, kind: WatchKind::Read });
+    assert_eq!(StopReason::Limit, debugger.run(Some(20)));
+}
+
+#[test]
+fn test_debugger_interrupt() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    let handle = debugger.interrupt_handle();
+    debugger.add_breakpoint(0x0205);
+    // a request made while stopped doesn't stop the next run
+    handle.store(true, Ordering::Relaxed);
+    assert_eq!(StopReason::Breakpoint(0x0205), debugger.run(None));
+    debugger.remove_breakpoint(0x0205);
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        handle.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(StopReason::Interrupted, debugger.run(None));
+    thread.join().unwrap();
+}
+
+fn mem_value(debugger: &Debugger<'_>, addr: u16) -> u8 {
+    debugger.cpu.mem().read8(addr as usize)
+}
+
+#[test]
+fn test_debugger_conditional_breakpoints() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    debugger.set_breakpoint(Breakpoint::new(0x020B).with_condition(Expr::parse("X == 2").unwrap()));
+    assert_eq!(StopReason::Breakpoint(0x020B), debugger.run(None));
+    assert_eq!((2, 1), (debugger.cpu.regs[Cpu::REG_X], debugger.cpu.peek8(0x10)));
+    assert_eq!(1, debugger.breakpoint(0x020B).unwrap().hits, "hits only count when the condition holds");
+    assert_eq!(StopReason::Limit, debugger.run(Some(100)));
+
+    debugger.cpu.pc = 0x0200;
+    debugger.set_breakpoint(Breakpoint::new(0x020B).with_ignore_count(1));
+    assert_eq!(StopReason::Breakpoint(0x020B), debugger.run(None));
+    assert_eq!(2, debugger.cpu.regs[Cpu::REG_X]);
+    assert_eq!(0, debugger.breakpoint(0x020B).unwrap().ignore_count);
+
+    debugger.cpu.pc = 0x0200;
+    debugger.set_breakpoint(Breakpoint::new(0x020B).with_hit_count(3));
+    assert_eq!(StopReason::Breakpoint(0x020B), debugger.run(None));
+    assert_eq!(1, debugger.cpu.regs[Cpu::REG_X]);
+    debugger.breakpoint_mut(0x020B).unwrap().condition = Some(Expr::parse("1 / 0").unwrap());
+    debugger.cpu.pc = 0x0202;
+    assert_eq!(StopReason::Breakpoint(0x020B), debugger.run(None), "conditions that fail to evaluate stop");
+}
+
+fn run_to_done(debugger: &mut Debugger<'_>) {
+    // small intervals, going back has to replay from several checkpoints
+    debugger.set_history(Some(History::with_interval(4)));
+    debugger.add_breakpoint(0x0208);
+    assert_eq!(StopReason::Breakpoint(0x0208), debugger.run(None));
+    debugger.remove_breakpoint(0x0208);
+    assert_eq!(22, debugger.history().unwrap().position());
+}
+
+#[test]
+fn test_debugger_step_back() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    run_to_done(&mut debugger);
+    assert_eq!(StopReason::Step, debugger.step_back());
+    assert_eq!((0x0206, 0), (debugger.cpu.pc, debugger.cpu.regs[Cpu::REG_X]));
+    for _ in 0..3 {
+        debugger.step_back();
+    }
+    // before the last sta $10
+    assert_eq!(0x020F, debugger.cpu.pc);
+    assert_eq!(2, mem_value(&debugger, 0x10));
+    assert_eq!(vec![0x020F, 0x0202], debugger.backtrace());
+    assert_eq!(18, debugger.history().unwrap().position());
+    debugger.step();
+    assert_eq!(3, mem_value(&debugger, 0x10));
+    // changes made while stopped are kept
+    debugger.step_back();
+    debugger.cpu.regs[Cpu::REG_A] = 0x40;
+    debugger.checkpoint();
+    debugger.step();
+    assert_eq!(0x40, mem_value(&debugger, 0x10));
+    debugger.step_back();
+    assert_eq!((2, 0x40), (mem_value(&debugger, 0x10), debugger.cpu.regs[Cpu::REG_A]));
+    while debugger.step_back() == StopReason::Step {}
+    assert_eq!((0x0200, 0), (debugger.cpu.pc, debugger.history().unwrap().position()));
+    assert_eq!(0, mem_value(&debugger, 0x10));
+}
+
+#[test]
+fn test_debugger_reverse_continue() {
+    let mut mem = mem_program();
+    let mut debugger = new_debugger(&mut mem);
+    run_to_done(&mut debugger);
+    debugger.add_watchpoint(Watchpoint::new(0x10, WatchKind::Write));
+    let hit = WatchHit { addr: 0x10, value: 3, write: true };
+    assert_eq!(StopReason::Watchpoint { pc: 0x020F, hit }, debugger.reverse_continue());
+    assert_eq!((0x0211, 19), (debugger.cpu.pc, debugger.history().unwrap().position()));
+    let hit = WatchHit { addr: 0x10, value: 2, write: true };
+    assert_eq!(StopReason::Watchpoint { pc: 0x020F, hit }, debugger.reverse_continue());
+    debugger.remove_watchpoint(&Watchpoint::new(0x10, WatchKind::Write));
+    debugger.set_breakpoint(Breakpoint::new(0x0205).with_condition(Expr::parse("X == 3").unwrap()));
+    assert_eq!(StopReason::Breakpoint(0x0205), debugger.reverse_continue());
+    assert_eq!((3, 6), (debugger.cpu.regs[Cpu::REG_X], debugger.history().unwrap().position()));
+    assert_eq!(StopReason::HistoryStart, debugger.reverse_continue());
+    assert_eq!(0x0200, debugger.cpu.pc);
+    // and forward again
+    assert_eq!(StopReason::Breakpoint(0x0205), debugger.run(None));
+    assert_eq!(1, debugger.breakpoint(0x0205).unwrap().hits, "going back doesn't count hits");
+}
+
+#[test]
+fn test_debugger_stops_on_invalid_opcode() {
+    let mut mem = Mem::new();
+    mem.load_programm_at(0x0200, &[Cpu::NOP_IMPLIED, 0x02, Cpu::NOP_IMPLIED]);
+    let mut debugger = new_debugger(&mut mem);
+    assert_eq!(StopReason::InvalidOpcode { pc: 0x0201, opcode: 0x02 }, debugger.run(None));
+    assert_eq!(0x0202, debugger.cpu.pc, "only the opcode is read");
+    assert_eq!(StopReason::Step, debugger.step());
+}
@@ -0,0 +1,90 @@
+use emulator6502::disasm::*;
+use emulator6502::opcodes::*;
+use emulator6502::symbols::SymbolTable;
+use emulator6502::*;
+use rstest::*;
+
+#[rstest]
+#[case::implied(&[Cpu::CLC_IMPLIED], "CLC", "clc")]
+#[case::accumulator(&[Cpu::ASL_IMPLIED], "ASL A", "asl a")]
+#[case::immediate(&[Cpu::LDA_IMMEDIATE, 0xCA], "LDA #$CA", "lda #$CA")]
+#[case::zero(&[Cpu::LDA_ZERO, 0x12], "LDA $12", "lda $12")]
+#[case::zero_x(&[Cpu::LDY_ZERO_X, 0x12], "LDY $12,X", "ldy $12,X")]
+#[case::zero_y(&[Cpu::STX_ZERO_Y, 0x12], "STX $12,Y", "stx $12,Y")]
+#[case::absolute(&[Cpu::STA_ABSOLUTE, 0x34, 0x12], "STA $1234", "sta $1234")]
+#[case::absolute_in_zero_page(&[Cpu::STA_ABSOLUTE, 0x34, 0x00], "STA $0034", "sta a:$0034")]
+#[case::absolute_x(&[Cpu::LDA_ABSOLUTE_X, 0x34, 0x12], "LDA $1234,X", "lda $1234,X")]
+#[case::absolute_y(&[Cpu::LDA_ABSOLUTE_Y, 0x10, 0x00], "LDA $0010,Y", "lda a:$0010,Y")]
+#[case::indirect(&[Cpu::JMP_INDIRECT, 0x00, 0x02], "JMP ($0200)", "jmp ($0200)")]
+#[case::jump_to_zero_page(&[Cpu::JSR_ABSOLUTE, 0x80, 0x00], "JSR $0080", "jsr $0080")]
+#[case::indirect_x(&[Cpu::LDA_INDIRECT_X, 0x80], "LDA ($80,X)", "lda ($80,X)")]
+#[case::indirect_y(&[Cpu::STA_INDIRECT_Y, 0x80], "STA ($80),Y", "sta ($80),Y")]
+#[case::branch_forward(&[Cpu::BNE_RELATIVE, 0x08], "BNE $100A", "bne $100A")]
+#[case::branch_backward(&[Cpu::BEQ_RELATIVE, 0xFC], "BEQ $0FFE", "beq $0FFE")]
+#[case::illegal(&[0xA7, 0x12], "*LAX $12", "lax $12")]
+#[case::illegal_isc(&[0xFF, 0x00, 0x20], "*ISB $2000,X", "isc $2000,X")]
+#[case::illegal_nop(&[0x04, 0x12], "*NOP $12", "nop $12")]
+#[case::unstable_duplicate(&[0x1A], "*NOP", ".byte $1A")]
+#[case::unstable_sbc(&[0xEB, 0x01], "*SBC #$01", ".byte $EB,$01")]
+#[case::jam(&[0x02], "*JAM", "jam")]
+fn disasm_tests(#[case] bytes: &[u8], #[case] generic: &str, #[case] ca65: &str) {
+    let mut mem = Mem::new();
+    mem.load_programm_at(0x1000, bytes);
+    let (inst, text) = Disassembler::new(Syntax::Generic).disassemble(&mem, 0x1000);
+    assert_eq!(generic, text);
+    assert_eq!(bytes, &inst.bytes()[..]);
+    assert_eq!(0x1000 + bytes.len() as u16, inst.next_addr());
+    assert_eq!(ca65, Disassembler::new(Syntax::Ca65).format(&inst));
+}
+
+#[test]
+fn test_disasm_symbolic_operands() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("main", 0xC000);
+    symbols.insert("ptr", 0x80);
+    symbols.insert("screen", 0x0400);
+    let program: &[u8] = &[Cpu::LDA_INDIRECT_Y, 0x81, Cpu::STA_ABSOLUTE_X, 0x00, 0x04, Cpu::BNE_RELATIVE, 0xF9, Cpu::LDA_IMMEDIATE, 0x80];
+    let mut mem = Mem::new();
+    mem.load_programm_at(0xC000, program);
+    let dis = Disassembler::new(Syntax::Ca65).with_symbols(&symbols);
+    let lines = dis.listing(&mem, 0xC000, 4);
+    assert_eq!(
+        vec![
+            "main:",
+            "C000  B1 81     lda (ptr+1),Y",
+            "C002  9D 00 04  sta screen,X",
+            "C005  D0 F9     bne main",
+            // immediate values are never symbolized
+            "C007  A9 80     lda #$80",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_disasm_from_slice_and_cpu() {
+    let program = vec![Cpu::JMP_ABSOLUTE, 0xE2, 0xFC];
+    let (inst, text) = Disassembler::new(Syntax::Generic).disassemble(&program, 0);
+    assert_eq!("JMP $FCE2", text);
+    assert_eq!(Some(0xFCE2), inst.target());
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&program);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    assert_eq!("JMP $FCE2", Disassembler::new(Syntax::Generic).disassemble(&cpu, cpu.pc).1);
+}
+
+#[test]
+fn test_opcode_table_round_trip() {
+    for opcode in 0..=255u8 {
+        let info = opcode_info(opcode);
+        match info.kind {
+            OpcodeKind::Official => assert_eq!(Some(opcode), find_opcode(info.mnemonic, info.mode, false)),
+            OpcodeKind::Illegal => assert_eq!(Some(opcode), find_opcode(info.mnemonic, info.mode, true)),
+            OpcodeKind::Unstable => assert_ne!(Some(opcode), find_opcode(info.mnemonic, info.mode, true)),
+        }
+    }
+    assert_eq!(151, (0..=255u8).filter(|op| opcode_info(*op).is_official()).count());
+    assert_eq!(None, find_opcode("LAX", AddressingMode::ZeroPage, false));
+}
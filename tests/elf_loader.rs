@@ -0,0 +1,145 @@
+use emulator6502::elf::*;
+use emulator6502::*;
+
+struct Sym {
+    name: &'static str,
+    value: u32,
+    size: u32,
+    info: u8,
+}
+
+// Builds a minimal llvm-mos like executable: header, program headers, segment data,
+// a symbol table and its string table
+fn build_elf(entry: u32, segments: &[(u32, &[u8], u32)], syms: &[Sym]) -> Vec<u8> {
+    let phoff = 52u32;
+    let mut data_off = phoff + 32 * segments.len() as u32;
+    let mut phdrs = Vec::new();
+    let mut body = Vec::new();
+    for (vaddr, data, mem_size) in segments {
+        for val in [1, data_off, *vaddr, *vaddr, data.len() as u32, *mem_size, 5, 1].iter() {
+            phdrs.extend_from_slice(&val.to_le_bytes());
+        }
+        body.extend_from_slice(data);
+        data_off += data.len() as u32;
+    }
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; 16];
+    for sym in syms {
+        symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+        symtab.extend_from_slice(&sym.value.to_le_bytes());
+        symtab.extend_from_slice(&sym.size.to_le_bytes());
+        symtab.extend_from_slice(&[sym.info, 0, 1, 0]);
+        strtab.extend_from_slice(sym.name.as_bytes());
+        strtab.push(0);
+    }
+    let symtab_off = data_off;
+    let strtab_off = symtab_off + symtab.len() as u32;
+    let shoff = strtab_off + strtab.len() as u32;
+
+    let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&EM_MOS.to_le_bytes());
+    for val in [1, entry, phoff, shoff, 0].iter() {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    for val in [52u16, 32, segments.len() as u16, 40, 3, 0].iter() {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    elf.extend(phdrs);
+    elf.extend(body);
+    elf.extend(symtab.iter());
+    elf.extend(strtab.iter());
+    // null section, .symtab linked to .strtab, .strtab
+    elf.extend_from_slice(&[0u8; 40]);
+    for val in [0, 2, 0, 0, symtab_off, symtab.len() as u32, 2, 1, 4, 16].iter() {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    for val in [0, 3, 0, 0, strtab_off, strtab.len() as u32, 0, 0, 1, 0].iter() {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    elf
+}
+
+fn sample_elf() -> Vec<u8> {
+    build_elf(
+        0x0800,
+        &[(0x0800, &[Cpu::LDA_IMMEDIATE, 0xCA, Cpu::STA_ABSOLUTE, 0x00, 0x02], 5), (0x0200, &[0xAA], 4)],
+        &[
+            Sym { name: "_start", value: 0x0800, size: 5, info: 0x12 },
+            Sym { name: "buffer", value: 0x0200, size: 4, info: 0x11 },
+            Sym { name: "label", value: 0x0802, size: 0, info: 0x10 },
+            Sym { name: "banked", value: 0x1_0000, size: 0, info: 0x12 },
+        ],
+    )
+}
+
+#[test]
+fn test_elf_parse_entry_and_segments() {
+    let elf = ElfFile::parse(&sample_elf()).unwrap();
+    assert_eq!(0x0800, elf.entry);
+    assert_eq!(2, elf.segments.len());
+    assert_eq!(0x0800, elf.segments[0].addr);
+    assert_eq!(vec![Cpu::LDA_IMMEDIATE, 0xCA, Cpu::STA_ABSOLUTE, 0x00, 0x02], elf.segments[0].data);
+    assert_eq!(4, elf.segments[1].mem_size);
+}
+
+#[test]
+fn test_elf_load_zero_fills_bss() {
+    let elf = ElfFile::parse(&sample_elf()).unwrap();
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.write8(0x0203, 0x55);
+    elf.load(&mut mem);
+    assert_eq!(Cpu::LDA_IMMEDIATE, mem.read8(0x0800));
+    assert_eq!(0xAA, mem.read8(0x0200));
+    assert_eq!(0, mem.read8(0x0203));
+}
+
+#[test]
+fn test_elf_run_from_entry() {
+    let elf = ElfFile::parse(&sample_elf()).unwrap();
+    let mut mem = Mem::new();
+    mem.reset();
+    elf.load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = elf.entry;
+    cpu.process(6);
+    assert_eq!(0xCA, cpu.regs[Cpu::REG_A]);
+    assert_eq!(0xCA, mem.read8(0x0200));
+}
+
+#[test]
+fn test_elf_symbols() {
+    let elf = ElfFile::parse(&sample_elf()).unwrap();
+    assert_eq!(3, elf.symbols.len(), "banked symbol must be dropped");
+    assert_eq!(0x0200, elf.symbol("buffer").unwrap().addr);
+    assert_eq!(SymbolKind::Function, elf.symbol("_start").unwrap().kind);
+    assert!(elf.symbol("banked").is_none());
+}
+
+#[test]
+fn test_elf_symbol_at() {
+    let elf = ElfFile::parse(&sample_elf()).unwrap();
+    let (sym, offset) = elf.symbol_at(0x0803).unwrap();
+    assert_eq!(("_start", 3), (sym.name.as_str(), offset));
+    let (sym, offset) = elf.symbol_at(0x0802).unwrap();
+    assert_eq!(("label", 0), (sym.name.as_str(), offset));
+    assert_eq!("buffer", elf.symbol_at(0x0201).unwrap().0.name);
+    assert!(elf.symbol_at(0x0204).is_none());
+    assert!(elf.symbol_at(0x0805).is_none());
+}
+
+#[test]
+fn test_elf_errors() {
+    assert_eq!(ElfError::NotElf, ElfFile::parse(&[0x4C, 0x00, 0x08]).unwrap_err());
+    let mut elf = sample_elf();
+    elf.truncate(60);
+    assert_eq!(ElfError::Truncated, ElfFile::parse(&elf).unwrap_err());
+    let mut elf = sample_elf();
+    elf[18] = 3; // EM_386
+    elf[19] = 0;
+    assert_eq!(ElfError::UnsupportedMachine(3), ElfFile::parse(&elf).unwrap_err());
+    let elf = build_elf(0x0800, &[(0xFFFE, &[1, 2, 3], 3)], &[]);
+    assert_eq!(ElfError::AddressOutOfRange(0x10000), ElfFile::parse(&elf).unwrap_err());
+}
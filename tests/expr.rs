@@ -0,0 +1,73 @@
+use emulator6502::expr::Expr;
+use emulator6502::symbols::SymbolTable;
+use emulator6502::*;
+
+fn eval(cpu: &Cpu, text: &str) -> i64 {
+    Expr::parse(text).unwrap().eval(cpu).unwrap()
+}
+
+fn parse_error(text: &str) -> String {
+    Expr::parse(text).unwrap_err().to_string()
+}
+
+#[test]
+fn test_expr_registers_flags_and_memory() {
+    let mut mem = Mem::new();
+    mem.write8(0xD012, 150);
+    mem.write8(0x00FE, 0x34);
+    mem.write8(0x00FF, 0x12);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.pc = 0xC000;
+    cpu.regs[Cpu::REG_A] = 0x40;
+    cpu.regs[Cpu::REG_Y] = 2;
+    cpu.regs[Cpu::REG_SP] = 0xFD;
+    cpu.regs[Cpu::REG_STAT] = 0x81;
+    cpu.cycles_run = 1234;
+    assert_eq!(1, eval(&cpu, "A == $40 && [$D012] > 100"));
+    assert_eq!(0x1236, eval(&cpu, "[$FE].w + y"));
+    assert_eq!(0x34, eval(&cpu, "[ $FE ]"));
+    assert_eq!((1, 0, 1), (eval(&cpu, "N"), eval(&cpu, "z"), eval(&cpu, "c")));
+    assert_eq!((0x81, 0xFD, 0xC000), (eval(&cpu, "P"), eval(&cpu, "SP"), eval(&cpu, "pc")));
+    assert_eq!(1, eval(&cpu, "cycles >= 1000 || [0] / 0"), "|| doesn't evaluate the right side");
+    assert_eq!("division by zero", Expr::parse("A / (X & 0)").unwrap().eval(&cpu).unwrap_err().to_string());
+}
+
+#[test]
+fn test_expr_operators() {
+    let mut mem = Mem::new();
+    let cpu = Cpu::new(&mut mem);
+    assert_eq!(7, eval(&cpu, "1 + 2 * 3"));
+    assert_eq!(9, eval(&cpu, "(1 + 2) * 3"));
+    assert_eq!(1, eval(&cpu, "1 | 2 == 2"), "comparisons bind tighter than bit operators");
+    assert_eq!(3, eval(&cpu, "1|2"));
+    assert_eq!(1, eval(&cpu, "1&&2"));
+    assert_eq!((2, 2), (eval(&cpu, "3 & 6"), eval(&cpu, "17 % 5")));
+    assert_eq!((64, 4, 1), (eval(&cpu, "1 << 6"), eval(&cpu, "%1000 >> 1"), eval(&cpu, "0x10 <= 16")));
+    assert_eq!((0, 1, -5, -1), (eval(&cpu, "!7"), eval(&cpu, "!0"), eval(&cpu, "-5"), eval(&cpu, "~0")));
+    assert_eq!((1, 0), (eval(&cpu, "3 != 4"), eval(&cpu, "3 > 4")));
+}
+
+#[test]
+fn test_expr_symbols_and_display() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("ptr", 0x00FE);
+    symbols.insert("x", 0x1234);
+    let mut mem = Mem::new();
+    mem.write8(0x00FE, 9);
+    let cpu = Cpu::new(&mut mem);
+    let expr = Expr::parse_with_symbols("  [ptr] == 9 ", &symbols).unwrap();
+    assert_eq!(1, expr.eval(&cpu).unwrap());
+    assert_eq!("[ptr] == 9", expr.to_string());
+    assert_eq!(0, Expr::parse_with_symbols("x", &symbols).unwrap().eval(&cpu).unwrap(), "registers hide symbols");
+}
+
+#[test]
+fn test_expr_errors() {
+    assert_eq!("missing expression", parse_error(" "));
+    assert_eq!("unknown symbol foo", parse_error("foo + 1"));
+    assert_eq!("missing )", parse_error("(1 + 2"));
+    assert_eq!("missing ]", parse_error("[1"));
+    assert_eq!("unexpected '=' in expression", parse_error("A = 1"));
+    assert_eq!("missing operand in expression", parse_error("1 +"));
+    assert_eq!("invalid number ''", parse_error("$"));
+}
@@ -0,0 +1,248 @@
+use emulator6502::asm::assemble;
+use emulator6502::debugger::Debugger;
+use emulator6502::gdb::GdbStub;
+use emulator6502::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::Duration;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  inc $10
+            rts
+";
+
+// A scripted gdb, sends a packet and returns the reply
+struct Client<S: Read + Write> {
+    stream: S,
+    ack: bool,
+}
+
+impl<S: Read + Write> Client<S> {
+    fn new(stream: S) -> Client<S> {
+        Client { stream, ack: true }
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send(&mut self, packet: &str) -> String {
+        self.write_packet(packet);
+        self.reply()
+    }
+
+    // `k` has no reply, the stub just ends the session
+    fn kill(&mut self) {
+        self.write_packet("k");
+    }
+
+    fn write_packet(&mut self, packet: &str) {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+        self.stream.flush().unwrap();
+        if self.ack {
+            assert_eq!(b'+', self.byte(), "ack for {}", packet);
+        }
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(b'$', self.byte());
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b'}' => {
+                    let byte = self.byte();
+                    data.push(byte ^ 0x20)
+                }
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let expected = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(format!("{:02x}", expected).as_bytes(), &checksum);
+        if self.ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
+}
+
+fn start_stub() -> (Client<TcpStream>, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // the cpu isn't Send, so the stub gets built on its own thread
+    let server = spawn(move || {
+        let mut mem = Mem::new();
+        assemble(PROGRAM).unwrap().load(&mut mem);
+        let mut cpu = Cpu::new(&mut mem);
+        cpu.reset();
+        cpu.pc = 0x0200;
+        let mut stub = GdbStub::new(Debugger::new(cpu));
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        stub.serve(stream.try_clone().unwrap(), stream).unwrap();
+    });
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client::new(stream), server)
+}
+
+#[test]
+fn test_gdb_handshake_and_target_description() {
+    let (mut gdb, server) = start_stub();
+    assert!(gdb.send("qSupported:multiprocess+;swbreak+;hwbreak+").contains("qXfer:features:read+"));
+    assert_eq!("S05", gdb.send("?"));
+    let xml = gdb.send("qXfer:features:read:target.xml:0,1000");
+    assert!(xml.starts_with("l<?xml"));
+    assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+    assert!(gdb.send("qXfer:features:read:target.xml:0,10").starts_with('m'), "a partial read is marked as such");
+    assert_eq!("", gdb.send("qUnknownPacket"), "unknown packets get an empty reply");
+    assert_eq!("OK", gdb.send("QStartNoAckMode"));
+    gdb.ack = false;
+    assert_eq!("00000000ff0002", gdb.send("g"));
+    assert_eq!("OK", gdb.send("D"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_registers_and_memory() {
+    let (mut gdb, server) = start_stub();
+    assert_eq!("OK", gdb.send("P0=5a"));
+    assert_eq!("5a", gdb.send("p0"));
+    assert_eq!("OK", gdb.send("P5=0802"));
+    assert_eq!("0802", gdb.send("p5"));
+    assert_eq!("OK", gdb.send("G01020381fd0002"));
+    assert_eq!("01020381fd0002", gdb.send("g"));
+    assert_eq!("E01", gdb.send("p6"));
+    assert_eq!("a20320", gdb.send("m200,3"));
+    assert_eq!("OK", gdb.send("M300,3:486921"));
+    assert_eq!("48692100", gdb.send("m300,4"));
+    assert_eq!("E01", gdb.send("mffff,2"));
+    assert_eq!("E01", gdb.send("M300,2:48"));
+    assert_eq!("E01", gdb.send("m10,ffffffff"), "the range doesn't overflow");
+    assert_eq!("OK", gdb.send("M300,1:02"));
+    assert_eq!("OK", gdb.send("P5=0003"));
+    assert_eq!("S04", gdb.send("s"), "an invalid opcode stops with SIGILL and prints nothing");
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_step_and_breakpoints() {
+    let (mut gdb, server) = start_stub();
+    assert_eq!("S05", gdb.send("s"));
+    assert_eq!("0202", gdb.send("p5"));
+    assert_eq!("OK", gdb.send("Z0,20b,1"));
+    assert_eq!("OK", gdb.send("Z1,205,1"));
+    assert_eq!("T05swbreak:;", gdb.send("c"));
+    assert_eq!("0b02", gdb.send("p5"));
+    assert_eq!("T05hwbreak:;", gdb.send("vCont;c"));
+    assert_eq!("0502", gdb.send("p5"));
+    assert_eq!("OK", gdb.send("z0,20b,1"));
+    assert_eq!("OK", gdb.send("z1,205,1"));
+    assert_eq!("S05", gdb.send("vCont;s:1"));
+    assert_eq!("0602", gdb.send("p5"));
+    assert_eq!("S05", gdb.send("s208"));
+    assert_eq!("0802", gdb.send("p5"));
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_watchpoints() {
+    let (mut gdb, server) = start_stub();
+    assert_eq!("OK", gdb.send("Z2,10,1"));
+    assert_eq!("T05watch:10;", gdb.send("c"));
+    assert_eq!("0d02", gdb.send("p5"), "stops after the instruction");
+    assert_eq!("01", gdb.send("m10,1"));
+    assert_eq!("OK", gdb.send("z2,10,1"));
+    assert_eq!("OK", gdb.send("Z3,f,2"));
+    assert_eq!("T05rwatch:10;", gdb.send("c"));
+    assert_eq!("OK", gdb.send("z3,f,2"));
+    assert_eq!("OK", gdb.send("Z4,10,1"));
+    assert_eq!("T05awatch:10;", gdb.send("c"));
+    assert_eq!("E01", gdb.send("Z2,10,ffffffff"), "the range doesn't overflow");
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_reverse_execution() {
+    let (mut gdb, server) = start_stub();
+    assert!(gdb.send("qSupported:multiprocess+").contains("ReverseStep+;ReverseContinue+"));
+    assert_eq!("T05replaylog:begin;", gdb.send("bs"), "nothing ran yet");
+    assert_eq!("OK", gdb.send("Z2,10,1"));
+    assert_eq!("T05watch:10;", gdb.send("c"));
+    assert_eq!("T05watch:10;", gdb.send("c"));
+    assert_eq!("02", gdb.send("m10,1"));
+    assert_eq!("S05", gdb.send("bs"));
+    assert_eq!("0b02", gdb.send("p5"));
+    assert_eq!("01", gdb.send("m10,1"));
+    assert_eq!("T05watch:10;", gdb.send("bc"));
+    assert_eq!("0d02", gdb.send("p5"));
+    assert_eq!("T05replaylog:begin;", gdb.send("bc"));
+    assert_eq!("0002", gdb.send("p5"));
+    assert_eq!("00", gdb.send("m10,1"));
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_interrupt() {
+    let (mut gdb, server) = start_stub();
+    assert_eq!("OK", gdb.send("QStartNoAckMode"));
+    gdb.ack = false;
+    gdb.write_packet("c");
+    sleep(Duration::from_millis(50));
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!("S02", gdb.reply());
+    assert_eq!("0802", gdb.send("p5"), "the program ended in the jmp loop");
+    drop(gdb);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_gdb_over_stdio() {
+    let path = std::env::temp_dir().join(format!("emulator6502-gdb-{}.bin", std::process::id()));
+    std::fs::write(&path, [Cpu::LDA_IMMEDIATE, 0x42]).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_emu6502")).args(["--gdb", "stdio", "--load", "$400"]).arg(&path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    let stream = Pipe { input: child.stdout.take().unwrap(), output: child.stdin.take().unwrap() };
+    let mut gdb = Client::new(stream);
+    assert_eq!("S05", gdb.send("s"));
+    assert_eq!("42000000ff0204", gdb.send("g"));
+    gdb.kill();
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_file(&path).unwrap();
+}
+
+struct Pipe {
+    input: std::process::ChildStdout,
+    output: std::process::ChildStdin,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
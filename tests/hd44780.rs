@@ -0,0 +1,128 @@
+use emulator6502::hd44780::Hd44780;
+
+// one transfer, and the time for it to finish
+fn send(lcd: &mut Hd44780, rs: bool, value: u8) {
+    lcd.set_pins(rs, false, true, value);
+    lcd.set_pins(rs, false, false, value);
+    lcd.tick(2000);
+}
+
+fn receive(lcd: &mut Hd44780, rs: bool) -> u8 {
+    lcd.set_pins(rs, true, true, 0xFF);
+    let value = lcd.data_out(rs);
+    lcd.set_pins(rs, true, false, 0xFF);
+    value
+}
+
+#[test]
+fn test_hd44780_instructions() {
+    let mut lcd = Hd44780::new();
+    send(&mut lcd, false, 0x38); // 8 bit, 2 lines
+    send(&mut lcd, false, 0x0E); // display and cursor on
+    send(&mut lcd, false, 0x06); // increment
+    assert_eq!(b"                ".to_vec(), lcd.line(0, 16));
+    lcd.set_pins(true, false, true, b'H');
+    lcd.set_pins(true, false, false, b'H');
+    assert!(lcd.busy());
+    assert_eq!(0x81, receive(&mut lcd, false), "busy flag and address counter");
+    lcd.set_pins(true, false, true, b'x');
+    lcd.set_pins(true, false, false, b'x');
+    lcd.tick(37);
+    assert!(!lcd.busy());
+    assert_eq!(0x01, receive(&mut lcd, false), "ignored while busy");
+    send(&mut lcd, true, b'i');
+    assert_eq!(b"Hi  ".to_vec(), lcd.line(0, 4));
+    assert_eq!((true, false), lcd.cursor());
+
+    send(&mut lcd, false, 0x80);
+    assert_eq!(b'H', receive(&mut lcd, true));
+    assert_eq!(b'i', receive(&mut lcd, true));
+    assert_eq!(2, lcd.address(), "data reads move the address");
+
+    send(&mut lcd, false, 0x18); // shift the display left
+    assert_eq!(b"i ".to_vec(), lcd.line(0, 2));
+    send(&mut lcd, false, 0x02); // home
+    assert_eq!(b"Hi".to_vec(), lcd.line(0, 2));
+    send(&mut lcd, false, 0x08);
+    assert!(!lcd.display_on());
+    assert_eq!(b"  ".to_vec(), lcd.line(0, 2), "display off");
+    send(&mut lcd, false, 0x0C);
+    send(&mut lcd, false, 0x01);
+    assert_eq!(b"  ".to_vec(), lcd.line(0, 2), "cleared");
+    assert_eq!(0, lcd.address());
+}
+
+#[test]
+fn test_hd44780_lines_and_cgram() {
+    let mut lcd = Hd44780::new();
+    send(&mut lcd, false, 0x38);
+    send(&mut lcd, false, 0x0C);
+    send(&mut lcd, false, 0x80 | 0x27);
+    send(&mut lcd, true, b'a');
+    send(&mut lcd, true, b'b');
+    assert_eq!(0x41, lcd.address(), "the end of line one goes on with line two");
+    assert_eq!(b"b ".to_vec(), lcd.line(1, 2));
+    send(&mut lcd, false, 0x04); // decrement
+    send(&mut lcd, false, 0x80);
+    send(&mut lcd, true, b'c');
+    assert_eq!(0x67, lcd.address(), "and back");
+    send(&mut lcd, false, 0x10); // cursor left
+    assert_eq!(0x66, lcd.address());
+
+    send(&mut lcd, false, 0x06);
+    send(&mut lcd, false, 0x40 | 8); // character 1
+    for row in 0..8 {
+        send(&mut lcd, true, 0x1F >> row);
+    }
+    assert_eq!([0x1F, 0x0F, 0x07, 0x03, 0x01, 0x00, 0x00, 0x00], lcd.cgram()[8..16]);
+    assert_eq!(0x10, lcd.address());
+    send(&mut lcd, false, 0x80 | 0x40);
+    send(&mut lcd, true, 1);
+    assert_eq!(vec![1, b' '], lcd.line(1, 2));
+    assert_eq!(b'c', lcd.ddram()[0]);
+}
+
+#[test]
+fn test_hd44780_4_bit() {
+    let mut lcd = Hd44780::new();
+    // still 8 bit, only D7-D4 are wired
+    send(&mut lcd, false, 0x20);
+    // 4 bit and one line, high nibble first
+    send(&mut lcd, false, 0x20);
+    send(&mut lcd, false, 0x00);
+    for byte in [0x0C, 0x06, 0x80 | 0x05] {
+        send(&mut lcd, false, byte & 0xF0);
+        send(&mut lcd, false, byte << 4);
+    }
+    assert_eq!(5, lcd.address());
+    send(&mut lcd, true, b'Z' & 0xF0);
+    send(&mut lcd, true, b'Z' << 4);
+    assert_eq!(b"     Z".to_vec(), lcd.line(0, 6));
+    assert_eq!(b"  ".to_vec(), lcd.line(1, 2), "one line");
+
+    let high = receive(&mut lcd, false);
+    let low = receive(&mut lcd, false);
+    assert_eq!((0x00, 0x60), (high, low), "the address counter a nibble at a time");
+    send(&mut lcd, false, 0x80);
+    send(&mut lcd, false, 0x05 << 4);
+    let high = receive(&mut lcd, true);
+    let low = receive(&mut lcd, true);
+    assert_eq!(b'Z', high & 0xF0 | low >> 4);
+    assert_eq!(6, lcd.address());
+}
+
+#[test]
+fn test_hd44780_address_counter_wraps_at_7_bits() {
+    let mut lcd = Hd44780::new();
+    send(&mut lcd, false, 0x38);
+    send(&mut lcd, false, 0x06);
+    send(&mut lcd, false, 0xFF); // display RAM address $7F, past the second line
+    send(&mut lcd, true, b'a');
+    assert_eq!(0x00, lcd.address());
+    send(&mut lcd, true, b'b');
+    assert_eq!((b'a', b'b'), (lcd.ddram()[0x7F], lcd.ddram()[0x00]));
+    send(&mut lcd, false, 0x04); // decrement
+    send(&mut lcd, false, 0x80 | 0x50);
+    assert_eq!(b' ', receive(&mut lcd, true));
+    assert_eq!(0x4F, lcd.address());
+}
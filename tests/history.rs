@@ -0,0 +1,50 @@
+use emulator6502::asm::assemble;
+use emulator6502::debugger::*;
+use emulator6502::history::History;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    loop:   inc $10
+            bne loop
+            inc $11
+            jmp loop
+";
+
+#[test]
+fn test_history_checkpoints() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let mut history = History::with_interval(1);
+    history.checkpoint(&cpu);
+    for _ in 0..1000 {
+        history.record(&cpu);
+        cpu.step();
+    }
+    assert_eq!(1000, history.position());
+    assert_eq!(0, history.start());
+    assert!(history.checkpoints() <= 256, "every other one is dropped");
+    history.checkpoint(&cpu);
+    assert_eq!(0, history.start(), "the checkpoints before stay");
+}
+
+#[test]
+fn test_history_long_replay() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let mut debugger = Debugger::new(cpu);
+    debugger.set_history(Some(History::with_interval(100)));
+    assert_eq!(StopReason::Limit, debugger.run(Some(100_000)));
+    let hi = debugger.cpu.peek8(0x11);
+    debugger.add_watchpoint(Watchpoint::new(0x11, WatchKind::Write));
+    let hit = WatchHit { addr: 0x11, value: hi, write: true };
+    assert_eq!(StopReason::Watchpoint { pc: 0x0204, hit }, debugger.reverse_continue());
+    assert_eq!((0, hi), (debugger.cpu.peek8(0x10), debugger.cpu.peek8(0x11)));
+    assert!(debugger.history().unwrap().checkpoints() <= 256);
+}
@@ -0,0 +1,62 @@
+use emulator6502::*;
+
+#[test]
+fn test_jmp_absolute() {
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::JMP_ABSOLUTE, 0x34, 0x12]);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.process(3);
+    assert_eq!(0x1234, cpu.pc);
+    assert_eq!(3, cpu.cycles_run);
+}
+
+#[test]
+fn test_jmp_indirect() {
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::JMP_INDIRECT, 0x20, 0x01]);
+    mem.write16(0x0120, 0x4321);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.process(5);
+    assert_eq!(0x4321, cpu.pc);
+    assert_eq!(5, cpu.cycles_run);
+}
+
+#[test]
+fn test_jmp_indirect_page_wrap() {
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::JMP_INDIRECT, 0xFF, 0x02]);
+    mem.write8(0x02FF, 0x34);
+    mem.write8(0x0200, 0x12);
+    mem.write8(0x0300, 0x56);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.process(5);
+    assert_eq!(0x1234, cpu.pc);
+}
+
+#[test]
+fn test_jsr_rts() {
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::JSR_ABSOLUTE, 0x00, 0x02, Cpu::NOP_IMPLIED]);
+    mem.load_programm_at(0x0200, &[Cpu::RTS_IMPLIED]);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.process(6);
+    assert_eq!(0x0200, cpu.pc);
+    assert_eq!(STACK_OFFSET_START - 2, cpu.regs[Cpu::REG_SP]);
+    assert_eq!(6, cpu.cycles_run);
+    cpu.process(6);
+    assert_eq!(RESET_EXEC_ADDRESS + 3, cpu.pc);
+    assert_eq!(STACK_OFFSET_START, cpu.regs[Cpu::REG_SP]);
+    assert_eq!(12, cpu.cycles_run);
+    // the return address points to the last byte of the JSR
+    let ret = RESET_EXEC_ADDRESS + 2;
+    assert_eq!((ret >> 8) as u8, mem.read8(STACK_REAL_START));
+    assert_eq!(ret as u8, mem.read8(STACK_REAL_START - 1));
+}
@@ -0,0 +1,120 @@
+use emulator6502::asm::assemble;
+use emulator6502::kim1::{self, Tty};
+use emulator6502::serial::Host;
+use emulator6502::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+struct Teletype {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Host for Teletype {
+    fn read(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.borrow_mut().pop_front())
+    }
+
+    fn write(&mut self, byte: u8) -> io::Result<()> {
+        self.output.borrow_mut().push(byte);
+        Ok(())
+    }
+}
+
+// answers each key with the next letter, sending and receiving the bits the way the monitor does,
+// timed with the 6530-002's timer
+const ROM: &str = "
+    PAD = $1740
+    PBD = $1742
+    PBDD = $1743
+    TIMER8 = $1745
+    FLAG = $1747
+    char = $00
+
+    .org $1C00
+    reset:  ldx #$FF
+            txs
+            lda #$01
+            sta PBD
+            sta PBDD
+            lda PAD
+            and #$01
+            bne keypad
+    loop:   jsr getch
+            cmp #$7F
+            beq loop
+            clc
+            adc #1
+            jsr outch
+            jmp loop
+    keypad: jmp keypad
+
+    getch:  bit PAD
+            bmi getch
+            jsr half
+            ldx #8
+    bits:   jsr delay
+            lda PAD
+            asl a
+            ror char
+            dex
+            bne bits
+            jsr delay
+            lda char
+            rts
+
+    outch:  sta char
+            lda #0
+            sta PBD
+            jsr delay
+            ldx #8
+    obits:  lsr char
+            lda #0
+            adc #0
+            sta PBD
+            jsr delay
+            dex
+            bne obits
+            lda #1
+            sta PBD
+            jsr delay
+            jmp delay
+
+    delay:  lda #98
+            sta TIMER8
+    wait:   bit FLAG
+            bpl wait
+            rts
+    half:   lda #49
+            sta TIMER8
+            jmp wait
+
+    .org $1FFA
+    .word reset
+    .word reset
+    .word reset
+";
+
+#[test]
+fn test_kim1_tty() {
+    let mut mem = Mem::new();
+    let mut cpu = Cpu::new(&mut mem);
+    let teletype = Teletype::default();
+    teletype.input.borrow_mut().push_back(b'a');
+    let mut tty = Tty::new();
+    tty.connect(teletype.clone());
+    let rom = assemble(ROM).unwrap().bytes();
+    assert_eq!(1024, rom.len());
+    kim1::setup(&mut cpu, &rom, tty).unwrap();
+    assert_eq!(0x1C00, cpu.pc);
+    assert_eq!(0x1C, cpu.mem().read8(0xFFFB), "the vectors in the copy at the top");
+    cpu.process(60_000);
+    assert_eq!(b"\x7FAB".to_vec(), *teletype.output.borrow(), "the RUBOUT and the key echoed, the answer");
+    teletype.input.borrow_mut().push_back(b'y');
+    cpu.process(40_000);
+    assert_eq!(b"\x7FABYZ".to_vec(), *teletype.output.borrow());
+    assert!(cpu.pc >= 0x1C00 && cpu.pc < 0x1C40, "TTY mode");
+}
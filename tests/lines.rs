@@ -0,0 +1,195 @@
+use emulator6502::lines::{LineEntry, LineTable};
+use emulator6502::loader::load;
+use emulator6502::*;
+
+const DBG: &str = r#"version	major=2,minor=0
+info	csym=0,file=2,lib=0,line=6,mod=1,scope=1,seg=1,span=5,sym=0,type=4
+file	id=0,name="main.s",size=120,mtime=0x60000000,mod=0
+file	id=1,name="macros.inc",size=40,mtime=0x60000000,mod=0
+line	id=0,file=0,line=1,span=0
+line	id=1,file=0,line=2
+line	id=2,file=0,line=3,span=1+2
+line	id=3,file=1,line=4,type=2,span=1
+line	id=4,file=0,line=5,span=3
+line	id=5,file=1,line=7,span=4
+mod	id=0,name="main.o",file=0
+seg	id=0,name="CODE",start=0x000200,size=0x000010,addrsize=absolute,type=ro,oname="main.bin",ooffs=0
+span	id=0,seg=0,start=0,size=2
+span	id=1,seg=0,start=2,size=6
+span	id=2,seg=0,start=12,size=1
+span	id=3,seg=0,start=2,size=1
+span	id=4,seg=0,start=8,size=4
+"#;
+
+#[test]
+fn test_lines_ca65_dbg() {
+    let table = LineTable::parse_ca65_dbg(DBG).unwrap();
+    assert_eq!(&["main.s".to_string(), "macros.inc".to_string()], table.files());
+    assert_eq!(Some(("main.s", 1)), table.line_at(0x0201));
+    // the macro invocation on line 3 covers $0202-$0207, the smaller span of line 5 wins
+    assert_eq!(Some(("main.s", 5)), table.line_at(0x0202));
+    assert_eq!(Some(("main.s", 3)), table.line_at(0x0203));
+    assert_eq!(Some(("macros.inc", 7)), table.line_at(0x0208));
+    assert_eq!(None, table.line_at(0x020D));
+    assert_eq!(vec![0x0202, 0x020C], table.addresses(0, 3));
+    assert_eq!(Vec::<u16>::new(), table.addresses(0, 2), "lines without spans have no code");
+    assert_eq!(Some(&LineEntry { addr: 0x0200, size: 2, file: 0, line: 1 }), table.entry_at(0x0200));
+}
+
+#[test]
+fn test_lines_find_file_and_merge() {
+    let mut table = LineTable::parse_ca65_dbg(DBG).unwrap();
+    assert_eq!(Some(0), table.find_file("/home/dev/project/main.s"));
+    assert_eq!(Some(1), table.find_file("macros.inc"));
+    assert_eq!(None, table.find_file("other.s"));
+    let other = LineTable::parse_ca65_dbg(&DBG.replace("0x000200", "0x000400")).unwrap();
+    table.merge(&other);
+    assert_eq!(2, table.files().len());
+    assert_eq!(Some(("main.s", 1)), table.line_at(0x0400));
+    assert_eq!(vec![0x0202, 0x020C, 0x0402, 0x040C], table.addresses(0, 3));
+}
+
+#[test]
+fn test_lines_ca65_dbg_errors() {
+    let err = LineTable::parse_ca65_dbg(&DBG.replace("span=3", "span=9")).unwrap_err();
+    assert_eq!("line 9: line record with an unknown span", err.to_string());
+    let err = LineTable::parse_ca65_dbg(&DBG.replace("line=5,", "")).unwrap_err();
+    assert_eq!("line 9: invalid line record", err.to_string());
+}
+
+#[test]
+fn test_lines_load_resolves_paths() {
+    let dir = std::env::temp_dir().join(format!("emulator6502-lines-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dbg = dir.join("main.dbg");
+    std::fs::write(&dbg, DBG).unwrap();
+    let vice = dir.join("main.lbl");
+    std::fs::write(&vice, "al C:0200 .start\n").unwrap();
+    let table = LineTable::load(&dbg).unwrap();
+    assert_eq!(dir.join("main.s").to_string_lossy(), table.files()[0]);
+    assert!(LineTable::load(&vice).unwrap().is_empty(), "only debug files have lines");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn uleb(mut value: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// line_base -5, line_range 14, opcode_base 13
+fn special(addr: u8, line: u8) -> u8 {
+    (line + 5) + 14 * addr + 13
+}
+
+fn line_unit(version: u16, tables: &[u8], program: &[u8]) -> Vec<u8> {
+    let mut header = vec![1, 1, 1, 0xFB, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+    if version < 4 {
+        header.remove(1);
+    }
+    header.extend_from_slice(tables);
+    let mut unit = version.to_le_bytes().to_vec();
+    if version >= 5 {
+        unit.extend_from_slice(&[2, 0]);
+    }
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend(header);
+    unit.extend_from_slice(program);
+    let mut data = (unit.len() as u32).to_le_bytes().to_vec();
+    data.extend(unit);
+    data
+}
+
+// An ELF file with just section headers: the name table and the given sections
+fn elf_with_sections(sections: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut names = vec![0u8];
+    let mut body = Vec::new();
+    let mut headers = vec![[0u32; 10]];
+    let mut add = |name: &str, data: &[u8], body: &mut Vec<u8>, names: &mut Vec<u8>| {
+        let name_offset = names.len() as u32;
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+        headers.push([name_offset, 1, 0, 0, 52 + body.len() as u32, data.len() as u32, 0, 0, 1, 0]);
+        body.extend_from_slice(data);
+    };
+    for (name, data) in sections {
+        add(name, data, &mut body, &mut names);
+    }
+    let shstrtab = [names.clone(), b".shstrtab\0".to_vec()].concat();
+    add(".shstrtab", &shstrtab, &mut body, &mut names);
+    let last = headers.len() - 1;
+    let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&elf::EM_MOS.to_le_bytes());
+    for val in [1u32, 0x0800, 0, 52 + body.len() as u32, 0] {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    for val in [52u16, 32, 0, 40, headers.len() as u16, last as u16] {
+        elf.extend_from_slice(&val.to_le_bytes());
+    }
+    elf.extend(body);
+    for header in headers {
+        for val in header {
+            elf.extend_from_slice(&val.to_le_bytes());
+        }
+    }
+    elf
+}
+
+#[test]
+fn test_lines_dwarf4() {
+    let mut tables = b"src\0\0main.c\0".to_vec();
+    tables.extend_from_slice(&[1, 0, 0, 0]);
+    let mut program = vec![0, 3, 2, 0x00, 0x08, 3];
+    program.extend(uleb(9));
+    program.extend_from_slice(&[1, special(3, 1), special(2, 2), 2, 1, 0, 1, 1]);
+    let elf = elf_with_sections(&[(".debug_line", line_unit(4, &tables, &program))]);
+    let table = LineTable::from_elf(&elf).unwrap();
+    assert_eq!(&["src/main.c".to_string()], table.files());
+    assert_eq!(Some(("src/main.c", 10)), table.line_at(0x0802));
+    assert_eq!(Some(("src/main.c", 11)), table.line_at(0x0803));
+    assert_eq!(Some(&LineEntry { addr: 0x0805, size: 1, file: 0, line: 13 }), table.entry_at(0x0805));
+    assert_eq!(None, table.line_at(0x0806), "end of the sequence");
+
+    let mut mem = Mem::new();
+    let loaded = load(&mut mem, &elf, None).unwrap();
+    assert_eq!(Some(("src/main.c", 11)), loaded.lines.line_at(0x0804));
+}
+
+#[test]
+fn test_lines_dwarf5() {
+    // directories: line_strp paths, files: string path and data1 directory index
+    let mut tables = vec![1, 1, 0x1F, 2, 0, 0, 0, 0, 10, 0, 0, 0];
+    tables.extend_from_slice(&[2, 1, 0x08, 2, 0x0B, 1]);
+    tables.extend_from_slice(b"crt0.s\0");
+    tables.push(1);
+    // file numbers start at 0 in DWARF 5, but the file register still starts at 1
+    let mut program = vec![4, 0, 0, 3, 2, 0x00, 0x09, 1, special(2, 1)];
+    // a second sequence whose rows are not statements
+    program.extend_from_slice(&[2, 1, 0, 1, 1, 4, 0, 0, 3, 2, 0x00, 0x0A, 6, 1, 2, 1, 0, 1, 1]);
+    let line_str = b"/home/dev\0lib\0".to_vec();
+    let elf = elf_with_sections(&[(".debug_line", line_unit(5, &tables, &program)), (".debug_line_str", line_str)]);
+    let table = LineTable::from_elf(&elf).unwrap();
+    assert_eq!(&["/home/dev/lib/crt0.s".to_string()], table.files());
+    assert_eq!(Some(("/home/dev/lib/crt0.s", 1)), table.line_at(0x0901));
+    assert_eq!(Some(("/home/dev/lib/crt0.s", 2)), table.line_at(0x0902));
+    assert_eq!(None, table.line_at(0x0A00));
+}
+
+#[test]
+fn test_lines_dwarf_errors() {
+    let elf = elf_with_sections(&[(".debug_line", line_unit(6, &[], &[]))]);
+    assert_eq!("unsupported ELF file: DWARF line table version", LineTable::from_elf(&elf).unwrap_err().to_string());
+    let mut unit = line_unit(4, b"\0\0", &[1]);
+    unit.truncate(unit.len() - 2);
+    let elf = elf_with_sections(&[(".debug_line", unit)]);
+    assert_eq!("ELF file is truncated", LineTable::from_elf(&elf).unwrap_err().to_string());
+    assert!(LineTable::from_elf(&elf_with_sections(&[])).unwrap().is_empty());
+}
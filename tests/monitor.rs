@@ -0,0 +1,279 @@
+use emulator6502::asm::assemble;
+use emulator6502::debugger::Debugger;
+use emulator6502::lines::LineTable;
+use emulator6502::monitor::Monitor;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  inc $10
+            rts
+";
+
+// a line for each of the first four instructions of PROGRAM
+const DBG: &str = r#"version	major=2,minor=0
+file	id=0,name="main.s",size=120,mtime=0x60000000,mod=0
+line	id=0,file=0,line=3,span=0
+line	id=1,file=0,line=4,span=1
+line	id=2,file=0,line=5,span=2
+line	id=3,file=0,line=6,span=3
+seg	id=0,name="CODE",start=0x000200,size=0x00000E,addrsize=absolute,type=ro,oname="main.bin",ooffs=0
+span	id=0,seg=0,start=0,size=2
+span	id=1,seg=0,start=2,size=3
+span	id=2,seg=0,start=5,size=1
+span	id=3,seg=0,start=6,size=2
+"#;
+
+fn run(monitor: &mut Monitor<'_>, command: &str) -> String {
+    let mut out = Vec::new();
+    assert!(monitor.execute(command, &mut out).unwrap());
+    String::from_utf8(out).unwrap()
+}
+
+fn new_monitor(mem: &mut Mem) -> Monitor<'_> {
+    let asm = assemble(PROGRAM).unwrap();
+    asm.load(mem);
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let mut monitor = Monitor::new(Debugger::new(cpu));
+    monitor.symbols = asm.symbols;
+    monitor
+}
+
+#[test]
+fn test_monitor_step_next_and_repeat() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("0202  20 0B 02  jsr count           PC:0202 A:00 X:03 Y:00 SP:FF P:00 nv-bdizc CYC:2\n", run(&mut monitor, "step"));
+    assert!(run(&mut monitor, "n").starts_with("0205  CA        dex "));
+    assert!(run(&mut monitor, "").starts_with("0206  D0 FA     bne loop "), "empty line repeats next");
+    assert!(run(&mut monitor, "s 2").starts_with("020B  E6 10     inc $10 "));
+}
+
+#[test]
+fn test_monitor_breakpoints_and_continue() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("Breakpoint at count\n", run(&mut monitor, "break count"));
+    run(&mut monitor, "b 205");
+    assert_eq!("Breakpoint at loop+3\nBreakpoint at count\n", run(&mut monitor, "break"));
+    assert!(run(&mut monitor, "c").starts_with("Breakpoint at count\n020B  "));
+    run(&mut monitor, "delete count");
+    assert!(run(&mut monitor, "continue").starts_with("Breakpoint at loop+3\n0205  CA"));
+    assert_eq!("error: no breakpoint at $020B\n", run(&mut monitor, "del 20b"));
+    run(&mut monitor, "del 205");
+    assert!(run(&mut monitor, "c 50").starts_with("0208  4C 08 02  jmp done"));
+}
+
+#[test]
+fn test_monitor_conditional_breakpoints() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("Breakpoint at count if X == 2 && [$10] == 1\n", run(&mut monitor, "break count if X == 2 && [$10] == 1"));
+    assert!(run(&mut monitor, "c").starts_with("Breakpoint at count\n020B  "));
+    assert_eq!("$0002 2\n", run(&mut monitor, "print x"));
+    assert_eq!("$020C 524\n", run(&mut monitor, "p count + 1"));
+    assert_eq!("Breakpoint at count if X == 2 && [$10] == 1, 1 hits\n", run(&mut monitor, "b"));
+    run(&mut monitor, "cond count");
+    run(&mut monitor, "hits count 2");
+    assert_eq!("Breakpoint at count, 1 hits, stops from hit 2\n", run(&mut monitor, "b"));
+    assert!(run(&mut monitor, "c").starts_with("Breakpoint at count\n"));
+    assert_eq!("$0001 1\n", run(&mut monitor, "p X"));
+    run(&mut monitor, "pc start");
+    run(&mut monitor, "ignore count 3");
+    assert!(run(&mut monitor, "c 100").starts_with("0208  4C 08 02  jmp done"));
+    assert_eq!("error: no breakpoint at $0205\n", run(&mut monitor, "ignore 205 1"));
+    assert_eq!("error: unknown symbol nowhere\n", run(&mut monitor, "b 205 if nowhere"));
+    assert_eq!("error: expected if and a condition\n", run(&mut monitor, "b 205 X == 1"));
+    assert_eq!("error: division by zero\n", run(&mut monitor, "p 1 / 0"));
+}
+
+#[test]
+fn test_monitor_backtrace() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("#0  0200  start\n", run(&mut monitor, "bt"));
+    run(&mut monitor, "s 2");
+    assert_eq!("#0  020B  count\n#1  0202  loop\n", run(&mut monitor, "backtrace"));
+    run(&mut monitor, "e 1fe 10 02");
+    run(&mut monitor, "s 2");
+    assert_eq!("#0  0211  count+6\nReturns that didn't match a call: 1, the last one at count+2 went to count+6\n", run(&mut monitor, "bt"));
+}
+
+#[test]
+fn test_monitor_profile() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("error: not profiling, try profile on\n", run(&mut monitor, "profile"));
+    assert_eq!("Profiling\n", run(&mut monitor, "profile on"));
+    run(&mut monitor, "c 14");
+    let report = run(&mut monitor, "profile 1");
+    assert!(report.starts_with("63 cycles, 14 instructions\n"), "{}", report);
+    assert!(report.contains("\ncount                              33  52.4%           33  52.4%        3\n"), "{}", report);
+    assert!(report.ends_with("0202  jsr count                                        18  28.6%          3\n"), "{}", report);
+    let path = std::env::temp_dir().join(format!("emulator6502-monitor-{}.callgrind", std::process::id()));
+    run(&mut monitor, &format!("profile save {}", path.display()));
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\nfn=count\n"));
+    std::fs::remove_file(&path).unwrap();
+    run(&mut monitor, "profile off");
+    assert!(monitor.debugger.cpu.profiler.is_none());
+}
+
+#[test]
+fn test_monitor_coverage() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("error: no coverage, try coverage on\n", run(&mut monitor, "coverage"));
+    assert_eq!("Recording coverage\n", run(&mut monitor, "coverage on"));
+    run(&mut monitor, "c 16");
+    let report = run(&mut monitor, "coverage");
+    assert!(report.starts_with("11 bytes executed\n$0200-$0207\n$020B-$020D\n"), "{}", report);
+    assert!(report.contains("\n0206  bne loop                          3  taken 2, not taken 1\n"), "{}", report);
+    let path = std::env::temp_dir().join(format!("emulator6502-monitor-{}.cov", std::process::id()));
+    run(&mut monitor, &format!("coverage save {}", path.display()));
+    assert!(std::fs::read_to_string(&path).unwrap().starts_with("11 bytes executed\n"), "no lines, no lcov");
+    monitor.lines = LineTable::parse_ca65_dbg(DBG).unwrap();
+    run(&mut monitor, &format!("coverage save {}", path.display()));
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\nBRDA:6,0,0,2\nBRDA:6,0,1,1\n"));
+    std::fs::remove_file(&path).unwrap();
+    run(&mut monitor, "coverage off");
+    assert!(monitor.debugger.cpu.coverage.is_none());
+}
+
+#[test]
+fn test_monitor_cdl() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("error: not logging, try cdl on\n", run(&mut monitor, "cdl"));
+    assert_eq!("Logging code and data\n", run(&mut monitor, "cdl on"));
+    run(&mut monitor, "c 16");
+    assert_eq!("6 opcode, 5 operand, 1 data and 0 pointer bytes\n", run(&mut monitor, "cdl"));
+    let path = std::env::temp_dir().join(format!("emulator6502-monitor-{}.cdl", std::process::id()));
+    run(&mut monitor, &format!("cdl save {}", path.display()));
+    let cdl = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(0x10000, cdl.len());
+    assert_eq!(0x02, cdl[0x10], "inc $10 reads it");
+    assert_eq!([0x01; 8], cdl[0x0200..0x0208]);
+    run(&mut monitor, "cdl off");
+    assert!(monitor.debugger.cpu.cdl.is_none());
+}
+
+#[test]
+fn test_monitor_reverse() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert!(run(&mut monitor, "rs").starts_with("Start of the history\n0200 "));
+    run(&mut monitor, "w w 10");
+    run(&mut monitor, "c");
+    run(&mut monitor, "c");
+    assert!(run(&mut monitor, "rs 2").starts_with("0202  20 0B 02  jsr count "));
+    assert_eq!("Watchpoint: write $0010 = $01 at count\n020D  60        rts                 PC:020D A:00 X:03 Y:00 SP:FD P:00 nv-bdizc CYC:13\n", run(&mut monitor, "rc"));
+    run(&mut monitor, "> 10 40");
+    run(&mut monitor, "c");
+    assert!(run(&mut monitor, "m 10 10").starts_with("0010  41 "), "the edit is part of the history");
+    run(&mut monitor, "rs");
+    assert!(run(&mut monitor, "m 10 10").starts_with("0010  40 "));
+    assert!(run(&mut monitor, "rc").starts_with("Watchpoint: write $0010 = $01 at count\n"));
+    assert!(run(&mut monitor, "rc").starts_with("Start of the history\n0200 "));
+}
+
+#[test]
+fn test_monitor_writes() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("error: not recording writes, try writes on\n", run(&mut monitor, "writes 10"));
+    assert_eq!("Recording writes\n", run(&mut monitor, "writes on"));
+    run(&mut monitor, "c 11");
+    assert_eq!("$02 by count at cycle 35\n$01 by count at cycle 13\n", run(&mut monitor, "writes 10"));
+    assert_eq!("No writes to done\n", run(&mut monitor, "writes done"));
+    run(&mut monitor, "writes off");
+    assert!(monitor.debugger.cpu.write_history.is_none());
+}
+
+#[test]
+fn test_monitor_watchpoints() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    run(&mut monitor, "watch w 10");
+    run(&mut monitor, "w r $20 $2F");
+    assert_eq!("Watchpoint (write) at $0010\nWatchpoint (read) at $0020 to $002F\n", run(&mut monitor, "watch"));
+    assert!(run(&mut monitor, "c").starts_with("Watchpoint: write $0010 = $01 at count\n"));
+    run(&mut monitor, "unwatch 10");
+    assert_eq!("error: no watchpoint at $0010\n", run(&mut monitor, "unwatch 10"));
+}
+
+#[test]
+fn test_monitor_registers() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("PC:0208 A:5A X:00 Y:00 SP:FD P:81 Nv-bdizC CYC:0\n", run(&mut monitor, "r a=5a sp=fd p=81 pc=done"));
+    assert_eq!(0x0208, monitor.debugger.cpu.pc);
+    assert_eq!("error: unknown register q\n", run(&mut monitor, "r q=1"));
+    assert_eq!("error: 256 doesn't fit in a\n", run(&mut monitor, "r a=100"));
+}
+
+#[test]
+fn test_monitor_memory() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    run(&mut monitor, "> 300 48 69 $21 0");
+    assert_eq!("0300  48 69 21 00                                      |Hi!.|\n", run(&mut monitor, "m 300 303"));
+    assert_eq!("0304  00 00                                            |..|\n", run(&mut monitor, "m 304 305"));
+    assert_eq!(8, run(&mut monitor, "m").lines().count(), "default dump is 8 rows");
+    assert_eq!("error: 100 is not a byte\n", run(&mut monitor, "e 300 100"));
+    assert_eq!(0x21, monitor.debugger.cpu.mem().read8(0x302));
+}
+
+#[test]
+fn test_monitor_disasm_continues() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("start:\n0200  A2 03     ldx #$03\nloop:\n0202  20 0B 02  jsr count\n", run(&mut monitor, "d start 2"));
+    assert!(run(&mut monitor, "d").starts_with("0205  CA        dex\n"));
+}
+
+#[test]
+fn test_monitor_errors_and_quit() {
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    assert_eq!("error: unknown command foo, try help\n", run(&mut monitor, "foo"));
+    assert_eq!("error: unknown symbol or invalid address nowhere\n", run(&mut monitor, "b nowhere"));
+    assert!(run(&mut monitor, "load /nonexistent/file.bin 200").starts_with("error: /nonexistent/file.bin: "));
+    assert!(run(&mut monitor, "help").contains("watch (w)"));
+    assert!(!monitor.execute("quit", &mut Vec::new()).unwrap());
+}
+
+#[test]
+fn test_monitor_load_raw_binary() {
+    let path = std::env::temp_dir().join(format!("emulator6502-monitor-{}.bin", std::process::id()));
+    std::fs::write(&path, [Cpu::LDA_IMMEDIATE, 0x42]).unwrap();
+    let mut mem = Mem::new();
+    let mut monitor = new_monitor(&mut mem);
+    let command = format!("load {} 400", path.display());
+    assert!(run(&mut monitor, &format!("load {}", path.display())).ends_with(": raw binaries need a load address\n"));
+    assert!(run(&mut monitor, &command).starts_with("0400  A9 42     lda #$42"));
+    std::fs::remove_file(&path).unwrap();
+    run(&mut monitor, "s");
+    assert_eq!(0x42, monitor.debugger.cpu.regs[Cpu::REG_A]);
+}
+
+#[test]
+fn test_monitor_reset_starts_at_the_reset_vector() {
+    let mut mem = Mem::new();
+    // a ROM at the top of memory with the vector low byte first, pointing at count
+    let mut rom = vec![0xEA; 0x10];
+    rom[0x0C..].copy_from_slice(&[0x0B, 0x02, 0x00, 0x00]);
+    emulator6502::loader::load(&mut mem, &rom, Some(0xFFF0)).unwrap();
+    let mut monitor = new_monitor(&mut mem);
+    run(&mut monitor, "step");
+    let out = run(&mut monitor, "reset");
+    assert!(out.starts_with("020B  E6 10     inc $10 "), "{}", out);
+    assert!(out.contains("PC:020B "), "{}", out);
+}
@@ -0,0 +1,83 @@
+use emulator6502::asm::assemble;
+use emulator6502::device::Device;
+use emulator6502::pia::Pia;
+use emulator6502::*;
+
+#[test]
+fn test_pia_ports() {
+    let mut pia = Pia::new();
+    pia.write(Pia::PB, 0x0F);
+    assert_eq!(0x0F, pia.read(Pia::PB), "DDRB while bit 2 of CRB is clear");
+    pia.write(Pia::CRB, Pia::CR_PORT);
+    pia.write(Pia::PB, 0x55);
+    pia.set_port_b(0xA0);
+    assert_eq!(0xA5, pia.read(Pia::PB));
+    assert_eq!(0xA5, pia.port_b());
+    pia.write(Pia::CRA, Pia::CR_PORT);
+    pia.set_port_a(0x42);
+    assert_eq!(0x42, pia.read(Pia::PA + 4), "mirrored");
+    pia.write(Pia::CRB, 0xFF);
+    assert_eq!(0x3F, pia.peek(Pia::CRB), "the flags can't be written");
+}
+
+#[test]
+fn test_pia_control_lines() {
+    let mut pia = Pia::new();
+    // CA1 on rising edges with its interrupt, CA2 handshake output
+    pia.write(Pia::CRA, Pia::CR_PORT | Pia::CR_C1_RISING | Pia::CR_C1_IRQ | 0x20);
+    pia.set_ca1(false);
+    assert!(!pia.irq());
+    pia.set_ca1(true);
+    assert!(pia.irq_a());
+    assert_eq!(Pia::CR_IRQ1, pia.peek(Pia::CRA) & 0xC0);
+    assert!(pia.ca2());
+    pia.read(Pia::PA);
+    assert!(!pia.irq(), "reading port A clears the flags");
+    assert!(!pia.ca2(), "data taken");
+    pia.set_ca1(false);
+    pia.set_ca1(true);
+    assert!(pia.ca2(), "data ready");
+
+    // CB2 falling edge input with its interrupt, then pulse output on writes
+    pia.write(Pia::CRB, Pia::CR_PORT | 0x08);
+    pia.set_cb2(false);
+    assert!(pia.irq_b());
+    pia.read(Pia::PB);
+    pia.write(Pia::CRB, Pia::CR_PORT | 0x28);
+    pia.write(Pia::PB, 0x00);
+    assert!(!pia.cb2());
+    pia.tick(1);
+    assert!(pia.cb2(), "low for one cycle");
+    pia.write(Pia::CRB, Pia::CR_PORT | 0x30);
+    assert!(!pia.cb2(), "set by hand");
+}
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  lda #$05        ; port A, interrupt on falling CA1
+            sta $D011
+            cli
+    loop:   jmp loop
+    irq:    lda $D010
+            sta $10
+            rti
+    .org $FFFE
+    .word irq
+";
+
+#[test]
+fn test_pia_cpu_irq() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let index = cpu.devices.map(0xD010..=0xD013, Pia::new());
+    cpu.process(100);
+    let pia = cpu.devices.get_mut::<Pia>(index).unwrap();
+    pia.set_port_a(0xC1);
+    pia.set_ca1(false);
+    cpu.process(100);
+    assert_eq!(0xC1, cpu.mem().read8(0x10));
+    assert!(!cpu.devices.irq(), "acknowledged by the handler");
+}
@@ -0,0 +1,109 @@
+use emulator6502::asm::{assemble, Assembly};
+use emulator6502::callstack::CallStack;
+use emulator6502::profile::*;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  inc $10
+            jsr twice
+            rts
+    twice:  asl $11
+            asl $11
+            rts
+";
+
+fn profile(mem: &mut Mem, asm: &Assembly) -> Profiler {
+    asm.load(mem);
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.call_stack = Some(CallStack::new());
+    cpu.profiler = Some(Profiler::new());
+    while cpu.pc != asm.symbols.lookup("done").unwrap() {
+        cpu.step();
+    }
+    cpu.profiler.take().unwrap()
+}
+
+#[test]
+fn test_profile_counts() {
+    let asm = assemble(PROGRAM).unwrap();
+    let addr = |name: &str| asm.symbols.lookup(name).unwrap();
+    let mut mem = Mem::new();
+    let profiler = profile(&mut mem, &asm);
+    // ldx 2, 3 * (jsr 6, dex 2), bne 3 + 3 + 2
+    assert_eq!(Counts { instructions: 10, cycles: 34 }, profiler.exclusive(None));
+    // 3 * (inc 5, jsr 6, rts 6)
+    assert_eq!(Counts { instructions: 9, cycles: 51 }, profiler.exclusive(Some(addr("count"))));
+    // 3 * (asl 5, asl 5, rts 6)
+    assert_eq!(Counts { instructions: 9, cycles: 48 }, profiler.exclusive(Some(addr("twice"))));
+    assert_eq!(Counts { instructions: 28, cycles: 133 }, profiler.total());
+    assert_eq!(CallCounts { calls: 3, inclusive: Counts { instructions: 18, cycles: 99 } }, profiler.calls_to(addr("count")));
+    assert_eq!(CallCounts { calls: 3, inclusive: Counts { instructions: 9, cycles: 48 } }, profiler.calls_to(addr("twice")));
+    assert_eq!(Counts { instructions: 3, cycles: 8 }, profiler.address(addr("loop") + 4));
+    assert_eq!((0x0200, Counts { instructions: 1, cycles: 2 }), profiler.addresses()[0]);
+}
+
+#[test]
+fn test_profile_report() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let profiler = profile(&mut mem, &asm);
+    let mut out = Vec::new();
+    profiler.report(&mut out, &mem, &asm.symbols, 2).unwrap();
+    let expected = "\
+133 cycles, 28 instructions
+
+function                       cycles      %    inclusive      %    calls
+count                              51  38.3%           99  74.4%        3
+twice                              48  36.1%           48  36.1%        3
+(top level)                        34  25.6%          133 100.0%        0
+
+instruction                                        cycles      %      count
+0202  jsr count                                        18  13.5%          3
+020D  jsr twice                                        18  13.5%          3
+";
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+}
+
+#[test]
+fn test_profile_callgrind() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    let profiler = profile(&mut mem, &asm);
+    let mut out = Vec::new();
+    profiler.write_callgrind(&mut out, &asm.symbols).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.starts_with("# callgrind format\nversion: 1\ncreator: emulator6502\npositions: instr\nevents: Cycles Instructions\nsummary: 133 28\n"));
+    assert!(text.contains("\nfn=(top level)\n0x0200 2 1\n0x0202 18 3\n0x0205 6 3\n0x0206 8 3\ncfn=count\ncalls=3 0x020B\n0x0202 99 18\n"));
+    assert!(text.contains("\nfn=count\n0x020B 15 3\n0x020D 18 3\n0x0210 18 3\ncfn=twice\ncalls=3 0x0211\n0x020D 48 9\n"));
+}
+
+#[test]
+fn test_profile_without_call_stack_and_open_calls() {
+    let asm = assemble(PROGRAM).unwrap();
+    let mut mem = Mem::new();
+    asm.load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.profiler = Some(Profiler::new());
+    cpu.process(50);
+    let profiler = cpu.profiler.take().unwrap();
+    assert_eq!(profiler.total(), profiler.exclusive(None), "everything is top level code");
+
+    cpu.call_stack = Some(CallStack::new());
+    cpu.profiler = Some(Profiler::new());
+    cpu.pc = 0x0202;
+    cpu.step();
+    cpu.step();
+    let profiler = cpu.profiler.as_ref().unwrap();
+    assert_eq!(Some(0x020B), profiler.current_function());
+    assert_eq!(CallCounts { calls: 1, inclusive: Counts { instructions: 1, cycles: 5 } }, profiler.calls_to(0x020B), "open calls count up to now");
+}
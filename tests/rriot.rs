@@ -0,0 +1,48 @@
+use emulator6502::device::Device;
+use emulator6502::rriot::Rriot;
+
+#[test]
+fn test_rriot_ports() {
+    let mut rriot = Rriot::new();
+    rriot.write(Rriot::PADD, 0x0F);
+    rriot.write(Rriot::PAD, 0x55);
+    rriot.set_port_a(0xA0);
+    assert_eq!(0xA5, rriot.read(Rriot::PAD));
+    assert_eq!(0x0F, rriot.read(Rriot::PADD));
+    rriot.write(Rriot::PBDD + 0x30, 0xFF);
+    rriot.write(Rriot::PBD + 0x10, 0x42);
+    assert_eq!(0x42, rriot.port_b(), "mirrored");
+    rriot.reset();
+    assert_eq!(0xFF, rriot.port_b(), "inputs after a reset");
+}
+
+#[test]
+fn test_rriot_timer() {
+    let mut rriot = Rriot::new();
+    // count every 8 cycles
+    rriot.write(Rriot::TIMER + 1, 3);
+    rriot.tick(8);
+    assert_eq!(2, rriot.read(Rriot::TIMER));
+    rriot.tick(23);
+    assert_eq!(0, rriot.peek(Rriot::TIMER));
+    assert_eq!(0x00, rriot.peek(Rriot::TIMER + 1));
+    rriot.tick(1);
+    assert_eq!(0x80, rriot.peek(Rriot::TIMER + 1), "passed zero");
+    assert_eq!(0xFF, rriot.peek(Rriot::TIMER));
+    rriot.tick(5);
+    assert_eq!(0xFA, rriot.peek(Rriot::TIMER), "every cycle from there");
+    assert!(!rriot.irq());
+    assert_eq!(0xFA, rriot.read(Rriot::TIMER | Rriot::TIMER_IRQ));
+    assert!(!rriot.timer_flag(), "reading clears the flag");
+    rriot.tick(8);
+    assert_eq!(0xF9, rriot.peek(Rriot::TIMER), "and goes back to the divider");
+
+    // every 1024 cycles with the interrupt
+    rriot.write(Rriot::TIMER + 3 + Rriot::TIMER_IRQ, 1);
+    rriot.tick(2047);
+    assert!(!rriot.irq());
+    rriot.tick(1);
+    assert!(rriot.irq());
+    rriot.write(Rriot::TIMER, 10);
+    assert!(!rriot.irq(), "writing clears it");
+}
@@ -0,0 +1,172 @@
+use emulator6502::asm::assemble;
+use emulator6502::loader::LoadError;
+use emulator6502::sim65::{self, Exit, Header, Paravirt};
+use emulator6502::*;
+
+// calls the hooks the way the cc65 runtime does, with the C stack pointer at $02
+const PROGRAM: &str = "
+    csp = $02
+    ptr = $20
+    argv = $0300
+    buf = $0400
+
+    .org $0200
+    start:  lda #$00
+            sta csp
+            lda #$C0
+            sta csp+1
+            lda #<argv
+            ldx #>argv
+            jsr $FFF8           ; args
+            sta $10
+            lda argv            ; argv[1]
+            sta ptr
+            lda argv+1
+            sta ptr+1
+            ldy #2
+            lda (ptr),y
+            sta $30
+            iny
+            lda (ptr),y
+            sta $31
+
+            lda $30             ; open(argv[1], O_WRONLY | O_CREAT | O_TRUNC)
+            ldx $31
+            jsr pushax
+            lda #$32
+            ldx #0
+            jsr pushax
+            ldy #4
+            jsr $FFF4
+            sta $11
+            ldx #0              ; write(fd, text, 3)
+            jsr pushax
+            lda #<text
+            ldx #>text
+            jsr pushax
+            lda #3
+            ldx #0
+            jsr $FFF7
+            sta $12
+            lda $11             ; close(fd)
+            ldx #0
+            jsr $FFF5
+            sta $13
+
+            lda $30             ; open(argv[1], O_RDONLY)
+            ldx $31
+            jsr pushax
+            lda #$01
+            ldx #0
+            jsr pushax
+            ldy #4
+            jsr $FFF4
+            ldx #0              ; read(fd, buf, 10)
+            jsr pushax
+            lda #<buf
+            ldx #>buf
+            jsr pushax
+            lda #10
+            ldx #0
+            jsr $FFF6
+            sta $14
+            lda #9              ; close(9) fails
+            ldx #0
+            jsr $FFF5
+            stx $15
+            lda #42
+            jmp $FFF9
+
+    pushax: pha
+            sec
+            lda csp
+            sbc #2
+            sta csp
+            bcs pushed
+            dec csp+1
+    pushed: pla
+            ldy #0
+            sta (csp),y
+            txa
+            iny
+            sta (csp),y
+            rts
+
+    text:   .byte \"hi\", 10
+";
+
+fn sim65_file(load: u16, reset: u16, code: &[u8]) -> Vec<u8> {
+    let mut data = b"sim65\x02\x00\x02".to_vec();
+    data.extend(&[load as u8, (load >> 8) as u8, reset as u8, (reset >> 8) as u8]);
+    data.extend(code);
+    data
+}
+
+#[test]
+fn test_sim65_hooks() {
+    let path = std::env::temp_dir().join(format!("sim65-test-{}.txt", std::process::id()));
+    let path_name = path.to_str().unwrap().to_string();
+    let code = assemble(PROGRAM).unwrap().bytes();
+    let mut mem = Mem::new();
+    let header = sim65::load(&mut mem, &sim65_file(0x0200, 0x0200, &code)).unwrap();
+    assert_eq!(Header { version: 2, cpu: 0, sp: 0x02, load: 0x0200, reset: 0x0200 }, header);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = header.reset;
+    let mut paravirt = Paravirt::new(&header, vec!["test".to_string(), path_name.clone()]);
+    let (exit, cycles) = sim65::run(&mut cpu, &mut paravirt, Some(100_000));
+    let written = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(Exit::Code(42), exit);
+    assert!(cycles > 100 && cycles < 10_000);
+    assert_eq!(Some(42), paravirt.exit_code());
+    assert_eq!(b"hi\n".to_vec(), written.unwrap());
+    let mem = cpu.mem();
+    assert_eq!(2, mem.read8(0x10), "argc");
+    assert_eq!(3, mem.read8(0x11), "the first free file descriptor");
+    assert_eq!([3, 0], [mem.read8(0x12), mem.read8(0x13)], "written, closed");
+    assert_eq!(3, mem.read8(0x14), "read");
+    assert_eq!(0xFF, mem.read8(0x15), "-1");
+    assert_eq!(b"hi\n".to_vec(), (0x400..0x403).map(|addr| mem.read8(addr)).collect::<Vec<u8>>());
+
+    // the arguments below the C stack, argv[2] is null
+    let argv = mem.read8(0x300) as usize | (mem.read8(0x301) as usize) << 8;
+    let word = |addr: usize| mem.read8(addr) as usize | (mem.read8(addr + 1) as usize) << 8;
+    let string = |addr: usize| (addr..).map(|addr| mem.read8(addr)).take_while(|&byte| byte != 0).collect::<Vec<u8>>();
+    assert_eq!(0xC000 - 6, argv);
+    assert_eq!(b"test".to_vec(), string(word(argv)));
+    assert_eq!(path_name.as_bytes().to_vec(), string(word(argv + 2)));
+    assert_eq!(0, word(argv + 4));
+    assert_eq!(word(argv + 2), word(0x02), "the C stack pointer below the strings");
+}
+
+#[test]
+fn test_sim65_load() {
+    let mut mem = Mem::new();
+    let header = sim65::load(&mut mem, b"sim65\x01\x00\xA9\x07\x4C\xF9\xFF").unwrap();
+    assert_eq!(Header { version: 1, cpu: 0, sp: 0x00, load: 0x0200, reset: 0x0200 }, header);
+    assert_eq!(0xA9, mem.read8(0x200));
+    assert!(matches!(sim65::load(&mut mem, b"sim65\x02\x01\x02\x00\x02\x00\x02"), Err(LoadError::Sim65(_))), "65C02");
+    assert!(matches!(sim65::load(&mut mem, b"sim65\x02\x00"), Err(LoadError::Sim65(_))), "truncated");
+    assert!(matches!(sim65::load(&mut mem, b"\x7FELF"), Err(LoadError::Sim65(_))));
+
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = header.reset;
+    let mut paravirt = Paravirt::new(&header, Vec::new());
+    assert_eq!(Exit::Code(7), sim65::run(&mut cpu, &mut paravirt, None).0);
+}
+
+#[test]
+fn test_sim65_timeout() {
+    let mut mem = Mem::new();
+    // jmp *
+    let header = sim65::load(&mut mem, &sim65_file(0x1000, 0x1000, &[0x4C, 0x00, 0x10])).unwrap();
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = header.reset;
+    let mut paravirt = Paravirt::new(&header, Vec::new());
+    let (exit, cycles) = sim65::run(&mut cpu, &mut paravirt, Some(1000));
+    assert_eq!(Exit::Timeout, exit);
+    assert_eq!(1002, cycles);
+}
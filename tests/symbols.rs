@@ -0,0 +1,127 @@
+use emulator6502::elf::{ElfFile, ElfSymbol, SymbolKind};
+use emulator6502::symbols::*;
+
+const VICE: &str = "al C:c000 .main\nal C:c010 .loop\nal 00fffa .nmi_vector\nbreak c000\n";
+
+const CA65_DBG: &str = r#"version	major=2,minor=0
+info	csym=0,file=1,lib=0,line=4,mod=1,scope=2,seg=1,span=4,sym=4,type=1
+file	id=0,name="main.s",size=120,mtime=0x60000000,mod=0
+seg	id=0,name="CODE",start=0x00C000,size=0x0010,addrsize=absolute,type=ro,oname="main.bin",ooffs=0
+scope	id=0,name="",mod=0,size=16,span=0
+scope	id=1,name="print",mod=0,type=scope,size=6,parent=0,sym=2,span=1
+sym	id=0,name="main",addrsize=absolute,size=10,scope=0,def=1,ref=3,val=0xC000,seg=0,type=lab
+sym	id=1,name="@loop",addrsize=absolute,scope=0,parent=0,def=2,val=0xC002,seg=0,type=lab
+sym	id=2,name="print",addrsize=absolute,scope=0,def=4,val=0xC00A,seg=0,type=lab
+sym	id=3,name="next",addrsize=absolute,scope=1,def=5,val=0xC00C,seg=0,type=lab
+sym	id=4,name="SCREEN",addrsize=absolute,scope=0,def=6,val=0x400,type=equ
+sym	id=5,name="CHROUT",addrsize=absolute,scope=0,ref=7,type=imp
+"#;
+
+const ACME: &str = "\tmain\t= $c000\n\tborder\t= $d020 ; ?\n!addr\tscreen\t= $0400\n\tcount\t= 12\n";
+
+const TASS64: &str = "main            = $c000\nloop            = $c010\nscope.inner     = 49170\n";
+
+#[test]
+fn test_symbols_insert_lookup() {
+    let mut table = SymbolTable::new();
+    table.insert("main", 0xC000);
+    table.insert("reset", 0xFCE2);
+    assert_eq!(Some(0xC000), table.lookup("main"));
+    assert_eq!(Some("reset"), table.name_at(0xFCE2));
+    assert_eq!(None, table.name_at(0xFCE3));
+    // redefining a name moves it
+    table.insert("main", 0xC100);
+    assert_eq!(None, table.name_at(0xC000));
+    assert_eq!(Some("main"), table.name_at(0xC100));
+    assert_eq!(Some(0xC100), table.remove("main"));
+    assert_eq!(1, table.len());
+}
+
+#[test]
+fn test_symbols_symbolize() {
+    let mut table = SymbolTable::new();
+    table.insert("main", 0xFCE2);
+    table.insert_sized("buffer", 0x0200, Some(4));
+    assert_eq!("main+3", table.format_addr(0xFCE5));
+    assert_eq!("main", table.format_addr(0xFCE2));
+    assert_eq!("buffer+3", table.format_addr(0x0203));
+    assert_eq!("$0204", table.format_addr(0x0204));
+    assert_eq!("$FCE1", table.format_addr(0xFCE1));
+    assert_eq!("$FDE2", table.format_addr(0xFCE2 + MAX_SYMBOL_OFFSET + 1));
+}
+
+#[test]
+fn test_symbols_vice() {
+    let table = SymbolTable::parse(VICE, SymbolFormat::Vice).unwrap();
+    assert_eq!(3, table.len());
+    assert_eq!(Some(0xC010), table.lookup("loop"));
+    assert_eq!(Some("nmi_vector"), table.name_at(0xFFFA));
+    assert_eq!(SymbolFormat::Vice, SymbolFormat::detect(VICE));
+    match SymbolTable::parse("al C:12345 .x", SymbolFormat::Vice) {
+        Err(SymbolError::Parse { line: 1, .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_symbols_ca65_dbg() {
+    assert_eq!(SymbolFormat::Ca65Dbg, SymbolFormat::detect(CA65_DBG));
+    let table = SymbolTable::parse(CA65_DBG, SymbolFormat::Ca65Dbg).unwrap();
+    assert_eq!(Some(0xC000), table.lookup("main"));
+    assert_eq!(Some(0xC002), table.lookup("main@loop"));
+    assert_eq!(Some(0xC00C), table.lookup("print::next"));
+    // equates and imports are not labels
+    assert_eq!(None, table.lookup("SCREEN"));
+    assert_eq!(None, table.lookup("CHROUT"));
+    assert_eq!("main@loop+1", table.format_addr(0xC003));
+    assert_eq!("print+1", table.format_addr(0xC00B));
+}
+
+#[test]
+fn test_symbols_acme_and_64tass() {
+    assert_eq!(SymbolFormat::Acme, SymbolFormat::detect(ACME));
+    let table = SymbolTable::parse(ACME, SymbolFormat::Acme).unwrap();
+    assert_eq!(Some(0xD020), table.lookup("border"));
+    assert_eq!(Some(0x0400), table.lookup("screen"));
+    assert_eq!(Some(12), table.lookup("count"));
+    assert_eq!(SymbolFormat::Tass64, SymbolFormat::detect(TASS64));
+    let table = SymbolTable::parse(TASS64, SymbolFormat::Tass64).unwrap();
+    assert_eq!(Some(0xC012), table.lookup("scope.inner"));
+    assert_eq!("loop+1", table.format_addr(0xC011));
+    assert!(SymbolTable::parse("main $c000", SymbolFormat::Tass64).is_err());
+}
+
+#[test]
+fn test_symbols_merge_and_from_elf() {
+    let elf = ElfFile { entry: 0x0800, segments: vec![], symbols: vec![ElfSymbol { name: "_start".to_string(), addr: 0x0800, size: 2, kind: SymbolKind::Function }] };
+    let mut table = SymbolTable::from(&elf);
+    assert_eq!("_start+1", table.format_addr(0x0801));
+    assert_eq!("$0802", table.format_addr(0x0802));
+    table.merge(&SymbolTable::parse(VICE, SymbolFormat::Vice).unwrap());
+    assert_eq!(4, table.len());
+    let names: Vec<&str> = table.iter().map(|sym| sym.name.as_str()).collect();
+    assert_eq!(vec!["_start", "main", "loop", "nmi_vector"], names);
+}
+
+#[test]
+fn test_symbols_from_elf_clamp_size() {
+    let elf = ElfFile { entry: 0, segments: vec![], symbols: vec![ElfSymbol { name: "all".to_string(), addr: 0, size: 0x1_0000, kind: SymbolKind::Object }] };
+    let table = SymbolTable::from(&elf);
+    assert!(table.format_addr(0xFFFE).starts_with("all+"), "a 64K symbol keeps its size");
+}
+
+#[test]
+fn test_symbols_ca65_dbg_clamp_size() {
+    let dbg = "scope\tid=0,name=\"\",mod=0\nsym\tid=0,name=\"all\",addrsize=absolute,size=0x10000,scope=0,val=0x0000,type=lab\n";
+    let table = SymbolTable::parse(dbg, SymbolFormat::Ca65Dbg).unwrap();
+    assert!(table.format_addr(0xFFFE).starts_with("all+"), "a 64K symbol keeps its size");
+}
+
+#[test]
+fn test_symbols_ca65_dbg_scope_cycle() {
+    let dbg = "scope\tid=0,name=\"a\",mod=0,parent=1\nscope\tid=1,name=\"b\",mod=0,parent=0\nsym\tid=0,name=\"main\",addrsize=absolute,scope=0,val=0xC000,type=lab\n";
+    match SymbolTable::parse(dbg, SymbolFormat::Ca65Dbg) {
+        Err(SymbolError::Parse { line, .. }) => assert_eq!(3, line),
+        other => panic!("expected a parse error, got {:?}", other.map(|table| table.len())),
+    }
+}
@@ -0,0 +1,149 @@
+use emulator6502::trace::*;
+use emulator6502::*;
+use rstest::*;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Default)]
+struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Lines taken from nestest.log, the memory set up as it is at that point
+#[rstest]
+#[case::jmp(&[0x4C, 0xF5, 0xC5], &[], [0x00, 0x00, 0x00, 0x24, 0xFD], 7, "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7")]
+#[case::immediate(&[0xA2, 0x00], &[], [0x00, 0x00, 0x00, 0x24, 0xFD], 10, "C000  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10")]
+#[case::zero(&[0x86, 0x00], &[(0x00, 0x00)], [0x00, 0x00, 0x00, 0x26, 0xFD], 16, "C000  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 48 CYC:16")]
+#[case::ind_x(&[0xA1, 0x80], &[(0x80, 0x00), (0x81, 0x02), (0x200, 0x5A)], [0x5A, 0x00, 0x69, 0x27, 0xFB], 2967, "C000  A1 80     LDA ($80,X) @ 80 = 0200 = 5A    A:5A X:00 Y:69 P:27 SP:FB PPU: 26, 35 CYC:2967")]
+#[case::ind_y(&[0xB1, 0x89], &[(0x89, 0x00), (0x8A, 0x03), (0x300, 0x89)], [0x00, 0x65, 0x00, 0x27, 0xFB], 4100, "C000  B1 89     LDA ($89),Y = 0300 @ 0300 = 89  A:00 X:65 Y:00 P:27 SP:FB PPU: 36, 24 CYC:4100")]
+#[case::abs_x(&[0xBD, 0x00, 0x03], &[(0x0333, 0x89)], [0x00, 0x33, 0x00, 0x27, 0xFB], 100, "C000  BD 00 03  LDA $0300,X @ 0333 = 89         A:00 X:33 Y:00 P:27 SP:FB PPU:  0,300 CYC:100")]
+#[case::zero_y(&[0xB6, 0xF0], &[(0x0010, 0x42)], [0x00, 0x00, 0x20, 0x27, 0xFB], 100, "C000  B6 F0     LDX $F0,Y @ 10 = 42             A:00 X:00 Y:20 P:27 SP:FB PPU:  0,300 CYC:100")]
+#[case::jmp_indirect_page_wrap(&[0x6C, 0xFF, 0x02], &[(0x02FF, 0x00), (0x0200, 0x03), (0x0300, 0x99)], [0x00, 0x00, 0x00, 0x27, 0xFB], 100, "C000  6C FF 02  JMP ($02FF) = 0300              A:00 X:00 Y:00 P:27 SP:FB PPU:  0,300 CYC:100")]
+#[case::illegal(&[0x04, 0xA9], &[(0xA9, 0x00)], [0xAA, 0x00, 0x00, 0xA4, 0xFB], 100, "C000  04 A9    *NOP $A9 = 00                    A:AA X:00 Y:00 P:A4 SP:FB PPU:  0,300 CYC:100")]
+#[case::accumulator(&[0x4A], &[], [0x01, 0x00, 0x00, 0x27, 0xFB], 100, "C000  4A        LSR A                           A:01 X:00 Y:00 P:27 SP:FB PPU:  0,300 CYC:100")]
+fn test_trace_line_matches_nestest(#[case] code: &[u8], #[case] data: &[(usize, u8)], #[case] regs: [u8; 5], #[case] cycles: u32, #[case] expected: &str) {
+    let mut mem = Mem::new();
+    mem.load_programm_at(0xC000, code);
+    for (addr, val) in data {
+        mem.write8(*addr, *val);
+    }
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.pc = 0xC000;
+    // nestest order is A X Y P SP
+    cpu.regs[Cpu::REG_A] = regs[0];
+    cpu.regs[Cpu::REG_X] = regs[1];
+    cpu.regs[Cpu::REG_Y] = regs[2];
+    cpu.regs[Cpu::REG_STAT] = regs[3];
+    cpu.regs[Cpu::REG_SP] = regs[4];
+    cpu.cycles_run = cycles;
+    assert_eq!(expected, trace_line(&cpu));
+}
+
+#[test]
+fn test_tracer_logs_each_instruction() {
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::LDA_IMMEDIATE, 0xCA, Cpu::TRANS_A_TO_X, Cpu::STA_ZERO, 0x10]);
+    let buf = SharedBuf::default();
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.tracer = Some(Tracer::new(buf.clone()));
+    cpu.process(7);
+    let log = String::from_utf8(buf.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(3, lines.len());
+    assert!(lines[0].starts_with("FCE2  A9 CA     LDA #$CA "), "{}", lines[0]);
+    assert!(lines[1].starts_with("FCE4  AA        TAX "), "{}", lines[1]);
+    assert!(lines[1].contains("A:CA X:00") && lines[1].ends_with("CYC:2"), "{}", lines[1]);
+    assert!(lines[2].starts_with("FCE5  85 10     STA $10 = 00 "), "{}", lines[2]);
+}
+
+#[test]
+fn test_tracer_to_file() {
+    let path = std::env::temp_dir().join(format!("emulator6502-trace-{}.log", std::process::id()));
+    let mut mem = Mem::new();
+    mem.reset();
+    mem.load_programm(&[Cpu::NOP_IMPLIED]);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.tracer = Some(Tracer::to_file(&path).unwrap());
+    cpu.step();
+    cpu.tracer = None;
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(log.starts_with("FCE2  EA        NOP "), "{}", log);
+}
+
+#[test]
+fn test_trace_record_parse() {
+    let line = "C000  4C F5 C5  JMP $C5F5                       A:00 X:01 Y:02 P:24 SP:FD PPU:  0, 21 CYC:7";
+    let record = TraceRecord::parse(line).unwrap();
+    assert_eq!(TraceRecord { pc: 0xC000, a: 0, x: 1, y: 2, p: 0x24, sp: 0xFD, cycles: Some(7) }, record);
+    // other emulators put less into the line
+    let record = TraceRecord::parse("C5F5 A:10 X:00 Y:00 P:A4 SP:FB").unwrap();
+    assert_eq!((0xC5F5, 0x10, 0xA4, None), (record.pc, record.a, record.p, record.cycles));
+    assert_eq!(None, TraceRecord::parse("; header"));
+    assert_eq!(None, TraceRecord::parse("C000  4C F5 C5  JMP $C5F5"));
+}
+
+#[test]
+fn test_trace_record_diff() {
+    let expected = TraceRecord { pc: 0xC000, a: 0, x: 1, y: 2, p: 0x24, sp: 0xFD, cycles: Some(7) };
+    assert!(expected.diff(&expected).is_empty());
+    let got = TraceRecord { a: 0x10, p: 0xA6, cycles: Some(8), ..expected };
+    assert_eq!(vec!["A: expected 00, got 10", "P: expected 24, got A6 (flags N Z differ)", "CYC: expected 7, got 8"], got.diff(&expected));
+    let got = TraceRecord { cycles: None, ..got };
+    assert_eq!(2, got.diff(&expected).len(), "cycles are skipped when missing");
+}
+
+// tests run in parallel, each run gets files of its own
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn run_trace_diff(program: &[u8], reference: &str) -> (i32, String) {
+    let dir = std::env::temp_dir();
+    let id = format!("{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed));
+    let program_path = dir.join(format!("trace-diff-{}.bin", id));
+    let reference_path = dir.join(format!("trace-diff-{}.log", id));
+    std::fs::write(&program_path, program).unwrap();
+    std::fs::write(&reference_path, reference).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_trace-diff")).arg(&program_path).arg(&reference_path).output().unwrap();
+    std::fs::remove_file(&program_path).unwrap();
+    std::fs::remove_file(&reference_path).unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_trace_diff_matching_log() {
+    let program = [Cpu::LDA_IMMEDIATE, 0x80, Cpu::TRANS_A_TO_X];
+    let reference = "0200 A:00 X:00 Y:00 P:00 SP:FD CYC:7\n0202 A:80 X:00 Y:00 P:80 SP:FD CYC:9\n0203 A:80 X:80 Y:00 P:80 SP:FD CYC:11\n";
+    let (code, out) = run_trace_diff(&program, reference);
+    assert_eq!((0, "3 instructions match\n"), (code, out.as_str()));
+}
+
+#[test]
+fn test_trace_diff_reports_first_divergence() {
+    let program = [Cpu::LDA_IMMEDIATE, 0x80, Cpu::TRANS_A_TO_X];
+    let reference = "0200 A:00 X:00 Y:00 P:00 SP:FD CYC:7\n0202 A:80 X:00 Y:00 P:80 SP:FD CYC:9\n0203 A:80 X:80 Y:00 P:00 SP:FD CYC:12\n";
+    let (code, out) = run_trace_diff(&program, reference);
+    assert_eq!(1, code);
+    assert!(out.starts_with("Divergence after 2 instructions, reference line 3, cycle 12\n"), "{}", out);
+    assert!(out.contains("  0202  AA        TAX "), "{}", out);
+    assert!(out.ends_with("P: expected 00, got 80 (flags N differ)\nCYC: expected 12, got 11\n"), "{}", out);
+}
+
+#[test]
+fn test_trace_diff_rejects_addresses_past_ffff() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_trace-diff")).args(["--pc", "$10000", "program.bin", "reference.log"]).output().unwrap();
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("trace-diff: invalid address $10000\n"));
+}
@@ -0,0 +1,119 @@
+use emulator6502::asm::assemble;
+use emulator6502::callstack::CallStack;
+use emulator6502::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+// copies the keys to the screen in upper case through the KERNAL, until a return
+const PROGRAM: &str = "
+    CHROUT = $FFD2
+    GETIN = $FFE4
+
+    .org $0200
+    start:  ldx #0
+    loop:   jsr GETIN
+            beq loop
+            cmp #13
+            beq done
+            and #$DF
+            jsr CHROUT
+            inx
+            jmp loop
+    done:   stx $10
+    halt:   jmp halt
+";
+
+#[test]
+fn test_traps_stub_kernal() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    cpu.call_stack = Some(CallStack::new());
+    let screen = Rc::new(RefCell::new(Vec::new()));
+    let output = screen.clone();
+    cpu.traps.set(0xFFD2, move |cpu| output.borrow_mut().push(cpu.regs[Cpu::REG_A]));
+    let mut keys: VecDeque<u8> = b"\0abc\0\rx".iter().copied().collect();
+    cpu.traps.set(0xFFE4, move |cpu| {
+        let key = keys.pop_front().unwrap_or(0);
+        cpu.regs[Cpu::REG_A] = key;
+        // Z from the key like the real GETIN
+        cpu.regs[Cpu::REG_STAT] &= !Cpu::FLAG_ZERO;
+        if key == 0 {
+            cpu.regs[Cpu::REG_STAT] |= Cpu::FLAG_ZERO;
+        }
+    });
+    assert!(cpu.traps.contains(0xFFD2));
+    assert_eq!(vec![0xFFD2, 0xFFE4], cpu.traps.addresses());
+
+    cpu.process(1000);
+    assert_eq!(b"ABC".to_vec(), *screen.borrow());
+    assert_eq!(3, cpu.mem().read8(0x10), "X kept");
+    assert_eq!(0xFF, cpu.regs[Cpu::REG_SP], "returned");
+    assert_eq!(0, cpu.call_stack.as_ref().unwrap().depth());
+
+    // one step for the JSR, one for the trap
+    cpu.pc = 0x0202;
+    cpu.step();
+    assert_eq!(0xFFE4, cpu.pc);
+    let cycles = cpu.cycles_run;
+    cpu.step();
+    assert_eq!(0x0205, cpu.pc);
+    assert_eq!(cycles, cpu.cycles_run, "no cycles");
+    assert_eq!(b'x', cpu.regs[Cpu::REG_A]);
+    assert!(cpu.traps.remove(0xFFE4));
+    assert!(!cpu.traps.remove(0xFFE4));
+    assert_eq!(1, cpu.traps.len());
+}
+
+#[test]
+fn test_trap_removes_itself() {
+    let mut mem = Mem::new();
+    // jsr $1000 twice
+    mem.load_programm_at(0x0200, &[0x20, 0x00, 0x10, 0x20, 0x00, 0x10]);
+    mem.write8(0x1000, 0x60);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    let calls = Rc::new(RefCell::new(0));
+    let counter = calls.clone();
+    cpu.traps.set(0x1000, move |cpu| {
+        *counter.borrow_mut() += 1;
+        assert!(cpu.traps.remove(0x1000));
+    });
+    for _ in 0..4 {
+        cpu.step();
+    }
+    assert_eq!(1, *calls.borrow(), "the second call runs the RTS in memory");
+    assert_eq!(0x0206, cpu.pc);
+    assert!(cpu.traps.is_empty());
+}
+
+#[test]
+fn test_trap_runs_code_that_reaches_another_trap() {
+    let mut mem = Mem::new();
+    // jsr $3000 twice, $1000 calls $2000
+    mem.load_programm_at(0x0200, &[0x20, 0x00, 0x30, 0x20, 0x00, 0x30]);
+    mem.load_programm_at(0x1000, &[0x20, 0x00, 0x20, 0x60]);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu.regs[Cpu::REG_SP] = 0xFF;
+    cpu.traps.set(0x2000, |cpu| cpu.regs[Cpu::REG_A] += 1);
+    cpu.traps.set(0x3000, |cpu| {
+        assert_eq!(vec![0x2000, 0x3000], cpu.traps.addresses(), "the running trap counts");
+        assert_eq!(2, cpu.traps.len());
+        let result = cpu.call(0x1000, cpu.regs[Cpu::REG_A], 0, 0).unwrap();
+        cpu.regs[Cpu::REG_A] = result.a;
+    });
+    for _ in 0..4 {
+        cpu.step();
+    }
+    assert_eq!(2, cpu.regs[Cpu::REG_A], "both calls reached the inner trap");
+    assert_eq!(0x0206, cpu.pc);
+    assert_eq!(vec![0x2000, 0x3000], cpu.traps.addresses());
+}
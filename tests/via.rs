@@ -0,0 +1,189 @@
+use emulator6502::asm::assemble;
+use emulator6502::callstack::*;
+use emulator6502::device::Device;
+use emulator6502::via::Via;
+use emulator6502::*;
+
+#[test]
+fn test_via_t1_one_shot() {
+    let mut via = Via::new();
+    via.write(Via::IER, 0x80 | Via::IRQ_T1);
+    via.write(Via::T1C_L, 0x10);
+    via.write(Via::T1C_H, 0x00);
+    via.tick(1);
+    assert_eq!(0x10, via.peek(Via::T1C_L), "loaded in the cycle after the write");
+    via.tick(0x10);
+    assert_eq!(0x00, via.peek(Via::T1C_L));
+    assert!(!via.irq());
+    via.tick(1);
+    assert_eq!(0xFF, via.peek(Via::T1C_H));
+    assert!(via.irq());
+    assert_eq!(Via::IRQ_ANY | Via::IRQ_T1, via.peek(Via::IFR));
+    assert!(via.irq(), "peek has no side effects");
+    assert_eq!(0xFF, via.read(Via::T1C_L));
+    assert!(!via.irq(), "reading T1C-L clears the flag");
+    via.tick(0x20000);
+    assert!(!via.irq(), "one shot");
+    via.write(Via::T1C_H, 0x00);
+    via.tick(0x12);
+    assert!(via.irq(), "writing T1C-H starts it again");
+}
+
+#[test]
+fn test_via_t1_free_run_pb7() {
+    let mut via = Via::new();
+    via.write(Via::ACR, Via::ACR_T1_FREE_RUN | Via::ACR_T1_PB7);
+    assert_eq!(0x80, via.port_b() & 0x80);
+    via.write(Via::T1C_L, 0x08);
+    via.write(Via::T1C_H, 0x00);
+    assert_eq!(0x00, via.port_b() & 0x80, "pb7 goes low on load");
+    let mut timeouts = Vec::new();
+    for cycle in 1..=50 {
+        via.tick(1);
+        if via.peek(Via::IFR) & Via::IRQ_T1 != 0 {
+            timeouts.push((cycle, via.port_b() & 0x80));
+            via.write(Via::IFR, Via::IRQ_T1);
+        }
+    }
+    assert_eq!(vec![(10, 0x80), (20, 0x00), (30, 0x80), (40, 0x00), (50, 0x80)], timeouts, "a period of N + 2 cycles");
+}
+
+#[test]
+fn test_via_t2() {
+    let mut via = Via::new();
+    via.write(Via::T2C_L, 0x04);
+    via.write(Via::T2C_H, 0x00);
+    via.tick(5);
+    assert_eq!(0, via.peek(Via::IFR));
+    via.tick(1);
+    assert_eq!(Via::IRQ_T2, via.peek(Via::IFR));
+    via.read(Via::T2C_L);
+    via.tick(0x10000);
+    assert_eq!(0, via.peek(Via::IFR), "T2 is always one shot");
+
+    // counting pulses on PB6
+    via.write(Via::ACR, Via::ACR_T2_PULSES);
+    via.write(Via::T2C_L, 0x02);
+    via.write(Via::T2C_H, 0x00);
+    via.tick(100);
+    assert_eq!(0x02, via.peek(Via::T2C_L), "cycles don't count");
+    for _ in 0..3 {
+        via.set_port_b(0xBF);
+        via.set_port_b(0xFF);
+    }
+    assert_eq!(0xFF, via.peek(Via::T2C_L));
+    assert_eq!(Via::IRQ_T2, via.peek(Via::IFR));
+}
+
+#[test]
+fn test_via_ports_and_handshake() {
+    let mut via = Via::new();
+    via.write(Via::DDRB, 0x0F);
+    via.write(Via::ORB, 0x55);
+    via.set_port_b(0xA0);
+    assert_eq!(0xA5, via.read(Via::ORB), "outputs from ORB, inputs from the pins");
+    assert_eq!(0xA5, via.port_b());
+
+    // port A latched on a rising CA1 edge, CA2 handshake output
+    via.write(Via::ACR, Via::ACR_LATCH_A);
+    via.write(Via::PCR, 0x01 | 0b100 << 1);
+    via.write(Via::IER, 0x80 | Via::IRQ_CA1);
+    via.set_port_a(0x42);
+    via.set_ca1(false);
+    assert!(!via.irq(), "falling edge");
+    via.set_ca1(true);
+    assert!(via.irq());
+    via.set_port_a(0x00);
+    assert_eq!(0x42, via.peek(Via::ORA_NH));
+    assert!(via.ca2());
+    assert_eq!(0x42, via.read(Via::ORA));
+    assert!(!via.irq(), "reading ORA clears CA1");
+    assert!(!via.ca2(), "data taken");
+    via.set_ca1(false);
+    via.set_ca1(true);
+    assert!(via.ca2(), "data ready");
+
+    // independent CB2 interrupt input isn't cleared by ORB
+    via.write(Via::PCR, 0b001 << 5);
+    via.set_cb2(false);
+    via.read(Via::ORB);
+    assert_eq!(Via::IRQ_CB2, via.peek(Via::IFR) & Via::IRQ_CB2);
+    via.write(Via::IFR, 0x7F);
+    assert_eq!(0, via.peek(Via::IFR));
+}
+
+#[test]
+fn test_via_shift_register() {
+    let mut via = Via::new();
+    via.write(Via::ACR, 0b110 << 2);
+    via.write(Via::IER, 0x80 | Via::IRQ_SR);
+    via.write(Via::SR, 0b1011_0010);
+    let mut bits = Vec::new();
+    while !via.irq() {
+        let clock = via.cb1();
+        via.tick(1);
+        if clock && !via.cb1() {
+            bits.push(via.cb2() as u8);
+        }
+    }
+    assert_eq!(vec![1, 0, 1, 1, 0, 0, 1, 0], bits, "msb first on CB2");
+    assert_eq!(0b1011_0010, via.peek(Via::SR));
+
+    // shift in under an external clock on CB1
+    via.write(Via::ACR, 0b011 << 2);
+    via.read(Via::SR);
+    for bit in [0, 1, 1, 0, 1, 0, 0, 1].iter() {
+        via.set_cb2(*bit == 1);
+        via.set_cb1(false);
+        via.set_cb1(true);
+    }
+    assert_eq!(0b0110_1001, via.peek(Via::SR));
+    assert!(via.irq());
+}
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  lda #$40
+            sta $600B       ; T1 free running
+            lda #$C0
+            sta $600E       ; T1 interrupts on
+            lda #$F0
+            sta $6004
+            lda #$00
+            sta $6005
+            cli
+    loop:   jmp loop
+    irq:    bit $6004
+            inc $10
+            rti
+    .org $FFFE
+    .word irq
+";
+
+#[test]
+fn test_via_cpu_irq() {
+    let mut mem = Mem::new();
+    assemble(PROGRAM).unwrap().load(&mut mem);
+    let mut cpu = Cpu::new(&mut mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    let index = cpu.devices.map(0x6000..=0x7FFF, Via::new());
+    cpu.call_stack = Some(CallStack::new());
+    while cpu.pc != 0x0218 {
+        cpu.step();
+    }
+    assert_eq!(Some(&Frame { kind: FrameKind::Irq, caller: 0x0215, target: 0x0218, return_addr: 0x0215, sp: 0xFF, cycles: 273 }), cpu.call_stack.as_ref().unwrap().frames().last());
+    assert_eq!(Cpu::FLAG_INTERRUPT, cpu.regs[Cpu::REG_STAT] & Cpu::FLAG_INTERRUPT);
+    assert_eq!(0, cpu.mem().read8(0x01FD) & Cpu::FLAG_BREAK, "pushed without the break flag");
+    assert_eq!(0xC0, cpu.peek8(0x6000 + Via::IFR), "peek leaves the flag alone");
+    let snapshot = cpu.snapshot();
+    cpu.process(20_000);
+    let count = cpu.mem().read8(0x10);
+    assert!((81..=83).contains(&count), "every 242 cycles: {}", count);
+    assert_eq!(0x6000..=0x7FFF, cpu.devices.range(index).unwrap());
+    cpu.restore(&snapshot);
+    assert_eq!(0xC0, cpu.peek8(0x7FFD), "the device comes back with the snapshot, mirrored");
+    cpu.devices.get_mut::<Via>(index).unwrap().write(Via::IER, 0x7F);
+    cpu.process(1000);
+    assert_eq!(1, cpu.mem().read8(0x10), "only the handler that was running");
+}
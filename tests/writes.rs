@@ -0,0 +1,58 @@
+use emulator6502::asm::assemble;
+use emulator6502::debugger::*;
+use emulator6502::writes::*;
+use emulator6502::*;
+
+const PROGRAM: &str = "
+    .org $0200
+    start:  ldx #3
+    loop:   jsr count
+            dex
+            bne loop
+    done:   jmp done
+    count:  inc $10
+            rts
+";
+
+fn new_cpu(mem: &mut Mem) -> Cpu<'_> {
+    assemble(PROGRAM).unwrap().load(mem);
+    let mut cpu = Cpu::new(mem);
+    cpu.reset();
+    cpu.pc = 0x0200;
+    cpu
+}
+
+#[test]
+fn test_writes_last_writer() {
+    let mut mem = Mem::new();
+    let mut cpu = new_cpu(&mut mem);
+    cpu.write_history = Some(WriteHistory::with_depth(2));
+    while cpu.pc != 0x0208 {
+        cpu.step();
+    }
+    let history = cpu.write_history.as_ref().unwrap();
+    assert_eq!(Some(Write { pc: 0x020B, cycle: 57, value: 3 }), history.last(0x10));
+    let writes: Vec<Write> = history.writes(0x10).copied().collect();
+    assert_eq!(vec![Write { pc: 0x020B, cycle: 57, value: 3 }, Write { pc: 0x020B, cycle: 35, value: 2 }], writes, "only the last two are kept");
+    // jsr pushes the high byte of the return address first
+    assert_eq!(Some(Write { pc: 0x0202, cycle: 50, value: 0x02 }), history.last(0x01FF));
+    assert_eq!(None, history.last(0x11));
+}
+
+#[test]
+fn test_writes_step_back_forgets() {
+    let mut mem = Mem::new();
+    let mut cpu = new_cpu(&mut mem);
+    cpu.write_history = Some(WriteHistory::new());
+    let mut debugger = Debugger::new(cpu);
+    debugger.add_watchpoint(Watchpoint::new(0x10, WatchKind::Write));
+    debugger.run(None);
+    debugger.run(None);
+    let last = |debugger: &Debugger<'_>| debugger.cpu.write_history.as_ref().unwrap().last(0x10).map(|write| write.value);
+    assert_eq!(Some(2), last(&debugger));
+    debugger.step_back();
+    assert_eq!(Some(1), last(&debugger));
+    assert_eq!(1, debugger.cpu.write_history.as_ref().unwrap().writes(0x10).count());
+    debugger.step();
+    assert_eq!(Some(2), last(&debugger), "writes are recorded again when going forward");
+}